@@ -1,10 +1,10 @@
+use crate::ir::id::FunctionID;
 use std::ops::Range;
 use wasmparser::BinaryReaderError;
 
+/// Error for parsing
 #[derive(Debug, Clone)]
 #[allow(clippy::enum_variant_names)]
-
-/// Error for parsing
 pub enum Error {
     BinaryReaderError(BinaryReaderError),
     UnknownVersion(u32),
@@ -33,6 +33,30 @@ pub enum Error {
     InvalidMemoryReservedByte {
         func_range: Range<usize>,
     },
+    /// Injected code leaves the blocks it opens open, or closes the blocks of the original code
+    UnbalancedInstrumentation {
+        func_idx: FunctionID,
+        instr_idx: usize,
+        reason: &'static str,
+    },
+    /// Two pieces of instrumentation on the same instruction cannot be applied together
+    InstrumentationConflict {
+        func_idx: FunctionID,
+        instr_idx: usize,
+        reason: &'static str,
+    },
+    /// The text format could not be converted to a binary
+    WatError(String),
+    /// The module could not be printed in the text format
+    PrintError(String),
+    /// An instruction refers to a tag that is not in the module
+    UnknownTag {
+        tag_idx: u32,
+    },
+    /// wasm-encoder cannot write this core type where it appears in a component
+    UnsupportedCoreType {
+        reason: &'static str,
+    },
 }
 
 impl From<BinaryReaderError> for Error {
@@ -94,6 +118,40 @@ impl std::fmt::Display for Error {
             Error::InvalidMemoryReservedByte { func_range } => {
                 write!(f, "Found a `memory.*` instruction with an invalid reserved byte in function at {:?}", func_range)
             }
+            Error::UnbalancedInstrumentation {
+                func_idx,
+                instr_idx,
+                reason,
+            } => {
+                write!(
+                    f,
+                    "Unbalanced instrumentation in function {}: {} at instruction {}",
+                    **func_idx, reason, instr_idx
+                )
+            }
+            Error::InstrumentationConflict {
+                func_idx,
+                instr_idx,
+                reason,
+            } => {
+                write!(
+                    f,
+                    "Conflicting instrumentation in function {} at instruction {}: {}",
+                    **func_idx, instr_idx, reason
+                )
+            }
+            Error::WatError(s) => {
+                write!(f, "Unable to parse the text format: {}", s)
+            }
+            Error::PrintError(s) => {
+                write!(f, "Unable to print the text format: {}", s)
+            }
+            Error::UnknownTag { tag_idx } => {
+                write!(f, "Instruction refers to the unknown tag {}", tag_idx)
+            }
+            Error::UnsupportedCoreType { reason } => {
+                write!(f, "Unable to encode the core type: {}", reason)
+            }
         }
     }
 }
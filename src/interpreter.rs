@@ -0,0 +1,1112 @@
+//! A reference interpreter for modules, to check the behavior of instrumented code without an external runtime.
+//!
+//! It covers the MVP numeric instructions, control flow, memories, globals, tables (through `call_indirect`)
+//! and functions imported from the host as Rust closures. A module is run as it would be encoded,
+//! so its pending instrumentation is run too.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use orca_wasm::interpreter::{Imports, Instance};
+//! use orca_wasm::ir::types::Value;
+//! use orca_wasm::Module;
+//!
+//! let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+//! let module = Module::parse(&buff, false).unwrap();
+//! let mut imports = Imports::new();
+//! imports.func("env", "log", |args| {
+//!     println!("{:?}", args);
+//!     Ok(vec![])
+//! });
+//! let mut instance = Instance::new(&module, imports).unwrap();
+//! let results = instance.invoke("main", &[Value::I32(1)]).unwrap();
+//! ```
+
+use crate::ir::id::{FunctionID, GlobalID, MemoryID, TypeID};
+use crate::ir::module::module_functions::FuncKind;
+use crate::ir::module::module_globals::GlobalKind;
+use crate::ir::module::module_types::FuncType;
+use crate::ir::module::{Iter, Module};
+use crate::ir::types::{
+    Body, DataSegmentKind, DataType, ElementItems, ElementKind, InitExpr, InitInstr, Value,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use wasmparser::{BlockType, MemArg, Operator, TypeRef, WasmFeatures};
+
+const PAGE_SIZE: usize = 65536;
+/// Maximum number of pages of a 32-bit memory
+const MAX_PAGES: u64 = 65536;
+/// Maximum number of nested calls, every call to a local function takes a frame of the Rust stack
+const MAX_CALL_DEPTH: usize = 256;
+
+/// Why running a module stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trap {
+    Unreachable,
+    IntegerDivideByZero,
+    IntegerOverflow,
+    InvalidConversionToInteger,
+    OutOfBoundsMemoryAccess,
+    OutOfBoundsTableAccess,
+    UninitializedElement,
+    IndirectCallTypeMismatch,
+    CallStackExhausted,
+    /// The encoded module does not validate
+    InvalidModule(String),
+    /// An import of the module was not given to the instance
+    UnknownImport {
+        module: String,
+        name: String,
+    },
+    /// No function is exported under this name
+    UnknownExport(String),
+    /// The values given to a function, or returned by a host function, do not match its type
+    TypeMismatch(String),
+    /// An instruction or a feature the interpreter does not support
+    Unsupported(String),
+    /// Raised by a host function
+    Host(String),
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trap::Unreachable => write!(f, "unreachable executed"),
+            Trap::IntegerDivideByZero => write!(f, "integer divide by zero"),
+            Trap::IntegerOverflow => write!(f, "integer overflow"),
+            Trap::InvalidConversionToInteger => write!(f, "invalid conversion to integer"),
+            Trap::OutOfBoundsMemoryAccess => write!(f, "out of bounds memory access"),
+            Trap::OutOfBoundsTableAccess => write!(f, "out of bounds table access"),
+            Trap::UninitializedElement => write!(f, "uninitialized element"),
+            Trap::IndirectCallTypeMismatch => write!(f, "indirect call type mismatch"),
+            Trap::CallStackExhausted => write!(f, "call stack exhausted"),
+            Trap::InvalidModule(err) => write!(f, "Invalid module: {}", err),
+            Trap::UnknownImport { module, name } => {
+                write!(f, "Unknown import: {}.{}", module, name)
+            }
+            Trap::UnknownExport(name) => write!(f, "Unknown export: {}", name),
+            Trap::TypeMismatch(err) => write!(f, "Type mismatch: {}", err),
+            Trap::Unsupported(what) => write!(f, "Unsupported: {}", what),
+            Trap::Host(err) => write!(f, "Host error: {}", err),
+        }
+    }
+}
+
+/// A function of the host, taking the arguments of the call and returning its results
+pub type HostFunc = Box<dyn FnMut(&[Value]) -> Result<Vec<Value>, Trap>>;
+
+/// The functions and globals given to the imports of a module, by module and name
+#[derive(Default)]
+pub struct Imports {
+    funcs: HashMap<(String, String), HostFunc>,
+    globals: HashMap<(String, String), Value>,
+}
+
+impl Imports {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give `func` to the imported function `module.name`
+    pub fn func(
+        &mut self,
+        module: &str,
+        name: &str,
+        func: impl FnMut(&[Value]) -> Result<Vec<Value>, Trap> + 'static,
+    ) -> &mut Self {
+        self.funcs
+            .insert((module.to_string(), name.to_string()), Box::new(func));
+        self
+    }
+
+    /// Give `value` to the imported global `module.name`
+    pub fn global(&mut self, module: &str, name: &str, value: Value) -> &mut Self {
+        self.globals
+            .insert((module.to_string(), name.to_string()), value);
+        self
+    }
+}
+
+/// A local function, ready to run
+struct Code {
+    num_results: usize,
+    /// The initial values of the locals, after the parameters
+    locals: Vec<Value>,
+    /// The offset of every operator in the binary of the instance
+    offsets: Vec<usize>,
+    /// The index of the `end` of every `block`, `loop`, `if` and `else`
+    ends: Vec<usize>,
+    /// The index of the `else` of every `if` that has one
+    elses: Vec<Option<usize>>,
+}
+
+impl Code {
+    fn new(ty: &FuncType, body: &Body) -> Result<Self, Trap> {
+        let mut locals = vec![];
+        for (count, ty) in body.locals.iter() {
+            locals.extend(std::iter::repeat_n(zero(ty)?, *count as usize));
+        }
+        let ops: Vec<&Operator> = body.instructions.iter().map(|i| &i.op).collect();
+        let offsets = body
+            .original
+            .as_ref()
+            .expect("The body is parsed from the binary")
+            .instr_offsets();
+        let mut ends = vec![usize::MAX; ops.len()];
+        let mut elses = vec![None; ops.len()];
+        let mut open: Vec<usize> = vec![];
+        for (idx, op) in ops.iter().enumerate() {
+            match op {
+                Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                    open.push(idx)
+                }
+                Operator::Else => {
+                    let if_idx = *open.last().expect("The module is validated");
+                    elses[if_idx] = Some(idx);
+                    open.push(idx);
+                }
+                Operator::End => {
+                    // the end of the function closes nothing
+                    while let Some(start) = open.pop() {
+                        ends[start] = idx;
+                        if !matches!(ops[start], Operator::Else) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Code {
+            num_results: ty.results.len(),
+            locals,
+            offsets,
+            ends,
+            elses,
+        })
+    }
+}
+
+enum Func {
+    /// Index of the host function
+    Host(usize),
+    Local(Rc<Code>),
+}
+
+struct Memory {
+    data: Vec<u8>,
+    max_pages: u64,
+}
+
+/// A block being run
+struct Label {
+    /// The height of the stack under the values the block takes
+    height: usize,
+    /// The number of values a branch to the block carries
+    arity: usize,
+    /// Where a branch to the block goes: the start of the body of a loop, after the end of other blocks
+    target: usize,
+    is_loop: bool,
+}
+
+/// Pop the operand of a unary operator and push the result of `$body`
+macro_rules! un {
+    ($stack:ident, $pop:ident, $res:ident, |$a:ident| $body:expr) => {{
+        let $a = $stack.$pop();
+        $stack.push(Value::$res($body));
+    }};
+}
+
+/// Pop the operands of a binary operator and push the result of `$body`
+macro_rules! bin {
+    ($stack:ident, $pop:ident, $res:ident, |$a:ident, $b:ident| $body:expr) => {{
+        let $b = $stack.$pop();
+        let $a = $stack.$pop();
+        $stack.push(Value::$res($body));
+    }};
+}
+
+/// An instantiated module, see the [module documentation](self)
+pub struct Instance {
+    /// The encoded module, the operators of the functions are read from it as they run
+    wasm: Rc<[u8]>,
+    types: Vec<Option<FuncType>>,
+    funcs: Vec<(FuncType, Func)>,
+    host: Vec<HostFunc>,
+    globals: Vec<Value>,
+    memories: Vec<Memory>,
+    tables: Vec<Vec<Option<u32>>>,
+    exports: HashMap<String, FunctionID>,
+    depth: usize,
+}
+
+impl Instance {
+    /// Instantiate the module as it would be encoded, with its pending instrumentation, and run its start function.
+    /// The IDs of functions, globals and memories are the ones of the encoded module.
+    pub fn new(module: &Module, imports: Imports) -> Result<Self, Trap> {
+        let wasm = module
+            .clone()
+            .try_encode()
+            .map_err(|e| Trap::InvalidModule(e.to_string()))?;
+        wasmparser::validate(&wasm).map_err(|e| Trap::InvalidModule(e.to_string()))?;
+        let module = Module::parse(&wasm, true).map_err(|e| Trap::InvalidModule(e.to_string()))?;
+
+        let unknown = |module: &str, name: &str| Trap::UnknownImport {
+            module: module.to_string(),
+            name: name.to_string(),
+        };
+        for import in module.imports.iter() {
+            if matches!(
+                import.ty,
+                TypeRef::Memory(_) | TypeRef::Table(_) | TypeRef::Tag(_)
+            ) {
+                return Err(Trap::Unsupported(format!(
+                    "imported {:?} {}.{}",
+                    import.ty, import.module, import.name
+                )));
+            }
+        }
+
+        let types: Vec<Option<FuncType>> = (0..module.types.len())
+            .map(|idx| module.types.get(TypeID(idx as u32)).cloned())
+            .collect();
+        let mut host_funcs = imports.funcs;
+        let mut host = vec![];
+        let mut funcs = vec![];
+        for func in module.functions.iter() {
+            let ty = types[*func.get_type_id() as usize]
+                .clone()
+                .ok_or_else(|| Trap::Unsupported("functions of a non-function type".to_string()))?;
+            let func = match func.kind() {
+                FuncKind::Import(import) => {
+                    let import = module.imports.get(import.import_id);
+                    let key = (import.module.to_string(), import.name.to_string());
+                    host.push(
+                        host_funcs
+                            .remove(&key)
+                            .ok_or_else(|| unknown(import.module, import.name))?,
+                    );
+                    Func::Host(host.len() - 1)
+                }
+                FuncKind::Local(local) => Func::Local(Rc::new(Code::new(&ty, &local.body)?)),
+            };
+            funcs.push((ty, func));
+        }
+
+        let mut globals: Vec<Value> = vec![];
+        for global in module.globals.iter() {
+            let value = match &global.kind {
+                GlobalKind::Import(import) => {
+                    let import = module.imports.get(import.import_id);
+                    let key = (import.module.to_string(), import.name.to_string());
+                    *imports
+                        .globals
+                        .get(&key)
+                        .ok_or_else(|| unknown(import.module, import.name))?
+                }
+                GlobalKind::Local(local) => eval(&local.init_expr, &globals)?,
+            };
+            globals.push(value);
+        }
+
+        let mut memories = vec![];
+        for memory in module.memories.iter() {
+            if memory.memory64 {
+                return Err(Trap::Unsupported("64-bit memories".to_string()));
+            }
+            memories.push(Memory {
+                data: vec![0; memory.initial as usize * PAGE_SIZE],
+                max_pages: memory.maximum.unwrap_or(MAX_PAGES).min(MAX_PAGES),
+            });
+        }
+        let mut tables = vec![];
+        for (ty, init) in module.tables.iter() {
+            if init.is_some() {
+                return Err(Trap::Unsupported("table initializers".to_string()));
+            }
+            tables.push(vec![None; ty.initial as usize]);
+        }
+
+        for (kind, items) in module.elements.iter() {
+            let ElementKind::Active {
+                table_index,
+                offset_expr,
+            } = kind
+            else {
+                continue;
+            };
+            let funcs: Vec<Option<u32>> = match items {
+                ElementItems::Functions(ids) => ids.iter().map(|id| Some(**id)).collect(),
+                ElementItems::ConstExprs { exprs, .. } => exprs
+                    .iter()
+                    .map(|expr| match expr.exprs.as_slice() {
+                        [InitInstr::RefFunc(id)] => Ok(Some(**id)),
+                        [InitInstr::RefNull(_)] => Ok(None),
+                        other => Err(Trap::Unsupported(format!("element {:?}", other))),
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+            let table = &mut tables[table_index.unwrap_or(0) as usize];
+            let offset = as_offset(eval(offset_expr, &globals)?)?;
+            table
+                .get_mut(offset..offset + funcs.len())
+                .ok_or(Trap::OutOfBoundsTableAccess)?
+                .copy_from_slice(&funcs);
+        }
+        for segment in module.data.iter() {
+            let DataSegmentKind::Active {
+                memory_index,
+                offset_expr,
+            } = &segment.kind
+            else {
+                continue;
+            };
+            let memory = &mut memories[*memory_index as usize];
+            let offset = as_offset(eval(offset_expr, &globals)?)?;
+            memory
+                .data
+                .get_mut(offset..offset + segment.data.len())
+                .ok_or(Trap::OutOfBoundsMemoryAccess)?
+                .copy_from_slice(&segment.data);
+        }
+
+        let exports = module
+            .exports
+            .iter()
+            .filter(|export| matches!(export.kind, wasmparser::ExternalKind::Func))
+            .map(|export| (export.name.clone(), FunctionID(export.index)))
+            .collect();
+        let start = module.start;
+        let mut instance = Instance {
+            wasm: wasm.into(),
+            types,
+            funcs,
+            host,
+            globals,
+            memories,
+            tables,
+            exports,
+            depth: 0,
+        };
+        if let Some(start) = start {
+            instance.call(start, &[])?;
+        }
+        Ok(instance)
+    }
+
+    /// Call the function exported as `name`
+    pub fn invoke(&mut self, name: &str, args: &[Value]) -> Result<Vec<Value>, Trap> {
+        let func_idx = *self
+            .exports
+            .get(name)
+            .ok_or_else(|| Trap::UnknownExport(name.to_string()))?;
+        self.call(func_idx, args)
+    }
+
+    /// Call a function of the module
+    pub fn call(&mut self, func_idx: FunctionID, args: &[Value]) -> Result<Vec<Value>, Trap> {
+        check_types(args, &self.funcs[*func_idx as usize].0.params, "arguments")?;
+        self.call_func(*func_idx, args.to_vec())
+    }
+
+    /// The current value of a global
+    pub fn global(&self, global_id: GlobalID) -> Value {
+        self.globals[*global_id as usize]
+    }
+
+    /// The current contents of a memory
+    pub fn memory(&self, memory_id: MemoryID) -> &[u8] {
+        &self.memories[*memory_id as usize].data
+    }
+
+    fn call_func(&mut self, func_idx: u32, args: Vec<Value>) -> Result<Vec<Value>, Trap> {
+        let code = match &self.funcs[func_idx as usize].1 {
+            Func::Host(host_idx) => {
+                let results = (self.host[*host_idx])(&args)?;
+                check_types(
+                    &results,
+                    &self.funcs[func_idx as usize].0.results,
+                    "host results",
+                )?;
+                return Ok(results);
+            }
+            Func::Local(code) => code.clone(),
+        };
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(Trap::CallStackExhausted);
+        }
+        self.depth += 1;
+        let results = self.run(&code, args);
+        self.depth -= 1;
+        results
+    }
+
+    /// The number of values a block takes and returns
+    fn block_arity(&self, ty: BlockType) -> (usize, usize) {
+        match ty {
+            BlockType::Empty => (0, 0),
+            BlockType::Type(_) => (0, 1),
+            BlockType::FuncType(idx) => {
+                let ty = self.types[idx as usize]
+                    .as_ref()
+                    .expect("The module is validated");
+                (ty.params.len(), ty.results.len())
+            }
+        }
+    }
+
+    fn run(&mut self, code: &Code, mut locals: Vec<Value>) -> Result<Vec<Value>, Trap> {
+        locals.extend_from_slice(&code.locals);
+        let mut stack = Stack(vec![]);
+        // the body of the function is a block, a branch to it returns
+        let mut labels = vec![Label {
+            height: 0,
+            arity: code.num_results,
+            target: code.offsets.len(),
+            is_loop: false,
+        }];
+        let wasm = self.wasm.clone();
+        let mut pc = 0;
+        while pc < code.offsets.len() {
+            let offset = code.offsets[pc];
+            let op = wasmparser::BinaryReader::new(&wasm[offset..], offset, WasmFeatures::all())
+                .read_operator()
+                .expect("The module is validated");
+            match &op {
+                Operator::Unreachable => return Err(Trap::Unreachable),
+                Operator::Nop => {}
+                Operator::Block { blockty } => {
+                    let (params, results) = self.block_arity(*blockty);
+                    labels.push(Label {
+                        height: stack.0.len() - params,
+                        arity: results,
+                        target: code.ends[pc] + 1,
+                        is_loop: false,
+                    });
+                }
+                Operator::Loop { blockty } => {
+                    let (params, _) = self.block_arity(*blockty);
+                    labels.push(Label {
+                        height: stack.0.len() - params,
+                        arity: params,
+                        target: pc + 1,
+                        is_loop: true,
+                    });
+                }
+                Operator::If { blockty } => {
+                    let cond = stack.i32();
+                    let (params, results) = self.block_arity(*blockty);
+                    labels.push(Label {
+                        height: stack.0.len() - params,
+                        arity: results,
+                        target: code.ends[pc] + 1,
+                        is_loop: false,
+                    });
+                    if cond == 0 {
+                        // run the `else` branch, or go to the `end` to leave the block
+                        pc = match code.elses[pc] {
+                            Some(else_idx) => else_idx + 1,
+                            None => code.ends[pc],
+                        };
+                        continue;
+                    }
+                }
+                Operator::Else => {
+                    // the end of the `if` branch
+                    pc = code.ends[pc];
+                    continue;
+                }
+                Operator::End => {
+                    labels.pop();
+                }
+                Operator::Br { relative_depth } => {
+                    pc = branch(&mut stack, &mut labels, *relative_depth);
+                    continue;
+                }
+                Operator::BrIf { relative_depth } => {
+                    if stack.i32() != 0 {
+                        pc = branch(&mut stack, &mut labels, *relative_depth);
+                        continue;
+                    }
+                }
+                Operator::BrTable { targets } => {
+                    let idx = stack.i32() as u32 as usize;
+                    let depths = targets
+                        .targets()
+                        .collect::<Result<Vec<u32>, _>>()
+                        .expect("The module is validated");
+                    let depth = depths.get(idx).copied().unwrap_or(targets.default());
+                    pc = branch(&mut stack, &mut labels, depth);
+                    continue;
+                }
+                Operator::Return => break,
+                Operator::Call { function_index } => {
+                    let num_params = self.funcs[*function_index as usize].0.params.len();
+                    let args = stack.0.split_off(stack.0.len() - num_params);
+                    let results = self.call_func(*function_index, args)?;
+                    stack.0.extend(results);
+                }
+                Operator::CallIndirect {
+                    type_index,
+                    table_index,
+                } => {
+                    let idx = stack.i32() as u32 as usize;
+                    let func_idx = self.tables[*table_index as usize]
+                        .get(idx)
+                        .ok_or(Trap::OutOfBoundsTableAccess)?
+                        .ok_or(Trap::UninitializedElement)?;
+                    let ty = &self.funcs[func_idx as usize].0;
+                    if self.types[*type_index as usize].as_ref() != Some(ty) {
+                        return Err(Trap::IndirectCallTypeMismatch);
+                    }
+                    let args = stack.0.split_off(stack.0.len() - ty.params.len());
+                    let results = self.call_func(func_idx, args)?;
+                    stack.0.extend(results);
+                }
+                Operator::Drop => {
+                    stack.pop();
+                }
+                Operator::Select | Operator::TypedSelect { .. } => {
+                    let cond = stack.i32();
+                    let b = stack.pop();
+                    let a = stack.pop();
+                    stack.push(if cond != 0 { a } else { b });
+                }
+
+                Operator::LocalGet { local_index } => stack.push(locals[*local_index as usize]),
+                Operator::LocalSet { local_index } => locals[*local_index as usize] = stack.pop(),
+                Operator::LocalTee { local_index } => {
+                    locals[*local_index as usize] =
+                        *stack.0.last().expect("The module is validated")
+                }
+                Operator::GlobalGet { global_index } => {
+                    stack.push(self.globals[*global_index as usize])
+                }
+                Operator::GlobalSet { global_index } => {
+                    self.globals[*global_index as usize] = stack.pop()
+                }
+                Operator::TableSize { table } => {
+                    stack.push(Value::I32(self.tables[*table as usize].len() as i32))
+                }
+
+                Operator::I32Load { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I32(i32::from_le_bytes(bytes)));
+                }
+                Operator::I64Load { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I64(i64::from_le_bytes(bytes)));
+                }
+                Operator::F32Load { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::F32(f32::from_le_bytes(bytes)));
+                }
+                Operator::F64Load { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::F64(f64::from_le_bytes(bytes)));
+                }
+                Operator::I32Load8S { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I32(i8::from_le_bytes(bytes) as i32));
+                }
+                Operator::I32Load8U { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I32(u8::from_le_bytes(bytes) as i32));
+                }
+                Operator::I32Load16S { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I32(i16::from_le_bytes(bytes) as i32));
+                }
+                Operator::I32Load16U { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I32(u16::from_le_bytes(bytes) as i32));
+                }
+                Operator::I64Load8S { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I64(i8::from_le_bytes(bytes) as i64));
+                }
+                Operator::I64Load8U { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I64(u8::from_le_bytes(bytes) as i64));
+                }
+                Operator::I64Load16S { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I64(i16::from_le_bytes(bytes) as i64));
+                }
+                Operator::I64Load16U { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I64(u16::from_le_bytes(bytes) as i64));
+                }
+                Operator::I64Load32S { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I64(i32::from_le_bytes(bytes) as i64));
+                }
+                Operator::I64Load32U { memarg } => {
+                    let bytes = self.load(memarg, stack.i32())?;
+                    stack.push(Value::I64(u32::from_le_bytes(bytes) as i64));
+                }
+                Operator::I32Store { memarg } => {
+                    let value = stack.i32();
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::I64Store { memarg } => {
+                    let value = stack.i64();
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::F32Store { memarg } => {
+                    let value = stack.f32();
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::F64Store { memarg } => {
+                    let value = stack.f64();
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::I32Store8 { memarg } => {
+                    let value = stack.i32() as u8;
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::I32Store16 { memarg } => {
+                    let value = stack.i32() as u16;
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::I64Store8 { memarg } => {
+                    let value = stack.i64() as u8;
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::I64Store16 { memarg } => {
+                    let value = stack.i64() as u16;
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::I64Store32 { memarg } => {
+                    let value = stack.i64() as u32;
+                    self.store(memarg, stack.i32(), &value.to_le_bytes())?;
+                }
+                Operator::MemorySize { mem } => {
+                    let pages = self.memories[*mem as usize].data.len() / PAGE_SIZE;
+                    stack.push(Value::I32(pages as i32));
+                }
+                Operator::MemoryGrow { mem } => {
+                    let delta = stack.i32() as u32 as u64;
+                    let memory = &mut self.memories[*mem as usize];
+                    let pages = (memory.data.len() / PAGE_SIZE) as u64;
+                    if pages + delta > memory.max_pages {
+                        stack.push(Value::I32(-1));
+                    } else {
+                        memory.data.resize((pages + delta) as usize * PAGE_SIZE, 0);
+                        stack.push(Value::I32(pages as i32));
+                    }
+                }
+                Operator::MemoryFill { mem } => {
+                    let len = stack.i32() as u32 as usize;
+                    let value = stack.i32() as u8;
+                    let dst = stack.i32() as u32 as usize;
+                    self.memories[*mem as usize]
+                        .data
+                        .get_mut(dst..dst + len)
+                        .ok_or(Trap::OutOfBoundsMemoryAccess)?
+                        .fill(value);
+                }
+                Operator::MemoryCopy { dst_mem, src_mem } => {
+                    let len = stack.i32() as u32 as usize;
+                    let src = stack.i32() as u32 as usize;
+                    let dst = stack.i32() as u32 as usize;
+                    let bytes = self.memories[*src_mem as usize]
+                        .data
+                        .get(src..src + len)
+                        .ok_or(Trap::OutOfBoundsMemoryAccess)?
+                        .to_vec();
+                    self.memories[*dst_mem as usize]
+                        .data
+                        .get_mut(dst..dst + len)
+                        .ok_or(Trap::OutOfBoundsMemoryAccess)?
+                        .copy_from_slice(&bytes);
+                }
+
+                Operator::I32Const { value } => stack.push(Value::I32(*value)),
+                Operator::I64Const { value } => stack.push(Value::I64(*value)),
+                Operator::F32Const { value } => {
+                    stack.push(Value::F32(f32::from_bits(value.bits())))
+                }
+                Operator::F64Const { value } => {
+                    stack.push(Value::F64(f64::from_bits(value.bits())))
+                }
+
+                Operator::I32Eqz => un!(stack, i32, I32, |a| (a == 0) as i32),
+                Operator::I32Eq => bin!(stack, i32, I32, |a, b| (a == b) as i32),
+                Operator::I32Ne => bin!(stack, i32, I32, |a, b| (a != b) as i32),
+                Operator::I32LtS => bin!(stack, i32, I32, |a, b| (a < b) as i32),
+                Operator::I32LtU => bin!(stack, i32, I32, |a, b| ((a as u32) < (b as u32)) as i32),
+                Operator::I32GtS => bin!(stack, i32, I32, |a, b| (a > b) as i32),
+                Operator::I32GtU => bin!(stack, i32, I32, |a, b| ((a as u32) > (b as u32)) as i32),
+                Operator::I32LeS => bin!(stack, i32, I32, |a, b| (a <= b) as i32),
+                Operator::I32LeU => bin!(stack, i32, I32, |a, b| ((a as u32) <= (b as u32)) as i32),
+                Operator::I32GeS => bin!(stack, i32, I32, |a, b| (a >= b) as i32),
+                Operator::I32GeU => bin!(stack, i32, I32, |a, b| ((a as u32) >= (b as u32)) as i32),
+                Operator::I64Eqz => un!(stack, i64, I32, |a| (a == 0) as i32),
+                Operator::I64Eq => bin!(stack, i64, I32, |a, b| (a == b) as i32),
+                Operator::I64Ne => bin!(stack, i64, I32, |a, b| (a != b) as i32),
+                Operator::I64LtS => bin!(stack, i64, I32, |a, b| (a < b) as i32),
+                Operator::I64LtU => bin!(stack, i64, I32, |a, b| ((a as u64) < (b as u64)) as i32),
+                Operator::I64GtS => bin!(stack, i64, I32, |a, b| (a > b) as i32),
+                Operator::I64GtU => bin!(stack, i64, I32, |a, b| ((a as u64) > (b as u64)) as i32),
+                Operator::I64LeS => bin!(stack, i64, I32, |a, b| (a <= b) as i32),
+                Operator::I64LeU => bin!(stack, i64, I32, |a, b| ((a as u64) <= (b as u64)) as i32),
+                Operator::I64GeS => bin!(stack, i64, I32, |a, b| (a >= b) as i32),
+                Operator::I64GeU => bin!(stack, i64, I32, |a, b| ((a as u64) >= (b as u64)) as i32),
+                Operator::F32Eq => bin!(stack, f32, I32, |a, b| (a == b) as i32),
+                Operator::F32Ne => bin!(stack, f32, I32, |a, b| (a != b) as i32),
+                Operator::F32Lt => bin!(stack, f32, I32, |a, b| (a < b) as i32),
+                Operator::F32Gt => bin!(stack, f32, I32, |a, b| (a > b) as i32),
+                Operator::F32Le => bin!(stack, f32, I32, |a, b| (a <= b) as i32),
+                Operator::F32Ge => bin!(stack, f32, I32, |a, b| (a >= b) as i32),
+                Operator::F64Eq => bin!(stack, f64, I32, |a, b| (a == b) as i32),
+                Operator::F64Ne => bin!(stack, f64, I32, |a, b| (a != b) as i32),
+                Operator::F64Lt => bin!(stack, f64, I32, |a, b| (a < b) as i32),
+                Operator::F64Gt => bin!(stack, f64, I32, |a, b| (a > b) as i32),
+                Operator::F64Le => bin!(stack, f64, I32, |a, b| (a <= b) as i32),
+                Operator::F64Ge => bin!(stack, f64, I32, |a, b| (a >= b) as i32),
+
+                Operator::I32Clz => un!(stack, i32, I32, |a| a.leading_zeros() as i32),
+                Operator::I32Ctz => un!(stack, i32, I32, |a| a.trailing_zeros() as i32),
+                Operator::I32Popcnt => un!(stack, i32, I32, |a| a.count_ones() as i32),
+                Operator::I32Add => bin!(stack, i32, I32, |a, b| a.wrapping_add(b)),
+                Operator::I32Sub => bin!(stack, i32, I32, |a, b| a.wrapping_sub(b)),
+                Operator::I32Mul => bin!(stack, i32, I32, |a, b| a.wrapping_mul(b)),
+                Operator::I32DivS => bin!(stack, i32, I32, |a, b| {
+                    if b == 0 {
+                        return Err(Trap::IntegerDivideByZero);
+                    }
+                    a.checked_div(b).ok_or(Trap::IntegerOverflow)?
+                }),
+                Operator::I32DivU => bin!(stack, i32, I32, |a, b| {
+                    (a as u32)
+                        .checked_div(b as u32)
+                        .ok_or(Trap::IntegerDivideByZero)? as i32
+                }),
+                Operator::I32RemS => bin!(stack, i32, I32, |a, b| {
+                    if b == 0 {
+                        return Err(Trap::IntegerDivideByZero);
+                    }
+                    a.wrapping_rem(b)
+                }),
+                Operator::I32RemU => bin!(stack, i32, I32, |a, b| {
+                    (a as u32)
+                        .checked_rem(b as u32)
+                        .ok_or(Trap::IntegerDivideByZero)? as i32
+                }),
+                Operator::I32And => bin!(stack, i32, I32, |a, b| a & b),
+                Operator::I32Or => bin!(stack, i32, I32, |a, b| a | b),
+                Operator::I32Xor => bin!(stack, i32, I32, |a, b| a ^ b),
+                Operator::I32Shl => bin!(stack, i32, I32, |a, b| a.wrapping_shl(b as u32)),
+                Operator::I32ShrS => bin!(stack, i32, I32, |a, b| a.wrapping_shr(b as u32)),
+                Operator::I32ShrU => {
+                    bin!(stack, i32, I32, |a, b| (a as u32).wrapping_shr(b as u32)
+                        as i32)
+                }
+                Operator::I32Rotl => bin!(stack, i32, I32, |a, b| a.rotate_left(b as u32)),
+                Operator::I32Rotr => bin!(stack, i32, I32, |a, b| a.rotate_right(b as u32)),
+                Operator::I64Clz => un!(stack, i64, I64, |a| a.leading_zeros() as i64),
+                Operator::I64Ctz => un!(stack, i64, I64, |a| a.trailing_zeros() as i64),
+                Operator::I64Popcnt => un!(stack, i64, I64, |a| a.count_ones() as i64),
+                Operator::I64Add => bin!(stack, i64, I64, |a, b| a.wrapping_add(b)),
+                Operator::I64Sub => bin!(stack, i64, I64, |a, b| a.wrapping_sub(b)),
+                Operator::I64Mul => bin!(stack, i64, I64, |a, b| a.wrapping_mul(b)),
+                Operator::I64DivS => bin!(stack, i64, I64, |a, b| {
+                    if b == 0 {
+                        return Err(Trap::IntegerDivideByZero);
+                    }
+                    a.checked_div(b).ok_or(Trap::IntegerOverflow)?
+                }),
+                Operator::I64DivU => bin!(stack, i64, I64, |a, b| {
+                    (a as u64)
+                        .checked_div(b as u64)
+                        .ok_or(Trap::IntegerDivideByZero)? as i64
+                }),
+                Operator::I64RemS => bin!(stack, i64, I64, |a, b| {
+                    if b == 0 {
+                        return Err(Trap::IntegerDivideByZero);
+                    }
+                    a.wrapping_rem(b)
+                }),
+                Operator::I64RemU => bin!(stack, i64, I64, |a, b| {
+                    (a as u64)
+                        .checked_rem(b as u64)
+                        .ok_or(Trap::IntegerDivideByZero)? as i64
+                }),
+                Operator::I64And => bin!(stack, i64, I64, |a, b| a & b),
+                Operator::I64Or => bin!(stack, i64, I64, |a, b| a | b),
+                Operator::I64Xor => bin!(stack, i64, I64, |a, b| a ^ b),
+                Operator::I64Shl => bin!(stack, i64, I64, |a, b| a.wrapping_shl(b as u32)),
+                Operator::I64ShrS => bin!(stack, i64, I64, |a, b| a.wrapping_shr(b as u32)),
+                Operator::I64ShrU => {
+                    bin!(stack, i64, I64, |a, b| (a as u64).wrapping_shr(b as u32)
+                        as i64)
+                }
+                Operator::I64Rotl => bin!(stack, i64, I64, |a, b| a.rotate_left(b as u32)),
+                Operator::I64Rotr => bin!(stack, i64, I64, |a, b| a.rotate_right(b as u32)),
+
+                Operator::F32Abs => un!(stack, f32, F32, |a| a.abs()),
+                Operator::F32Neg => un!(stack, f32, F32, |a| -a),
+                Operator::F32Ceil => un!(stack, f32, F32, |a| a.ceil()),
+                Operator::F32Floor => un!(stack, f32, F32, |a| a.floor()),
+                Operator::F32Trunc => un!(stack, f32, F32, |a| a.trunc()),
+                Operator::F32Nearest => un!(stack, f32, F32, |a| a.round_ties_even()),
+                Operator::F32Sqrt => un!(stack, f32, F32, |a| a.sqrt()),
+                Operator::F32Add => bin!(stack, f32, F32, |a, b| a + b),
+                Operator::F32Sub => bin!(stack, f32, F32, |a, b| a - b),
+                Operator::F32Mul => bin!(stack, f32, F32, |a, b| a * b),
+                Operator::F32Div => bin!(stack, f32, F32, |a, b| a / b),
+                Operator::F32Min => bin!(stack, f32, F32, |a, b| f32_min(a, b)),
+                Operator::F32Max => bin!(stack, f32, F32, |a, b| f32_max(a, b)),
+                Operator::F32Copysign => bin!(stack, f32, F32, |a, b| a.copysign(b)),
+                Operator::F64Abs => un!(stack, f64, F64, |a| a.abs()),
+                Operator::F64Neg => un!(stack, f64, F64, |a| -a),
+                Operator::F64Ceil => un!(stack, f64, F64, |a| a.ceil()),
+                Operator::F64Floor => un!(stack, f64, F64, |a| a.floor()),
+                Operator::F64Trunc => un!(stack, f64, F64, |a| a.trunc()),
+                Operator::F64Nearest => un!(stack, f64, F64, |a| a.round_ties_even()),
+                Operator::F64Sqrt => un!(stack, f64, F64, |a| a.sqrt()),
+                Operator::F64Add => bin!(stack, f64, F64, |a, b| a + b),
+                Operator::F64Sub => bin!(stack, f64, F64, |a, b| a - b),
+                Operator::F64Mul => bin!(stack, f64, F64, |a, b| a * b),
+                Operator::F64Div => bin!(stack, f64, F64, |a, b| a / b),
+                Operator::F64Min => bin!(stack, f64, F64, |a, b| f64_min(a, b)),
+                Operator::F64Max => bin!(stack, f64, F64, |a, b| f64_max(a, b)),
+                Operator::F64Copysign => bin!(stack, f64, F64, |a, b| a.copysign(b)),
+
+                Operator::I32WrapI64 => un!(stack, i64, I32, |a| a as i32),
+                Operator::I32TruncF32S => {
+                    un!(stack, f32, I32, |a| trunc(a as f64, I32_RANGE)? as i32)
+                }
+                Operator::I32TruncF32U => {
+                    un!(stack, f32, I32, |a| trunc(a as f64, U32_RANGE)? as u32
+                        as i32)
+                }
+                Operator::I32TruncF64S => un!(stack, f64, I32, |a| trunc(a, I32_RANGE)? as i32),
+                Operator::I32TruncF64U => {
+                    un!(stack, f64, I32, |a| trunc(a, U32_RANGE)? as u32 as i32)
+                }
+                Operator::I64ExtendI32S => un!(stack, i32, I64, |a| a as i64),
+                Operator::I64ExtendI32U => un!(stack, i32, I64, |a| a as u32 as i64),
+                Operator::I64TruncF32S => {
+                    un!(stack, f32, I64, |a| trunc(a as f64, I64_RANGE)? as i64)
+                }
+                Operator::I64TruncF32U => {
+                    un!(stack, f32, I64, |a| trunc(a as f64, U64_RANGE)? as u64
+                        as i64)
+                }
+                Operator::I64TruncF64S => un!(stack, f64, I64, |a| trunc(a, I64_RANGE)? as i64),
+                Operator::I64TruncF64U => {
+                    un!(stack, f64, I64, |a| trunc(a, U64_RANGE)? as u64 as i64)
+                }
+                Operator::F32ConvertI32S => un!(stack, i32, F32, |a| a as f32),
+                Operator::F32ConvertI32U => un!(stack, i32, F32, |a| a as u32 as f32),
+                Operator::F32ConvertI64S => un!(stack, i64, F32, |a| a as f32),
+                Operator::F32ConvertI64U => un!(stack, i64, F32, |a| a as u64 as f32),
+                Operator::F32DemoteF64 => un!(stack, f64, F32, |a| a as f32),
+                Operator::F64ConvertI32S => un!(stack, i32, F64, |a| a as f64),
+                Operator::F64ConvertI32U => un!(stack, i32, F64, |a| a as u32 as f64),
+                Operator::F64ConvertI64S => un!(stack, i64, F64, |a| a as f64),
+                Operator::F64ConvertI64U => un!(stack, i64, F64, |a| a as u64 as f64),
+                Operator::F64PromoteF32 => un!(stack, f32, F64, |a| a as f64),
+                Operator::I32ReinterpretF32 => un!(stack, f32, I32, |a| a.to_bits() as i32),
+                Operator::I64ReinterpretF64 => un!(stack, f64, I64, |a| a.to_bits() as i64),
+                Operator::F32ReinterpretI32 => un!(stack, i32, F32, |a| f32::from_bits(a as u32)),
+                Operator::F64ReinterpretI64 => un!(stack, i64, F64, |a| f64::from_bits(a as u64)),
+                Operator::I32Extend8S => un!(stack, i32, I32, |a| a as i8 as i32),
+                Operator::I32Extend16S => un!(stack, i32, I32, |a| a as i16 as i32),
+                Operator::I64Extend8S => un!(stack, i64, I64, |a| a as i8 as i64),
+                Operator::I64Extend16S => un!(stack, i64, I64, |a| a as i16 as i64),
+                Operator::I64Extend32S => un!(stack, i64, I64, |a| a as i32 as i64),
+                // `as` saturates
+                Operator::I32TruncSatF32S => un!(stack, f32, I32, |a| a as i32),
+                Operator::I32TruncSatF32U => un!(stack, f32, I32, |a| a as u32 as i32),
+                Operator::I32TruncSatF64S => un!(stack, f64, I32, |a| a as i32),
+                Operator::I32TruncSatF64U => un!(stack, f64, I32, |a| a as u32 as i32),
+                Operator::I64TruncSatF32S => un!(stack, f32, I64, |a| a as i64),
+                Operator::I64TruncSatF32U => un!(stack, f32, I64, |a| a as u64 as i64),
+                Operator::I64TruncSatF64S => un!(stack, f64, I64, |a| a as i64),
+                Operator::I64TruncSatF64U => un!(stack, f64, I64, |a| a as u64 as i64),
+
+                op => return Err(Trap::Unsupported(format!("{:?}", op))),
+            }
+            pc += 1;
+        }
+        Ok(stack.0.split_off(stack.0.len() - code.num_results))
+    }
+
+    /// The memory accessed by a load or store of `len` bytes at `addr`
+    fn access(&mut self, memarg: &MemArg, addr: i32, len: usize) -> Result<&mut [u8], Trap> {
+        let start = addr as u32 as u64 + memarg.offset;
+        let data = &mut self.memories[memarg.memory as usize].data;
+        usize::try_from(start)
+            .ok()
+            .and_then(|start| data.get_mut(start..start.checked_add(len)?))
+            .ok_or(Trap::OutOfBoundsMemoryAccess)
+    }
+
+    fn load<const N: usize>(&mut self, memarg: &MemArg, addr: i32) -> Result<[u8; N], Trap> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.access(memarg, addr, N)?);
+        Ok(bytes)
+    }
+
+    fn store(&mut self, memarg: &MemArg, addr: i32, bytes: &[u8]) -> Result<(), Trap> {
+        self.access(memarg, addr, bytes.len())?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// The operand stack of a function, the module being validated the operands have the expected types
+struct Stack(Vec<Value>);
+
+impl Stack {
+    fn push(&mut self, value: Value) {
+        self.0.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.0.pop().expect("The module is validated")
+    }
+
+    fn i32(&mut self) -> i32 {
+        match self.pop() {
+            Value::I32(value) => value,
+            other => unreachable!("Expected an i32, got {:?}", other),
+        }
+    }
+
+    fn i64(&mut self) -> i64 {
+        match self.pop() {
+            Value::I64(value) => value,
+            other => unreachable!("Expected an i64, got {:?}", other),
+        }
+    }
+
+    fn f32(&mut self) -> f32 {
+        match self.pop() {
+            Value::F32(value) => value,
+            other => unreachable!("Expected an f32, got {:?}", other),
+        }
+    }
+
+    fn f64(&mut self) -> f64 {
+        match self.pop() {
+            Value::F64(value) => value,
+            other => unreachable!("Expected an f64, got {:?}", other),
+        }
+    }
+}
+
+/// Branch to the block `depth` levels up, returns where to continue
+fn branch(stack: &mut Stack, labels: &mut Vec<Label>, depth: u32) -> usize {
+    let label_idx = labels.len() - 1 - depth as usize;
+    let label = &labels[label_idx];
+    let values = stack.0.split_off(stack.0.len() - label.arity);
+    stack.0.truncate(label.height);
+    stack.0.extend(values);
+    let target = label.target;
+    // a loop is entered again, other blocks are left
+    labels.truncate(if label.is_loop {
+        label_idx + 1
+    } else {
+        label_idx
+    });
+    target
+}
+
+/// The bounds, both excluded, of the floats that truncate to an integer type
+const I32_RANGE: (f64, f64) = (-2147483649.0, 2147483648.0);
+const U32_RANGE: (f64, f64) = (-1.0, 4294967296.0);
+const I64_RANGE: (f64, f64) = (-9223372036854777856.0, 9223372036854775808.0);
+const U64_RANGE: (f64, f64) = (-1.0, 18446744073709551616.0);
+
+fn trunc(value: f64, (min, max): (f64, f64)) -> Result<f64, Trap> {
+    if value.is_nan() {
+        return Err(Trap::InvalidConversionToInteger);
+    }
+    let value = value.trunc();
+    if value <= min || value >= max {
+        return Err(Trap::IntegerOverflow);
+    }
+    Ok(value)
+}
+
+macro_rules! float_min_max {
+    ($min:ident, $max:ident, $ty:ty) => {
+        /// NaN if an operand is NaN, -0 is less than +0
+        fn $min(a: $ty, b: $ty) -> $ty {
+            if a.is_nan() || b.is_nan() {
+                <$ty>::NAN
+            } else if a == b {
+                <$ty>::from_bits(a.to_bits() | b.to_bits())
+            } else {
+                a.min(b)
+            }
+        }
+
+        /// NaN if an operand is NaN, +0 is greater than -0
+        fn $max(a: $ty, b: $ty) -> $ty {
+            if a.is_nan() || b.is_nan() {
+                <$ty>::NAN
+            } else if a == b {
+                <$ty>::from_bits(a.to_bits() & b.to_bits())
+            } else {
+                a.max(b)
+            }
+        }
+    };
+}
+float_min_max!(f32_min, f32_max, f32);
+float_min_max!(f64_min, f64_max, f64);
+
+/// The default value of a local
+fn zero(ty: &DataType) -> Result<Value, Trap> {
+    match ty {
+        DataType::I32 => Ok(Value::I32(0)),
+        DataType::I64 => Ok(Value::I64(0)),
+        DataType::F32 => Ok(Value::F32(0.0)),
+        DataType::F64 => Ok(Value::F64(0.0)),
+        DataType::V128 => Ok(Value::V128(0)),
+        other => Err(Trap::Unsupported(format!("locals of type {:?}", other))),
+    }
+}
+
+fn check_types(values: &[Value], types: &[DataType], what: &str) -> Result<(), Trap> {
+    let matches = values.len() == types.len()
+        && values.iter().zip(types).all(|(value, ty)| {
+            matches!(
+                (value, ty),
+                (Value::I32(_), DataType::I32)
+                    | (Value::I64(_), DataType::I64)
+                    | (Value::F32(_), DataType::F32)
+                    | (Value::F64(_), DataType::F64)
+                    | (Value::V128(_), DataType::V128)
+            )
+        });
+    if matches {
+        Ok(())
+    } else {
+        Err(Trap::TypeMismatch(format!(
+            "expected {} of types {:?}, got {:?}",
+            what, types, values
+        )))
+    }
+}
+
+/// Evaluate a constant expression, with the globals defined so far
+fn eval(expr: &InitExpr, globals: &[Value]) -> Result<Value, Trap> {
+    expr.eval_with(|global_id| globals.get(*global_id as usize).copied())
+        .ok_or_else(|| Trap::Unsupported(format!("constant expression {:?}", expr)))
+}
+
+/// An offset in a memory or a table
+fn as_offset(value: Value) -> Result<usize, Trap> {
+    match value {
+        Value::I32(offset) => Ok(offset as u32 as usize),
+        other => Err(Trap::Unsupported(format!("offset {:?}", other))),
+    }
+}
@@ -0,0 +1,179 @@
+//! Printing of a module in the text format with its pending instrumentation marked inline,
+//! see [`Module::to_wat_annotated`].
+//!
+//! [`Module::to_wat_annotated`]: crate::Module::to_wat_annotated
+
+use crate::error::Error;
+use crate::ir::helpers::{edit_script, Edit};
+use crate::ir::module::Module;
+use crate::ir::offset_map;
+use crate::ir::types::InstrumentationMode;
+use std::ops::Range;
+
+/// Prefix of a line that is only in the instrumented module
+const INSERT: &str = ";; << ";
+/// Separates the text of a line from its text in the instrumented module
+const REPLACE: &str = ";; < ";
+/// Suffix of a line that is not in the instrumented module
+const REMOVE: &str = ";; rm";
+
+/// A printed code line, with the instruction it belongs to and the mode if it is instrumentation
+type CodeLine = (String, usize, Option<InstrumentationMode>);
+
+#[derive(Default)]
+struct PrintedFunc {
+    /// The function declaration and its locals
+    head: Vec<String>,
+    code: Vec<CodeLine>,
+    /// The closing of the function
+    tail: Vec<String>,
+}
+
+/// A module printed line by line, split into the lines before the functions, the functions and the lines after them
+#[derive(Default)]
+struct Printed {
+    prelude: Vec<String>,
+    funcs: Vec<PrintedFunc>,
+    tail: Vec<String>,
+}
+
+impl Printed {
+    /// Print the module, without its instrumentation if `clean`
+    fn new(module: &Module, clean: bool) -> Result<Self, Error> {
+        let mut module = module.clone();
+        if clean {
+            module.clear_all_instr();
+        }
+        let mut funcs = vec![];
+        let wasm = module.encode_internal(false, Some(&mut funcs))?.finish();
+        let ranges = offset_map::encoded_ranges(&wasm, &funcs);
+        let bodies = offset_map::body_ranges(&wasm).remove(0);
+
+        let mut printed = Printed {
+            funcs: (0..bodies.len()).map(|_| PrintedFunc::default()).collect(),
+            ..Printed::default()
+        };
+        let mut storage = String::new();
+        let lines = wasmprinter::Config::new()
+            .offsets_and_lines(&wasm, &mut storage)
+            .map_err(|e| Error::PrintError(e.to_string()))?;
+        // lines without an offset belong with the previous line
+        let mut curr_func = None;
+        for (offset, line) in lines {
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            if let Some(offset) = offset {
+                curr_func = func_at(&bodies, offset);
+            }
+            let Some(func_idx) = curr_func else {
+                if printed.funcs.iter().all(|func| func.head.is_empty()) {
+                    printed.prelude.push(line.to_string());
+                } else if !line.is_empty() {
+                    printed.tail.push(line.to_string());
+                }
+                continue;
+            };
+            let func = &mut printed.funcs[func_idx];
+            let code = offset.and_then(|offset| {
+                let ranges = &ranges[func_idx];
+                let pos = ranges.partition_point(|r| r.range.end <= offset);
+                ranges
+                    .get(pos)
+                    .filter(|r| r.range.contains(&offset))
+                    .map(|r| (r.instr_idx, r.mode))
+            });
+            match code {
+                Some((instr_idx, mode)) => func.code.push((line.to_string(), instr_idx, mode)),
+                None if func.code.is_empty() && offset.is_some() => {
+                    func.head.push(line.to_string())
+                }
+                None => func.tail.push(line.to_string()),
+            }
+        }
+        Ok(printed)
+    }
+}
+
+/// The function whose body contains `offset`
+fn func_at(bodies: &[Range<usize>], offset: usize) -> Option<usize> {
+    let pos = bodies.partition_point(|body| body.end <= offset);
+    bodies
+        .get(pos)
+        .filter(|body| body.contains(&offset))
+        .map(|_| pos)
+}
+
+pub(crate) fn print(module: &Module) -> Result<String, Error> {
+    let clean = Printed::new(module, true)?;
+    let instrumented = Printed::new(module, false)?;
+    let mut out = vec![];
+    diff_lines(&clean.prelude, &instrumented.prelude, &mut out);
+    for (clean, instrumented) in clean.funcs.iter().zip(&instrumented.funcs) {
+        diff_lines(&clean.head, &instrumented.head, &mut out);
+        merge_code(&clean.code, &instrumented.code, &mut out);
+        diff_lines(&clean.tail, &instrumented.tail, &mut out);
+    }
+    diff_lines(&clean.tail, &instrumented.tail, &mut out);
+    Ok(out.into_iter().map(|line| line + "\n").collect())
+}
+
+/// Merge the code of a function with its instrumented code. The instrumented code is in the order of the
+/// instructions, the instrumentation of an instruction being before or after it.
+fn merge_code(clean: &[CodeLine], instrumented: &[CodeLine], out: &mut Vec<String>) {
+    let mut next = 0;
+    for (text, instr_idx, mode) in instrumented {
+        // where the line is relative to the instruction it belongs to
+        let after_instr = !matches!(mode, Some(InstrumentationMode::Before));
+        let mut paired = false;
+        while let Some((clean_text, clean_idx, _)) = clean.get(next) {
+            if *clean_idx > *instr_idx || (*clean_idx == *instr_idx && !after_instr) {
+                break;
+            }
+            next += 1;
+            if *clean_idx == *instr_idx && mode.is_none() {
+                out.push(replaced(clean_text, text));
+                paired = true;
+                break;
+            }
+            // the instruction was replaced
+            out.push(removed(clean_text));
+        }
+        if !paired {
+            out.push(inserted(text, *mode));
+        }
+    }
+    for (clean_text, ..) in &clean[next..] {
+        out.push(removed(clean_text));
+    }
+}
+
+/// Merge lines with the lines they became in the instrumented module
+fn diff_lines(clean: &[String], instrumented: &[String], out: &mut Vec<String>) {
+    for edit in edit_script(clean, instrumented) {
+        out.push(match edit {
+            Edit::Same(j) => instrumented[j].clone(),
+            Edit::Remove(i) => removed(&clean[i]),
+            Edit::Insert(j) => inserted(&instrumented[j], None),
+        });
+    }
+}
+
+fn inserted(line: &str, mode: Option<InstrumentationMode>) -> String {
+    let text = line.trim_start();
+    let indent = &line[..line.len() - text.len()];
+    match mode {
+        Some(mode) => format!("{indent}{INSERT}{text} ;; {:?}", mode),
+        None => format!("{indent}{INSERT}{text}"),
+    }
+}
+
+fn replaced(clean: &str, line: &str) -> String {
+    if clean == line {
+        clean.to_string()
+    } else {
+        format!("{clean} {REPLACE}{}", line.trim_start())
+    }
+}
+
+fn removed(clean: &str) -> String {
+    format!("{clean} {REMOVE}")
+}
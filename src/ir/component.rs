@@ -8,23 +8,50 @@ use crate::ir::helpers::{
 };
 use crate::ir::id::{CustomSectionID, FunctionID, GlobalID, ModuleID};
 use crate::ir::module::{Iter, Module};
+use crate::ir::offset_map;
+use crate::ir::offset_map::{ModuleOffsets, OffsetMap};
 use crate::ir::section::ComponentSection;
+use crate::ir::stats::{self, ComponentStats};
 use crate::ir::wrappers::{
     add_to_namemap, convert_component_type, convert_instance_type, convert_module_type_declaration,
-    convert_results, encode_core_type_subtype, process_alias,
+    convert_results, encode_core_type, encode_core_type_subtype, process_alias,
 };
 
 use crate::ir::module::module_functions::FuncKind;
 use crate::ir::module::module_globals::Global;
-use crate::ir::types::CustomSections;
+use crate::ir::types::{ComponentPath, CustomSections};
 use wasm_encoder::reencode::Reencode;
-use wasm_encoder::{ComponentAliasSection, ModuleArg, ModuleSection, NestedComponentSection};
+use wasm_encoder::{
+    ComponentAliasSection, ComponentSectionId, Encode, ModuleArg, ModuleSection,
+    NestedComponentSection, RawSection,
+};
 use wasmparser::{
     CanonicalFunction, ComponentAlias, ComponentExport, ComponentImport, ComponentInstance,
     ComponentStartFunction, ComponentType, ComponentTypeDeclaration, CoreType, Encoding, Instance,
     Parser, Payload,
 };
 
+/// A component converted from the text format, see [`Component::from_wat`]. Owns the binary that the
+/// parsed `Component` borrows from.
+#[cfg(feature = "wat")]
+#[derive(Clone, Debug)]
+pub struct WatComponent {
+    wasm: Vec<u8>,
+}
+
+#[cfg(feature = "wat")]
+impl WatComponent {
+    /// Parse the `Component`, as [`Component::parse`] does without multi-memory.
+    pub fn component(&self) -> Result<Component<'_>, Error> {
+        Component::parse(&self.wasm, false)
+    }
+
+    /// The wasm binary
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.wasm
+    }
+}
+
 #[derive(Debug)]
 /// Intermediate Representation of a wasm component.
 pub struct Component<'a> {
@@ -132,7 +159,56 @@ impl<'a> Component<'a> {
 
     /// Add a Global to this Component.
     pub fn add_globals(&mut self, global: Global, module_idx: usize) -> GlobalID {
-        self.modules[module_idx].globals.add(global)
+        self.add_globals_at(global, &ComponentPath::default(), module_idx)
+    }
+
+    /// Add a Global to a module of the (nested) component at `path`.
+    pub fn add_globals_at(
+        &mut self,
+        global: Global,
+        path: &ComponentPath,
+        module_idx: usize,
+    ) -> GlobalID {
+        self.get_module_mut(path, ModuleID(module_idx as u32))
+            .globals
+            .add(global)
+    }
+
+    /// Get the nested component at `path`, the empty path is this component.
+    pub fn get_component(&self, path: &ComponentPath) -> &Component<'a> {
+        path.indices()
+            .iter()
+            .fold(self, |comp, idx| &comp.components[*idx as usize])
+    }
+
+    /// Get the nested component at `path` mutably, the empty path is this component.
+    pub fn get_component_mut(&mut self, path: &ComponentPath) -> &mut Component<'a> {
+        path.indices()
+            .iter()
+            .fold(self, |comp, idx| &mut comp.components[*idx as usize])
+    }
+
+    /// Get a module of the (nested) component at `path`.
+    pub fn get_module(&self, path: &ComponentPath, mod_idx: ModuleID) -> &Module<'a> {
+        &self.get_component(path).modules[*mod_idx as usize]
+    }
+
+    /// Get a module of the (nested) component at `path` mutably.
+    pub fn get_module_mut(&mut self, path: &ComponentPath, mod_idx: ModuleID) -> &mut Module<'a> {
+        &mut self.get_component_mut(path).modules[*mod_idx as usize]
+    }
+
+    /// Paths to this component and all of its nested components, outer components first.
+    pub fn component_paths(&self) -> Vec<ComponentPath> {
+        let mut paths = vec![ComponentPath::default()];
+        let mut idx = 0;
+        while idx < paths.len() {
+            let path = paths[idx].clone();
+            let num_nested = self.get_component(&path).components.len();
+            paths.extend((0..num_nested as u32).map(|nested| path.child(nested)));
+            idx += 1;
+        }
+        paths
     }
 
     fn add_to_sections(
@@ -165,6 +241,23 @@ impl<'a> Component<'a> {
         Component::parse_comp(wasm, enable_multi_memory, parser, 0, &mut vec![])
     }
 
+    /// Convert a component from the text format. The returned [`WatComponent`] owns the binary,
+    /// the `Component` is parsed from it with [`WatComponent::component`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use orca_wasm::Component;
+    ///
+    /// let wat = Component::from_wat("(component)").unwrap();
+    /// let comp = wat.component().unwrap();
+    /// ```
+    #[cfg(feature = "wat")]
+    pub fn from_wat(wat: &str) -> Result<WatComponent, Error> {
+        let wasm = wat::parse_str(wat).map_err(|e| Error::WatError(e.to_string()))?;
+        Ok(WatComponent { wasm })
+    }
+
     fn parse_comp(
         wasm: &'a [u8],
         enable_multi_memory: bool,
@@ -479,10 +572,57 @@ impl<'a> Component<'a> {
     /// let result = comp.encode();
     /// ```
     pub fn encode(&mut self) -> Vec<u8> {
-        self.encode_comp().finish()
+        self.try_encode().expect("Unable to encode the component")
+    }
+
+    /// Encode the component into a wasm binary, failing instead of panicking when one of its modules
+    /// cannot be encoded, see [`Module::try_encode`].
+    pub fn try_encode(&mut self) -> Result<Vec<u8>, Error> {
+        Ok(self.encode_comp(&ComponentPath::default(), None)?.finish())
+    }
+
+    /// Encode the component into a wasm binary along with the map of the offsets of the instructions
+    /// of the parsed binary to their offsets in the encoded binary, for all the modules of
+    /// the component and its nested components.
+    ///
+    /// ```no_run
+    /// use orca_wasm::Component;
+    ///
+    /// let file = "path_to_file";
+    /// let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    /// let mut comp = Component::parse(&buff, false).unwrap();
+    /// let (result, map) = comp.encode_with_map();
+    /// ```
+    pub fn encode_with_map(&mut self) -> (Vec<u8>, OffsetMap) {
+        let mut modules = vec![];
+        let result = self
+            .encode_comp(&ComponentPath::default(), Some(&mut modules))
+            .expect("Unable to encode the component")
+            .finish();
+        let map = offset_map::build(&result, &modules);
+        (result, map)
     }
 
-    fn encode_comp(&mut self) -> wasm_encoder::Component {
+    /// Statistics of the component, covering the core modules of the component and of its nested components.
+    /// Fails if one of the modules cannot be encoded.
+    ///
+    /// ```no_run
+    /// use orca_wasm::Component;
+    ///
+    /// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+    /// let comp = Component::parse(&buff, false).unwrap();
+    /// println!("{}", comp.stats().unwrap().to_json());
+    /// ```
+    pub fn stats(&self) -> Result<ComponentStats, Error> {
+        stats::component_stats(self)
+    }
+
+    /// Encodes the component at `path`, recording the offsets of its modules in `offsets` if given.
+    fn encode_comp(
+        &mut self,
+        path: &ComponentPath,
+        mut offsets: Option<&mut Vec<ModuleOffsets>>,
+    ) -> Result<wasm_encoder::Component, Error> {
         let mut component = wasm_encoder::Component::new();
         let mut reencode = wasm_encoder::reencode::RoundtripReencoder;
         // NOTE: All of these are 1-indexed and not 0-indexed
@@ -506,7 +646,8 @@ impl<'a> Component<'a> {
                     );
                     for comp_idx in last_processed_component..last_processed_component + num {
                         component.section(&NestedComponentSection(
-                            &self.components[comp_idx as usize].encode_comp(),
+                            &self.components[comp_idx as usize]
+                                .encode_comp(&path.child(comp_idx), offsets.as_deref_mut())?,
                         ));
                         last_processed_component += 1;
                     }
@@ -514,9 +655,17 @@ impl<'a> Component<'a> {
                 ComponentSection::Module => {
                     assert!(*num as usize + last_processed_module as usize <= self.modules.len());
                     for mod_idx in last_processed_module..last_processed_module + num {
+                        let mut funcs = vec![];
+                        let record = offsets.is_some().then_some(&mut funcs);
                         component.section(&ModuleSection(
-                            &self.modules[mod_idx as usize].encode_internal(),
+                            &self.modules[mod_idx as usize].encode_internal(false, record)?,
                         ));
+                        if let Some(offsets) = offsets.as_deref_mut() {
+                            offsets.push(ModuleOffsets {
+                                module: Some((path.clone(), ModuleID(mod_idx))),
+                                funcs,
+                            });
+                        }
                         last_processed_module += 1;
                     }
                 }
@@ -524,21 +673,21 @@ impl<'a> Component<'a> {
                     assert!(
                         *num as usize + last_processed_core_ty as usize <= self.core_types.len()
                     );
-                    let mut type_section = wasm_encoder::CoreTypeSection::new();
+                    // Written by hand, `CoreTypeSection` can't hold struct and array types
+                    let mut data = vec![];
+                    num.encode(&mut data);
                     for cty_idx in last_processed_core_ty..last_processed_core_ty + num {
-                        match &self.core_types[cty_idx as usize] {
-                            CoreType::Sub(subtype) => {
-                                let enc = type_section.ty();
-                                encode_core_type_subtype(enc, subtype, &mut reencode);
-                            }
-                            CoreType::Module(module) => {
-                                let enc = type_section.ty();
-                                convert_module_type_declaration(module, enc, &mut reencode);
-                            }
-                        }
+                        encode_core_type(
+                            &self.core_types[cty_idx as usize],
+                            &mut data,
+                            &mut reencode,
+                        )?;
                         last_processed_core_ty += 1;
                     }
-                    component.section(&type_section);
+                    component.section(&RawSection {
+                        id: ComponentSectionId::CoreType.into(),
+                        data: &data,
+                    });
                 }
                 ComponentSection::ComponentType => {
                     assert!(
@@ -577,10 +726,10 @@ impl<'a> Component<'a> {
                                             reencode.component_val_type(*val_type)
                                         })),
                                     wasmparser::ComponentDefinedType::Flags(flags) => {
-                                        enc.flags(flags.clone().into_vec().into_iter())
+                                        enc.flags(flags.clone().into_vec())
                                     }
                                     wasmparser::ComponentDefinedType::Enum(en) => {
-                                        enc.enum_type(en.clone().into_vec().into_iter())
+                                        enc.enum_type(en.clone().into_vec())
                                     }
                                     wasmparser::ComponentDefinedType::Option(opt) => {
                                         enc.option(reencode.component_val_type(*opt))
@@ -614,7 +763,7 @@ impl<'a> Component<'a> {
                                         ComponentTypeDeclaration::CoreType(core) => match core {
                                             CoreType::Sub(sub) => {
                                                 let enc = new_comp.core_type();
-                                                encode_core_type_subtype(enc, sub, &mut reencode);
+                                                encode_core_type_subtype(enc, sub, &mut reencode)?;
                                             }
                                             CoreType::Module(module) => {
                                                 let enc = new_comp.core_type();
@@ -622,7 +771,7 @@ impl<'a> Component<'a> {
                                                     module,
                                                     enc,
                                                     &mut reencode,
-                                                );
+                                                )?;
                                             }
                                         },
                                         ComponentTypeDeclaration::Type(typ) => {
@@ -631,7 +780,7 @@ impl<'a> Component<'a> {
                                                 &(*typ).clone(),
                                                 enc,
                                                 &mut reencode,
-                                            );
+                                            )?;
                                         }
                                         ComponentTypeDeclaration::Alias(a) => {
                                             new_comp.alias(process_alias(a, &mut reencode));
@@ -652,7 +801,7 @@ impl<'a> Component<'a> {
                             }
                             ComponentType::Instance(inst) => {
                                 component_ty_section
-                                    .instance(&convert_instance_type(inst, &mut reencode));
+                                    .instance(&convert_instance_type(inst, &mut reencode)?);
                             }
                             ComponentType::Resource { rep, dtor } => {
                                 component_ty_section
@@ -864,7 +1013,7 @@ impl<'a> Component<'a> {
         // Add the name section back to the component
         component.section(&name_sec);
 
-        component
+        Ok(component)
     }
 
     /// Print a rudimentary textual representation of a `Component`
@@ -917,8 +1066,9 @@ impl<'a> Component<'a> {
 
     /// Emit the Component into a wasm binary file.
     pub fn emit_wasm(&mut self, file_name: &str) -> Result<(), std::io::Error> {
-        let comp = self.encode_comp();
-        let wasm = comp.finish();
+        let wasm = self
+            .try_encode()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
         std::fs::write(file_name, wasm)?;
         Ok(())
     }
@@ -932,13 +1082,10 @@ impl<'a> Component<'a> {
             .enumerate()
         {
             if let FuncKind::Local(l) = &func.kind {
-                match &l.body.name {
-                    Some(n) => {
-                        if n == name {
-                            return Some(FunctionID(idx as u32));
-                        }
+                if let Some(n) = &l.body.name {
+                    if n == name {
+                        return Some(FunctionID(idx as u32));
                     }
-                    None => {}
                 }
             }
         }
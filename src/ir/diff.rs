@@ -0,0 +1,354 @@
+//! Structural diff between two modules, see [`diff`].
+
+use crate::ir::helpers::{edit_script, Edit};
+use crate::ir::id::{FunctionID, TypeID};
+use crate::ir::module::module_functions::FuncKind;
+use crate::ir::module::module_globals::GlobalKind;
+use crate::ir::module::module_types::ModuleTypes;
+use crate::ir::module::{Iter, Module};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// How an item differs between the two modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Only in the second module
+    Added,
+    /// Only in the first module
+    Removed,
+    /// In both modules, but not the same
+    Modified,
+}
+
+/// An import, export, global, data segment or custom section that differs between the two modules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemDiff {
+    /// The name of the item for imports (`module.name`), exports and custom sections, its index otherwise
+    pub key: String,
+    pub change: Change,
+    /// The item in the first module, `None` if it was added
+    pub before: Option<String>,
+    /// The item in the second module, `None` if it was removed
+    pub after: Option<String>,
+}
+
+/// An instruction that is only in one of the two bodies of a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstrDiff {
+    /// Only in the first body, at `instr_idx`
+    Removed { instr_idx: usize, op: String },
+    /// Only in the second body, at `instr_idx`
+    Added { instr_idx: usize, op: String },
+}
+
+/// A function that differs between the two modules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncDiff {
+    pub func_idx: FunctionID,
+    pub change: Change,
+    /// The signature and locals of the function in the first module, `None` if it was added
+    pub before: Option<String>,
+    /// The signature and locals of the function in the second module, `None` if it was removed
+    pub after: Option<String>,
+    /// The shortest list of instructions to remove from and add to the first body to get the second one
+    pub instrs: Vec<InstrDiff>,
+}
+
+/// What changed between two modules, as returned by [`diff`]. Printing it gives a human-readable report.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleDiff {
+    pub functions: Vec<FuncDiff>,
+    pub imports: Vec<ItemDiff>,
+    pub exports: Vec<ItemDiff>,
+    pub globals: Vec<ItemDiff>,
+    pub data: Vec<ItemDiff>,
+    pub custom_sections: Vec<ItemDiff>,
+}
+
+impl ModuleDiff {
+    /// Whether the two modules are the same
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+            && self.imports.is_empty()
+            && self.exports.is_empty()
+            && self.globals.is_empty()
+            && self.data.is_empty()
+            && self.custom_sections.is_empty()
+    }
+}
+
+/// Compare two modules as they are encoded, so pending instrumentation is part of the comparison.
+/// Functions, globals and data segments are matched by index, imports, exports and custom sections by name.
+///
+/// # Example
+///
+/// ```no_run
+/// use orca_wasm::ir::diff::diff;
+/// use orca_wasm::Module;
+///
+/// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+/// let before = Module::parse(&buff, false).unwrap();
+/// let mut after = before.clone();
+/// // ... run a pass on `after`
+/// println!("{}", diff(&before, &after));
+/// ```
+pub fn diff(before: &Module, after: &Module) -> ModuleDiff {
+    let before_wasm = before.clone().encode();
+    let after_wasm = after.clone().encode();
+    let before = Module::parse(&before_wasm, true).expect("Unable to parse the encoded module");
+    let after = Module::parse(&after_wasm, true).expect("Unable to parse the encoded module");
+
+    ModuleDiff {
+        functions: diff_functions(&before, &after),
+        imports: diff_items(imports(&before), imports(&after)),
+        exports: diff_items(exports(&before), exports(&after)),
+        globals: diff_items(globals(&before), globals(&after)),
+        data: diff_items(data(&before), data(&after)),
+        custom_sections: diff_items(custom_sections(&before), custom_sections(&after)),
+    }
+}
+
+/// The key and description of every item of a kind
+type Items = Vec<(String, String)>;
+
+/// Match the items by key, the ones with the same key in the order they appear
+fn diff_items(before: Items, after: Items) -> Vec<ItemDiff> {
+    let mut unmatched: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (idx, (key, _)) in after.iter().enumerate() {
+        unmatched.entry(key.as_str()).or_default().push_back(idx);
+    }
+    let mut matched = vec![false; after.len()];
+    let mut diffs = vec![];
+    for (key, desc) in &before {
+        match unmatched
+            .get_mut(key.as_str())
+            .and_then(|idx| idx.pop_front())
+        {
+            Some(idx) => {
+                matched[idx] = true;
+                if after[idx].1 != *desc {
+                    diffs.push(ItemDiff {
+                        key: key.clone(),
+                        change: Change::Modified,
+                        before: Some(desc.clone()),
+                        after: Some(after[idx].1.clone()),
+                    });
+                }
+            }
+            None => diffs.push(ItemDiff {
+                key: key.clone(),
+                change: Change::Removed,
+                before: Some(desc.clone()),
+                after: None,
+            }),
+        }
+    }
+    for ((key, desc), _) in after.iter().zip(matched).filter(|(_, matched)| !matched) {
+        diffs.push(ItemDiff {
+            key: key.clone(),
+            change: Change::Added,
+            before: None,
+            after: Some(desc.clone()),
+        });
+    }
+    diffs
+}
+
+fn imports(module: &Module) -> Items {
+    module
+        .imports
+        .iter()
+        .map(|import| {
+            (
+                format!("{}.{}", import.module, import.name),
+                format!("{:?}", import.ty),
+            )
+        })
+        .collect()
+}
+
+fn exports(module: &Module) -> Items {
+    module
+        .exports
+        .iter()
+        .map(|export| {
+            (
+                export.name.clone(),
+                format!("{:?} {}", export.kind, export.index),
+            )
+        })
+        .collect()
+}
+
+fn globals(module: &Module) -> Items {
+    module
+        .globals
+        .iter()
+        .enumerate()
+        .map(|(idx, global)| {
+            let desc = match &global.kind {
+                GlobalKind::Local(local) => format!("{:?} = {:?}", local.ty, local.init_expr),
+                GlobalKind::Import(import) => format!("{:?} imported", import.ty),
+            };
+            (idx.to_string(), desc)
+        })
+        .collect()
+}
+
+fn data(module: &Module) -> Items {
+    module
+        .data
+        .iter()
+        .enumerate()
+        .map(|(idx, segment)| {
+            (
+                idx.to_string(),
+                format!("{:?} \"{}\"", segment.kind, segment.data.escape_ascii()),
+            )
+        })
+        .collect()
+}
+
+fn custom_sections(module: &Module) -> Items {
+    module
+        .custom_sections
+        .iter()
+        .map(|section| {
+            (
+                section.name.to_string(),
+                format!("\"{}\"", section.data.escape_ascii()),
+            )
+        })
+        .collect()
+}
+
+fn diff_functions(before: &Module, after: &Module) -> Vec<FuncDiff> {
+    let before_funcs: Vec<_> = before
+        .functions
+        .iter()
+        .map(|f| describe_func(before, &f.kind))
+        .collect();
+    let after_funcs: Vec<_> = after
+        .functions
+        .iter()
+        .map(|f| describe_func(after, &f.kind))
+        .collect();
+    let mut diffs = vec![];
+    for func_idx in 0..before_funcs.len().max(after_funcs.len()) {
+        let (before, after) = (before_funcs.get(func_idx), after_funcs.get(func_idx));
+        let no_ops = vec![];
+        let before_ops = before.map_or(&no_ops, |(_, ops)| ops);
+        let after_ops = after.map_or(&no_ops, |(_, ops)| ops);
+        let instrs: Vec<InstrDiff> = edit_script(before_ops, after_ops)
+            .into_iter()
+            .filter_map(|edit| match edit {
+                Edit::Same(_) => None,
+                Edit::Remove(instr_idx) => Some(InstrDiff::Removed {
+                    instr_idx,
+                    op: before_ops[instr_idx].clone(),
+                }),
+                Edit::Insert(instr_idx) => Some(InstrDiff::Added {
+                    instr_idx,
+                    op: after_ops[instr_idx].clone(),
+                }),
+            })
+            .collect();
+        let change = match (before, after) {
+            (None, _) => Change::Added,
+            (_, None) => Change::Removed,
+            (Some((before, _)), Some((after, _))) if before == after && instrs.is_empty() => {
+                continue;
+            }
+            _ => Change::Modified,
+        };
+        diffs.push(FuncDiff {
+            func_idx: FunctionID(func_idx as u32),
+            change,
+            before: before.map(|(desc, _)| desc.clone()),
+            after: after.map(|(desc, _)| desc.clone()),
+            instrs,
+        });
+    }
+    diffs
+}
+
+/// The signature and locals of a function, and its instructions
+fn describe_func(module: &Module, kind: &FuncKind) -> (String, Vec<String>) {
+    let signature = signature(&module.types, kind.get_type());
+    match kind {
+        FuncKind::Import(_) => (format!("{signature} imported"), vec![]),
+        FuncKind::Local(local) => (
+            format!("{signature} locals {:?}", local.body.locals),
+            local
+                .body
+                .instructions
+                .iter()
+                .map(|instr| format!("{:?}", instr.op))
+                .collect(),
+        ),
+    }
+}
+
+fn signature(types: &ModuleTypes, ty: TypeID) -> String {
+    match types.get(ty) {
+        Some(ty) => format!("{:?} -> {:?}", ty.params, ty.results),
+        None => format!("type {}", *ty),
+    }
+}
+
+impl fmt::Display for ModuleDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for func in &self.functions {
+            writeln!(
+                f,
+                "function {} {}",
+                *func.func_idx,
+                change_name(func.change)
+            )?;
+            if func.before != func.after {
+                write_before_after(f, &func.before, &func.after)?;
+            }
+            for instr in &func.instrs {
+                match instr {
+                    InstrDiff::Removed { instr_idx, op } => writeln!(f, "  - {instr_idx}: {op}")?,
+                    InstrDiff::Added { instr_idx, op } => writeln!(f, "  + {instr_idx}: {op}")?,
+                }
+            }
+        }
+        for (kind, items) in [
+            ("import", &self.imports),
+            ("export", &self.exports),
+            ("global", &self.globals),
+            ("data", &self.data),
+            ("custom section", &self.custom_sections),
+        ] {
+            for item in items {
+                writeln!(f, "{kind} {} {}", item.key, change_name(item.change))?;
+                write_before_after(f, &item.before, &item.after)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn change_name(change: Change) -> &'static str {
+    match change {
+        Change::Added => "added",
+        Change::Removed => "removed",
+        Change::Modified => "modified",
+    }
+}
+
+fn write_before_after(
+    f: &mut fmt::Formatter<'_>,
+    before: &Option<String>,
+    after: &Option<String>,
+) -> fmt::Result {
+    if let Some(before) = before {
+        writeln!(f, "  - {before}")?;
+    }
+    if let Some(after) = after {
+        writeln!(f, "  + {after}")?;
+    }
+    Ok(())
+}
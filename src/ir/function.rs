@@ -3,9 +3,9 @@
 use crate::ir::id::{FunctionID, ImportsID, LocalID, ModuleID, TypeID};
 use crate::ir::module::module_functions::{add_local, LocalFunction};
 use crate::ir::module::{Module, ReIndexable};
-use crate::ir::types::DataType;
 use crate::ir::types::InstrumentationMode;
 use crate::ir::types::{Body, FuncInstrFlag, FuncInstrMode};
+use crate::ir::types::{ComponentPath, DataType};
 use crate::module_builder::AddLocal;
 use crate::opcode::{Inject, InjectAt, Instrumenter, MacroOpcode, Opcode};
 use crate::{Component, Location};
@@ -81,22 +81,27 @@ impl<'a> FunctionBuilder<'a> {
 
     /// Finish building a function (have side effect on component IR),
     /// return function index
-    pub fn finish_component(mut self, comp: &mut Component<'a>, mod_idx: ModuleID) -> FunctionID {
+    pub fn finish_component(self, comp: &mut Component<'a>, mod_idx: ModuleID) -> FunctionID {
+        self.finish_component_at(comp, &ComponentPath::default(), mod_idx)
+    }
+
+    /// Finish building a function in a module of the (nested) component at `path`
+    /// (have side effect on component IR), return function index
+    pub fn finish_component_at(
+        mut self,
+        comp: &mut Component<'a>,
+        path: &ComponentPath,
+        mod_idx: ModuleID,
+    ) -> FunctionID {
         // add End as last instruction
         self.end();
 
-        let id = comp.modules[*mod_idx as usize].add_local_func(
-            self.name,
-            &self.params,
-            &self.results,
-            self.body.clone(),
-        );
+        let module = comp.get_module_mut(path, mod_idx);
+        let id = module.add_local_func(self.name, &self.params, &self.results, self.body.clone());
 
         assert_eq!(
-            comp.modules[*mod_idx as usize].functions.len() as u32,
-            comp.modules[*mod_idx as usize].num_local_functions
-                + comp.modules[*mod_idx as usize].imports.num_funcs
-                + comp.modules[*mod_idx as usize].imports.num_funcs_added
+            module.functions.len() as u32,
+            module.num_local_functions + module.imports.num_funcs + module.imports.num_funcs_added
         );
         id
     }
@@ -127,6 +132,22 @@ impl AddLocal for FunctionBuilder<'_> {
             &mut self.body.locals,
         )
     }
+
+    fn scratch_local(&mut self, ty: DataType) -> LocalID {
+        self.body.scratch_local(ty, self.params.len())
+    }
+
+    fn release_scratch_local(&mut self, id: LocalID) {
+        self.body.release_scratch_local(id)
+    }
+
+    fn enter_scratch_scope(&mut self) {
+        self.body.enter_scratch_scope()
+    }
+
+    fn exit_scratch_scope(&mut self) {
+        self.body.exit_scratch_scope()
+    }
 }
 
 /// Modify a function
@@ -167,6 +188,26 @@ impl<'a, 'b> FunctionModifier<'a, 'b> {
             &mut self.body.locals,
         )
     }
+
+    /// take a scratch local, see [`AddLocal::scratch_local`]
+    pub fn scratch_local(&mut self, ty: DataType) -> LocalID {
+        self.body.scratch_local(ty, self.args.len())
+    }
+
+    /// release a scratch local, see [`AddLocal::release_scratch_local`]
+    pub fn release_scratch_local(&mut self, id: LocalID) {
+        self.body.release_scratch_local(id)
+    }
+
+    /// open a scratch scope, see [`AddLocal::enter_scratch_scope`]
+    pub fn enter_scratch_scope(&mut self) {
+        self.body.enter_scratch_scope()
+    }
+
+    /// exit a scratch scope, see [`AddLocal::exit_scratch_scope`]
+    pub fn exit_scratch_scope(&mut self) {
+        self.body.exit_scratch_scope()
+    }
 }
 
 impl<'a, 'b> Inject<'b> for FunctionModifier<'a, 'b> {
@@ -193,7 +234,7 @@ impl<'a, 'b> InjectAt<'b> for FunctionModifier<'a, 'b> {
             func_idx: FunctionID(0), // not used
             instr_idx: idx,
         };
-        self.set_instrument_mode_at(mode, loc);
+        self.set_instrument_mode_at(mode, loc.clone());
         self.add_instr_at(loc, instr);
     }
 }
@@ -263,7 +304,7 @@ impl<'a, 'b> Instrumenter<'b> for FunctionModifier<'a, 'b> {
         self
     }
 
-    fn get_injected_val(&self, idx: usize) -> &Operator {
+    fn get_injected_val(&self, idx: usize) -> &Operator<'_> {
         self.body.instructions[idx].instr_flag.get_instr(idx)
     }
 }
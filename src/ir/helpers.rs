@@ -131,3 +131,83 @@ pub fn print_component_import(imp: &ComponentImport) {
 pub fn print_component_export(exp: &ComponentExport) {
     eprintln!("Component Export: {:?}", exp.name.0);
 }
+
+/// A step of an edit script, see [`edit_script`]
+pub(crate) enum Edit {
+    /// An unchanged item, given by its index in the second sequence
+    #[cfg_attr(not(feature = "wasmprinter"), allow(dead_code))]
+    Same(usize),
+    /// An item of the first sequence, not in the second
+    Remove(usize),
+    /// An item of the second sequence, not in the first
+    Insert(usize),
+}
+
+/// The shortest edit script turning `a` into `b` (Myers' diff algorithm)
+pub(crate) fn edit_script<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    // the furthest x reached on every diagonal k = x - y, for every number of edits d,
+    // stored from diagonal -d to d
+    let mut trace: Vec<Vec<isize>> = vec![];
+    let mut v = vec![0isize; 1];
+    let get = |v: &Vec<isize>, d: isize, k: isize| v[(k + d) as usize];
+    'search: for d in 0..=(n + m) {
+        let prev = v;
+        v = vec![0; 2 * d as usize + 1];
+        for k in (-d..=d).step_by(2) {
+            let mut x = if d == 0 {
+                0
+            } else if k == -d || (k != d && get(&prev, d - 1, k - 1) < get(&prev, d - 1, k + 1)) {
+                get(&prev, d - 1, k + 1)
+            } else {
+                get(&prev, d - 1, k - 1) + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + d) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(v);
+                break 'search;
+            }
+        }
+        trace.push(v.clone());
+    }
+
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for d in (0..trace.len() as isize).rev() {
+        let k = x - y;
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let prev = &trace[d as usize - 1];
+            let prev_k = if k == -d || (k != d && get(prev, d - 1, k - 1) < get(prev, d - 1, k + 1))
+            {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = get(prev, d - 1, prev_k);
+            (prev_x, prev_x - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Same(y as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                edits.push(Edit::Insert(y as usize));
+            } else {
+                x -= 1;
+                edits.push(Edit::Remove(x as usize));
+            }
+        }
+    }
+    edits.reverse();
+    edits
+}
@@ -184,3 +184,18 @@ impl std::ops::DerefMut for MemoryID {
         &mut self.0
     }
 }
+
+/// Tag ID in a module
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TagID(pub u32);
+impl std::ops::Deref for TagID {
+    type Target = u32;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl std::ops::DerefMut for TagID {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
@@ -70,6 +70,86 @@ fn test_semantic_after_supported_ops() {
     run_check_works(BRANCHING_OPERATORS, InstrumentationMode::SemanticAfter);
 }
 
+#[test]
+fn test_branch_outcome_unsupported_ops() {
+    for (mode, mode_name) in [
+        (InstrumentationMode::BranchTaken, "branch taken"),
+        (InstrumentationMode::BranchNotTaken, "branch not taken"),
+    ] {
+        run_check_panics(NON_SPECIAL_OPERATORS, mode, mode_name);
+        run_check_panics(
+            &[
+                Operator::Br { relative_depth: 0 },
+                Operator::Block {
+                    blockty: BlockType::Empty,
+                },
+            ],
+            mode,
+            mode_name,
+        );
+    }
+}
+#[test]
+fn test_branch_outcome_supported_ops() {
+    for mode in [
+        InstrumentationMode::BranchTaken,
+        InstrumentationMode::BranchNotTaken,
+    ] {
+        run_check_works(CONDITIONAL_OPERATORS, mode);
+    }
+}
+
+#[test]
+fn test_br_table_target_unsupported_ops() {
+    run_check_panics(
+        NON_SPECIAL_OPERATORS,
+        InstrumentationMode::BrTableTarget(0),
+        "br_table target",
+    );
+    run_check_panics(
+        BRANCHING_OPERATORS,
+        InstrumentationMode::BrTableDefault,
+        "br_table default",
+    );
+}
+
+#[test]
+fn test_loop_unsupported_ops() {
+    for (mode, mode_name) in [
+        (InstrumentationMode::LoopIteration, "loop iteration"),
+        (InstrumentationMode::LoopBackEdge, "loop back edge"),
+    ] {
+        run_check_panics(NON_SPECIAL_OPERATORS, mode, mode_name);
+        run_check_panics(BRANCHING_OPERATORS, mode, mode_name);
+        run_check_panics(
+            &[
+                Operator::Block {
+                    blockty: BlockType::Empty,
+                },
+                Operator::If {
+                    blockty: BlockType::Empty,
+                },
+            ],
+            mode,
+            mode_name,
+        );
+    }
+}
+#[test]
+fn test_loop_supported_ops() {
+    for mode in [
+        InstrumentationMode::LoopIteration,
+        InstrumentationMode::LoopBackEdge,
+    ] {
+        run_check_works(
+            &[Operator::Loop {
+                blockty: BlockType::Empty,
+            }],
+            mode,
+        );
+    }
+}
+
 // ==== HELPER FUNCTIONS ====
 
 pub fn run_check_works(operators: &[Operator], mode: InstrumentationMode) {
@@ -106,7 +186,7 @@ pub fn assert_panics_with_message(func: impl FnOnce() + UnwindSafe, msg: &str) {
     };
 
     err.downcast::<String>()
-        .map(|s| chk(&**s))
+        .map(|s| chk(&s))
         .or_else(|err| err.downcast::<&str>().map(|s| chk(*s)))
         .expect("Unexpected panic type!");
 }
@@ -150,6 +230,13 @@ const BRANCHING_OPERATORS: &[Operator] = &[
     Operator::BrOnNonNull { relative_depth: 0 },
 ];
 
+const CONDITIONAL_OPERATORS: &[Operator] = &[
+    Operator::BrIf { relative_depth: 0 },
+    Operator::If {
+        blockty: BlockType::Empty,
+    },
+];
+
 const BLOCK_STYLE_OPERATORS: &[Operator] = &[
     Operator::Block {
         blockty: BlockType::Empty,
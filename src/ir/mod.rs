@@ -1,12 +1,17 @@
 //! The Intermediate Representation for components and modules.
 
+#[cfg(feature = "wasmprinter")]
+mod annotated_wat;
 pub mod component;
+pub mod diff;
 pub mod function;
 mod helpers;
 pub mod id;
 #[cfg(test)]
 pub mod instr_tests;
 pub mod module;
+pub mod offset_map;
 pub mod section;
+pub mod stats;
 pub mod types;
 pub(crate) mod wrappers;
@@ -2,45 +2,63 @@
 
 use super::types::{DataType, Instruction, InstrumentationMode};
 use crate::error::Error;
+#[cfg(feature = "wasmprinter")]
+use crate::ir::annotated_wat;
 use crate::ir::function::FunctionModifier;
-use crate::ir::id::{DataSegmentID, FunctionID, GlobalID, ImportsID, LocalID, MemoryID, TypeID};
+use crate::ir::id::{
+    DataSegmentID, FunctionID, GlobalID, ImportsID, LocalID, MemoryID, TableID, TagID, TypeID,
+};
 use crate::ir::module::module_exports::{Export, ModuleExports};
 use crate::ir::module::module_functions::{
-    add_local, FuncKind, Function, Functions, ImportedFunction, LocalFunction,
+    FuncKind, Function, Functions, ImportedFunction, LocalFunction,
 };
 use crate::ir::module::module_globals::{
     Global, GlobalKind, ImportedGlobal, LocalGlobal, ModuleGlobals,
 };
 use crate::ir::module::module_imports::{Import, ModuleImports};
 use crate::ir::module::module_tables::ModuleTables;
-use crate::ir::module::module_types::{FuncType, ModuleTypes};
-use crate::ir::types::InstrumentationMode::{BlockAlt, BlockEntry, BlockExit, SemanticAfter};
+use crate::ir::module::module_tags::ModuleTags;
+use crate::ir::module::module_types::{FuncType, ModuleTypes, RecGroup, SubType};
+use crate::ir::offset_map;
+use crate::ir::offset_map::{EncodedRange, FuncOffsets, ModuleOffsets, OffsetMap};
+use crate::ir::stats::{self, ModuleStats};
+use crate::ir::types::local_index;
+use crate::ir::types::InstrumentationMode::{
+    After, Alternate, Before, BlockAlt, BlockEntry, BlockExit, BrTableDefault, BrTableTarget,
+    BranchNotTaken, BranchTaken, LoopBackEdge, LoopIteration, SemanticAfter,
+};
+use crate::ir::types::TailCallExit;
 use crate::ir::types::{
-    BlockType, Body, CustomSections, DataSegment, DataSegmentKind, ElementItems, ElementKind,
-    InstrumentationFlag,
+    BlockType, Body, Catch, CustomSections, DataSegment, DataSegmentKind, ElementItems,
+    ElementKind, FuncInstrFlag, FuncInstrMode, InstrumentationFlag, OriginalBody,
 };
 use crate::ir::wrappers::{
     indirect_namemap_parser2encoder, namemap_parser2encoder, refers_to_func, refers_to_global,
-    update_fn_instr, update_global_instr,
+    refers_to_tag, update_fn_instr, update_global_instr, update_tag_instr,
 };
-use crate::opcode::{Inject, Instrumenter};
+use crate::opcode::{Inject, Instrumenter, MacroOpcode};
 use crate::{InitExpr, Location, Opcode};
 use log::{error, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use std::vec::IntoIter;
 use wasm_encoder::reencode::{Reencode, RoundtripReencoder};
-use wasmparser::{ExternalKind, GlobalType, MemoryType, Operator, Parser, Payload, TypeRef};
+use wasmparser::{
+    ExternalKind, GlobalType, MemoryType, Operator, Parser, Payload, TableType, TagKind, TagType,
+    TypeRef,
+};
 
 pub mod module_exports;
 pub mod module_functions;
 pub mod module_globals;
 pub mod module_imports;
 pub mod module_tables;
+pub mod module_tags;
 pub mod module_types;
 #[cfg(test)]
 mod test;
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 /// Intermediate Representation of a wasm module. See the [WASM Spec] for different sections.
 ///
 /// [WASM Spec]: https://webassembly.github.io/spec/core/binary/modules.html
@@ -58,6 +76,8 @@ pub struct Module<'a> {
     pub tables: ModuleTables<'a>,
     /// Memories
     pub memories: Vec<MemoryType>,
+    /// Tags (exception handling)
+    pub tags: ModuleTags,
     /// Globals
     pub globals: ModuleGlobals,
     /// Data Sections
@@ -68,7 +88,7 @@ pub struct Module<'a> {
     /// Index of the start function.
     pub start: Option<FunctionID>,
     /// Elements
-    pub elements: Vec<(ElementKind<'a>, ElementItems<'a>)>,
+    pub elements: Vec<(ElementKind, ElementItems)>,
     /// Custom Sections
     pub custom_sections: CustomSections<'a>,
     /// Number of local functions (not counting imported functions)
@@ -95,6 +115,27 @@ pub struct Module<'a> {
     pub(crate) tag_names: wasm_encoder::NameMap,
 }
 
+/// A module converted from the text format, see [`Module::from_wat`]. Owns the binary that the
+/// parsed `Module` borrows from.
+#[cfg(feature = "wat")]
+#[derive(Clone, Debug)]
+pub struct WatModule {
+    wasm: Vec<u8>,
+}
+
+#[cfg(feature = "wat")]
+impl WatModule {
+    /// Parse the `Module`, as [`Module::parse`] does without multi-memory.
+    pub fn module(&self) -> Result<Module<'_>, Error> {
+        Module::parse(&self.wasm, false)
+    }
+
+    /// The wasm binary
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.wasm
+    }
+}
+
 impl<'a> Module<'a> {
     /// Parses a `Module` from a wasm binary.
     ///
@@ -112,16 +153,35 @@ impl<'a> Module<'a> {
         Module::parse_internal(wasm, enable_multi_memory, parser)
     }
 
+    /// Convert a module from the text format. The returned [`WatModule`] owns the binary,
+    /// the `Module` is parsed from it with [`WatModule::module`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use orca_wasm::Module;
+    ///
+    /// let wat = Module::from_wat("(module)").unwrap();
+    /// let module = wat.module().unwrap();
+    /// ```
+    #[cfg(feature = "wat")]
+    pub fn from_wat(wat: &str) -> Result<WatModule, Error> {
+        let wasm = wat::parse_str(wat).map_err(|e| Error::WatError(e.to_string()))?;
+        Ok(WatModule { wasm })
+    }
+
     pub(crate) fn parse_internal(
         wasm: &'a [u8],
         enable_multi_memory: bool,
         parser: Parser,
     ) -> Result<Self, Error> {
         let mut imports: ModuleImports = ModuleImports::default();
-        let mut types: Vec<FuncType> = vec![];
+        let mut types: Vec<SubType> = vec![];
+        let mut rec_groups: Vec<RecGroup> = vec![];
         let mut data = vec![];
         let mut tables = vec![];
         let mut memories = vec![];
+        let mut tags = vec![];
         let mut functions = vec![];
         let mut elements = vec![];
         let mut code_section_count = 0;
@@ -158,22 +218,16 @@ impl<'a> Module<'a> {
                     imports = ModuleImports::new(temp);
                 }
                 Payload::TypeSection(type_section_reader) => {
-                    for ty in type_section_reader.into_iter_err_on_gc_types() {
-                        let fun_ty = ty?;
-                        let params = fun_ty
-                            .params()
-                            .iter()
-                            .map(|x| DataType::from(*x))
-                            .collect::<Vec<_>>()
-                            .into_boxed_slice();
-                        let results = fun_ty
-                            .results()
-                            .iter()
-                            .map(|x| DataType::from(*x))
-                            .collect::<Vec<_>>()
-                            .into_boxed_slice();
-
-                        types.push(FuncType::new(params, results));
+                    for rec_group in type_section_reader.into_iter() {
+                        let rec_group = rec_group?;
+                        let start = TypeID(types.len() as u32);
+                        let explicit = rec_group.is_explicit_rec_group();
+                        types.extend(rec_group.into_types().map(SubType::from));
+                        rec_groups.push(RecGroup {
+                            start,
+                            len: types.len() as u32 - *start,
+                            explicit,
+                        });
                     }
                 }
                 Payload::DataSection(data_section_reader) => {
@@ -201,6 +255,9 @@ impl<'a> Module<'a> {
                         .into_iter()
                         .collect::<Result<_, _>>()?;
                 }
+                Payload::TagSection(tag_section_reader) => {
+                    tags = tag_section_reader.into_iter().collect::<Result<_, _>>()?;
+                }
                 Payload::FunctionSection(function_section_reader) => {
                     let temp: Vec<u32> = function_section_reader
                         .into_iter()
@@ -282,6 +339,13 @@ impl<'a> Module<'a> {
                         instructions: instructions_bool.clone(),
                         num_instructions: instructions_bool.len(),
                         name: None,
+                        scratch: None,
+                        original: Some(Box::new(OriginalBody {
+                            bytes: body.as_bytes(),
+                            offset: body.range().start,
+                            num_locals,
+                            num_instructions: instructions_bool.len(),
+                        })),
                     });
                 }
                 Payload::CustomSection(custom_section_reader) => {
@@ -386,8 +450,7 @@ impl<'a> Module<'a> {
                     contents: _,
                     range: _,
                 } => return Err(Error::UnknownSection { section_id: id }),
-                Payload::TagSection(_)
-                | Payload::ModuleSection {
+                Payload::ModuleSection {
                     parser: _,
                     unchecked_range: _,
                 }
@@ -446,7 +509,11 @@ impl<'a> Module<'a> {
                     functions[index],
                     FunctionID(imports.num_funcs + index as u32),
                     (*code_sec).clone(),
-                    types[*functions[index] as usize].params.len(),
+                    types[*functions[index] as usize]
+                        .as_func()
+                        .expect("function with a non-function type")
+                        .params
+                        .len(),
                 )),
                 (*code_sec).clone().name,
             ));
@@ -456,12 +523,14 @@ impl<'a> Module<'a> {
         let num_memories = memories.len() as u32;
         let num_tables = tables.len() as u32;
         let module_globals = ModuleGlobals::new(&imports, globals);
+        let module_tags = ModuleTags::new(&imports, tags);
         Ok(Module {
-            types: ModuleTypes::new(types),
+            types: ModuleTypes::new(types, rec_groups),
             imports,
             functions: Functions::new(final_funcs),
             tables: ModuleTables::new(tables),
             memories,
+            tags: module_tags,
             globals: module_globals,
             exports: ModuleExports::new(exports),
             start,
@@ -490,8 +559,9 @@ impl<'a> Module<'a> {
 
     /// Emit the module into a wasm binary file.
     pub fn emit_wasm(&mut self, file_name: &str) -> Result<(), std::io::Error> {
-        let module = self.encode_internal();
-        let wasm = module.finish();
+        let wasm = self
+            .try_encode()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
         std::fs::write(file_name, wasm)?;
         Ok(())
     }
@@ -509,13 +579,141 @@ impl<'a> Module<'a> {
     /// let result = module.encode();
     /// ```
     pub fn encode(&mut self) -> Vec<u8> {
-        self.encode_internal().finish()
+        self.try_encode().expect("Unable to encode the module")
+    }
+
+    /// Encode the module into a wasm binary, failing instead of panicking when the module
+    /// cannot be encoded, e.g. when an instruction refers to a tag that is not in the module.
+    ///
+    /// ```no_run
+    /// use orca_wasm::Module;
+    ///
+    /// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+    /// let mut module = Module::parse(&buff, false).unwrap();
+    /// match module.try_encode() {
+    ///     Ok(result) => println!("{} bytes", result.len()),
+    ///     Err(e) => println!("{}", e),
+    /// }
+    /// ```
+    pub fn try_encode(&mut self) -> Result<Vec<u8>, Error> {
+        Ok(self.encode_internal(false, None)?.finish())
+    }
+
+    /// Encode the module into a wasm binary along with the map of the offsets of the instructions
+    /// of the parsed binary to their offsets in the encoded binary.
+    ///
+    /// ```no_run
+    /// use orca_wasm::Module;
+    ///
+    /// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+    /// let mut module = Module::parse(&buff, false).unwrap();
+    /// let (result, map) = module.encode_with_map();
+    /// for instr in map.instrs() {
+    ///     println!("{:?}: {} -> {:?}", instr.location, instr.original, instr.new);
+    /// }
+    /// ```
+    pub fn encode_with_map(&mut self) -> (Vec<u8>, OffsetMap) {
+        let mut funcs = vec![];
+        let result = self
+            .encode_internal(false, Some(&mut funcs))
+            .expect("Unable to encode the module")
+            .finish();
+        let map = offset_map::build(
+            &result,
+            &[ModuleOffsets {
+                module: None,
+                funcs,
+            }],
+        );
+        (result, map)
+    }
+
+    /// Encode the module into a wasm binary, resolving and encoding the function bodies
+    /// on multiple threads. The output is identical to [`Module::encode`].
+    pub fn encode_parallel(&mut self) -> Vec<u8> {
+        self.encode_internal(true, None)
+            .expect("Unable to encode the module")
+            .finish()
+    }
+
+    /// Print the module in the text format with its pending instrumentation marked inline.
+    /// An injected line starts with `;; << ` and ends with its `InstrumentationMode`, a changed line is followed by
+    /// `;; < ` and its new text, and a line that will be removed ends with `;; rm`. The module is left untouched.
+    ///
+    /// ```no_run
+    /// use orca_wasm::Module;
+    ///
+    /// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+    /// let module = Module::parse(&buff, false).unwrap();
+    /// println!("{}", module.to_wat_annotated().unwrap());
+    /// ```
+    #[cfg(feature = "wasmprinter")]
+    pub fn to_wat_annotated(&self) -> Result<String, Error> {
+        annotated_wat::print(self)
+    }
+
+    /// Statistics of the module: counts of functions, instructions and locals, the opcode histogram,
+    /// section sizes and how much the pending instrumentation grows every function.
+    /// Fails if the module, with or without the pending instrumentation, cannot be encoded.
+    ///
+    /// ```no_run
+    /// use orca_wasm::Module;
+    ///
+    /// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+    /// let module = Module::parse(&buff, false).unwrap();
+    /// println!("{}", module.stats().unwrap().to_json());
+    /// ```
+    pub fn stats(&self) -> Result<ModuleStats, Error> {
+        stats::module_stats(self)
+    }
+
+    /// Run `f` on every local function of the module, on multiple threads.
+    /// Functions are independent from each other, so module-level additions (types, globals, imports, ...)
+    /// must be done before calling this.
+    ///
+    /// ```no_run
+    /// use orca_wasm::ir::id::FunctionID;
+    /// use orca_wasm::ir::types::Location;
+    /// use orca_wasm::opcode::Instrumenter;
+    /// use orca_wasm::{Module, Opcode};
+    ///
+    /// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+    /// let mut module = Module::parse(&buff, false).unwrap();
+    /// // inject a `nop` at the start of every function
+    /// module.par_for_each_local_func(|_func_id, mut modifier| {
+    ///     modifier.before_at(Location::Module {
+    ///         func_idx: FunctionID(0), // not used
+    ///         instr_idx: 0,
+    ///     });
+    ///     modifier.nop();
+    /// });
+    /// ```
+    pub fn par_for_each_local_func(
+        &mut self,
+        f: impl Fn(FunctionID, FunctionModifier<'_, 'a>) + Sync,
+    ) {
+        let mut funcs: Vec<_> = self
+            .functions
+            .iter_mut()
+            .filter(|func| !func.is_deleted() && func.is_local())
+            .map(|func| func.unwrap_local_mut())
+            .collect();
+        for_each_func(&mut funcs, true, |func| {
+            f(
+                func.func_id,
+                FunctionModifier::init(&mut func.body, &mut func.args),
+            )
+        });
     }
 
     /// Visits the Orca Module and resolves the special instrumentation by
     /// translating them into the straightforward before/after/alt modes.
-    fn resolve_special_instrumentation(&mut self) {
+    /// If `parallel`, the function bodies are resolved on multiple threads.
+    fn resolve_special_instrumentation(&mut self, parallel: bool) -> Result<(), Error> {
         if !self.num_local_functions > 0 {
+            // only wrap function bodies to catch exceptions if the module can actually throw
+            let catch_exceptions = self.uses_exceptions();
+            let mut pending: HashMap<usize, SpecialInstr> = HashMap::new();
             for rel_func_idx in self.imports.num_funcs as usize..self.functions.len() {
                 let func_idx = FunctionID(rel_func_idx as u32);
                 if let FuncKind::Import(..) = &self.functions.get_kind(func_idx) {
@@ -525,6 +723,7 @@ impl<'a> Module<'a> {
 
                 let mut instr_func_on_entry = None;
                 let mut instr_func_on_exit = None;
+                let mut tail_call_exit = TailCallExit::default();
                 if let FuncKind::Local(LocalFunction { instr_flag, .. }) =
                     self.functions.get_kind_mut(func_idx)
                 {
@@ -542,253 +741,51 @@ impl<'a> Module<'a> {
                         instr_func_on_exit = Some(instr_flag.exit.to_owned());
                         instr_flag.exit = vec![];
                     }
+                    tail_call_exit = instr_flag.tail_call_exit;
                 }
 
-                // initialize with 0 to store the func block!
-                let mut block_stack: Vec<BlockID> = vec![0];
-                let mut delete_block: Option<BlockID> = None;
-                let mut retain_end = true;
-                let mut resolve_on_else_or_end: HashMap<InstrumentationMode, InstrToInject> =
-                    HashMap::new();
-                let mut resolve_on_end: HashMap<
-                    BlockID,
-                    HashMap<InstrumentationMode, InstrToInject>,
-                > = HashMap::new();
-                let mut builder = self.functions.get_fn_modifier(func_idx).unwrap();
-
-                // Must make copy to be able to iterate over body while calling builder.* methods that mutate the instrumentation flag!
-                let readable_copy_of_body = builder.body.instructions.clone();
-                for (
-                    idx,
-                    Instruction {
-                        op,
-                        instr_flag: instrumentation,
-                    },
-                ) in readable_copy_of_body.iter().enumerate()
-                {
-                    // resolve function-level instrumentation
-                    if let Some(on_entry) = &mut instr_func_on_entry {
-                        if !on_entry.is_empty() {
-                            resolve_function_entry(&mut builder, on_entry, idx);
-                        }
-                    }
-                    if let Some(on_exit) = &mut instr_func_on_exit {
-                        if !on_exit.is_empty() {
-                            resolve_function_exit(&mut builder, on_exit, idx);
-                        }
-                    }
-
-                    // resolve instruction-level instrumentation
-                    match op {
-                        Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
-                            // The block ID will just be the curr len of the stack!
-                            block_stack.push(block_stack.len() as u32);
-
-                            // Handle block alt
-                            if let Some(block_alt) = &instrumentation.block_alt {
-                                // only plan to handle if we're not already removing the block this instr is in
-                                if delete_block.is_none()
-                                    && plan_resolution_block_alt(
-                                        block_alt,
-                                        &mut builder,
-                                        &mut retain_end,
-                                        op,
-                                        idx,
-                                    )
-                                {
-                                    builder.clear_instr_at(
-                                        Location::Module {
-                                            func_idx: FunctionID(0), // not used
-                                            instr_idx: idx,
-                                        },
-                                        BlockAlt,
-                                    );
-                                    // we've got a match, which injected the alt body. continue to the next instruction
-                                    delete_block = Some(*block_stack.last().unwrap());
-                                    continue;
-                                }
-                            }
-
-                            if delete_block.is_some() {
-                                // delete this block and skip all instrumentation handling (like below)
-                                builder.empty_alternate_at(Location::Module {
-                                    func_idx: FunctionID(0), // not used
-                                    instr_idx: idx,
-                                });
-                                continue;
-                            }
-                        }
-                        Operator::Else => {
-                            // necessary for if statements with block_exit instrumentation
-                            for (mode, instr_to_inject) in resolve_on_else_or_end.iter() {
-                                // resolve bodies at the else
-                                resolve_bodies(&mut builder, mode, instr_to_inject, idx);
-                            }
-                            resolve_on_else_or_end.clear();
-
-                            // Handle block alt
-                            if let Some(block_alt) = &instrumentation.block_alt {
-                                // only plan to handle if we're not already removing the block this instr is in
-                                if delete_block.is_none()
-                                    && plan_resolution_block_alt(
-                                        block_alt,
-                                        &mut builder,
-                                        &mut retain_end,
-                                        op,
-                                        idx,
-                                    )
-                                {
-                                    builder.clear_instr_at(
-                                        Location::Module {
-                                            func_idx: FunctionID(0), // not used
-                                            instr_idx: idx,
-                                        },
-                                        BlockAlt,
-                                    );
-                                    // we've got a match, which injected the alt body. continue to the next instruction
-                                    delete_block = Some(*block_stack.last().unwrap());
-                                    continue;
-                                }
-                            }
-
-                            if delete_block.is_some() {
-                                // delete this block and skip all instrumentation handling (like below)
-                                builder.empty_alternate_at(Location::Module {
-                                    func_idx: FunctionID(0), // not used
-                                    instr_idx: idx,
-                                });
-                                continue;
-                            }
-                        }
-                        Operator::End => {
-                            // Pop the stack and check to see if we have instrumentation to inject!
-                            if let Some(block_id) = block_stack.pop() {
-                                if let Some(delete_block_id) = delete_block.as_mut() {
-                                    // Delete the block, but don't remove the end if we say not to
-                                    // should still process instrumentation on the end though...
-                                    // (consider if/else where the else has an alt block)
-                                    if (*delete_block_id).eq(&block_id) {
-                                        // completing the alt block logic, clear state
-                                        delete_block = None;
-                                        if !retain_end {
-                                            // delete this end and skip all instrumentation handling (like below)
-                                            builder.empty_alternate_at(Location::Module {
-                                                func_idx: FunctionID(0), // not used
-                                                instr_idx: idx,
-                                            });
-                                            retain_end = true;
-                                            continue;
-                                        }
-                                        // fall through to the instrumentation handling
-                                        retain_end = true;
-                                    } else {
-                                        // delete this instruction and skip all instrumentation handling (like below)
-                                        builder.empty_alternate_at(Location::Module {
-                                            func_idx: FunctionID(0), // not used
-                                            instr_idx: idx,
-                                        });
-                                        continue;
-                                    }
-                                }
-
-                                // we've reached an end, make sure resolve_on_else is cleared!
-                                // resolve bodies for else OR end
-                                for (mode, instr_to_inject) in resolve_on_else_or_end.iter() {
-                                    resolve_bodies(&mut builder, mode, instr_to_inject, idx);
-                                }
-                                resolve_on_else_or_end.clear();
-
-                                // remove top of stack! (end of vec)
-                                // remove it, so we don't try to re-inject!
-                                if let Some(to_resolve) = resolve_on_end.remove(&block_id) {
-                                    for (mode, instr_to_inject) in to_resolve.iter() {
-                                        // resolve bodies at the end
-                                        resolve_bodies(&mut builder, mode, instr_to_inject, idx);
-                                    }
-                                }
-                            }
-                        }
-                        _ => {
-                            // non block-structured opcodes
-                            if delete_block.is_some() {
-                                // delete this instruction and skip all instrumentation handling (like below)
-                                builder.empty_alternate_at(Location::Module {
-                                    func_idx: FunctionID(0), // not used
-                                    instr_idx: idx,
-                                });
-                                continue;
-                            }
-                        }
-                    }
-
-                    // plan instruction-level instrumentation resolution
-                    // this must go after the above logic to ensure the block_id is on the top of the stack!
-                    if instrumentation.has_instr() {
-                        // this instruction has instrumentation, check if there is any to resolve!
-                        let InstrumentationFlag {
-                            semantic_after,
-                            block_entry,
-                            block_exit,
-                            block_alt: _, // handled before here!
-                            before: _,
-                            after: _,
-                            alternate: _,
-                            current_mode: _,
-                            // exhaustive to help identify where to add code to handle other special modes.
-                        } = instrumentation;
-
-                        // Handle block entry
-                        if !block_entry.is_empty() {
-                            resolve_block_entry(block_entry, &mut builder, op, idx);
-                            builder.clear_instr_at(
-                                Location::Module {
-                                    func_idx: FunctionID(0), // not used
-                                    instr_idx: idx,
-                                },
-                                BlockEntry,
-                            );
-                        }
-
-                        // Handle block exit
-                        if !block_exit.is_empty() {
-                            plan_resolution_block_exit(
-                                block_exit,
-                                &block_stack,
-                                &mut resolve_on_else_or_end,
-                                &mut resolve_on_end,
-                                op,
-                            );
-                            builder.clear_instr_at(
-                                Location::Module {
-                                    func_idx: FunctionID(0), // not used
-                                    instr_idx: idx,
-                                },
-                                BlockExit,
-                            );
-                        }
+                // the function exit should also fire when an exception leaves the function,
+                // the body is wrapped in a `try_table` with the function's results
+                let exit_on_throw = if catch_exceptions && instr_func_on_exit.is_some() {
+                    let ty_id = self.functions.get_type_id(func_idx);
+                    let results = self.types.get(ty_id).unwrap().results.clone();
+                    Some(match results.len() {
+                        0 => BlockType::Empty,
+                        1 => BlockType::Type(results[0]),
+                        _ => BlockType::FuncType(self.types.add(&[], &results)),
+                    })
+                } else {
+                    None
+                };
 
-                        // Handle semantic_after!
-                        if !semantic_after.is_empty() {
-                            plan_resolution_semantic_after(
-                                semantic_after,
-                                &mut builder,
-                                &block_stack,
-                                &mut resolve_on_end,
-                                op,
-                                idx,
-                            );
-                            builder.clear_instr_at(
-                                Location::Module {
-                                    func_idx: FunctionID(0), // not used
-                                    instr_idx: idx,
-                                },
-                                SemanticAfter,
-                            );
-                        }
-                    }
-                }
+                pending.insert(
+                    rel_func_idx,
+                    SpecialInstr {
+                        on_entry: instr_func_on_entry,
+                        on_exit: instr_func_on_exit,
+                        exit_on_throw,
+                        tail_call_exit,
+                    },
+                );
             }
+
+            // the module-level additions are done, the functions can be resolved independently
+            let mut to_resolve: Vec<_> = self
+                .functions
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(idx, func)| {
+                    let special = pending.remove(&idx)?;
+                    Some((func.unwrap_local_mut(), special))
+                })
+                .collect();
+            for_each_func(&mut to_resolve, parallel, |(func, special)| {
+                resolve_special_instr_in_func(func, special)
+            })
+            .into_iter()
+            .collect::<Result<(), Error>>()?;
         }
+        Ok(())
     }
 
     /// Reorganises items (both local and imports) in the correct ordering after any potential modifications
@@ -863,9 +860,15 @@ impl<'a> Module<'a> {
 
     /// Encodes an Orca Module to a wasm_encoder Module.
     /// This requires a mutable reference to self due to the special instrumentation resolution step.
-    pub(crate) fn encode_internal(&mut self) -> wasm_encoder::Module {
+    /// If `parallel`, the function bodies are resolved and encoded on multiple threads.
+    /// If `offsets` is given, the offsets of the encoded functions are recorded in it, in the order of the code section.
+    pub(crate) fn encode_internal(
+        &mut self,
+        parallel: bool,
+        mut offsets: Option<&mut Vec<FuncOffsets>>,
+    ) -> Result<wasm_encoder::Module, Error> {
         // First resolve any instrumentation that needs to be translated to before/after/alt
-        self.resolve_special_instrumentation();
+        self.resolve_special_instrumentation(parallel)?;
 
         let func_mapping = if self.functions.recalculate_ids {
             Self::recalculate_ids(
@@ -883,6 +886,7 @@ impl<'a> Module<'a> {
         } else {
             Self::get_mapping_generic(self.globals.iter())
         };
+        let tag_mapping = self.tags.get_mapping();
 
         let mut module = wasm_encoder::Module::new();
         let mut reencode = RoundtripReencoder;
@@ -890,19 +894,19 @@ impl<'a> Module<'a> {
         if !self.types.is_empty() {
             let mut types = wasm_encoder::TypeSection::new();
 
-            for ty in self.types.iter() {
-                let params = ty
-                    .params
-                    .iter()
-                    .map(wasm_encoder::ValType::from)
-                    .collect::<Vec<_>>();
-                let results = ty
-                    .results
+            for group in self.types.iter_rec_groups() {
+                let start = *group.start as usize;
+                let subtypes = self.types.types[start..start + group.len as usize]
                     .iter()
-                    .map(wasm_encoder::ValType::from)
-                    .collect::<Vec<_>>();
-
-                types.function(params, results);
+                    .map(wasm_encoder::SubType::from);
+                if group.explicit {
+                    types.rec(subtypes);
+                } else {
+                    // a type in its own implicit recursion group
+                    subtypes.for_each(|ty| {
+                        types.subtype(&ty);
+                    });
+                }
             }
             module.section(&types);
         }
@@ -978,6 +982,17 @@ impl<'a> Module<'a> {
             module.section(&memories);
         }
 
+        if self.tags.num_local() > 0 {
+            let mut tags = wasm_encoder::TagSection::new();
+            for tag in self.tags.iter() {
+                // skip imported tags
+                if tag.is_local() {
+                    tags.tag(reencode.tag_type(tag.ty));
+                }
+            }
+            module.section(&tags);
+        }
+
         if !self.globals.is_empty() {
             let mut globals = wasm_encoder::GlobalSection::new();
             for global in self.globals.iter() {
@@ -989,7 +1004,7 @@ impl<'a> Module<'a> {
                                 mutable: ty.mutable,
                                 shared: ty.shared,
                             },
-                            &init_expr.to_wasmencoder_type(),
+                            &init_expr.to_wasmencoder_type(&func_mapping, &global_mapping),
                         );
                     }
                 }
@@ -1012,6 +1027,13 @@ impl<'a> Module<'a> {
                                 *func_mapping.get(&(export.index)).unwrap(),
                             );
                         }
+                        ExternalKind::Tag => {
+                            exports.export(
+                                &export.name,
+                                wasm_encoder::ExportKind::from(export.kind),
+                                *tag_mapping.get(&(export.index)).unwrap(),
+                            );
+                        }
                         _ => {
                             exports.export(
                                 &export.name,
@@ -1050,11 +1072,8 @@ impl<'a> Module<'a> {
                     ElementItems::ConstExprs { ty, exprs } => {
                         temp_const_exprs.reserve(exprs.len());
                         for e in exprs.iter() {
-                            temp_const_exprs.push(
-                                reencode
-                                    .const_expr((*e).clone())
-                                    .expect("Unable to convert element constant expr"),
-                            );
+                            temp_const_exprs
+                                .push(e.to_wasmencoder_type(&func_mapping, &global_mapping));
                         }
                         wasm_encoder::Elements::Expressions(
                             wasm_encoder::RefType {
@@ -1076,9 +1095,7 @@ impl<'a> Module<'a> {
                     } => {
                         elements.active(
                             *table_index,
-                            &reencode
-                                .const_expr((*offset_expr).clone())
-                                .expect("Unable to convert offset expr"),
+                            &offset_expr.to_wasmencoder_type(&func_mapping, &global_mapping),
                             element_items,
                         );
                     }
@@ -1099,146 +1116,58 @@ impl<'a> Module<'a> {
 
         if !self.num_local_functions > 0 {
             let mut code = wasm_encoder::CodeSection::new();
-            for rel_func_idx in 0..self.functions.len() {
-                if self.functions.is_deleted(FunctionID(rel_func_idx as u32)) {
-                    continue;
-                }
-                if let FuncKind::Import(_) =
-                    &self.functions.get_kind(FunctionID(rel_func_idx as u32))
-                {
-                    continue;
-                }
-
-                let func = self
-                    .functions
-                    .get_mut(FunctionID(rel_func_idx as u32))
-                    .unwrap_local_mut();
-                let Body {
-                    instructions,
-                    locals,
-                    name,
-                    ..
-                } = &mut func.body;
-                let mut converted_locals = Vec::with_capacity(locals.len());
-                for (c, ty) in locals {
-                    converted_locals.push((*c, wasm_encoder::ValType::from(&*ty)));
-                }
-                let mut function = wasm_encoder::Function::new(converted_locals);
-                let instr_len = instructions.len() - 1;
-                for (
-                    idx,
-                    Instruction {
-                        op,
-                        instr_flag: instrument,
-                    },
-                ) in instructions.iter_mut().enumerate()
-                {
-                    if refers_to_func(op) {
-                        update_fn_instr(op, &func_mapping);
-                    }
-                    if refers_to_global(op) {
-                        update_global_instr(op, &global_mapping);
-                    }
-                    if !instrument.has_instr() {
-                        encode(&op.clone(), &mut function, &mut reencode);
-                    } else {
-                        // this instruction has instrumentation, handle it!
-                        let InstrumentationFlag {
-                            current_mode: _current_mode,
-                            before,
-                            after,
-                            alternate,
-                            semantic_after,
-                            block_entry,
-                            block_exit,
-                            block_alt,
-                        } = instrument;
-
-                        // Check if special instrumentation modes have been resolved!
-                        if !semantic_after.is_empty() {
-                            error!("BUG: Semantic after instrumentation should be resolved already, please report.");
-                        }
-                        if !block_entry.is_empty() {
-                            error!("BUG: Block entry instrumentation should be resolved already, please report.");
-                        }
-                        if !block_exit.is_empty() {
-                            error!("BUG: Block exit instrumentation should be resolved already, please report.");
-                        }
-                        if !block_alt.is_none() {
-                            error!("BUG: Block alt instrumentation should be resolved already, please report.");
-                        }
-                        // If we're at the `end` of the function, drop this instrumentation
-                        let at_end = idx >= instr_len;
-
-                        // First encode before instructions
-                        update_ids_and_encode(
-                            before,
+            let mut to_encode: Vec<_> = self
+                .functions
+                .iter_mut()
+                .enumerate()
+                .filter(|(_, func)| !func.is_deleted() && func.is_local())
+                .map(|(rel_func_idx, func)| (rel_func_idx as u32, func.unwrap_local_mut()))
+                .collect();
+            // untouched bodies can be copied from the parsed binary as long as the IDs they refer to did not move
+            let is_identity =
+                |mapping: &HashMap<u32, u32>| mapping.iter().all(|(old, new)| old == new);
+            let reuse_original = is_identity(&func_mapping)
+                && is_identity(&global_mapping)
+                && is_identity(&tag_mapping);
+            // the bodies are encoded independently, then appended in order to keep the output deterministic
+            let record = offsets.is_some();
+            let encoded = for_each_func(&mut to_encode, parallel, |(_, func)| {
+                match func.body.original_bytes() {
+                    Some(bytes) if reuse_original => Ok((EncodedBody::Original(bytes), None)),
+                    _ => {
+                        let mut ranges = record.then(Vec::new);
+                        let function = encode_func_body(
+                            func,
                             &func_mapping,
                             &global_mapping,
-                            &mut function,
-                            &mut reencode,
-                        );
-
-                        // If there are any alternate, encode the alternate
-                        if !at_end && !alternate.is_none() {
-                            if let Some(alt) = alternate {
-                                update_ids_and_encode(
-                                    alt,
-                                    &func_mapping,
-                                    &global_mapping,
-                                    &mut function,
-                                    &mut reencode,
-                                );
-                            }
-                        } else {
-                            encode(&op.clone(), &mut function, &mut reencode);
-                        }
-
-                        // Now encode the after instructions
-                        if !at_end {
-                            update_ids_and_encode(
-                                after,
-                                &func_mapping,
-                                &global_mapping,
-                                &mut function,
-                                &mut reencode,
-                            );
-                        }
-                    }
-
-                    fn update_ids_and_encode(
-                        instrs: &mut Vec<Operator>,
-                        func_mapping: &HashMap<u32, u32>,
-                        global_mapping: &HashMap<u32, u32>,
-                        function: &mut wasm_encoder::Function,
-                        reencode: &mut RoundtripReencoder,
-                    ) {
-                        for instr in instrs {
-                            if refers_to_func(instr) {
-                                update_fn_instr(instr, func_mapping);
-                            }
-                            if refers_to_global(instr) {
-                                update_global_instr(instr, global_mapping);
-                            }
-                            encode(instr, function, reencode);
-                        }
-                    }
-                    fn encode(
-                        instr: &Operator,
-                        function: &mut wasm_encoder::Function,
-                        reencode: &mut RoundtripReencoder,
-                    ) {
-                        function.instruction(
-                            &reencode
-                                .instruction(instr.clone())
-                                .expect("Unable to convert Instruction"),
-                        );
+                            &tag_mapping,
+                            &mut ranges,
+                        )?;
+                        Ok((EncodedBody::Encoded(function), ranges))
                     }
                 }
-                if let Some(name) = name {
-                    function_names.append(rel_func_idx as u32, name.as_str());
+            })
+            .into_iter()
+            .collect::<Result<Vec<_>, Error>>()?;
+            for ((rel_func_idx, func), (body, ranges)) in to_encode.iter().zip(encoded) {
+                if let Some(name) = &func.body.name {
+                    function_names.append(*rel_func_idx, name.as_str());
+                }
+                match body {
+                    EncodedBody::Original(bytes) => code.raw(bytes),
+                    EncodedBody::Encoded(function) => code.function(&function),
+                };
+                if let Some(offsets) = offsets.as_deref_mut() {
+                    offsets.push(FuncOffsets {
+                        func_idx: FunctionID(*rel_func_idx),
+                        original: func
+                            .body
+                            .original
+                            .as_ref()
+                            .map(|o| (o.offset, o.instr_offsets())),
+                        encoded: ranges,
+                    });
                 }
-                code.function(&function);
             }
             module.section(&code);
         }
@@ -1254,7 +1183,7 @@ impl<'a> Module<'a> {
                         offset_expr,
                     } => data.active(
                         memory_index,
-                        &offset_expr.to_wasmencoder_type(),
+                        &offset_expr.to_wasmencoder_type(&func_mapping, &global_mapping),
                         segment_data,
                     ),
                 };
@@ -1290,7 +1219,7 @@ impl<'a> Module<'a> {
             });
         }
 
-        module
+        Ok(module)
     }
 
     /// Add a new Data Segment to the module.
@@ -1314,10 +1243,126 @@ impl<'a> Module<'a> {
         None
     }
 
+    /// Get the type of a memory, imported memories come first in the index space.
+    pub fn get_memory_type(&self, mem_id: MemoryID) -> Option<MemoryType> {
+        let mut imported = self.imports.iter().filter_map(|import| match import.ty {
+            TypeRef::Memory(ty) => Some(ty),
+            _ => None,
+        });
+        if let Some(ty) = imported.nth(*mem_id as usize) {
+            return Some(ty);
+        }
+        let local_idx = *mem_id - self.imports.num_memories;
+        self.memories.get(local_idx as usize).copied()
+    }
+
+    /// Get the address type of a memory, `I64` for a 64-bit (memory64) memory, `I32` otherwise.
+    pub fn get_memory_addr_type(&self, mem_id: MemoryID) -> Option<DataType> {
+        self.get_memory_type(mem_id)
+            .map(|ty| DataType::addr_type(&ty))
+    }
+
+    /// Get the type of a table, imported tables come first in the index space.
+    pub fn get_table_type(&self, table_id: TableID) -> Option<TableType> {
+        let mut imported = self.imports.iter().filter_map(|import| match import.ty {
+            TypeRef::Table(ty) => Some(ty),
+            _ => None,
+        });
+        if let Some(ty) = imported.nth(*table_id as usize) {
+            return Some(ty);
+        }
+        self.tables
+            .get(TableID(*table_id - self.imports.num_tables))
+    }
+
+    /// Get the type of the function called by a call instruction, along with the type of the
+    /// operand that selects the callee of an indirect call (the table index of a `call_indirect`,
+    /// the function reference of a `call_ref`). Returns `None` if `op` is not a call.
+    pub fn get_call_type(&self, op: &Operator) -> Option<(FuncType, Option<DataType>)> {
+        match op {
+            Operator::Call { function_index } | Operator::ReturnCall { function_index } => {
+                let ty_id = self.functions.get_type_id(FunctionID(*function_index));
+                Some((self.types.get(ty_id)?.clone(), None))
+            }
+            Operator::CallIndirect {
+                type_index,
+                table_index,
+            }
+            | Operator::ReturnCallIndirect {
+                type_index,
+                table_index,
+            } => {
+                let table = self.get_table_type(TableID(*table_index))?;
+                let index_ty = if table.table64 {
+                    DataType::I64
+                } else {
+                    DataType::I32
+                };
+                Some((self.types.get(TypeID(*type_index))?.clone(), Some(index_ty)))
+            }
+            Operator::CallRef { type_index } | Operator::ReturnCallRef { type_index } => {
+                let ref_ty = DataType::Concrete {
+                    ty_id: TypeID(*type_index),
+                    nullable: true,
+                };
+                Some((self.types.get(TypeID(*type_index))?.clone(), Some(ref_ty)))
+            }
+            _ => None,
+        }
+    }
+
     // ==============================
     // ==== Module Manipulations ====
     // ==============================
 
+    /// Runs `edit` on the module as a transaction: if it returns an error, every change it made
+    /// to the module (injected instrumentation, added locals, globals, imports, functions, ...) is undone.
+    ///
+    /// As `edit` can change any part of the module, the whole module is cloned before running it,
+    /// which costs as much as the module is large: every function body with its instrumentation and
+    /// the data segments are copied. To only undo injected instrumentation, clearing it with
+    /// [`Module::clear_function_instr`] or [`Module::clear_all_instr`] is cheaper.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use orca_wasm::iterator::iterator_trait::{IteratingInstrumenter, Iterator};
+    /// use orca_wasm::iterator::module_iterator::ModuleIterator;
+    /// use orca_wasm::module_builder::AddLocal;
+    /// use orca_wasm::{DataType, Module, Opcode};
+    /// use wasmparser::Operator;
+    ///
+    /// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+    /// let mut module = Module::parse(&buff, false).unwrap();
+    /// let result = module.transaction(|module| {
+    ///     let mut mod_it = ModuleIterator::new(module, &vec![]);
+    ///     loop {
+    ///         match mod_it.curr_op() {
+    ///             Some(Operator::Call { .. }) => {
+    ///                 let local = mod_it.add_local(DataType::I32);
+    ///                 mod_it.before().i32_const(0).local_set(local);
+    ///             }
+    ///             Some(Operator::CallIndirect { .. }) => return Err("unsupported call"),
+    ///             _ => {}
+    ///         }
+    ///         if mod_it.next().is_none() {
+    ///             return Ok(());
+    ///         }
+    ///     }
+    /// });
+    /// ```
+    pub fn transaction<T, E>(
+        &mut self,
+        edit: impl FnOnce(&mut Module<'a>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let snapshot = self.clone();
+        let result = edit(self);
+        if result.is_err() {
+            *self = snapshot;
+        }
+        result
+    }
+
     pub(crate) fn add_import(&mut self, import: Import<'a>) -> (u32, ImportsID) {
         let (num_local, num_imported, num_total) = match import.ty {
             TypeRef::Func(..) => (
@@ -1331,7 +1376,11 @@ impl<'a> Module<'a> {
                 self.globals.len() as u32,
             ),
             TypeRef::Table(..) => todo!(),
-            TypeRef::Tag(..) => todo!(),
+            TypeRef::Tag(..) => (
+                self.tags.num_local(),
+                self.imports.num_tags,
+                self.tags.len() as u32,
+            ),
             TypeRef::Memory(..) => todo!(),
         };
 
@@ -1460,6 +1509,67 @@ impl<'a> Module<'a> {
         true
     }
 
+    /// The instructions that have pending instrumentation, with the modes they are instrumented in.
+    /// The function-level instrumentation is listed by [`Module::instrumented_functions`].
+    pub fn instrumented_locations(&self) -> Vec<(Location, InstrumentationMode)> {
+        let mut locations = vec![];
+        for func in self.functions.iter() {
+            if let FuncKind::Local(l) = &func.kind {
+                for (instr_idx, instr) in l.body.instructions.iter().enumerate() {
+                    for mode in instr.instr_flag.modes() {
+                        let loc = Location::Module {
+                            func_idx: l.func_id,
+                            instr_idx,
+                        };
+                        locations.push((loc, mode));
+                    }
+                }
+            }
+        }
+        locations
+    }
+
+    /// The functions that have pending function-level instrumentation (`func_entry`, `func_exit`),
+    /// with the modes they are instrumented in.
+    pub fn instrumented_functions(&self) -> Vec<(FunctionID, FuncInstrMode)> {
+        let mut funcs = vec![];
+        for func in self.functions.iter() {
+            if let FuncKind::Local(l) = &func.kind {
+                for mode in l.instr_flag.modes() {
+                    funcs.push((l.func_id, mode));
+                }
+            }
+        }
+        funcs
+    }
+
+    /// Remove all the pending instrumentation of a function, at its instructions and at the function level.
+    /// The locals added to the function are kept, use [`Module::transaction`] to undo those as well.
+    pub fn clear_function_instr(&mut self, func_idx: FunctionID) {
+        if let FuncKind::Local(l) = self.functions.get_kind_mut(func_idx) {
+            l.instr_flag = FuncInstrFlag::default();
+            for instr in l.body.instructions.iter_mut() {
+                instr.instr_flag = InstrumentationFlag::default();
+            }
+        } else {
+            warn!("Cannot clear the instrumentation of an imported function!");
+        }
+    }
+
+    /// Remove all the pending instrumentation of the module, so that it encodes its functions as they were parsed.
+    /// The locals, globals, imports and functions added to the module are kept,
+    /// use [`Module::transaction`] to undo those as well.
+    pub fn clear_all_instr(&mut self) {
+        for func in self.functions.iter_mut() {
+            if let FuncKind::Local(l) = &mut func.kind {
+                l.instr_flag = FuncInstrFlag::default();
+                for instr in l.body.instructions.iter_mut() {
+                    instr.instr_flag = InstrumentationFlag::default();
+                }
+            }
+        }
+    }
+
     /// Set the name of a function using its ID.
     pub fn set_fn_name(&mut self, id: FunctionID, name: String) {
         if *id < self.imports.num_funcs {
@@ -1549,18 +1659,86 @@ impl<'a> Module<'a> {
             self.imports.delete(*import_id);
         }
     }
-}
 
-pub trait GetID {
-    fn get_id(&self) -> u32;
-}
+    // ==========================
+    // ==== Tags Management ====
+    // ==========================
 
-pub(crate) trait Iter<T> {
-    fn iter(&self) -> std::slice::Iter<'_, T>;
-    fn get_into_iter(&self) -> IntoIter<T>;
-}
+    /// Create a new locally-defined exception tag whose payload is the params of `ty_id`.
+    /// The type must not have any results.
+    pub fn add_tag(&mut self, ty_id: TypeID) -> TagID {
+        self.tags.add_local(TagType {
+            kind: TagKind::Exception,
+            func_type_idx: *ty_id,
+        })
+    }
 
-pub(crate) trait ReIndexable<T> {
+    /// Add a new imported exception tag to the module, returns:
+    ///
+    /// - TagID: The ID that indexes into the tag ID space. To be used when referring to the tag, like in `throw`.
+    /// - ImportsID: The ID that indexes into the import section.
+    pub fn add_import_tag(
+        &mut self,
+        module: String,
+        name: String,
+        ty_id: TypeID,
+    ) -> (TagID, ImportsID) {
+        let ty = TagType {
+            kind: TagKind::Exception,
+            func_type_idx: *ty_id,
+        };
+        let (imp_tag_id, imp_id) = self.add_import(Import {
+            module: module.leak(),
+            name: name.leak(),
+            ty: TypeRef::Tag(ty),
+            custom_name: None,
+            deleted: false,
+        });
+
+        // Add to tags as well since it has imported tags
+        let tag_id = self.tags.add_import(imp_id, ty);
+        debug_assert_eq!(*tag_id, imp_tag_id);
+        (tag_id, imp_id)
+    }
+
+    /// Export a tag from the module under `name`.
+    pub fn add_export_tag(&mut self, name: String, tag_id: TagID) {
+        self.exports.add_export_tag(name, *tag_id);
+    }
+
+    /// Whether this module makes use of the exception-handling proposal, i.e. it defines or
+    /// imports tags, or one of its functions contains an exception-handling instruction.
+    pub fn uses_exceptions(&self) -> bool {
+        !self.tags.is_empty()
+            || self.functions.iter().any(|func| match &func.kind {
+                FuncKind::Local(LocalFunction { body, .. }) => {
+                    body.instructions.iter().any(|instr| {
+                        matches!(
+                            instr.op,
+                            Operator::TryTable { .. }
+                                | Operator::Throw { .. }
+                                | Operator::ThrowRef
+                                | Operator::Try { .. }
+                                | Operator::Rethrow { .. }
+                                | Operator::Delegate { .. }
+                        )
+                    })
+                }
+                FuncKind::Import(..) => false,
+            })
+    }
+}
+
+pub trait GetID {
+    fn get_id(&self) -> u32;
+}
+
+pub(crate) trait Iter<T> {
+    fn iter(&self) -> std::slice::Iter<'_, T>;
+    fn get_into_iter(&self) -> IntoIter<T>;
+}
+
+pub(crate) trait ReIndexable<T> {
     fn len(&self) -> usize;
     fn remove(&mut self, id: u32) -> T;
     fn insert(&mut self, id: u32, val: T);
@@ -1582,6 +1760,983 @@ pub trait LocalOrImport {
 // ================================
 
 type BlockID = u32;
+/// The special function-level instrumentation of a function, taken off its flag before
+/// resolving the function body.
+struct SpecialInstr<'a> {
+    on_entry: Option<InstrBody<'a>>,
+    on_exit: Option<InstrBody<'a>>,
+    exit_on_throw: Option<BlockType>,
+    tail_call_exit: TailCallExit,
+}
+
+/// Resolves the special instrumentation of a single function by translating them into
+/// the straightforward before/after/alt modes.
+fn resolve_special_instr_in_func<'a>(
+    func: &mut LocalFunction<'a>,
+    special: &mut SpecialInstr<'a>,
+) -> Result<(), Error> {
+    let SpecialInstr {
+        on_entry: instr_func_on_entry,
+        on_exit: instr_func_on_exit,
+        exit_on_throw,
+        tail_call_exit,
+    } = special;
+    let (exit_on_throw, tail_call_exit) = (*exit_on_throw, *tail_call_exit);
+    let func_id = func.func_id;
+
+    // initialize with 0 to store the func block!
+    let mut block_stack: Vec<BlockID> = vec![0];
+    let mut delete_block: Option<BlockID> = None;
+    let mut retain_end = true;
+    let mut resolve_on_else_or_end: HashMap<InstrumentationMode, InstrToInject> = HashMap::new();
+    let mut resolve_on_end: HashMap<BlockID, HashMap<InstrumentationMode, InstrToInject>> =
+        HashMap::new();
+    // the code to inject on the branches to the blocks we're in (the back-edges of loops and
+    // the exits of the function), by block ID
+    let mut on_branch_to: HashMap<BlockID, InstrBody> = HashMap::new();
+    if let Some(on_exit) = instr_func_on_exit {
+        if !on_exit.is_empty() {
+            // branching to the function block leaves the function
+            on_branch_to.insert(0, on_exit.clone());
+        }
+    }
+    let mut builder = FunctionModifier::init(&mut func.body, &mut func.args);
+
+    // Must make copy to be able to iterate over body while calling builder.* methods that mutate the instrumentation flag!
+    let readable_copy_of_body = builder.body.instructions.clone();
+    let mut in_use = locals_used(&readable_copy_of_body);
+    in_use.extend(
+        instr_func_on_entry
+            .iter()
+            .chain(instr_func_on_exit.iter())
+            .flatten()
+            .filter_map(local_index),
+    );
+    let mut locals = ResolutionLocals {
+        in_use,
+        release_on_end: HashMap::new(),
+    };
+    for (
+        idx,
+        Instruction {
+            op,
+            instr_flag: instrumentation,
+        },
+    ) in readable_copy_of_body.iter().enumerate()
+    {
+        // resolve function-level instrumentation
+        if let Some(on_entry) = instr_func_on_entry {
+            if !on_entry.is_empty() {
+                resolve_function_entry(&mut builder, on_entry, idx);
+            }
+        }
+        if let Some(on_exit) = instr_func_on_exit {
+            if !on_exit.is_empty() {
+                resolve_function_exit(
+                    &mut builder,
+                    on_exit,
+                    exit_on_throw,
+                    tail_call_exit,
+                    func_id,
+                    op,
+                    idx,
+                )?;
+            }
+        }
+
+        // resolve instruction-level instrumentation
+        match op {
+            Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::TryTable { .. }
+            | Operator::Try { .. } => {
+                // The block ID will just be the curr len of the stack!
+                block_stack.push(block_stack.len() as u32);
+
+                // Handle block alt
+                if let Some(block_alt) = &instrumentation.block_alt {
+                    // only plan to handle if we're not already removing the block this instr is in
+                    if delete_block.is_none()
+                        && plan_resolution_block_alt(
+                            block_alt,
+                            &mut builder,
+                            &mut retain_end,
+                            op,
+                            idx,
+                        )
+                    {
+                        builder.clear_instr_at(
+                            Location::Module {
+                                func_idx: FunctionID(0), // not used
+                                instr_idx: idx,
+                            },
+                            BlockAlt,
+                        );
+                        // we've got a match, which injected the alt body. continue to the next instruction
+                        delete_block = Some(*block_stack.last().unwrap());
+                        continue;
+                    }
+                }
+
+                if delete_block.is_some() {
+                    // delete this block and skip all instrumentation handling (like below)
+                    builder.empty_alternate_at(Location::Module {
+                        func_idx: FunctionID(0), // not used
+                        instr_idx: idx,
+                    });
+                    continue;
+                }
+            }
+            Operator::Else => {
+                // necessary for if statements with block_exit instrumentation
+                for (mode, instr_to_inject) in resolve_on_else_or_end.iter() {
+                    // resolve bodies at the else
+                    resolve_bodies(&mut builder, mode, instr_to_inject, idx);
+                }
+                resolve_on_else_or_end.clear();
+
+                // Handle block alt
+                if let Some(block_alt) = &instrumentation.block_alt {
+                    // only plan to handle if we're not already removing the block this instr is in
+                    if delete_block.is_none()
+                        && plan_resolution_block_alt(
+                            block_alt,
+                            &mut builder,
+                            &mut retain_end,
+                            op,
+                            idx,
+                        )
+                    {
+                        builder.clear_instr_at(
+                            Location::Module {
+                                func_idx: FunctionID(0), // not used
+                                instr_idx: idx,
+                            },
+                            BlockAlt,
+                        );
+                        // we've got a match, which injected the alt body. continue to the next instruction
+                        delete_block = Some(*block_stack.last().unwrap());
+                        continue;
+                    }
+                }
+
+                if delete_block.is_some() {
+                    // delete this block and skip all instrumentation handling (like below)
+                    builder.empty_alternate_at(Location::Module {
+                        func_idx: FunctionID(0), // not used
+                        instr_idx: idx,
+                    });
+                    continue;
+                }
+            }
+            Operator::End => {
+                // Pop the stack and check to see if we have instrumentation to inject!
+                if let Some(block_id) = block_stack.pop() {
+                    // the branches to a block are all inside it
+                    on_branch_to.remove(&block_id);
+                    locals.release_at_end(&mut builder, block_id);
+
+                    if let Some(delete_block_id) = delete_block.as_mut() {
+                        // Delete the block, but don't remove the end if we say not to
+                        // should still process instrumentation on the end though...
+                        // (consider if/else where the else has an alt block)
+                        if (*delete_block_id).eq(&block_id) {
+                            // completing the alt block logic, clear state
+                            delete_block = None;
+                            if !retain_end {
+                                // delete this end and skip all instrumentation handling (like below)
+                                builder.empty_alternate_at(Location::Module {
+                                    func_idx: FunctionID(0), // not used
+                                    instr_idx: idx,
+                                });
+                                retain_end = true;
+                                continue;
+                            }
+                            // fall through to the instrumentation handling
+                            retain_end = true;
+                        } else {
+                            // delete this instruction and skip all instrumentation handling (like below)
+                            builder.empty_alternate_at(Location::Module {
+                                func_idx: FunctionID(0), // not used
+                                instr_idx: idx,
+                            });
+                            continue;
+                        }
+                    }
+
+                    // we've reached an end, make sure resolve_on_else is cleared!
+                    // resolve bodies for else OR end
+                    for (mode, instr_to_inject) in resolve_on_else_or_end.iter() {
+                        resolve_bodies(&mut builder, mode, instr_to_inject, idx);
+                    }
+                    resolve_on_else_or_end.clear();
+
+                    // remove top of stack! (end of vec)
+                    // remove it, so we don't try to re-inject!
+                    if let Some(to_resolve) = resolve_on_end.remove(&block_id) {
+                        for (mode, instr_to_inject) in to_resolve.iter() {
+                            // resolve bodies at the end
+                            resolve_bodies(&mut builder, mode, instr_to_inject, idx);
+                        }
+                    }
+                }
+            }
+            _ => {
+                // non block-structured opcodes
+                if delete_block.is_some() {
+                    // delete this instruction and skip all instrumentation handling (like below)
+                    builder.empty_alternate_at(Location::Module {
+                        func_idx: FunctionID(0), // not used
+                        instr_idx: idx,
+                    });
+                    continue;
+                }
+            }
+        }
+
+        // resolve the branches to the blocks we're in
+        if !on_branch_to.is_empty() {
+            resolve_branches_to(&on_branch_to, &block_stack, &mut builder, &locals, op, idx);
+        }
+
+        // plan instruction-level instrumentation resolution
+        // this must go after the above logic to ensure the block_id is on the top of the stack!
+        if instrumentation.has_instr() {
+            // this instruction has instrumentation, check if there is any to resolve!
+            let InstrumentationFlag {
+                semantic_after,
+                block_entry,
+                block_exit,
+                block_alt: _, // handled before here!
+                branch_taken,
+                branch_not_taken,
+                br_table_targets,
+                br_table_default,
+                loop_iteration,
+                loop_back_edge,
+                before: _,
+                after: _,
+                alternate: _,
+                current_mode: _,
+                // exhaustive to help identify where to add code to handle other special modes.
+            } = instrumentation;
+
+            // Handle block entry
+            if !block_entry.is_empty() {
+                resolve_block_entry(block_entry, &mut builder, op, idx);
+                builder.clear_instr_at(
+                    Location::Module {
+                        func_idx: FunctionID(0), // not used
+                        instr_idx: idx,
+                    },
+                    BlockEntry,
+                );
+            }
+
+            // Handle block exit
+            if !block_exit.is_empty() {
+                plan_resolution_block_exit(
+                    block_exit,
+                    &block_stack,
+                    &mut resolve_on_else_or_end,
+                    &mut resolve_on_end,
+                    op,
+                );
+                builder.clear_instr_at(
+                    Location::Module {
+                        func_idx: FunctionID(0), // not used
+                        instr_idx: idx,
+                    },
+                    BlockExit,
+                );
+            }
+
+            // Handle semantic_after!
+            if !semantic_after.is_empty() {
+                plan_resolution_semantic_after(
+                    semantic_after,
+                    &mut builder,
+                    &mut locals,
+                    &block_stack,
+                    &mut resolve_on_end,
+                    op,
+                    idx,
+                );
+                builder.clear_instr_at(
+                    Location::Module {
+                        func_idx: FunctionID(0), // not used
+                        instr_idx: idx,
+                    },
+                    SemanticAfter,
+                );
+            }
+
+            // Handle branch outcomes
+            if !branch_taken.is_empty() || !branch_not_taken.is_empty() {
+                resolve_branch_outcome(
+                    branch_taken,
+                    branch_not_taken,
+                    &mut builder,
+                    &locals,
+                    op,
+                    idx,
+                );
+                for mode in [BranchTaken, BranchNotTaken] {
+                    builder.clear_instr_at(
+                        Location::Module {
+                            func_idx: FunctionID(0), // not used
+                            instr_idx: idx,
+                        },
+                        mode,
+                    );
+                }
+            }
+
+            // Handle br_table targets
+            if !br_table_targets.is_empty() || !br_table_default.is_empty() {
+                resolve_br_table_targets(
+                    br_table_targets,
+                    br_table_default,
+                    &mut builder,
+                    &locals,
+                    op,
+                    idx,
+                );
+                let target_modes = br_table_targets.iter().map(|(t, _)| BrTableTarget(*t));
+                for mode in target_modes.chain([BrTableDefault]) {
+                    builder.clear_instr_at(
+                        Location::Module {
+                            func_idx: FunctionID(0), // not used
+                            instr_idx: idx,
+                        },
+                        mode,
+                    );
+                }
+            }
+
+            // Handle loop iterations and back-edges
+            if !loop_iteration.is_empty() || !loop_back_edge.is_empty() {
+                // the loop's block ID is on the top of the stack
+                let block_id = *block_stack.last().unwrap();
+                let on_back_edge = plan_resolution_loop(
+                    loop_iteration,
+                    loop_back_edge,
+                    &mut builder,
+                    &mut locals,
+                    block_id,
+                    idx,
+                );
+                on_branch_to.insert(block_id, on_back_edge);
+                for mode in [LoopIteration, LoopBackEdge] {
+                    builder.clear_instr_at(
+                        Location::Module {
+                            func_idx: FunctionID(0), // not used
+                            instr_idx: idx,
+                        },
+                        mode,
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The scratch locals taken by the code injected while resolving the instrumentation of a function
+struct ResolutionLocals {
+    /// The locals used by the function and its instrumentation before it is resolved. They are
+    /// never taken, as released scratch locals may still be written by the injected code.
+    in_use: HashSet<u32>,
+    /// The scratch locals to release at the end of a block, by block ID
+    release_on_end: HashMap<BlockID, Vec<LocalID>>,
+}
+
+impl ResolutionLocals {
+    /// Take an `i32` scratch local that is live within the code injected at a single instruction
+    fn take(&self, builder: &mut FunctionModifier) -> LocalID {
+        take_scratch_local(builder, &self.in_use)
+    }
+
+    /// Take an `i32` scratch local that is live within the block `block_id`, opened at `start`
+    /// (`None` for the function body). It is set to 0 on every entry to the block, and released at its end.
+    /// The locals of the function body start at 0, and the local is not written anywhere else in it.
+    fn take_for_block(
+        &mut self,
+        builder: &mut FunctionModifier,
+        block_id: BlockID,
+        start: Option<usize>,
+    ) -> LocalID {
+        let instructions = &builder.body.instructions;
+        let end = block_end(instructions, start);
+        // the scratch locals of the code already injected in the block
+        let mut in_use = locals_used(&instructions[start.unwrap_or(0)..=end]);
+        in_use.extend(&self.in_use);
+        let id = take_scratch_local(builder, &in_use);
+        if let Some(start) = start {
+            builder
+                .before_at(Location::Module {
+                    func_idx: FunctionID(0), // not used
+                    instr_idx: start,
+                })
+                .i32_const(0)
+                .local_set(id);
+        }
+        self.release_on_end.entry(block_id).or_default().push(id);
+        id
+    }
+
+    /// Release the scratch locals that are live within the block `block_id`, at its end
+    fn release_at_end(&mut self, builder: &mut FunctionModifier, block_id: BlockID) {
+        for id in self.release_on_end.remove(&block_id).unwrap_or_default() {
+            builder.release_scratch_local(id);
+        }
+    }
+}
+
+/// Take an `i32` scratch local that is not one of the locals in `in_use`
+fn take_scratch_local(builder: &mut FunctionModifier, in_use: &HashSet<u32>) -> LocalID {
+    let mut skipped = vec![];
+    let id = loop {
+        let id = builder.scratch_local(DataType::I32);
+        if !in_use.contains(&*id) {
+            break id;
+        }
+        skipped.push(id);
+    };
+    for id in skipped {
+        builder.release_scratch_local(id);
+    }
+    id
+}
+
+/// The locals referenced by `instructions` and their instrumentation
+fn locals_used(instructions: &[Instruction]) -> HashSet<u32> {
+    instructions
+        .iter()
+        .flat_map(|instr| std::iter::once(&instr.op).chain(instr.instr_flag.ops()))
+        .filter_map(local_index)
+        .collect()
+}
+
+/// The index of the instruction opening the block `relative_depth` levels out of the
+/// instruction at `idx`, `None` for the function body
+fn enclosing_block_start(
+    instructions: &[Instruction],
+    idx: usize,
+    mut relative_depth: u32,
+) -> Option<usize> {
+    // the blocks closed between the opener and `idx`
+    let mut closed = 0;
+    for (start, instr) in instructions[..idx].iter().enumerate().rev() {
+        if matches!(instr.op, Operator::End | Operator::Delegate { .. }) {
+            closed += 1;
+        } else if opens_block(&instr.op) {
+            if closed > 0 {
+                closed -= 1;
+            } else if relative_depth == 0 {
+                return Some(start);
+            } else {
+                relative_depth -= 1;
+            }
+        }
+    }
+    None
+}
+
+/// The index of the end of the block opened at `start`, the end of the function for `None`
+fn block_end(instructions: &[Instruction], start: Option<usize>) -> usize {
+    let Some(start) = start else {
+        return instructions.len() - 1;
+    };
+    let mut depth = 0;
+    for (idx, instr) in instructions.iter().enumerate().skip(start + 1) {
+        if opens_block(&instr.op) {
+            depth += 1;
+        } else if matches!(instr.op, Operator::End | Operator::Delegate { .. }) {
+            if depth == 0 {
+                return idx;
+            }
+            depth -= 1;
+        }
+    }
+    instructions.len() - 1
+}
+
+/// Which code opened a block of an encoded function body
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Frame {
+    /// Opened by the original instruction at this index
+    Original(usize),
+    /// Opened by injected code
+    Injected,
+}
+
+/// Finds the original branches whose relative depths are changed by the blocks opened by
+/// the injected code, returns their new depths (in the order of `branch_depths`) by
+/// instruction index. Branches of the injected code are left as they are.
+///
+/// Fails if the injected code leaves its blocks open or closes the original ones.
+fn fix_branch_depths(
+    func_id: FunctionID,
+    instructions: &[Instruction],
+) -> Result<HashMap<usize, Vec<u32>>, Error> {
+    let mut fixed = HashMap::new();
+    // the blocks of the encoded body, and the original blocks by the index of their opener
+    let mut frames: Vec<Frame> = vec![];
+    let mut original: Vec<usize> = vec![];
+    let last = instructions.len() - 1;
+    for (idx, Instruction { op, instr_flag }) in instructions.iter().enumerate() {
+        let at_end = idx == last;
+        // the original block closed by this instruction, `None` for the function body
+        let closes = if matches!(op, Operator::End | Operator::Delegate { .. }) {
+            original.pop()
+        } else {
+            None
+        };
+        let enclosing = original.last().copied();
+        if opens_block(op) {
+            original.push(idx);
+        }
+
+        for injected in instr_flag.before.iter() {
+            apply_frame(func_id, idx, injected, Frame::Injected, None, &mut frames)?;
+        }
+        match &instr_flag.alternate {
+            Some(alternate) if !at_end => {
+                // the alternate stands in for the instruction, it may close the block the
+                // instruction closes and the block it leaves open replaces the one it opens
+                let depth = frames.len();
+                for injected in alternate.iter() {
+                    apply_frame(func_id, idx, injected, Frame::Injected, closes, &mut frames)?;
+                }
+                if opens_block(op) && frames.len() == depth + 1 {
+                    *frames.last_mut().unwrap() = Frame::Original(idx);
+                }
+            }
+            _ => {
+                if let Operator::Else | Operator::Catch { .. } | Operator::CatchAll = op {
+                    if frames.last() != enclosing.map(Frame::Original).as_ref() {
+                        return Err(Error::UnbalancedInstrumentation {
+                            func_idx: func_id,
+                            instr_idx: idx,
+                            reason: "a block opened by injected code is still open",
+                        });
+                    }
+                }
+                if matches!(op, Operator::Delegate { .. }) {
+                    // the label of a delegate is outside of the block it closes
+                    apply_frame(func_id, idx, op, Frame::Original(idx), closes, &mut frames)?;
+                }
+                let depths = branch_depths(op);
+                if !depths.is_empty() {
+                    let new_depths: Vec<u32> = depths
+                        .iter()
+                        .map(|depth| fix_depth(*depth, &frames))
+                        .collect();
+                    if new_depths != depths {
+                        fixed.insert(idx, new_depths);
+                    }
+                }
+                if !matches!(op, Operator::Delegate { .. }) {
+                    apply_frame(func_id, idx, op, Frame::Original(idx), closes, &mut frames)?;
+                }
+            }
+        }
+        if !at_end {
+            for injected in instr_flag.after.iter() {
+                apply_frame(func_id, idx, injected, Frame::Injected, None, &mut frames)?;
+            }
+        }
+    }
+    Ok(fixed)
+}
+
+fn opens_block(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Try { .. }
+            | Operator::TryTable { .. }
+    )
+}
+
+/// Updates the blocks of the encoded body with an instruction opened by `opener`.
+/// `closes` is the original block that may be closed by it, `None` for the function body.
+fn apply_frame(
+    func_id: FunctionID,
+    idx: usize,
+    op: &Operator,
+    opener: Frame,
+    closes: Option<usize>,
+    frames: &mut Vec<Frame>,
+) -> Result<(), Error> {
+    if opens_block(op) {
+        frames.push(opener);
+    } else if matches!(op, Operator::End | Operator::Delegate { .. }) {
+        let balanced = match (opener, frames.last()) {
+            // the end of the function body, all the blocks must be closed
+            (Frame::Original(_), None) => closes.is_none(),
+            (Frame::Original(_), Some(frame)) => closes.map(Frame::Original) == Some(*frame),
+            (Frame::Injected, Some(Frame::Injected)) => true,
+            // an alternate standing in for the end of an original block
+            (Frame::Injected, Some(frame)) => closes.map(Frame::Original) == Some(*frame),
+            (Frame::Injected, None) => false,
+        };
+        if !balanced {
+            return Err(Error::UnbalancedInstrumentation {
+                func_idx: func_id,
+                instr_idx: idx,
+                reason: match opener {
+                    Frame::Original(_) => "a block opened by injected code is still open",
+                    Frame::Injected => "injected code closes a block it did not open",
+                },
+            });
+        }
+        frames.pop();
+    }
+    Ok(())
+}
+
+/// The new relative depth of an original label, counting the blocks of the injected code
+fn fix_depth(depth: u32, frames: &[Frame]) -> u32 {
+    let mut originals = 0;
+    for (new_depth, frame) in frames.iter().rev().enumerate() {
+        if let Frame::Original(_) = frame {
+            if originals == depth {
+                return new_depth as u32;
+            }
+            originals += 1;
+        }
+    }
+    if originals == depth {
+        // the label of the function body
+        frames.len() as u32
+    } else {
+        // invalid label, leave it to the validator
+        depth
+    }
+}
+
+/// The relative depths of the labels an instruction refers to
+fn branch_depths(op: &Operator) -> Vec<u32> {
+    match op {
+        Operator::Br { relative_depth }
+        | Operator::BrIf { relative_depth }
+        | Operator::BrOnNull { relative_depth }
+        | Operator::BrOnNonNull { relative_depth }
+        | Operator::BrOnCast { relative_depth, .. }
+        | Operator::BrOnCastFail { relative_depth, .. }
+        | Operator::Delegate { relative_depth }
+        | Operator::Rethrow { relative_depth } => vec![*relative_depth],
+        Operator::BrTable { targets } => targets
+            .targets()
+            .chain([Ok(targets.default())])
+            .collect::<Result<_, _>>()
+            .expect("Unable to read br_table targets"),
+        Operator::TryTable { try_table } => try_table
+            .catches
+            .iter()
+            .map(|catch| match catch {
+                wasmparser::Catch::One { label, .. }
+                | wasmparser::Catch::OneRef { label, .. }
+                | wasmparser::Catch::All { label }
+                | wasmparser::Catch::AllRef { label } => *label,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Sets the relative depths of an encoded instruction, in the order of `branch_depths`
+fn set_branch_depths(instr: &mut wasm_encoder::Instruction, depths: &[u32]) {
+    use wasm_encoder::Instruction as I;
+    match instr {
+        I::Br(depth)
+        | I::BrIf(depth)
+        | I::BrOnNull(depth)
+        | I::BrOnNonNull(depth)
+        | I::BrOnCast {
+            relative_depth: depth,
+            ..
+        }
+        | I::BrOnCastFail {
+            relative_depth: depth,
+            ..
+        }
+        | I::Delegate(depth)
+        | I::Rethrow(depth) => *depth = depths[0],
+        I::BrTable(targets, default) => {
+            let (last, rest) = depths.split_last().unwrap();
+            *targets = rest.to_vec().into();
+            *default = *last;
+        }
+        I::TryTable(_, catches) => {
+            *catches = catches
+                .iter()
+                .zip(depths)
+                .map(|(catch, label)| match *catch {
+                    wasm_encoder::Catch::One { tag, .. } => {
+                        wasm_encoder::Catch::One { tag, label: *label }
+                    }
+                    wasm_encoder::Catch::OneRef { tag, .. } => {
+                        wasm_encoder::Catch::OneRef { tag, label: *label }
+                    }
+                    wasm_encoder::Catch::All { .. } => wasm_encoder::Catch::All { label: *label },
+                    wasm_encoder::Catch::AllRef { .. } => {
+                        wasm_encoder::Catch::AllRef { label: *label }
+                    }
+                })
+                .collect::<Vec<_>>()
+                .into();
+        }
+        _ => {}
+    }
+}
+
+/// A function body ready to be put in the code section
+enum EncodedBody<'a> {
+    /// The body is copied from the parsed binary
+    Original(&'a [u8]),
+    Encoded(wasm_encoder::Function),
+}
+
+/// Encodes the body of a local function, updating the IDs it refers to with the mappings.
+fn encode_func_body(
+    func: &mut LocalFunction,
+    func_mapping: &HashMap<u32, u32>,
+    global_mapping: &HashMap<u32, u32>,
+    tag_mapping: &HashMap<u32, u32>,
+    ranges: &mut Option<Vec<EncodedRange>>,
+) -> Result<wasm_encoder::Function, Error> {
+    let mut reencode = RoundtripReencoder;
+    let func_id = func.func_id;
+    func.body.trim_scratch_locals(func.args.len());
+    let Body {
+        instructions,
+        locals,
+        ..
+    } = &mut func.body;
+    // blocks opened by the injected code shift the labels of the original branches
+    let fixed_depths = if instructions
+        .iter()
+        .any(|instr| instr.instr_flag.has_instr())
+    {
+        fix_branch_depths(func_id, instructions)?
+    } else {
+        HashMap::new()
+    };
+    let mut converted_locals = Vec::with_capacity(locals.len());
+    for (c, ty) in locals {
+        converted_locals.push((*c, wasm_encoder::ValType::from(&*ty)));
+    }
+    let mut function = wasm_encoder::Function::new(converted_locals);
+    let instr_len = instructions.len() - 1;
+    for (
+        idx,
+        Instruction {
+            op,
+            instr_flag: instrument,
+        },
+    ) in instructions.iter_mut().enumerate()
+    {
+        if refers_to_func(op) {
+            update_fn_instr(op, func_mapping);
+        }
+        if refers_to_global(op) {
+            update_global_instr(op, global_mapping);
+        }
+        if refers_to_tag(op) {
+            update_tag_instr(op, tag_mapping)?;
+        }
+        if !instrument.has_instr() {
+            let start = function.byte_len();
+            encode(op, fixed_depths.get(&idx), &mut function, &mut reencode);
+            record_range(ranges, idx, None, start..function.byte_len());
+        } else {
+            // this instruction has instrumentation, handle it!
+            let InstrumentationFlag {
+                current_mode: _current_mode,
+                before,
+                after,
+                alternate,
+                semantic_after,
+                block_entry,
+                block_exit,
+                block_alt,
+                branch_taken,
+                branch_not_taken,
+                br_table_targets,
+                br_table_default,
+                loop_iteration,
+                loop_back_edge,
+            } = instrument;
+
+            // Check if special instrumentation modes have been resolved!
+            if !semantic_after.is_empty() {
+                error!("BUG: Semantic after instrumentation should be resolved already, please report.");
+            }
+            if !block_entry.is_empty() {
+                error!(
+                    "BUG: Block entry instrumentation should be resolved already, please report."
+                );
+            }
+            if !block_exit.is_empty() {
+                error!(
+                    "BUG: Block exit instrumentation should be resolved already, please report."
+                );
+            }
+            if !block_alt.is_none() {
+                error!("BUG: Block alt instrumentation should be resolved already, please report.");
+            }
+            if !branch_taken.is_empty() || !branch_not_taken.is_empty() {
+                error!("BUG: Branch outcome instrumentation should be resolved already, please report.");
+            }
+            if !br_table_targets.is_empty() || !br_table_default.is_empty() {
+                error!("BUG: Br table target instrumentation should be resolved already, please report.");
+            }
+            if !loop_iteration.is_empty() || !loop_back_edge.is_empty() {
+                error!("BUG: Loop instrumentation should be resolved already, please report.");
+            }
+            // If we're at the `end` of the function, drop this instrumentation
+            let at_end = idx >= instr_len;
+
+            // First encode before instructions
+            let start = function.byte_len();
+            update_ids_and_encode(
+                before,
+                func_mapping,
+                global_mapping,
+                tag_mapping,
+                &mut function,
+                &mut reencode,
+            )?;
+            record_range(ranges, idx, Some(Before), start..function.byte_len());
+
+            // If there are any alternate, encode the alternate
+            let start = function.byte_len();
+            if !at_end && !alternate.is_none() {
+                if let Some(alt) = alternate {
+                    update_ids_and_encode(
+                        alt,
+                        func_mapping,
+                        global_mapping,
+                        tag_mapping,
+                        &mut function,
+                        &mut reencode,
+                    )?;
+                }
+                record_range(ranges, idx, Some(Alternate), start..function.byte_len());
+            } else {
+                encode(op, fixed_depths.get(&idx), &mut function, &mut reencode);
+                record_range(ranges, idx, None, start..function.byte_len());
+            }
+
+            // Now encode the after instructions
+            if !at_end {
+                let start = function.byte_len();
+                update_ids_and_encode(
+                    after,
+                    func_mapping,
+                    global_mapping,
+                    tag_mapping,
+                    &mut function,
+                    &mut reencode,
+                )?;
+                record_range(ranges, idx, Some(After), start..function.byte_len());
+            }
+        }
+
+        fn update_ids_and_encode(
+            instrs: &mut Vec<Operator>,
+            func_mapping: &HashMap<u32, u32>,
+            global_mapping: &HashMap<u32, u32>,
+            tag_mapping: &HashMap<u32, u32>,
+            function: &mut wasm_encoder::Function,
+            reencode: &mut RoundtripReencoder,
+        ) -> Result<(), Error> {
+            for instr in instrs {
+                if refers_to_func(instr) {
+                    update_fn_instr(instr, func_mapping);
+                }
+                if refers_to_global(instr) {
+                    update_global_instr(instr, global_mapping);
+                }
+                if refers_to_tag(instr) {
+                    update_tag_instr(instr, tag_mapping)?;
+                }
+                encode(instr, None, function, reencode);
+            }
+            Ok(())
+        }
+        fn encode(
+            instr: &Operator,
+            depths: Option<&Vec<u32>>,
+            function: &mut wasm_encoder::Function,
+            reencode: &mut RoundtripReencoder,
+        ) {
+            let mut instr = reencode
+                .instruction(instr.clone())
+                .expect("Unable to convert Instruction");
+            if let Some(depths) = depths {
+                set_branch_depths(&mut instr, depths);
+            }
+            function.instruction(&instr);
+        }
+    }
+    Ok(function)
+}
+
+/// Records the range taken by the instruction at `instr_idx` (`mode` is `None`) or by its instrumentation,
+/// if the ranges are asked for. Empty instrumentation is left out.
+fn record_range(
+    ranges: &mut Option<Vec<EncodedRange>>,
+    instr_idx: usize,
+    mode: Option<InstrumentationMode>,
+    range: Range<usize>,
+) {
+    if let Some(ranges) = ranges {
+        if mode.is_none() || !range.is_empty() {
+            ranges.push(EncodedRange {
+                instr_idx,
+                mode,
+                range,
+            });
+        }
+    }
+}
+
+/// Runs `f` on every item, on multiple threads if `parallel`. The results are in the order of the items.
+fn for_each_func<T: Send, R: Send>(
+    items: &mut [T],
+    parallel: bool,
+    f: impl Fn(&mut T) -> R + Sync,
+) -> Vec<R> {
+    let num_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if !parallel || num_threads <= 1 || items.len() <= 1 {
+        return items.iter_mut().map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(num_threads);
+    let f = &f;
+    std::thread::scope(|s| {
+        let handles: Vec<_> = items
+            .chunks_mut(chunk_size)
+            .map(|chunk| s.spawn(move || chunk.iter_mut().map(f).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            // re-raise the panic of a worker with its original payload
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+            })
+            .collect()
+    })
+}
+
 type InstrBody<'a> = Vec<Operator<'a>>;
 struct InstrBodyFlagged<'a> {
     body: InstrBody<'a>,
@@ -1614,22 +2769,106 @@ fn resolve_function_entry<'a, 'b, 'c>(
 
 fn resolve_function_exit<'a, 'b, 'c>(
     builder: &mut FunctionModifier<'a, 'b>,
-    instr_func_on_entry: &mut InstrBody<'c>,
+    instr_func_on_exit: &mut InstrBody<'c>,
+    exit_on_throw: Option<BlockType>,
+    tail_call_exit: TailCallExit,
+    func_id: FunctionID,
+    op: &Operator<'c>,
     idx: usize,
-) where
+) -> Result<(), Error>
+where
     'c: 'b,
 {
+    // the frame is also left on `return` and on tail calls
+    let loc = Location::Module {
+        func_idx: FunctionID(0), // not used
+        instr_idx: idx,
+    };
+    match op {
+        Operator::Return => {
+            builder.before_at(loc);
+            builder.inject_all(instr_func_on_exit);
+        }
+        Operator::ReturnCall { .. }
+        | Operator::ReturnCallIndirect { .. }
+        | Operator::ReturnCallRef { .. } => match tail_call_exit {
+            TailCallExit::BeforeCall => {
+                builder.before_at(loc);
+                builder.inject_all(instr_func_on_exit);
+            }
+            TailCallExit::AfterCallee => {
+                if builder.body.instructions[idx]
+                    .instr_flag
+                    .alternate
+                    .is_some()
+                {
+                    // the tail call is rewritten through its alternate
+                    return Err(Error::InstrumentationConflict {
+                        func_idx: func_id,
+                        instr_idx: idx,
+                        reason: "the tail call already has an alternate, it cannot be turned into a call followed by the function exit",
+                    });
+                }
+                builder.alternate_at(loc);
+                builder.inject(match *op {
+                    Operator::ReturnCall { function_index } => Operator::Call { function_index },
+                    Operator::ReturnCallIndirect {
+                        type_index,
+                        table_index,
+                    } => Operator::CallIndirect {
+                        type_index,
+                        table_index,
+                    },
+                    Operator::ReturnCallRef { type_index } => Operator::CallRef { type_index },
+                    _ => unreachable!(),
+                });
+                builder.inject_all(instr_func_on_exit);
+                builder.return_stmt();
+            }
+        },
+        _ => {}
+    }
+
+    if let (Some(results), 0) = (exit_on_throw, idx) {
+        // we're at the function entry, open the blocks that catch any thrown exception:
+        // block (result exnref)
+        //   try_table (result <func results>) (catch_all_ref 0)
+        builder.before_at(Location::Module {
+            func_idx: FunctionID(0), // not used
+            instr_idx: idx,
+        });
+        builder.inject(Operator::Block {
+            blockty: wasmparser::BlockType::Type(wasmparser::ValType::EXNREF),
+        });
+        builder.try_table(results, vec![Catch::AllRef { label: 0 }]);
+    }
     if idx == builder.body.instructions.len() - 1 {
         // we're at the end of the function!
         builder.before_at(Location::Module {
             func_idx: FunctionID(0), // not used
             instr_idx: idx,
         });
-        builder.inject_all(instr_func_on_entry);
+        if exit_on_throw.is_some() {
+            //   end  ;; try_table, normal exit
+            //   <exit>
+            //   return
+            // end  ;; block, the exception is on the stack
+            // <exit>
+            // throw_ref
+            builder.end();
+            builder.inject_all(instr_func_on_exit);
+            builder.return_stmt();
+            builder.end();
+            builder.inject_all(instr_func_on_exit);
+            builder.throw_ref();
+        } else {
+            builder.inject_all(instr_func_on_exit);
+        }
 
         // remove the contents of the body now that it's been resolved
-        instr_func_on_entry.clear();
+        instr_func_on_exit.clear();
     }
+    Ok(())
 }
 
 fn resolve_block_entry<'a, 'b, 'c>(
@@ -1645,7 +2884,8 @@ fn resolve_block_entry<'a, 'b, 'c>(
         Operator::Block { .. }
         | Operator::Loop { .. }
         | Operator::If { .. }
-        | Operator::Else { .. } => {
+        | Operator::Else { .. }
+        | Operator::TryTable { .. } => {
             // just inject immediately after the start of the block
             builder.after_at(Location::Module {
                 func_idx: FunctionID(0), // not used
@@ -1681,7 +2921,10 @@ fn plan_resolution_block_exit<'a, 'b, 'c>(
                 block_exit,
             );
         }
-        Operator::Block { .. } | Operator::Loop { .. } | Operator::Else { .. } => {
+        Operator::Block { .. }
+        | Operator::Loop { .. }
+        | Operator::Else { .. }
+        | Operator::TryTable { .. } => {
             // add body-to-inject as non-flagged
             let block_id = block_stack.last().unwrap(); // should always have something (e.g. func block)
             save_not_flagged_body_to_resolve(
@@ -1711,7 +2954,8 @@ where
         Operator::Block { .. }
         | Operator::Loop { .. }
         | Operator::If { .. }
-        | Operator::Else { .. } => {
+        | Operator::Else { .. }
+        | Operator::TryTable { .. } => {
             let loc = Location::Module {
                 func_idx: FunctionID(0), // not used
                 instr_idx: idx,
@@ -1743,6 +2987,7 @@ where
 fn plan_resolution_semantic_after<'a, 'b, 'c>(
     semantic_after: &InstrBody<'c>,
     builder: &mut FunctionModifier<'a, 'b>,
+    locals: &mut ResolutionLocals,
     block_stack: &[BlockID],
     resolve_on_end: &mut HashMap<BlockID, HashMap<InstrumentationMode, InstrToInject<'c>>>,
     op: &Operator,
@@ -1755,7 +3000,8 @@ fn plan_resolution_semantic_after<'a, 'b, 'c>(
         Operator::Block { .. }
         | Operator::Loop { .. }
         | Operator::If { .. }
-        | Operator::Else { .. } => {
+        | Operator::Else { .. }
+        | Operator::TryTable { .. } => {
             // add body-to-inject as non-flagged
             let block_id = block_stack.last().unwrap(); // should always have something (e.g. func block)
             save_not_flagged_body_to_resolve(
@@ -1766,7 +3012,22 @@ fn plan_resolution_semantic_after<'a, 'b, 'c>(
             );
         }
         Operator::BrTable { targets } => {
-            let bool_flag_id = create_bool_flag(builder, idx, op, semantic_after);
+            // the flag is live up to the end of the outermost target
+            let max_depth = targets
+                .targets()
+                .filter_map(Result::ok)
+                .chain([targets.default()])
+                .max()
+                .unwrap();
+            let bool_flag_id = create_bool_flag(
+                builder,
+                locals,
+                block_stack,
+                idx,
+                op,
+                semantic_after,
+                max_depth,
+            );
             targets.targets().for_each(|target| {
                 if let Ok(relative_depth) = target {
                     save_flagged_body_to_resolve(
@@ -1795,7 +3056,15 @@ fn plan_resolution_semantic_after<'a, 'b, 'c>(
         | Operator::BrOnCastFail { relative_depth, .. }
         | Operator::BrOnNonNull { relative_depth }
         | Operator::BrOnNull { relative_depth } => {
-            let bool_flag_id = create_bool_flag(builder, idx, op, semantic_after);
+            let bool_flag_id = create_bool_flag(
+                builder,
+                locals,
+                block_stack,
+                idx,
+                op,
+                semantic_after,
+                *relative_depth,
+            );
             save_flagged_body_to_resolve(
                 resolve_on_end,
                 InstrumentationMode::After,
@@ -1809,22 +3078,202 @@ fn plan_resolution_semantic_after<'a, 'b, 'c>(
     }
 }
 
+fn resolve_branch_outcome<'a, 'b, 'c>(
+    branch_taken: &InstrBody<'c>,
+    branch_not_taken: &InstrBody<'c>,
+    builder: &mut FunctionModifier<'a, 'b>,
+    locals: &ResolutionLocals,
+    op: &Operator,
+    idx: usize,
+) where
+    'c: 'b,
+{
+    let loc = Location::Module {
+        func_idx: FunctionID(0), // not used
+        instr_idx: idx,
+    };
+    // the body to inject at the start of its path, and the one that needs the condition to be tested
+    let (on_path, on_condition, negate) = match op {
+        // the fallthrough of a `br_if` is the not taken path
+        Operator::BrIf { .. } => (branch_not_taken, branch_taken, false),
+        // the start of the `then` body is the taken path
+        Operator::If { .. } => (branch_taken, branch_not_taken, true),
+        _ => return, // only applicable to conditional ops
+    };
+    if !on_path.is_empty() {
+        builder.after_at(loc.clone()).inject_all(on_path);
+    }
+    if !on_condition.is_empty() {
+        // save the condition to test it without evaluating it again
+        let cond = locals.take(builder);
+        builder.before_at(loc).local_tee(cond);
+        if negate {
+            builder.i32_eqz();
+        }
+        builder
+            .if_stmt(BlockType::Empty)
+            .inject_all(on_condition)
+            .end()
+            .local_get(cond);
+        builder.release_scratch_local(cond);
+    }
+}
+
+fn resolve_br_table_targets<'a, 'b, 'c>(
+    br_table_targets: &[(u32, InstrBody<'c>)],
+    br_table_default: &InstrBody<'c>,
+    builder: &mut FunctionModifier<'a, 'b>,
+    locals: &ResolutionLocals,
+    op: &Operator,
+    idx: usize,
+) where
+    'c: 'b,
+{
+    let Operator::BrTable { targets } = op else {
+        return; // only applicable to br_table
+    };
+    // save the selector to test it without evaluating it again
+    let selector = locals.take(builder);
+    builder
+        .before_at(Location::Module {
+            func_idx: FunctionID(0), // not used
+            instr_idx: idx,
+        })
+        .local_tee(selector);
+    for (target, body) in br_table_targets {
+        if *target >= targets.len() {
+            warn!(
+                "br_table target {} is out of bounds, it has {} targets",
+                target,
+                targets.len()
+            );
+            continue;
+        }
+        builder
+            .local_get(selector)
+            .u32_const(*target)
+            .i32_eq()
+            .if_stmt(BlockType::Empty)
+            .inject_all(body)
+            .end();
+    }
+    if !br_table_default.is_empty() {
+        // any selector out of the bounds of the target list selects the default
+        builder
+            .local_get(selector)
+            .u32_const(targets.len())
+            .i32_gte_unsigned()
+            .if_stmt(BlockType::Empty)
+            .inject_all(br_table_default)
+            .end();
+    }
+    // the selector is still on the stack for the br_table
+    builder.release_scratch_local(selector);
+}
+
+/// Injects the loop iteration instrumentation at the header of the `loop` at `idx`, returns
+/// the code to inject on every back-edge of the loop.
+fn plan_resolution_loop<'a, 'b, 'c>(
+    loop_iteration: &InstrBody<'c>,
+    loop_back_edge: &InstrBody<'c>,
+    builder: &mut FunctionModifier<'a, 'b>,
+    locals: &mut ResolutionLocals,
+    block_id: BlockID,
+    idx: usize,
+) -> InstrBody<'c>
+where
+    'c: 'b,
+{
+    let mut on_back_edge = loop_back_edge.clone();
+    if !loop_iteration.is_empty() {
+        // the back-edges raise the flag, the header only fires if it is raised
+        // so that the first entry into the loop is not counted
+        let flag = locals.take_for_block(builder, block_id, Some(idx));
+        builder
+            .after_at(Location::Module {
+                func_idx: FunctionID(0), // not used
+                instr_idx: idx,
+            })
+            .local_get(flag)
+            .if_stmt(BlockType::Empty)
+            .inject_all(loop_iteration)
+            .i32_const(0)
+            .local_set(flag)
+            .end();
+        on_back_edge.push(Operator::I32Const { value: 1 });
+        on_back_edge.push(Operator::LocalSet { local_index: *flag });
+    }
+    on_back_edge
+}
+
+/// Injects the code to run on the branches to a block at the branch at `idx` if it targets
+/// one of the blocks in `on_branch_to`. Only fires when the branch is taken.
+fn resolve_branches_to<'a, 'b, 'c>(
+    on_branch_to: &HashMap<BlockID, InstrBody<'c>>,
+    block_stack: &[BlockID],
+    builder: &mut FunctionModifier<'a, 'b>,
+    locals: &ResolutionLocals,
+    op: &Operator,
+    idx: usize,
+) where
+    'c: 'b,
+{
+    let curr_block = *block_stack.last().unwrap();
+    let on_branch = |relative_depth: u32| {
+        curr_block
+            .checked_sub(relative_depth)
+            .and_then(|block_id| on_branch_to.get(&block_id))
+    };
+    match op {
+        Operator::Br { relative_depth } => {
+            if let Some(body) = on_branch(*relative_depth) {
+                builder
+                    .before_at(Location::Module {
+                        func_idx: FunctionID(0), // not used
+                        instr_idx: idx,
+                    })
+                    .inject_all(body);
+            }
+        }
+        Operator::BrIf { relative_depth } => {
+            if let Some(body) = on_branch(*relative_depth) {
+                resolve_branch_outcome(body, &vec![], builder, locals, op, idx);
+            }
+        }
+        Operator::BrTable { targets } => {
+            let on_targets: Vec<(u32, InstrBody)> = targets
+                .targets()
+                .enumerate()
+                .filter_map(|(target, depth)| {
+                    let body = on_branch(depth.expect("Unable to read br_table target"))?;
+                    Some((target as u32, body.clone()))
+                })
+                .collect();
+            let on_default = on_branch(targets.default()).cloned().unwrap_or_default();
+            if !on_targets.is_empty() || !on_default.is_empty() {
+                resolve_br_table_targets(&on_targets, &on_default, builder, locals, op, idx);
+            }
+        }
+        _ => {}
+    }
+}
+
 fn create_bool_flag<'a, 'b, 'c>(
     builder: &mut FunctionModifier<'a, 'b>,
+    locals: &mut ResolutionLocals,
+    block_stack: &[BlockID],
     idx: usize,
     op: &Operator,
     semantic_after: &Vec<Operator<'c>>,
+    relative_depth: u32,
 ) -> LocalID
 where
     'c: 'b,
 {
-    // add body-to-inject as flagged
-    let bool_flag_id = add_local(
-        DataType::I32,
-        builder.args.len(),
-        &mut builder.body.num_locals,
-        &mut builder.body.locals,
-    );
+    // add body-to-inject as flagged, the flag is live within the target block
+    let start = enclosing_block_start(&builder.body.instructions, idx, relative_depth);
+    let block_id = *block_stack.last().unwrap() - relative_depth;
+    let bool_flag_id = locals.take_for_block(builder, block_id, start);
 
     // set flag to true before the opcode
     builder
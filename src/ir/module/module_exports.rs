@@ -1,6 +1,6 @@
 //! Intermediate Representation of a Module's Exports
 
-use crate::ir::id::{ExportsID, FunctionID};
+use crate::ir::id::{ExportsID, FunctionID, TagID};
 use wasmparser::ExternalKind;
 
 #[derive(Debug, Clone)]
@@ -59,6 +59,17 @@ impl ModuleExports {
         self.exports.push(export);
     }
 
+    /// Add an exported tag
+    pub fn add_export_tag(&mut self, name: String, exp_id: u32) {
+        let export = Export {
+            name,
+            kind: ExternalKind::Tag,
+            index: exp_id,
+            deleted: false,
+        };
+        self.exports.push(export);
+    }
+
     /// Get export by name and return if present
     pub fn get_by_name(&self, name: String) -> Option<Export> {
         for exp in self.exports.iter() {
@@ -108,6 +119,16 @@ impl ModuleExports {
         None
     }
 
+    /// Get the tag ID of an exported tag
+    pub fn get_tag_by_name(&self, name: String) -> Option<TagID> {
+        for exp in self.exports.iter() {
+            if matches!(exp.kind, ExternalKind::Tag) && exp.name == name {
+                return Some(TagID(exp.index));
+            }
+        }
+        None
+    }
+
     /// Delete an export by its exports ID
     pub fn delete(&mut self, id: ExportsID) {
         // Must just mark for deletion as or else will result in indicies getting messed up
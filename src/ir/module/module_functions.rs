@@ -164,6 +164,34 @@ impl<'a> LocalFunction<'a> {
         )
     }
 
+    /// Take a scratch local, see [`AddLocal::scratch_local`]
+    ///
+    /// [`AddLocal::scratch_local`]: crate::module_builder::AddLocal::scratch_local
+    pub fn scratch_local(&mut self, ty: DataType) -> LocalID {
+        self.body.scratch_local(ty, self.args.len())
+    }
+
+    /// Release a scratch local, see [`AddLocal::release_scratch_local`]
+    ///
+    /// [`AddLocal::release_scratch_local`]: crate::module_builder::AddLocal::release_scratch_local
+    pub fn release_scratch_local(&mut self, id: LocalID) {
+        self.body.release_scratch_local(id)
+    }
+
+    /// Open a scratch scope, see [`AddLocal::enter_scratch_scope`]
+    ///
+    /// [`AddLocal::enter_scratch_scope`]: crate::module_builder::AddLocal::enter_scratch_scope
+    pub fn enter_scratch_scope(&mut self) {
+        self.body.enter_scratch_scope()
+    }
+
+    /// Exit a scratch scope, see [`AddLocal::exit_scratch_scope`]
+    ///
+    /// [`AddLocal::exit_scratch_scope`]: crate::module_builder::AddLocal::exit_scratch_scope
+    pub fn exit_scratch_scope(&mut self) {
+        self.body.exit_scratch_scope()
+    }
+
     pub fn add_instr(&mut self, instr: Operator<'a>, instr_idx: usize) {
         if self.instr_flag.current_mode.is_some() {
             // inject at function level
@@ -265,6 +293,11 @@ impl<'a> ReIndexable<Function<'a>> for Functions<'a> {
 }
 
 impl<'a> Functions<'a> {
+    /// Get a mutable iterator for the functions
+    pub(crate) fn iter_mut(&mut self) -> std::slice::IterMut<'_, Function<'a>> {
+        self.functions.iter_mut()
+    }
+
     /// Create a new functions section
     pub fn new(functions: Vec<Function<'a>>) -> Self {
         Functions {
@@ -349,13 +382,10 @@ impl<'a> Functions<'a> {
     pub fn get_local_fid_by_name(&self, name: &str) -> Option<FunctionID> {
         for (idx, func) in self.functions.iter().enumerate() {
             if let FuncKind::Local(l) = &func.kind {
-                match &l.body.name {
-                    Some(n) => {
-                        if n == name {
-                            return Some(FunctionID(idx as u32));
-                        }
+                if let Some(n) = &l.body.name {
+                    if n == name {
+                        return Some(FunctionID(idx as u32));
                     }
-                    None => {}
                 }
             }
         }
@@ -372,10 +402,10 @@ impl<'a> Functions<'a> {
         func_id: FunctionID,
     ) -> Option<FunctionModifier<'b, 'a>> {
         // grab type and section and code section
-        return match &mut self.functions.get_mut(*func_id as usize)?.kind {
+        match &mut self.functions.get_mut(*func_id as usize)?.kind {
             FuncKind::Local(ref mut l) => Some(FunctionModifier::init(&mut l.body, &mut l.args)),
             _ => None,
-        };
+        }
     }
 
     /// Delete a function
@@ -0,0 +1,116 @@
+//! Intermediate representation of the Tags in a Module
+
+use crate::ir::id::{ImportsID, TagID};
+use crate::ir::module::module_imports::ModuleImports;
+use std::collections::HashMap;
+use wasmparser::{TagType, TypeRef};
+
+/// Represents a tag (exception-handling proposal) in a module.
+#[derive(Clone, Debug)]
+pub struct Tag {
+    /// The type of the tag.
+    pub ty: TagType,
+    /// The location of the tag in the imports section, `None` if the tag is locally defined.
+    pub import_id: Option<ImportsID>,
+}
+
+impl Tag {
+    /// Returns whether this tag is locally defined (not imported).
+    pub fn is_local(&self) -> bool {
+        self.import_id.is_none()
+    }
+
+    /// Returns whether this tag is imported.
+    pub fn is_import(&self) -> bool {
+        self.import_id.is_some()
+    }
+}
+
+/// The Tags of a module. Indexed by `TagID`, where each ID is the position of the tag
+/// at the time it was added. Imported tags are moved in front of the local ones on encode.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleTags {
+    tags: Vec<Tag>,
+}
+
+impl ModuleTags {
+    /// Create a new tag section from the imported tags in `imports` and the locally-defined `tags`
+    pub fn new(imports: &ModuleImports, tags: Vec<TagType>) -> Self {
+        let mut all = vec![];
+        for (idx, import) in imports.iter().enumerate() {
+            if let TypeRef::Tag(ty) = import.ty {
+                all.push(Tag {
+                    ty,
+                    import_id: Some(ImportsID(idx as u32)),
+                });
+            }
+        }
+        all.extend(tags.into_iter().map(|ty| Tag {
+            ty,
+            import_id: None,
+        }));
+        ModuleTags { tags: all }
+    }
+
+    /// Check if there are any tags
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    /// Get the number of tags (including imported ones)
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    /// Get the number of locally-defined tags
+    pub fn num_local(&self) -> u32 {
+        self.tags.iter().filter(|tag| tag.is_local()).count() as u32
+    }
+
+    /// Create an iterable over all the tags, in `TagID` order
+    pub fn iter(&self) -> std::slice::Iter<'_, Tag> {
+        self.tags.iter()
+    }
+
+    /// Get a tag by its ID
+    pub fn get(&self, tag_id: TagID) -> Option<&Tag> {
+        self.tags.get(*tag_id as usize)
+    }
+
+    /// Add a locally-defined tag
+    pub(crate) fn add_local(&mut self, ty: TagType) -> TagID {
+        let id = TagID(self.tags.len() as u32);
+        self.tags.push(Tag {
+            ty,
+            import_id: None,
+        });
+        id
+    }
+
+    /// Add an imported tag
+    pub(crate) fn add_import(&mut self, import_id: ImportsID, ty: TagType) -> TagID {
+        let id = TagID(self.tags.len() as u32);
+        self.tags.push(Tag {
+            ty,
+            import_id: Some(import_id),
+        });
+        id
+    }
+
+    /// Get the mapping of old ID -> new ID, imports are placed before the local tags
+    pub(crate) fn get_mapping(&self) -> HashMap<u32, u32> {
+        self.tags
+            .iter()
+            .enumerate()
+            .filter(|(_, tag)| tag.is_import())
+            .chain(
+                self.tags
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, tag)| tag.is_local()),
+            )
+            .enumerate()
+            .map(|(new_id, (old_id, _))| (old_id as u32, new_id as u32))
+            .collect()
+    }
+}
@@ -20,22 +20,213 @@ impl FuncType {
     }
 }
 
+/// Storage type of a struct field or an array element (GC proposal).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum StorageType {
+    /// Packed 8-bit integer
+    I8,
+    /// Packed 16-bit integer
+    I16,
+    /// Any value type
+    Val(DataType),
+}
+
+impl From<wasmparser::StorageType> for StorageType {
+    fn from(value: wasmparser::StorageType) -> Self {
+        match value {
+            wasmparser::StorageType::I8 => StorageType::I8,
+            wasmparser::StorageType::I16 => StorageType::I16,
+            wasmparser::StorageType::Val(val) => StorageType::Val(DataType::from(val)),
+        }
+    }
+}
+
+impl From<&StorageType> for wasm_encoder::StorageType {
+    fn from(ty: &StorageType) -> Self {
+        match ty {
+            StorageType::I8 => wasm_encoder::StorageType::I8,
+            StorageType::I16 => wasm_encoder::StorageType::I16,
+            StorageType::Val(val) => {
+                wasm_encoder::StorageType::Val(wasm_encoder::ValType::from(val))
+            }
+        }
+    }
+}
+
+/// A struct field or the element of an array (GC proposal).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct FieldType {
+    pub element_type: StorageType,
+    pub mutable: bool,
+}
+impl FieldType {
+    /// Create a new Field Type
+    pub fn new(element_type: StorageType, mutable: bool) -> Self {
+        Self {
+            element_type,
+            mutable,
+        }
+    }
+}
+
+impl From<wasmparser::FieldType> for FieldType {
+    fn from(value: wasmparser::FieldType) -> Self {
+        FieldType {
+            element_type: StorageType::from(value.element_type),
+            mutable: value.mutable,
+        }
+    }
+}
+
+impl From<&FieldType> for wasm_encoder::FieldType {
+    fn from(ty: &FieldType) -> Self {
+        wasm_encoder::FieldType {
+            element_type: wasm_encoder::StorageType::from(&ty.element_type),
+            mutable: ty.mutable,
+        }
+    }
+}
+
+/// The shape of a type in the type section.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum CompositeType {
+    Func(FuncType),
+    /// An array type (GC proposal)
+    Array(FieldType),
+    /// A struct type (GC proposal)
+    Struct(Box<[FieldType]>),
+}
+
+/// A type in the type section. Types that are not part of the GC proposal
+/// are final and have no supertype.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SubType {
+    pub is_final: bool,
+    pub supertype: Option<TypeID>,
+    /// Part of the shared-everything-threads proposal
+    pub shared: bool,
+    pub composite_type: CompositeType,
+}
+impl SubType {
+    /// Create a new final type without a supertype
+    pub fn new(composite_type: CompositeType) -> Self {
+        Self {
+            is_final: true,
+            supertype: None,
+            shared: false,
+            composite_type,
+        }
+    }
+
+    /// Get the function type, if this is one
+    pub fn as_func(&self) -> Option<&FuncType> {
+        match &self.composite_type {
+            CompositeType::Func(ty) => Some(ty),
+            _ => None,
+        }
+    }
+}
+
+impl From<wasmparser::SubType> for SubType {
+    fn from(value: wasmparser::SubType) -> Self {
+        let composite_type = match value.composite_type.inner {
+            wasmparser::CompositeInnerType::Func(func) => CompositeType::Func(FuncType::new(
+                func.params().iter().map(|x| DataType::from(*x)).collect(),
+                func.results().iter().map(|x| DataType::from(*x)).collect(),
+            )),
+            wasmparser::CompositeInnerType::Array(array) => {
+                CompositeType::Array(FieldType::from(array.0))
+            }
+            wasmparser::CompositeInnerType::Struct(st) => CompositeType::Struct(
+                st.fields
+                    .iter()
+                    .map(|field| FieldType::from(*field))
+                    .collect(),
+            ),
+        };
+        SubType {
+            is_final: value.is_final,
+            supertype: value
+                .supertype_idx
+                .map(|idx| TypeID(idx.as_module_index().expect("canonicalized supertype"))),
+            shared: value.composite_type.shared,
+            composite_type,
+        }
+    }
+}
+
+impl From<&SubType> for wasm_encoder::SubType {
+    fn from(ty: &SubType) -> Self {
+        let inner = match &ty.composite_type {
+            CompositeType::Func(func) => {
+                wasm_encoder::CompositeInnerType::Func(wasm_encoder::FuncType::new(
+                    func.params.iter().map(wasm_encoder::ValType::from),
+                    func.results.iter().map(wasm_encoder::ValType::from),
+                ))
+            }
+            CompositeType::Array(field) => wasm_encoder::CompositeInnerType::Array(
+                wasm_encoder::ArrayType(wasm_encoder::FieldType::from(field)),
+            ),
+            CompositeType::Struct(fields) => {
+                wasm_encoder::CompositeInnerType::Struct(wasm_encoder::StructType {
+                    fields: fields.iter().map(wasm_encoder::FieldType::from).collect(),
+                })
+            }
+        };
+        wasm_encoder::SubType {
+            is_final: ty.is_final,
+            supertype_idx: ty.supertype.map(|id| *id),
+            composite_type: wasm_encoder::CompositeType {
+                inner,
+                shared: ty.shared,
+            },
+        }
+    }
+}
+
+/// A recursion group (GC proposal), a run of consecutive types in the type section.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecGroup {
+    /// ID of the first type of the group
+    pub start: TypeID,
+    /// Number of types in the group
+    pub len: u32,
+    /// Whether the group is written as `(rec ...)`, a single type without it
+    /// is its own implicit group
+    pub explicit: bool,
+}
+impl RecGroup {
+    /// Check if a type is part of this group
+    pub fn contains(&self, id: TypeID) -> bool {
+        *self.start <= *id && *id < *self.start + self.len
+    }
+}
+
 /// The Module Types Section
 #[derive(Clone, Debug, Default)]
 pub struct ModuleTypes {
-    pub types: Vec<FuncType>,
-    /// This enables us to quickly do a lookup to determine if a type has already been added
-    pub types_map: HashMap<FuncType, TypeID>,
+    pub types: Vec<SubType>,
+    /// This enables us to quickly do a lookup to determine if a type has already been added.
+    /// Only holds types that are in their own implicit recursion group.
+    pub types_map: HashMap<SubType, TypeID>,
+    pub rec_groups: Vec<RecGroup>,
 }
 
 impl ModuleTypes {
     /// Create a new Module Types section
-    pub fn new(types: Vec<FuncType>) -> Self {
+    pub fn new(types: Vec<SubType>, rec_groups: Vec<RecGroup>) -> Self {
         let mut types_map = HashMap::default();
-        for (id, ty) in types.iter().enumerate() {
-            types_map.insert(ty.clone(), TypeID(id as u32));
+        for group in rec_groups.iter() {
+            if !group.explicit {
+                let ty = types[*group.start as usize].clone();
+                types_map.entry(ty).or_insert(group.start);
+            }
+        }
+        ModuleTypes {
+            types,
+            types_map,
+            rec_groups,
         }
-        ModuleTypes { types, types_map }
     }
 
     /// Check if there are any types in this module
@@ -45,19 +236,54 @@ impl ModuleTypes {
 
     /// Add a new type to the module, returns the index of the new type.
     pub fn add(&mut self, param: &[DataType], ret: &[DataType]) -> TypeID {
-        let index = self.types.len();
-        let ty = FuncType::new(
+        self.add_subtype(SubType::new(CompositeType::Func(FuncType::new(
             param.to_vec().into_boxed_slice(),
             ret.to_vec().into_boxed_slice(),
-        );
+        ))))
+    }
+
+    /// Add a new struct type to the module, returns the index of the new type.
+    pub fn add_struct(&mut self, fields: &[FieldType]) -> TypeID {
+        self.add_subtype(SubType::new(CompositeType::Struct(
+            fields.to_vec().into_boxed_slice(),
+        )))
+    }
+
+    /// Add a new array type to the module, returns the index of the new type.
+    pub fn add_array(&mut self, element: FieldType) -> TypeID {
+        self.add_subtype(SubType::new(CompositeType::Array(element)))
+    }
 
-        if !self.types_map.contains_key(&ty) {
-            self.types.push(ty.clone());
+    /// Add a new type in its own recursion group, returns the index of the new type.
+    /// If an identical type already exists, its index is returned instead.
+    pub fn add_subtype(&mut self, ty: SubType) -> TypeID {
+        if let Some(id) = self.types_map.get(&ty) {
+            return *id;
         }
-        *self
-            .types_map
-            .entry(ty.clone())
-            .or_insert(TypeID(index as u32))
+        let id = TypeID(self.types.len() as u32);
+        self.types.push(ty.clone());
+        self.rec_groups.push(RecGroup {
+            start: id,
+            len: 1,
+            explicit: false,
+        });
+        self.types_map.insert(ty, id);
+        id
+    }
+
+    /// Add a new explicit recursion group to the module, returns the indices of its types.
+    /// Types inside the group can refer to each other by these indices, the first type
+    /// of the group gets the index `len()`.
+    pub fn add_rec_group(&mut self, types: Vec<SubType>) -> Vec<TypeID> {
+        let start = self.types.len() as u32;
+        let len = types.len() as u32;
+        self.types.extend(types);
+        self.rec_groups.push(RecGroup {
+            start: TypeID(start),
+            len,
+            explicit: true,
+        });
+        (start..start + len).map(TypeID).collect()
     }
 
     /// Number of types in this module
@@ -66,12 +292,28 @@ impl ModuleTypes {
     }
 
     /// Create an iterable over the Type Section
-    pub fn iter(&self) -> std::slice::Iter<'_, FuncType> {
+    pub fn iter(&self) -> std::slice::Iter<'_, SubType> {
         self.types.iter()
     }
 
-    /// Get type from index of the type section
+    /// Create an iterable over the recursion groups of the Type Section
+    pub fn iter_rec_groups(&self) -> std::slice::Iter<'_, RecGroup> {
+        self.rec_groups.iter()
+    }
+
+    /// Get function type from index of the type section, returns `None` if the
+    /// index is out of bounds or the type is not a function type
     pub fn get(&self, index: TypeID) -> Option<&FuncType> {
+        self.get_subtype(index).and_then(|ty| ty.as_func())
+    }
+
+    /// Get type from index of the type section
+    pub fn get_subtype(&self, index: TypeID) -> Option<&SubType> {
         self.types.get(*index as usize)
     }
+
+    /// Get the recursion group a type belongs to
+    pub fn get_rec_group(&self, index: TypeID) -> Option<&RecGroup> {
+        self.rec_groups.iter().find(|group| group.contains(index))
+    }
 }
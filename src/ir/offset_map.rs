@@ -0,0 +1,268 @@
+//! Mapping of code offsets from a parsed binary to its instrumented encoding
+
+use crate::ir::id::{FunctionID, ModuleID};
+use crate::ir::types::{ComponentPath, InstrumentationMode, Location};
+use std::collections::HashMap;
+use std::ops::Range;
+use wasmparser::{Encoding, Parser, Payload};
+
+/// Where an instruction of the parsed binary ended up in the encoded binary
+#[derive(Debug, Clone)]
+pub struct InstrOffset {
+    pub location: Location,
+    /// Offset of the instruction in the parsed binary
+    pub original: usize,
+    /// Offset of the instruction in the encoded binary, `None` if it was removed or replaced
+    /// by `alternate` instrumentation
+    pub new: Option<usize>,
+}
+
+/// Instrumentation injected at an instruction, as found in the encoded binary
+#[derive(Debug, Clone)]
+pub struct InjectedRange {
+    /// The instruction the instrumentation was injected at
+    pub location: Location,
+    /// Special modes are resolved into `Before`, `After` and `Alternate` before encoding,
+    /// so these are the only modes found here.
+    pub mode: InstrumentationMode,
+    /// Offsets of the injected code in the encoded binary
+    pub range: Range<usize>,
+}
+
+/// Maps the offsets of the instructions of a parsed binary to their offsets in the encoded binary,
+/// see [`Module::encode_with_map`] and [`Component::encode_with_map`].
+/// Offsets are from the start of the binaries, like the offsets reported by `wasmparser`.
+///
+/// [`Module::encode_with_map`]: crate::Module::encode_with_map
+/// [`Component::encode_with_map`]: crate::Component::encode_with_map
+#[derive(Debug, Clone, Default)]
+pub struct OffsetMap {
+    /// In the order of the encoded binary
+    instrs: Vec<InstrOffset>,
+    /// In the order of the encoded binary
+    injected: Vec<InjectedRange>,
+    /// Original offset -> index in `instrs`
+    by_original: HashMap<usize, usize>,
+    /// (new offset, index in `instrs`) sorted by new offset
+    by_new: Vec<(usize, usize)>,
+}
+
+impl OffsetMap {
+    fn new(instrs: Vec<InstrOffset>, injected: Vec<InjectedRange>) -> Self {
+        let by_original = instrs
+            .iter()
+            .enumerate()
+            .map(|(idx, instr)| (instr.original, idx))
+            .collect();
+        let mut by_new: Vec<(usize, usize)> = instrs
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, instr)| Some((instr.new?, idx)))
+            .collect();
+        by_new.sort_unstable();
+        OffsetMap {
+            instrs,
+            injected,
+            by_original,
+            by_new,
+        }
+    }
+
+    /// The instructions of the parsed binary
+    pub fn instrs(&self) -> &[InstrOffset] {
+        &self.instrs
+    }
+
+    /// The instrumentation injected in the encoded binary
+    pub fn injected(&self) -> &[InjectedRange] {
+        &self.injected
+    }
+
+    /// Get the offset in the encoded binary of the instruction at `original` in the parsed binary
+    pub fn new_offset(&self, original: usize) -> Option<usize> {
+        self.instrs[*self.by_original.get(&original)?].new
+    }
+
+    /// Get the instruction of the parsed binary that starts at `new` in the encoded binary
+    pub fn original_instr(&self, new: usize) -> Option<&InstrOffset> {
+        let pos = self.by_new.binary_search_by_key(&new, |(n, _)| *n).ok()?;
+        Some(&self.instrs[self.by_new[pos].1])
+    }
+
+    /// Get the injected instrumentation that `new` in the encoded binary falls into
+    pub fn injected_at(&self, new: usize) -> Option<&InjectedRange> {
+        let pos = self.injected.partition_point(|inj| inj.range.end <= new);
+        self.injected
+            .get(pos)
+            .filter(|inj| inj.range.contains(&new))
+    }
+}
+
+/// The range taken by an instruction or its instrumentation in an encoded function body
+#[derive(Debug, Clone)]
+pub(crate) struct EncodedRange {
+    pub(crate) instr_idx: usize,
+    /// `None` for the instruction itself
+    pub(crate) mode: Option<InstrumentationMode>,
+    /// Relative to the start of the body
+    pub(crate) range: Range<usize>,
+}
+
+/// What is known of the offsets of a function while encoding it
+#[derive(Debug, Clone)]
+pub(crate) struct FuncOffsets {
+    pub(crate) func_idx: FunctionID,
+    /// The offset of the body and of its instructions in the parsed binary,
+    /// `None` if the function was not parsed
+    pub(crate) original: Option<(usize, Vec<usize>)>,
+    /// The encoded ranges, `None` if the body was copied from the parsed binary
+    pub(crate) encoded: Option<Vec<EncodedRange>>,
+}
+
+/// The functions of an encoded module, in the order of the code section
+#[derive(Debug, Clone)]
+pub(crate) struct ModuleOffsets {
+    /// The module in its component, `None` for a standalone module
+    pub(crate) module: Option<(ComponentPath, ModuleID)>,
+    pub(crate) funcs: Vec<FuncOffsets>,
+}
+
+impl ModuleOffsets {
+    fn location(&self, func_idx: FunctionID, instr_idx: usize) -> Location {
+        match &self.module {
+            None => Location::Module {
+                func_idx,
+                instr_idx,
+            },
+            Some((path, mod_idx)) => {
+                Location::in_component(path.clone(), *mod_idx, func_idx, instr_idx)
+            }
+        }
+    }
+}
+
+/// The range of every function body in the encoded binary `wasm`, per module
+pub(crate) fn body_ranges(wasm: &[u8]) -> Vec<Vec<Range<usize>>> {
+    let mut body_ranges: Vec<Vec<Range<usize>>> = vec![];
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.expect("Unable to parse the encoded binary") {
+            // the header of a standalone or nested module
+            Payload::Version {
+                encoding: Encoding::Module,
+                ..
+            } => body_ranges.push(vec![]),
+            Payload::CodeSectionEntry(body) => body_ranges
+                .last_mut()
+                .expect("Function body outside of a module")
+                .push(body.range()),
+            _ => {}
+        }
+    }
+    body_ranges
+}
+
+/// The ranges of the instructions and instrumentation of every function of the encoded standalone module `wasm`,
+/// in the order of the code section. The ranges are from the start of the binary.
+#[cfg(feature = "wasmprinter")]
+pub(crate) fn encoded_ranges(wasm: &[u8], funcs: &[FuncOffsets]) -> Vec<Vec<EncodedRange>> {
+    let bodies = body_ranges(wasm);
+    assert_eq!(bodies.len(), 1);
+    assert_eq!(bodies[0].len(), funcs.len());
+    funcs
+        .iter()
+        .zip(&bodies[0])
+        .map(|(func, body)| match (&func.original, &func.encoded) {
+            (Some((orig_start, offsets)), None) => {
+                // copied as is, an instruction ends where the next one starts
+                let starts: Vec<usize> = offsets
+                    .iter()
+                    .map(|off| off - orig_start + body.start)
+                    .collect();
+                let ends = starts.iter().skip(1).copied().chain([body.end]);
+                starts
+                    .iter()
+                    .zip(ends)
+                    .enumerate()
+                    .map(|(instr_idx, (start, end))| EncodedRange {
+                        instr_idx,
+                        mode: None,
+                        range: *start..end,
+                    })
+                    .collect()
+            }
+            (_, Some(ranges)) => ranges
+                .iter()
+                .map(|encoded| EncodedRange {
+                    range: encoded.range.start + body.start..encoded.range.end + body.start,
+                    ..encoded.clone()
+                })
+                .collect(),
+            (None, None) => unreachable!("A function is either copied or encoded"),
+        })
+        .collect()
+}
+
+/// Builds the map of the encoded binary `wasm`, `modules` must be in the order of the binary
+pub(crate) fn build(wasm: &[u8], modules: &[ModuleOffsets]) -> OffsetMap {
+    // the start of every function body in the encoded binary, per module
+    let body_starts: Vec<Vec<usize>> = body_ranges(wasm)
+        .into_iter()
+        .map(|bodies| bodies.into_iter().map(|body| body.start).collect())
+        .collect();
+    assert_eq!(body_starts.len(), modules.len());
+
+    let mut instrs = vec![];
+    let mut injected = vec![];
+    for (module, starts) in modules.iter().zip(body_starts) {
+        assert_eq!(starts.len(), module.funcs.len());
+        for (func, new_start) in module.funcs.iter().zip(starts) {
+            let loc = |instr_idx| module.location(func.func_idx, instr_idx);
+            match (&func.original, &func.encoded) {
+                (Some((orig_start, offsets)), None) => {
+                    // copied as is, the instructions only moved with the body
+                    instrs.extend(offsets.iter().enumerate().map(|(idx, off)| InstrOffset {
+                        location: loc(idx),
+                        original: *off,
+                        new: Some(off - orig_start + new_start),
+                    }));
+                }
+                (original, Some(ranges)) => {
+                    let original = original.as_ref().map(|(_, offsets)| offsets);
+                    // instructions without an encoded range were removed
+                    let mut new = vec![None; original.map_or(0, |o| o.len())];
+                    for EncodedRange {
+                        instr_idx,
+                        mode,
+                        range,
+                    } in ranges
+                    {
+                        let range = range.start + new_start..range.end + new_start;
+                        match mode {
+                            None => {
+                                if let Some(new) = new.get_mut(*instr_idx) {
+                                    *new = Some(range.start);
+                                }
+                            }
+                            Some(mode) => injected.push(InjectedRange {
+                                location: loc(*instr_idx),
+                                mode: *mode,
+                                range,
+                            }),
+                        }
+                    }
+                    if let Some(original) = original {
+                        instrs.extend(original.iter().zip(new).enumerate().map(
+                            |(idx, (off, new))| InstrOffset {
+                                location: loc(idx),
+                                original: *off,
+                                new,
+                            },
+                        ));
+                    }
+                }
+                (None, None) => unreachable!("A function is either copied or encoded"),
+            }
+        }
+    }
+    OffsetMap::new(instrs, injected)
+}
@@ -0,0 +1,243 @@
+//! Statistics of modules and components, see [`Module::stats`] and [`Component::stats`].
+
+use crate::error::Error;
+use crate::ir::component::Component;
+use crate::ir::id::FunctionID;
+use crate::ir::module::module_functions::FuncKind;
+use crate::ir::module::{Iter, Module};
+use crate::ir::offset_map::{self, FuncOffsets};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use wasmparser::{Parser, Payload};
+
+/// Statistics of a local function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub func_idx: FunctionID,
+    pub name: Option<String>,
+    pub num_instructions: usize,
+    /// Not counting the parameters
+    pub num_locals: usize,
+    /// Number of injected instructions pending on the function
+    pub num_injected: usize,
+    /// Size of the encoded body without the pending instrumentation, in bytes
+    pub size: usize,
+    /// Size of the encoded body with the pending instrumentation, in bytes
+    pub instrumented_size: usize,
+}
+
+impl FunctionStats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "func_idx": *self.func_idx,
+            "name": self.name,
+            "num_instructions": self.num_instructions,
+            "num_locals": self.num_locals,
+            "num_injected": self.num_injected,
+            "size": self.size,
+            "instrumented_size": self.instrumented_size,
+        })
+    }
+}
+
+/// Statistics of a module, as returned by [`Module::stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleStats {
+    pub num_local_functions: usize,
+    pub num_imported_functions: usize,
+    pub num_instructions: usize,
+    pub num_locals: usize,
+    /// Number of occurrences of every opcode in the bodies, pending instrumentation excluded
+    pub opcodes: BTreeMap<String, usize>,
+    /// The local functions, in order
+    pub functions: Vec<FunctionStats>,
+    /// Size of the contents of every section of the encoded module, in order. Custom sections are named `custom:<name>`.
+    pub sections: Vec<(String, usize)>,
+    pub data_bytes: usize,
+    /// Size of the encoded module, with the pending instrumentation
+    pub size: usize,
+}
+
+impl ModuleStats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "num_local_functions": self.num_local_functions,
+            "num_imported_functions": self.num_imported_functions,
+            "num_instructions": self.num_instructions,
+            "num_locals": self.num_locals,
+            "opcodes": self.opcodes,
+            "functions": self.functions.iter().map(FunctionStats::to_json).collect::<Vec<_>>(),
+            "sections": self
+                .sections
+                .iter()
+                .map(|(name, size)| json!({ "name": name, "size": size }))
+                .collect::<Vec<_>>(),
+            "data_bytes": self.data_bytes,
+            "size": self.size,
+        })
+    }
+}
+
+/// Statistics of a component, as returned by [`Component::stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentStats {
+    pub num_imports: usize,
+    pub num_exports: usize,
+    pub num_core_instances: usize,
+    pub num_component_instances: usize,
+    pub num_canons: usize,
+    /// The core modules of the component, in order
+    pub modules: Vec<ModuleStats>,
+    /// The nested components, in order
+    pub components: Vec<ComponentStats>,
+}
+
+impl ComponentStats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "num_imports": self.num_imports,
+            "num_exports": self.num_exports,
+            "num_core_instances": self.num_core_instances,
+            "num_component_instances": self.num_component_instances,
+            "num_canons": self.num_canons,
+            "modules": self.modules.iter().map(ModuleStats::to_json).collect::<Vec<_>>(),
+            "components": self.components.iter().map(ComponentStats::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+pub(crate) fn module_stats(module: &Module) -> Result<ModuleStats, Error> {
+    let mut instrumented = module.clone();
+    let num_injected: HashMap<FunctionID, usize> = instrumented
+        .functions
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(idx, func)| match &mut func.kind {
+            FuncKind::Local(local) => {
+                let flag = &local.instr_flag;
+                let func_level = flag.entry.len() + flag.exit.len();
+                let injected = local
+                    .body
+                    .instructions
+                    .iter_mut()
+                    .map(|instr| instr.instr_flag.ops_mut().count())
+                    .sum::<usize>();
+                Some((FunctionID(idx as u32), func_level + injected))
+            }
+            FuncKind::Import(_) => None,
+        })
+        .collect();
+    let mut clean = module.clone();
+    clean.clear_all_instr();
+    let (wasm, instrumented_sizes) = body_sizes(&mut instrumented)?;
+    let (_, sizes) = body_sizes(&mut clean)?;
+
+    let mut stats = ModuleStats {
+        num_local_functions: 0,
+        num_imported_functions: 0,
+        num_instructions: 0,
+        num_locals: 0,
+        opcodes: BTreeMap::new(),
+        functions: vec![],
+        sections: section_sizes(&wasm)?,
+        data_bytes: module.data.iter().map(|segment| segment.data.len()).sum(),
+        size: wasm.len(),
+    };
+    for (idx, func) in module.functions.iter().enumerate() {
+        if func.deleted {
+            continue;
+        }
+        let local = match &func.kind {
+            FuncKind::Local(local) => local,
+            FuncKind::Import(_) => {
+                stats.num_imported_functions += 1;
+                continue;
+            }
+        };
+        let func_idx = FunctionID(idx as u32);
+        let num_locals = local.body.locals.iter().map(|(n, _)| *n as usize).sum();
+        for instr in &local.body.instructions {
+            *stats.opcodes.entry(opcode_name(&instr.op)).or_default() += 1;
+        }
+        stats.num_local_functions += 1;
+        stats.num_instructions += local.body.instructions.len();
+        stats.num_locals += num_locals;
+        stats.functions.push(FunctionStats {
+            func_idx,
+            name: local.body.name.clone(),
+            num_instructions: local.body.instructions.len(),
+            num_locals,
+            num_injected: num_injected[&func_idx],
+            size: sizes[&func_idx],
+            instrumented_size: instrumented_sizes[&func_idx],
+        });
+    }
+    Ok(stats)
+}
+
+pub(crate) fn component_stats(component: &Component) -> Result<ComponentStats, Error> {
+    Ok(ComponentStats {
+        num_imports: component.imports.len(),
+        num_exports: component.exports.len(),
+        num_core_instances: component.instances.len(),
+        num_component_instances: component.component_instance.len(),
+        num_canons: component.canons.len(),
+        modules: component
+            .modules
+            .iter()
+            .map(module_stats)
+            .collect::<Result<_, _>>()?,
+        components: component
+            .components
+            .iter()
+            .map(component_stats)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Encode the module, along with the size of every encoded body
+fn body_sizes(module: &mut Module) -> Result<(Vec<u8>, HashMap<FunctionID, usize>), Error> {
+    let mut funcs: Vec<FuncOffsets> = vec![];
+    let wasm = module.encode_internal(false, Some(&mut funcs))?.finish();
+    let bodies = offset_map::body_ranges(&wasm).remove(0);
+    let sizes = funcs
+        .iter()
+        .zip(bodies)
+        .map(|(func, body)| (func.func_idx, body.len()))
+        .collect();
+    Ok((wasm, sizes))
+}
+
+fn section_sizes(wasm: &[u8]) -> Result<Vec<(String, usize)>, Error> {
+    let mut sections = vec![];
+    for payload in Parser::new(0).parse_all(wasm) {
+        let (name, range) = match payload? {
+            Payload::TypeSection(reader) => ("type".to_string(), reader.range()),
+            Payload::ImportSection(reader) => ("import".to_string(), reader.range()),
+            Payload::FunctionSection(reader) => ("function".to_string(), reader.range()),
+            Payload::TableSection(reader) => ("table".to_string(), reader.range()),
+            Payload::MemorySection(reader) => ("memory".to_string(), reader.range()),
+            Payload::TagSection(reader) => ("tag".to_string(), reader.range()),
+            Payload::GlobalSection(reader) => ("global".to_string(), reader.range()),
+            Payload::ExportSection(reader) => ("export".to_string(), reader.range()),
+            Payload::StartSection { range, .. } => ("start".to_string(), range),
+            Payload::ElementSection(reader) => ("element".to_string(), reader.range()),
+            Payload::DataCountSection { range, .. } => ("data count".to_string(), range),
+            Payload::CodeSectionStart { range, .. } => ("code".to_string(), range),
+            Payload::DataSection(reader) => ("data".to_string(), reader.range()),
+            Payload::CustomSection(reader) => (format!("custom:{}", reader.name()), reader.range()),
+            _ => continue,
+        };
+        sections.push((name, range.len()));
+    }
+    Ok(sections)
+}
+
+/// The name of the opcode of an operator, as in `I32Const`
+fn opcode_name(op: &wasmparser::Operator) -> String {
+    let name = format!("{:?}", op);
+    match name.find([' ', '{', '(']) {
+        Some(end) => name[..end].to_string(),
+        None => name,
+    }
+}
@@ -1,20 +1,32 @@
 //! Intermediate representation of sections in a wasm module.
 
 use crate::error::Error;
-use crate::ir::id::{CustomSectionID, FunctionID, GlobalID, ModuleID, TypeID};
+use crate::ir::id::{
+    CustomSectionID, FunctionID, GlobalID, LocalID, MemoryID, ModuleID, TagID, TypeID,
+};
+use crate::ir::module::module_functions::add_local;
 use std::cmp::PartialEq;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Formatter;
 use std::fmt::{self};
 use std::mem::discriminant;
 use std::slice::Iter;
 use wasm_encoder::reencode::Reencode;
-use wasm_encoder::AbstractHeapType;
-use wasmparser::{ConstExpr, Operator, RefType, ValType};
+use wasmparser::types::{CoreTypeId, TypeIdentifier};
+use wasmparser::{ConstExpr, MemoryType, Operator, RefType, ValType};
 
 type Result<T> = std::result::Result<T, Error>;
 
 /// Orca's Datatype. Combination of multiple [`wasmparser`] datatypes.
 ///
+/// Reference types without a `Ref` suffix are non-nullable (e.g. `Any` is `(ref any)`), the ones
+/// with the suffix are nullable and follow the text format shorthands (e.g. `AnyRef` is `anyref`).
+///
+/// **Breaking change:** parsing used to drop the nullability of references, so `anyref` became
+/// `Any` and `(ref func)` became `FuncRef`. Nullable references now parse to the `Ref` variants,
+/// non-nullable ones to the variants without the suffix, and references to a concrete type to
+/// `Concrete`. Code that matched on e.g. `Any` to find `anyref` locals has to match `AnyRef`.
+///
 /// [ValType]: https://docs.rs/wasmparser/latest/wasmparser/enum.ValType.html
 #[derive(Debug, Clone, Eq, Hash, PartialEq, Copy)]
 pub enum DataType {
@@ -25,6 +37,8 @@ pub enum DataType {
     V128,
     FuncRef,
     ExternRef,
+    Func,
+    Extern,
     Any,
     None,
     NoExtern,
@@ -35,11 +49,30 @@ pub enum DataType {
     I31,
     Exn,
     NoExn,
+    AnyRef,
+    NullRef,
+    NullExternRef,
+    NullFuncRef,
+    EqRef,
+    StructRef,
+    ArrayRef,
+    I31Ref,
+    ExnRef,
+    NullExnRef,
+    /// A reference to a type defined in the module's type section (GC proposal)
+    Concrete {
+        ty_id: TypeID,
+        nullable: bool,
+    },
+    /// A non-nullable reference to a type defined in the module's type section, parsing
+    /// no longer produces it
+    #[deprecated(note = "use `DataType::Concrete`, which also carries the nullability")]
     Module(ModuleID),
     RecGroup(u32),
-    CoreTypeId(u32), // TODO: Look at this
+    CoreTypeId(u32),
 }
 
+#[allow(deprecated)]
 impl fmt::Display for DataType {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match *self {
@@ -50,6 +83,8 @@ impl fmt::Display for DataType {
             DataType::V128 => write!(f, "DataType: V128"),
             DataType::FuncRef => write!(f, "DataType: FuncRef"),
             DataType::ExternRef => write!(f, "DataType: ExternRef"),
+            DataType::Func => write!(f, "DataType: Func"),
+            DataType::Extern => write!(f, "DataType: Extern"),
             DataType::Any => write!(f, "DataType: Any"),
             DataType::None => write!(f, "DataType: None"),
             DataType::NoExtern => write!(f, "DataType: NoExtern"),
@@ -60,6 +95,23 @@ impl fmt::Display for DataType {
             DataType::I31 => write!(f, "DataType: I31"),
             DataType::Exn => write!(f, "DataType: Exn"),
             DataType::NoExn => write!(f, "DataType: NoExn"),
+            DataType::AnyRef => write!(f, "DataType: AnyRef"),
+            DataType::NullRef => write!(f, "DataType: NullRef"),
+            DataType::NullExternRef => write!(f, "DataType: NullExternRef"),
+            DataType::NullFuncRef => write!(f, "DataType: NullFuncRef"),
+            DataType::EqRef => write!(f, "DataType: EqRef"),
+            DataType::StructRef => write!(f, "DataType: StructRef"),
+            DataType::ArrayRef => write!(f, "DataType: ArrayRef"),
+            DataType::I31Ref => write!(f, "DataType: I31Ref"),
+            DataType::ExnRef => write!(f, "DataType: ExnRef"),
+            DataType::NullExnRef => write!(f, "DataType: NullExnRef"),
+            DataType::Concrete { ty_id, nullable } => {
+                write!(
+                    f,
+                    "DataType: Concrete {:?} (nullable: {})",
+                    *ty_id, nullable
+                )
+            }
             DataType::Module(idx) => write!(f, "DataType: Module {:?}", idx),
             DataType::RecGroup(idx) => write!(f, "DataType: RecGroup {:?}", idx),
             DataType::CoreTypeId(idx) => write!(f, "DataType: CoreTypeId {:?}", idx),
@@ -67,6 +119,59 @@ impl fmt::Display for DataType {
     }
 }
 
+impl DataType {
+    /// The type of addresses and offsets into the given memory,
+    /// `I64` for a 64-bit (memory64) memory and `I32` otherwise
+    pub fn addr_type(memory: &MemoryType) -> DataType {
+        if memory.memory64 {
+            DataType::I64
+        } else {
+            DataType::I32
+        }
+    }
+
+    /// Whether this is a reference type
+    pub fn is_ref(&self) -> bool {
+        !matches!(
+            self,
+            DataType::I32 | DataType::I64 | DataType::F32 | DataType::F64 | DataType::V128
+        )
+    }
+
+    /// The nullability and abstract heap type of a reference type,
+    /// `None` for value types and concrete references.
+    fn abstract_ref(&self) -> Option<(bool, wasmparser::AbstractHeapType)> {
+        use wasmparser::AbstractHeapType as Ty;
+        Some(match self {
+            DataType::FuncRef => (true, Ty::Func),
+            DataType::ExternRef => (true, Ty::Extern),
+            DataType::Func => (false, Ty::Func),
+            DataType::Extern => (false, Ty::Extern),
+            DataType::Any => (false, Ty::Any),
+            DataType::None => (false, Ty::None),
+            DataType::NoExtern => (false, Ty::NoExtern),
+            DataType::NoFunc => (false, Ty::NoFunc),
+            DataType::Eq => (false, Ty::Eq),
+            DataType::Struct => (false, Ty::Struct),
+            DataType::Array => (false, Ty::Array),
+            DataType::I31 => (false, Ty::I31),
+            DataType::Exn => (false, Ty::Exn),
+            DataType::NoExn => (false, Ty::NoExn),
+            DataType::AnyRef => (true, Ty::Any),
+            DataType::NullRef => (true, Ty::None),
+            DataType::NullExternRef => (true, Ty::NoExtern),
+            DataType::NullFuncRef => (true, Ty::NoFunc),
+            DataType::EqRef => (true, Ty::Eq),
+            DataType::StructRef => (true, Ty::Struct),
+            DataType::ArrayRef => (true, Ty::Array),
+            DataType::I31Ref => (true, Ty::I31),
+            DataType::ExnRef => (true, Ty::Exn),
+            DataType::NullExnRef => (true, Ty::NoExn),
+            _ => return Option::None,
+        })
+    }
+}
+
 impl From<ValType> for DataType {
     fn from(value: ValType) -> Self {
         match value {
@@ -75,27 +180,50 @@ impl From<ValType> for DataType {
             ValType::F32 => DataType::F32,
             ValType::F64 => DataType::F64,
             ValType::V128 => DataType::V128,
-            ValType::Ref(ref_type) => match ref_type.heap_type() {
-                wasmparser::HeapType::Abstract { shared: _, ty } => match ty {
-                    wasmparser::AbstractHeapType::Func => DataType::FuncRef,
-                    wasmparser::AbstractHeapType::Extern => DataType::ExternRef,
-                    wasmparser::AbstractHeapType::Any => DataType::Any,
-                    wasmparser::AbstractHeapType::None => DataType::None,
-                    wasmparser::AbstractHeapType::NoExtern => DataType::NoExtern,
-                    wasmparser::AbstractHeapType::NoFunc => DataType::NoFunc,
-                    wasmparser::AbstractHeapType::Eq => DataType::Eq,
-                    wasmparser::AbstractHeapType::Struct => DataType::Struct,
-                    wasmparser::AbstractHeapType::Array => DataType::Array,
-                    wasmparser::AbstractHeapType::I31 => DataType::I31,
-                    wasmparser::AbstractHeapType::Exn => DataType::Exn,
-                    wasmparser::AbstractHeapType::NoExn => DataType::NoExn,
-                },
-                wasmparser::HeapType::Concrete(u) => match u {
-                    wasmparser::UnpackedIndex::Module(idx) => DataType::Module(ModuleID(idx)),
-                    wasmparser::UnpackedIndex::RecGroup(idx) => DataType::RecGroup(idx),
-                    wasmparser::UnpackedIndex::Id(_id) => panic!("Not supported yet!"),
-                },
-            },
+            ValType::Ref(ref_type) => {
+                let nullable = ref_type.is_nullable();
+                match ref_type.heap_type() {
+                    wasmparser::HeapType::Abstract { shared: _, ty } => {
+                        use wasmparser::AbstractHeapType as Ty;
+                        match (ty, nullable) {
+                            (Ty::Func, true) => DataType::FuncRef,
+                            (Ty::Extern, true) => DataType::ExternRef,
+                            (Ty::Func, false) => DataType::Func,
+                            (Ty::Extern, false) => DataType::Extern,
+                            (Ty::Any, false) => DataType::Any,
+                            (Ty::None, false) => DataType::None,
+                            (Ty::NoExtern, false) => DataType::NoExtern,
+                            (Ty::NoFunc, false) => DataType::NoFunc,
+                            (Ty::Eq, false) => DataType::Eq,
+                            (Ty::Struct, false) => DataType::Struct,
+                            (Ty::Array, false) => DataType::Array,
+                            (Ty::I31, false) => DataType::I31,
+                            (Ty::Exn, false) => DataType::Exn,
+                            (Ty::NoExn, false) => DataType::NoExn,
+                            (Ty::Any, true) => DataType::AnyRef,
+                            (Ty::None, true) => DataType::NullRef,
+                            (Ty::NoExtern, true) => DataType::NullExternRef,
+                            (Ty::NoFunc, true) => DataType::NullFuncRef,
+                            (Ty::Eq, true) => DataType::EqRef,
+                            (Ty::Struct, true) => DataType::StructRef,
+                            (Ty::Array, true) => DataType::ArrayRef,
+                            (Ty::I31, true) => DataType::I31Ref,
+                            (Ty::Exn, true) => DataType::ExnRef,
+                            (Ty::NoExn, true) => DataType::NullExnRef,
+                        }
+                    }
+                    wasmparser::HeapType::Concrete(u) => match u {
+                        wasmparser::UnpackedIndex::Module(idx) => DataType::Concrete {
+                            ty_id: TypeID(idx),
+                            nullable,
+                        },
+                        wasmparser::UnpackedIndex::RecGroup(idx) => DataType::RecGroup(idx),
+                        wasmparser::UnpackedIndex::Id(id) => {
+                            DataType::CoreTypeId(id.index() as u32)
+                        }
+                    },
+                }
+            }
         }
     }
 }
@@ -103,102 +231,47 @@ impl From<ValType> for DataType {
 /// Converts from Orca's DataType to [`wasm_encoder::ValType`].
 ///
 /// [`wasm_encoder::ValType`]: https://docs.rs/wasm-encoder/0.214.0/wasm_encoder/enum.ValType.html
+#[allow(deprecated)]
 impl From<&DataType> for wasm_encoder::ValType {
     fn from(ty: &DataType) -> Self {
+        let mut reencoder = wasm_encoder::reencode::RoundtripReencoder;
         match ty {
             DataType::I32 => wasm_encoder::ValType::I32,
             DataType::I64 => wasm_encoder::ValType::I64,
             DataType::F32 => wasm_encoder::ValType::F32,
             DataType::F64 => wasm_encoder::ValType::F64,
             DataType::V128 => wasm_encoder::ValType::V128,
-            DataType::FuncRef => wasm_encoder::ValType::FUNCREF,
-            DataType::ExternRef => wasm_encoder::ValType::EXTERNREF,
-            DataType::Any => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::Any,
-                },
-            }),
-            DataType::None => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::None,
-                },
-            }),
-            DataType::NoExtern => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::NoExtern,
-                },
-            }),
-            DataType::NoFunc => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::NoFunc,
-                },
-            }),
-            DataType::Eq => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::Eq,
-                },
-            }),
-            DataType::Struct => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::Struct,
-                },
-            }),
-            DataType::Array => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::Array,
-                },
-            }),
-            DataType::I31 => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::I31,
-                },
-            }),
-            DataType::Exn => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::Exn,
-                },
-            }),
-            DataType::NoExn => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::NoExn,
-                },
-            }),
+            DataType::Concrete { ty_id, nullable } => {
+                wasm_encoder::ValType::Ref(wasm_encoder::RefType {
+                    nullable: *nullable,
+                    heap_type: wasm_encoder::HeapType::Concrete(**ty_id),
+                })
+            }
             DataType::Module(idx) => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
                 nullable: false,
                 heap_type: wasm_encoder::HeapType::Concrete(**idx),
             }),
-            DataType::RecGroup(idx) => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Concrete(*idx),
-            }),
-            DataType::CoreTypeId(idx) => wasm_encoder::ValType::Ref(wasm_encoder::RefType {
-                nullable: false,
-                heap_type: wasm_encoder::HeapType::Concrete(*idx),
-            }),
+            DataType::RecGroup(idx) | DataType::CoreTypeId(idx) => {
+                wasm_encoder::ValType::Ref(wasm_encoder::RefType {
+                    nullable: false,
+                    heap_type: wasm_encoder::HeapType::Concrete(*idx),
+                })
+            }
+            _ => {
+                let (nullable, ty) = ty.abstract_ref().unwrap();
+                wasm_encoder::ValType::Ref(wasm_encoder::RefType {
+                    nullable,
+                    heap_type: wasm_encoder::HeapType::Abstract {
+                        shared: false,
+                        ty: reencoder.abstract_heap_type(ty),
+                    },
+                })
+            }
         }
     }
 }
 
+#[allow(deprecated)]
 impl From<&DataType> for ValType {
     fn from(ty: &DataType) -> Self {
         match ty {
@@ -207,123 +280,46 @@ impl From<&DataType> for ValType {
             DataType::F32 => ValType::F32,
             DataType::F64 => ValType::F64,
             DataType::V128 => ValType::V128,
-            DataType::FuncRef => ValType::FUNCREF,
-            DataType::ExternRef => ValType::EXTERNREF,
-            DataType::Any => ValType::Ref(
-                RefType::new(
-                    false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::Any,
-                    },
-                )
-                .unwrap(),
-            ),
-            DataType::None => ValType::Ref(
-                RefType::new(
-                    false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::None,
-                    },
-                )
-                .unwrap(),
-            ),
-            DataType::NoExtern => ValType::Ref(
-                RefType::new(
-                    false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::NoExtern,
-                    },
-                )
-                .unwrap(),
-            ),
-            DataType::NoFunc => ValType::Ref(
-                RefType::new(
-                    false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::NoFunc,
-                    },
-                )
-                .unwrap(),
-            ),
-            DataType::Eq => ValType::Ref(
-                RefType::new(
-                    false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::Eq,
-                    },
-                )
-                .unwrap(),
-            ),
-            DataType::Struct => ValType::Ref(
+            DataType::Concrete { ty_id, nullable } => ValType::Ref(
                 RefType::new(
-                    false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::Struct,
-                    },
+                    *nullable,
+                    wasmparser::HeapType::Concrete(wasmparser::UnpackedIndex::Module(**ty_id)),
                 )
                 .unwrap(),
             ),
-            DataType::Array => ValType::Ref(
-                RefType::new(
-                    false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::Array,
-                    },
-                )
-                .unwrap(),
-            ),
-            DataType::I31 => ValType::Ref(
-                RefType::new(
-                    false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::I31,
-                    },
-                )
-                .unwrap(),
-            ),
-            DataType::Exn => ValType::Ref(
+            DataType::Module(idx) => ValType::Ref(
                 RefType::new(
                     false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::Exn,
-                    },
+                    wasmparser::HeapType::Concrete(wasmparser::UnpackedIndex::Module(**idx)),
                 )
                 .unwrap(),
             ),
-            DataType::NoExn => ValType::Ref(
+            DataType::RecGroup(idx) => ValType::Ref(
                 RefType::new(
                     false,
-                    wasmparser::HeapType::Abstract {
-                        shared: false,
-                        ty: wasmparser::AbstractHeapType::NoExn,
-                    },
+                    wasmparser::HeapType::Concrete(wasmparser::UnpackedIndex::RecGroup(*idx)),
                 )
                 .unwrap(),
             ),
-            DataType::Module(idx) => ValType::Ref(
+            DataType::CoreTypeId(idx) => ValType::Ref(
                 RefType::new(
                     false,
-                    wasmparser::HeapType::Concrete(wasmparser::UnpackedIndex::Module(**idx)),
+                    wasmparser::HeapType::Concrete(wasmparser::UnpackedIndex::Id(
+                        CoreTypeId::from_index(*idx),
+                    )),
                 )
                 .unwrap(),
             ),
-            DataType::RecGroup(idx) => ValType::Ref(
-                RefType::new(
-                    false,
-                    wasmparser::HeapType::Concrete(wasmparser::UnpackedIndex::RecGroup(*idx)),
+            _ => {
+                let (nullable, ty) = ty.abstract_ref().unwrap();
+                ValType::Ref(
+                    RefType::new(
+                        nullable,
+                        wasmparser::HeapType::Abstract { shared: false, ty },
+                    )
+                    .unwrap(),
                 )
-                .unwrap(),
-            ),
-            DataType::CoreTypeId(_idx) => panic!("Not Supported Yet!"),
+            }
         }
     }
 }
@@ -371,6 +367,33 @@ pub enum DataSegmentKind {
 }
 
 impl DataSegmentKind {
+    /// Create an active data segment kind placed at a constant offset, the offset
+    /// is an `i64` for a 64-bit memory and an `i32` otherwise
+    pub fn active(memory: MemoryID, memory_ty: &MemoryType, offset: u64) -> DataSegmentKind {
+        let offset = if memory_ty.memory64 {
+            Value::I64(offset as i64)
+        } else {
+            Value::I32(offset as i32)
+        };
+        DataSegmentKind::Active {
+            memory_index: *memory,
+            offset_expr: InitExpr::from(offset),
+        }
+    }
+
+    /// The offset of an active data segment, if it is a constant.
+    /// Offsets are unsigned, an `i32` offset is zero-extended.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            DataSegmentKind::Active { offset_expr, .. } => match offset_expr.value()? {
+                Value::I32(offset) => Some(offset as u32 as u64),
+                Value::I64(offset) => Some(offset as u64),
+                _ => None,
+            },
+            DataSegmentKind::Passive => None,
+        }
+    }
+
     pub(crate) fn from_wasmparser(kind: wasmparser::DataKind) -> Result<DataSegmentKind> {
         Ok(match kind {
             wasmparser::DataKind::Passive => DataSegmentKind::Passive,
@@ -379,7 +402,7 @@ impl DataSegmentKind {
                 offset_expr,
             } => DataSegmentKind::Active {
                 memory_index,
-                offset_expr: InitExpr::eval(&offset_expr),
+                offset_expr: InitExpr::eval(&offset_expr)?,
             },
         })
     }
@@ -387,16 +410,16 @@ impl DataSegmentKind {
 
 #[derive(Debug, Clone)]
 /// Kind of Element
-pub enum ElementKind<'a> {
+pub enum ElementKind {
     Passive,
     Active {
         table_index: Option<u32>,
-        offset_expr: ConstExpr<'a>,
+        offset_expr: InitExpr,
     },
     Declared,
 }
 
-impl ElementKind<'_> {
+impl ElementKind {
     pub(crate) fn from_wasmparser(kind: wasmparser::ElementKind) -> Result<ElementKind> {
         match kind {
             wasmparser::ElementKind::Passive => Ok(ElementKind::Passive),
@@ -406,7 +429,7 @@ impl ElementKind<'_> {
                 offset_expr,
             } => Ok(ElementKind::Active {
                 table_index,
-                offset_expr,
+                offset_expr: InitExpr::eval(&offset_expr)?,
             }),
         }
     }
@@ -414,15 +437,12 @@ impl ElementKind<'_> {
 
 #[derive(Debug, Clone)]
 /// Type of element
-pub enum ElementItems<'a> {
+pub enum ElementItems {
     Functions(Vec<FunctionID>),
-    ConstExprs {
-        ty: RefType,
-        exprs: Vec<ConstExpr<'a>>,
-    },
+    ConstExprs { ty: RefType, exprs: Vec<InitExpr> },
 }
 
-impl ElementItems<'_> {
+impl ElementItems {
     pub(crate) fn from_wasmparser(items: wasmparser::ElementItems) -> Result<ElementItems> {
         match items {
             wasmparser::ElementItems::Functions(reader) => {
@@ -436,7 +456,8 @@ impl ElementItems<'_> {
             wasmparser::ElementItems::Expressions(ref_type, reader) => {
                 let exprs = reader
                     .into_iter()
-                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                    .map(|expr| InitExpr::eval(&expr?))
+                    .collect::<Result<Vec<_>>>()?;
                 Ok(ElementItems::ConstExprs {
                     ty: ref_type,
                     exprs,
@@ -446,13 +467,33 @@ impl ElementItems<'_> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
 /// Mode of Function in case the function is mark as instrumented
 pub enum FuncInstrMode {
     Entry,
+    /// Fires at the end of the function, before `return` and before tail calls (see [`TailCallExit`]).
+    /// If the module uses exception handling, it also fires when an exception leaves the
+    /// function (the exception is then rethrown).
     Exit,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Placement of the `FuncInstrMode::Exit` instrumentation at tail calls
+/// (`return_call`, `return_call_indirect` and `return_call_ref`).
+///
+/// Set it through the function's `instr_flag`, e.g.
+/// `module.functions.unwrap_local(fid).instr_flag.tail_call_exit = TailCallExit::AfterCallee`.
+pub enum TailCallExit {
+    /// Inject the exit instrumentation right before the tail call. The exit fires
+    /// before the callee runs, but the call remains a tail call.
+    #[default]
+    BeforeCall,
+    /// Turn the tail call into a regular call followed by the exit instrumentation and a `return`.
+    /// The exit fires after the callee returns, at the cost of the frame no longer being reused.
+    /// The call is rewritten through its alternate, so encoding fails if the tail call already has one.
+    AfterCallee,
+}
+
 #[derive(Default, Debug, Clone)]
 /// Instrumentation Data that is stored with every function
 pub struct FuncInstrFlag<'a> {
@@ -462,6 +503,8 @@ pub struct FuncInstrFlag<'a> {
     pub current_mode: Option<FuncInstrMode>,
     pub entry: Vec<Operator<'a>>,
     pub exit: Vec<Operator<'a>>,
+    /// Where the exit instrumentation goes at tail calls
+    pub tail_call_exit: TailCallExit,
 }
 
 impl fmt::Display for FuncInstrFlag<'_> {
@@ -471,6 +514,7 @@ impl fmt::Display for FuncInstrFlag<'_> {
             entry,
             exit,
             current_mode: _,
+            tail_call_exit: _,
         } = self;
         if !self.has_instr() {
             write!(f, "Not Instrumented")?;
@@ -495,10 +539,12 @@ impl PartialEq for FuncInstrFlag<'_> {
             entry,
             exit,
             current_mode,
+            tail_call_exit,
         } = self;
         let mut result = *has_special_instr == other.has_special_instr;
         result &= entry.eq(&other.entry);
         result &= exit.eq(&other.exit);
+        result &= *tail_call_exit == other.tail_call_exit;
         result &= discriminant(current_mode) == discriminant(&other.current_mode);
 
         result
@@ -515,10 +561,23 @@ impl<'a> FuncInstrFlag<'a> {
             exit,
             has_special_instr: _,
             current_mode: _,
+            tail_call_exit: _,
         } = self;
         !entry.is_empty() || !exit.is_empty()
     }
 
+    /// The modes that have instrumentation
+    pub fn modes(&self) -> Vec<FuncInstrMode> {
+        let mut modes = vec![];
+        if !self.entry.is_empty() {
+            modes.push(FuncInstrMode::Entry);
+        }
+        if !self.exit.is_empty() {
+            modes.push(FuncInstrMode::Exit);
+        }
+        modes
+    }
+
     pub fn has_special_instr(&self) -> bool {
         self.has_special_instr
     }
@@ -536,7 +595,7 @@ impl<'a> FuncInstrFlag<'a> {
     }
 
     /// Get an instruction to the current FuncInstrMode's list
-    pub fn get_instr(&self, idx: usize) -> &Operator {
+    pub fn get_instr(&self, idx: usize) -> &Operator<'_> {
         match self.current_mode {
             None => {
                 panic!("Current mode is not set...cannot grab instruction without context!")
@@ -559,6 +618,18 @@ pub enum InstrumentationMode {
     BlockEntry,
     BlockExit,
     BlockAlt,
+    /// Fires when the branch of a `br_if` is taken or when the `then` body of an `if` is entered
+    BranchTaken,
+    /// Fires when a `br_if` falls through or when the `then` body of an `if` is skipped
+    BranchNotTaken,
+    /// Fires when a `br_table` branches to the target at this index of its target list
+    BrTableTarget(u32),
+    /// Fires when a `br_table` branches to its default target
+    BrTableDefault,
+    /// Fires at the header of a `loop` each time it is re-entered through a back-edge
+    LoopIteration,
+    /// Fires at every `br`, `br_if` and `br_table` that branches back to a `loop`, when it is taken
+    LoopBackEdge,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -580,6 +651,13 @@ pub struct InstrumentationFlag<'a> {
     /// Some(vec) means to replace with the vec of instructions
     /// Some(empty vec) means there is no alt instrumentation
     pub block_alt: Option<Vec<Operator<'a>>>,
+    pub branch_taken: Vec<Operator<'a>>,
+    pub branch_not_taken: Vec<Operator<'a>>,
+    /// The bodies to inject per `br_table` target, as (index into the target list, body)
+    pub br_table_targets: Vec<(u32, Vec<Operator<'a>>)>,
+    pub br_table_default: Vec<Operator<'a>>,
+    pub loop_iteration: Vec<Operator<'a>>,
+    pub loop_back_edge: Vec<Operator<'a>>,
 }
 
 impl fmt::Display for InstrumentationFlag<'_> {
@@ -592,6 +670,12 @@ impl fmt::Display for InstrumentationFlag<'_> {
             block_entry,
             block_exit,
             block_alt,
+            branch_taken,
+            branch_not_taken,
+            br_table_targets,
+            br_table_default,
+            loop_iteration,
+            loop_back_edge,
             current_mode: _,
         } = self;
         if !self.has_instr() {
@@ -605,14 +689,29 @@ impl fmt::Display for InstrumentationFlag<'_> {
                    Semantic After: {:?} instructions\n \
                    Block Entry: {:?} instructions\n \
                    Block Exit: {:?} instructions\n \
-                   Block Alt: {:?} instructions",
+                   Block Alt: {:?} instructions\n \
+                   Branch Taken: {:?} instructions\n \
+                   Branch Not Taken: {:?} instructions\n \
+                   Br Table Targets: {:?} instructions\n \
+                   Br Table Default: {:?} instructions\n \
+                   Loop Iteration: {:?} instructions\n \
+                   Loop Back Edge: {:?} instructions",
             before.len(),
             after.len(),
             alternate.as_ref().unwrap().len(),
             semantic_after.len(),
             block_entry.len(),
             block_exit.len(),
-            block_alt.as_ref().unwrap().len()
+            block_alt.as_ref().unwrap().len(),
+            branch_taken.len(),
+            branch_not_taken.len(),
+            br_table_targets
+                .iter()
+                .map(|(_, body)| body.len())
+                .sum::<usize>(),
+            br_table_default.len(),
+            loop_iteration.len(),
+            loop_back_edge.len()
         )
     }
 }
@@ -628,6 +727,12 @@ impl PartialEq for InstrumentationFlag<'_> {
             block_entry,
             block_exit,
             block_alt,
+            branch_taken,
+            branch_not_taken,
+            br_table_targets,
+            br_table_default,
+            loop_iteration,
+            loop_back_edge,
             current_mode,
         } = self;
         let mut result = before.eq(&other.before);
@@ -637,6 +742,12 @@ impl PartialEq for InstrumentationFlag<'_> {
         result &= block_entry.eq(&other.block_entry);
         result &= block_exit.eq(&other.block_exit);
         result &= block_alt.eq(&other.block_alt);
+        result &= branch_taken.eq(&other.branch_taken);
+        result &= branch_not_taken.eq(&other.branch_not_taken);
+        result &= br_table_targets.eq(&other.br_table_targets);
+        result &= br_table_default.eq(&other.br_table_default);
+        result &= loop_iteration.eq(&other.loop_iteration);
+        result &= loop_back_edge.eq(&other.loop_back_edge);
         result &= *current_mode == other.current_mode;
 
         result
@@ -656,6 +767,12 @@ impl<'a> InstrumentationFlag<'a> {
             block_entry,
             block_exit,
             block_alt,
+            branch_taken,
+            branch_not_taken,
+            br_table_targets,
+            br_table_default,
+            loop_iteration,
+            loop_back_edge,
             current_mode: _,
         } = self;
         !before.is_empty()
@@ -665,6 +782,73 @@ impl<'a> InstrumentationFlag<'a> {
             || !block_entry.is_empty()
             || !block_exit.is_empty()
             || !block_alt.is_none() // Some(vec![]) means block removal!
+            || !branch_taken.is_empty()
+            || !branch_not_taken.is_empty()
+            || !br_table_targets.is_empty()
+            || !br_table_default.is_empty()
+            || !loop_iteration.is_empty()
+            || !loop_back_edge.is_empty()
+    }
+
+    /// The modes that have instrumentation, in the order they are listed in [`InstrumentationMode`]
+    pub fn modes(&self) -> Vec<InstrumentationMode> {
+        // Using pattern match to help identify when this function needs to be extended in the future
+        let Self {
+            before,
+            after,
+            alternate,
+            semantic_after,
+            block_entry,
+            block_exit,
+            block_alt,
+            branch_taken,
+            branch_not_taken,
+            br_table_targets,
+            br_table_default,
+            loop_iteration,
+            loop_back_edge,
+            current_mode: _,
+        } = self;
+        let mut modes = vec![];
+        let mut add = |has_instr: bool, mode| {
+            if has_instr {
+                modes.push(mode)
+            }
+        };
+        add(!before.is_empty(), InstrumentationMode::Before);
+        add(!after.is_empty(), InstrumentationMode::After);
+        add(alternate.is_some(), InstrumentationMode::Alternate);
+        add(
+            !semantic_after.is_empty(),
+            InstrumentationMode::SemanticAfter,
+        );
+        add(!block_entry.is_empty(), InstrumentationMode::BlockEntry);
+        add(!block_exit.is_empty(), InstrumentationMode::BlockExit);
+        add(block_alt.is_some(), InstrumentationMode::BlockAlt);
+        add(!branch_taken.is_empty(), InstrumentationMode::BranchTaken);
+        add(
+            !branch_not_taken.is_empty(),
+            InstrumentationMode::BranchNotTaken,
+        );
+        for (target, body) in br_table_targets {
+            add(
+                !body.is_empty(),
+                InstrumentationMode::BrTableTarget(*target),
+            );
+        }
+        add(
+            !br_table_default.is_empty(),
+            InstrumentationMode::BrTableDefault,
+        );
+        add(
+            !loop_iteration.is_empty(),
+            InstrumentationMode::LoopIteration,
+        );
+        add(
+            !loop_back_edge.is_empty(),
+            InstrumentationMode::LoopBackEdge,
+        );
+        modes
     }
 
     /// Add an instruction to the current InstrumentationMode's list
@@ -742,6 +926,81 @@ impl<'a> InstrumentationFlag<'a> {
                     );
                 }
             }
+            Some(InstrumentationMode::BranchTaken) => {
+                if Self::is_conditional_op(op) {
+                    self.branch_taken.push(val);
+                    true
+                } else {
+                    // instrumentation type not applicable!
+                    panic!(
+                        "Cannot apply branch taken instrumentation mode to op type: {:?}",
+                        op
+                    );
+                }
+            }
+            Some(InstrumentationMode::BranchNotTaken) => {
+                if Self::is_conditional_op(op) {
+                    self.branch_not_taken.push(val);
+                    true
+                } else {
+                    // instrumentation type not applicable!
+                    panic!(
+                        "Cannot apply branch not taken instrumentation mode to op type: {:?}",
+                        op
+                    );
+                }
+            }
+            Some(InstrumentationMode::BrTableTarget(target)) => {
+                if matches!(op, Operator::BrTable { .. }) {
+                    match self.br_table_targets.iter_mut().find(|(t, _)| *t == target) {
+                        None => self.br_table_targets.push((target, vec![val])),
+                        Some((_, body)) => body.push(val),
+                    }
+                    true
+                } else {
+                    // instrumentation type not applicable!
+                    panic!(
+                        "Cannot apply br_table target instrumentation mode to op type: {:?}",
+                        op
+                    );
+                }
+            }
+            Some(InstrumentationMode::BrTableDefault) => {
+                if matches!(op, Operator::BrTable { .. }) {
+                    self.br_table_default.push(val);
+                    true
+                } else {
+                    // instrumentation type not applicable!
+                    panic!(
+                        "Cannot apply br_table default instrumentation mode to op type: {:?}",
+                        op
+                    );
+                }
+            }
+            Some(InstrumentationMode::LoopIteration) => {
+                if matches!(op, Operator::Loop { .. }) {
+                    self.loop_iteration.push(val);
+                    true
+                } else {
+                    // instrumentation type not applicable!
+                    panic!(
+                        "Cannot apply loop iteration instrumentation mode to op type: {:?}",
+                        op
+                    );
+                }
+            }
+            Some(InstrumentationMode::LoopBackEdge) => {
+                if matches!(op, Operator::Loop { .. }) {
+                    self.loop_back_edge.push(val);
+                    true
+                } else {
+                    // instrumentation type not applicable!
+                    panic!(
+                        "Cannot apply loop back edge instrumentation mode to op type: {:?}",
+                        op
+                    );
+                }
+            }
         }
     }
 
@@ -760,9 +1019,89 @@ impl<'a> InstrumentationFlag<'a> {
             InstrumentationMode::BlockAlt => {
                 self.block_alt = None;
             }
+            InstrumentationMode::BranchTaken => self.branch_taken.clear(),
+            InstrumentationMode::BranchNotTaken => self.branch_not_taken.clear(),
+            InstrumentationMode::BrTableTarget(target) => {
+                self.br_table_targets.retain(|(t, _)| *t != target);
+            }
+            InstrumentationMode::BrTableDefault => self.br_table_default.clear(),
+            InstrumentationMode::LoopIteration => self.loop_iteration.clear(),
+            InstrumentationMode::LoopBackEdge => self.loop_back_edge.clear(),
         }
     }
 
+    /// All the injected operators, in no particular order
+    pub(crate) fn ops(&self) -> impl std::iter::Iterator<Item = &Operator<'a>> {
+        let InstrumentationFlag {
+            current_mode: _,
+            before,
+            after,
+            alternate,
+            semantic_after,
+            block_entry,
+            block_exit,
+            block_alt,
+            branch_taken,
+            branch_not_taken,
+            br_table_targets,
+            br_table_default,
+            loop_iteration,
+            loop_back_edge,
+        } = self;
+        before
+            .iter()
+            .chain(after.iter())
+            .chain(alternate.iter().flatten())
+            .chain(semantic_after.iter())
+            .chain(block_entry.iter())
+            .chain(block_exit.iter())
+            .chain(block_alt.iter().flatten())
+            .chain(branch_taken.iter())
+            .chain(branch_not_taken.iter())
+            .chain(br_table_targets.iter().flat_map(|(_, body)| body.iter()))
+            .chain(br_table_default.iter())
+            .chain(loop_iteration.iter())
+            .chain(loop_back_edge.iter())
+    }
+
+    /// All the injected operators, in no particular order
+    pub(crate) fn ops_mut(&mut self) -> impl std::iter::Iterator<Item = &mut Operator<'a>> {
+        let InstrumentationFlag {
+            current_mode: _,
+            before,
+            after,
+            alternate,
+            semantic_after,
+            block_entry,
+            block_exit,
+            block_alt,
+            branch_taken,
+            branch_not_taken,
+            br_table_targets,
+            br_table_default,
+            loop_iteration,
+            loop_back_edge,
+        } = self;
+        before
+            .iter_mut()
+            .chain(after.iter_mut())
+            .chain(alternate.iter_mut().flatten())
+            .chain(semantic_after.iter_mut())
+            .chain(block_entry.iter_mut())
+            .chain(block_exit.iter_mut())
+            .chain(block_alt.iter_mut().flatten())
+            .chain(branch_taken.iter_mut())
+            .chain(branch_not_taken.iter_mut())
+            .chain(
+                br_table_targets
+                    .iter_mut()
+                    .flat_map(|(_, body)| body.iter_mut()),
+            )
+            .chain(br_table_default.iter_mut())
+            .chain(loop_iteration.iter_mut())
+            .chain(loop_back_edge.iter_mut())
+    }
+
     fn is_block_style_op(op: &Operator) -> bool {
         matches!(
             op,
@@ -770,9 +1109,15 @@ impl<'a> InstrumentationFlag<'a> {
                 | Operator::Loop { .. }
                 | Operator::If { .. }
                 | Operator::Else { .. }
+                | Operator::TryTable { .. }
         )
     }
 
+    /// Ops whose control flow depends on a condition
+    fn is_conditional_op(op: &Operator) -> bool {
+        matches!(op, Operator::BrIf { .. } | Operator::If { .. })
+    }
+
     fn is_branching_op(op: &Operator) -> bool {
         matches!(
             op,
@@ -787,7 +1132,7 @@ impl<'a> InstrumentationFlag<'a> {
     }
 
     /// Get an instruction to the current InstrumentationMode's list
-    pub fn get_instr(&self, idx: usize) -> &Operator {
+    pub fn get_instr(&self, idx: usize) -> &Operator<'_> {
         match self.current_mode {
             None => {
                 panic!("Current mode is not set...cannot grab instruction without context!")
@@ -805,12 +1150,83 @@ impl<'a> InstrumentationFlag<'a> {
                 None => panic!("No block alt instructions to pull idx '{}' from", idx),
                 Some(block_alt) => block_alt.get(idx).unwrap(),
             },
+            Some(InstrumentationMode::BranchTaken) => self.branch_taken.get(idx).unwrap(),
+            Some(InstrumentationMode::BranchNotTaken) => self.branch_not_taken.get(idx).unwrap(),
+            Some(InstrumentationMode::BrTableTarget(target)) => {
+                match self.br_table_targets.iter().find(|(t, _)| *t == target) {
+                    None => panic!(
+                        "No instructions for br_table target {} to pull idx '{}' from",
+                        target, idx
+                    ),
+                    Some((_, body)) => body.get(idx).unwrap(),
+                }
+            }
+            Some(InstrumentationMode::BrTableDefault) => self.br_table_default.get(idx).unwrap(),
+            Some(InstrumentationMode::LoopIteration) => self.loop_iteration.get(idx).unwrap(),
+            Some(InstrumentationMode::LoopBackEdge) => self.loop_back_edge.get(idx).unwrap(),
+        }
+    }
+}
+
+/// The locals holding the operands of a memory access, see
+/// [`IteratingInstrumenter::save_mem_access_to_locals`].
+///
+/// [`IteratingInstrumenter::save_mem_access_to_locals`]: crate::iterator::iterator_trait::IteratingInstrumenter::save_mem_access_to_locals
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MemoryAccess {
+    /// The memory that is accessed
+    pub memory: MemoryID,
+    /// Holds the effective address, the address operand plus the static offset of the `MemArg`
+    pub addr: LocalID,
+    /// Holds the value written to memory, `None` if the access does not write to memory
+    pub value: Option<LocalID>,
+    /// Number of bytes accessed
+    pub width: u32,
+}
+
+/// Path from a component to one of its nested components, given as the index into
+/// `Component::components` at every level. The empty path is the component itself.
+#[derive(Debug, Clone, Default, Eq, Hash, PartialEq)]
+pub struct ComponentPath {
+    indices: Vec<u32>,
+}
+
+impl ComponentPath {
+    /// Create a path from the indices of the nested components at every level
+    pub fn new(indices: &[u32]) -> Self {
+        ComponentPath {
+            indices: indices.to_vec(),
         }
     }
+
+    /// The path to the nested component `idx` of the component at this path
+    pub fn child(&self, idx: u32) -> Self {
+        let mut path = self.clone();
+        path.indices.push(idx);
+        path
+    }
+
+    /// Whether this path points to the outermost component
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// The number of nested components along this path
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// The indices of the nested components along this path
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
 }
 
 /// Used to represent a unique location in a wasm component or module.
-#[derive(Debug, Clone, Copy)]
+///
+/// **Breaking change:** `Location` is no longer `Copy` since the path to a nested component is
+/// stored on the heap, clone it to use it more than once.
+#[derive(Debug, Clone)]
 pub enum Location {
     Component {
         mod_idx: ModuleID,
@@ -821,6 +1237,57 @@ pub enum Location {
         func_idx: FunctionID,
         instr_idx: usize,
     },
+    /// A location in a module of a nested component
+    NestedComponent {
+        path: ComponentPath,
+        mod_idx: ModuleID,
+        func_idx: FunctionID,
+        instr_idx: usize,
+    },
+}
+
+impl Location {
+    /// Create a location in a module of the component at `path`
+    pub fn in_component(
+        path: ComponentPath,
+        mod_idx: ModuleID,
+        func_idx: FunctionID,
+        instr_idx: usize,
+    ) -> Self {
+        if path.is_empty() {
+            Location::Component {
+                mod_idx,
+                func_idx,
+                instr_idx,
+            }
+        } else {
+            Location::NestedComponent {
+                path,
+                mod_idx,
+                func_idx,
+                instr_idx,
+            }
+        }
+    }
+
+    /// For a location in a component, returns the path to its (nested) component,
+    /// the module, the function and the instruction index
+    pub fn component_parts(&self) -> Option<(ComponentPath, ModuleID, FunctionID, usize)> {
+        match self {
+            Location::Component {
+                mod_idx,
+                func_idx,
+                instr_idx,
+            } => Some((ComponentPath::default(), *mod_idx, *func_idx, *instr_idx)),
+            Location::NestedComponent {
+                path,
+                mod_idx,
+                func_idx,
+                instr_idx,
+            } => Some((path.clone(), *mod_idx, *func_idx, *instr_idx)),
+            Location::Module { .. } => None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -837,6 +1304,85 @@ pub struct Body<'a> {
     pub instructions: Vec<Instruction<'a>>,
     pub num_instructions: usize,
     pub name: Option<String>,
+    /// The body as found in the parsed binary, `None` for functions that were not parsed
+    pub(crate) original: Option<Box<OriginalBody<'a>>>,
+    /// `None` until a scratch local is taken
+    pub(crate) scratch: Option<Box<ScratchLocals>>,
+}
+
+/// Locals handed out to injected code to hold temporary values, see [`AddLocal::scratch_local`].
+/// They are reused within their function, and the ones that end up unused are removed when encoding.
+///
+/// [`AddLocal::scratch_local`]: crate::module_builder::AddLocal::scratch_local
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ScratchLocals {
+    /// Every scratch local of the function
+    all: Vec<(LocalID, DataType)>,
+    /// The released scratch locals, per type
+    free: HashMap<DataType, Vec<LocalID>>,
+    /// The scratch locals taken in each open scope, released when exiting the scope
+    scopes: Vec<Vec<LocalID>>,
+}
+
+/// The bytes of a parsed function body (locals and code) along with the shape of the body
+/// when it was parsed, to check that it has not been modified since.
+#[derive(Debug, Clone)]
+pub(crate) struct OriginalBody<'a> {
+    pub(crate) bytes: &'a [u8],
+    /// Offset of the body in the parsed binary
+    pub(crate) offset: usize,
+    pub(crate) num_locals: usize,
+    pub(crate) num_instructions: usize,
+}
+
+impl<'a> OriginalBody<'a> {
+    /// Read the body again from the parsed binary
+    fn reader(&self) -> wasmparser::FunctionBody<'a> {
+        wasmparser::FunctionBody::new(wasmparser::BinaryReader::new(
+            self.bytes,
+            self.offset,
+            wasmparser::WasmFeatures::all(),
+        ))
+    }
+
+    /// Offsets of the instructions in the parsed binary
+    pub(crate) fn instr_offsets(&self) -> Vec<usize> {
+        self.reader()
+            .get_operators_reader()
+            .map(|reader| {
+                reader
+                    .into_iter_with_offsets()
+                    .map_while(|op| op.ok().map(|(_, offset)| offset))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether `locals` and `instructions` are still the ones of the parsed body
+    fn matches(&self, locals: &[(u32, DataType)], instructions: &[Instruction]) -> bool {
+        let body = self.reader();
+        let same_locals = body
+            .get_locals_reader()
+            .and_then(|reader| {
+                reader
+                    .into_iter()
+                    .collect::<std::result::Result<Vec<_>, _>>()
+            })
+            .is_ok_and(|parsed| {
+                parsed.len() == locals.len()
+                    && parsed
+                        .iter()
+                        .zip(locals)
+                        .all(|((count, ty), (n, dt))| count == n && DataType::from(*ty) == *dt)
+            });
+        same_locals
+            && body.get_operators_reader().is_ok_and(|reader| {
+                reader
+                    .into_iter()
+                    .zip(instructions)
+                    .all(|(op, instr)| op.is_ok_and(|op| op == instr.op))
+            })
+    }
 }
 
 // 'b should outlive 'a
@@ -851,12 +1397,12 @@ where
     }
 
     /// Get some operator (instruction) at the specified index of the body
-    pub fn get_op(&self, idx: usize) -> &Operator {
+    pub fn get_op(&self, idx: usize) -> &Operator<'_> {
         &self.instructions[idx].op
     }
 
     /// Get the instrumentation of some operator in the body
-    pub fn get_instr_flag(&self, idx: usize) -> &InstrumentationFlag {
+    pub fn get_instr_flag(&self, idx: usize) -> &InstrumentationFlag<'_> {
         &self.instructions[idx].instr_flag
     }
 
@@ -869,6 +1415,144 @@ where
     pub fn end(&mut self) {
         self.push_op(Operator::End);
     }
+
+    /// Take a scratch local of type `ty`, a released one is reused if there is one
+    pub(crate) fn scratch_local(&mut self, ty: DataType, num_params: usize) -> LocalID {
+        let scratch = self.scratch.get_or_insert_default();
+        let id = match scratch.free.get_mut(&ty).and_then(Vec::pop) {
+            Some(id) => id,
+            None => {
+                let id = add_local(ty, num_params, &mut self.num_locals, &mut self.locals);
+                scratch.all.push((id, ty));
+                id
+            }
+        };
+        if let Some(scope) = scratch.scopes.last_mut() {
+            scope.push(id);
+        }
+        id
+    }
+
+    /// Release a scratch local so that it can be handed out again, releasing it twice does nothing
+    pub(crate) fn release_scratch_local(&mut self, id: LocalID) {
+        let Some((scratch, ty)) = self.scratch.as_mut().and_then(|scratch| {
+            let (_, ty) = *scratch.all.iter().find(|(local, _)| *local == id)?;
+            Some((scratch, ty))
+        }) else {
+            panic!("{:?} is not a scratch local of this function", id);
+        };
+        let free = scratch.free.entry(ty).or_default();
+        if !free.contains(&id) {
+            free.push(id);
+        }
+        for scope in scratch.scopes.iter_mut() {
+            scope.retain(|local| *local != id);
+        }
+    }
+
+    /// Open a scope, the scratch locals taken until it is exited are released then
+    pub(crate) fn enter_scratch_scope(&mut self) {
+        self.scratch.get_or_insert_default().scopes.push(vec![]);
+    }
+
+    /// Exit the innermost scope and release the scratch locals taken in it
+    pub(crate) fn exit_scratch_scope(&mut self) {
+        let scope = self
+            .scratch
+            .as_mut()
+            .and_then(|scratch| scratch.scopes.pop())
+            .expect("No scratch scope to exit");
+        for id in scope {
+            self.release_scratch_local(id);
+        }
+    }
+
+    /// Remove the scratch locals that no instruction or instrumentation of the body uses.
+    /// The locals after them are renumbered, in the code as well.
+    pub(crate) fn trim_scratch_locals(&mut self, num_params: usize) {
+        let Some(scratch) = &self.scratch else {
+            return;
+        };
+        let mut unused: HashSet<LocalID> = scratch.all.iter().map(|(id, _)| *id).collect();
+        for instr in self.instructions.iter_mut() {
+            for op in std::iter::once(&mut instr.op).chain(instr.instr_flag.ops_mut()) {
+                if let Some(local_index) = local_index_mut(op) {
+                    unused.remove(&LocalID(*local_index));
+                }
+            }
+        }
+        if unused.is_empty() {
+            return;
+        }
+        let mut unused: Vec<u32> = unused.into_iter().map(|id| *id).collect();
+        unused.sort_unstable();
+        let renumber = |id: u32| id - unused.partition_point(|trimmed| *trimmed < id) as u32;
+
+        // the types of the locals, one by one
+        let types: Vec<DataType> = self
+            .locals
+            .iter()
+            .flat_map(|(count, ty)| std::iter::repeat_n(*ty, *count as usize))
+            .collect();
+        self.locals.clear();
+        self.num_locals = 0;
+        for (idx, ty) in types.into_iter().enumerate() {
+            if unused.binary_search(&((num_params + idx) as u32)).is_err() {
+                add_local(ty, num_params, &mut self.num_locals, &mut self.locals);
+            }
+        }
+
+        for instr in self.instructions.iter_mut() {
+            for op in std::iter::once(&mut instr.op).chain(instr.instr_flag.ops_mut()) {
+                if let Some(local_index) = local_index_mut(op) {
+                    *local_index = renumber(*local_index);
+                }
+            }
+        }
+        let ScratchLocals { all, free, scopes } = self.scratch.as_deref_mut().unwrap();
+        all.retain(|(id, _)| unused.binary_search(id).is_err());
+        for (id, _) in all.iter_mut() {
+            *id = LocalID(renumber(**id));
+        }
+        for ids in free.values_mut().chain(scopes.iter_mut()) {
+            ids.retain(|id| unused.binary_search(id).is_err());
+            for id in ids.iter_mut() {
+                *id = LocalID(renumber(**id));
+            }
+        }
+    }
+
+    /// The bytes of the body in the parsed binary if it has not been modified or instrumented since,
+    /// in which case they can be copied as is instead of encoding the body again.
+    /// `locals` and `instructions` can be edited in place, so they are compared with the parsed body.
+    pub(crate) fn original_bytes(&self) -> Option<&'a [u8]> {
+        let original = self.original.as_ref()?;
+        let unmodified = self.num_locals == original.num_locals
+            && self.instructions.len() == original.num_instructions
+            && self.instructions.iter().all(|i| !i.instr_flag.has_instr())
+            && original.matches(&self.locals, &self.instructions);
+        unmodified.then_some(original.bytes)
+    }
+}
+
+/// The index of the local accessed by `op`, if any
+pub(crate) fn local_index(op: &Operator) -> Option<u32> {
+    match op {
+        Operator::LocalGet { local_index }
+        | Operator::LocalSet { local_index }
+        | Operator::LocalTee { local_index } => Some(*local_index),
+        _ => None,
+    }
+}
+
+/// A mutable reference to the index of the local accessed by `op`, if any
+fn local_index_mut<'a, 'b>(op: &'a mut Operator<'b>) -> Option<&'a mut u32> {
+    match op {
+        Operator::LocalGet { local_index }
+        | Operator::LocalSet { local_index }
+        | Operator::LocalTee { local_index } => Some(local_index),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -892,10 +1576,9 @@ where
     }
 }
 
-/// A constant which is produced in WebAssembly, typically used in global
-/// initializers or element/data offsets.
+/// An instruction of a constant expression
 #[derive(Debug, Copy, Clone)]
-pub enum InitExpr {
+pub enum InitInstr {
     /// An immediate constant value
     Value(Value),
     /// A constant value referenced by the global specified
@@ -904,62 +1587,203 @@ pub enum InitExpr {
     RefNull(RefType),
     /// A function initializer
     RefFunc(FunctionID),
+    // Extended constant expressions
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    // GC constant expressions
+    RefI31,
+    StructNew(TypeID),
+    StructNewDefault(TypeID),
+    ArrayNew(TypeID),
+    ArrayNewDefault(TypeID),
+    ArrayNewFixed {
+        ty: TypeID,
+        size: u32,
+    },
+    AnyConvertExtern,
+    ExternConvertAny,
+}
+
+/// A constant expression which is produced in WebAssembly, typically used in global
+/// initializers or element/data offsets. With the extended-const proposal this is a
+/// sequence of instructions rather than a single one.
+#[derive(Debug, Clone)]
+pub struct InitExpr {
+    pub exprs: Vec<InitInstr>,
+}
+
+impl From<Value> for InitExpr {
+    fn from(value: Value) -> Self {
+        InitExpr::new(vec![InitInstr::Value(value)])
+    }
 }
 
 impl InitExpr {
-    pub(crate) fn eval(init: &ConstExpr) -> InitExpr {
-        use wasmparser::Operator::*;
-        let mut reader = init.get_operators_reader();
-        let val = match reader.read().unwrap() {
-            I32Const { value } => InitExpr::Value(Value::I32(value)),
-            I64Const { value } => InitExpr::Value(Value::I64(value)),
-            F32Const { value } => InitExpr::Value(Value::F32(f32::from_bits(value.bits()))),
-            F64Const { value } => InitExpr::Value(Value::F64(f64::from_bits(value.bits()))),
-            V128Const { value } => InitExpr::Value(Value::V128(v128_to_u128(&value))),
-            GlobalGet { global_index } => InitExpr::Global(GlobalID(global_index)),
-            // Marking nullable as true as it's a null reference
-            RefNull { hty } => InitExpr::RefNull(RefType::new(true, hty).unwrap()),
-            RefFunc { function_index } => InitExpr::RefFunc(FunctionID(function_index)),
-            _ => panic!("invalid constant expression"),
-        };
-        match reader.read().unwrap() {
-            End => {}
-            _ => panic!("invalid constant expression"),
+    /// Create a new constant expression from its instructions (without the final `end`)
+    pub fn new(exprs: Vec<InitInstr>) -> Self {
+        Self { exprs }
+    }
+
+    /// Evaluate the expression to a number, returns `None` if it reads a global
+    /// or produces a reference.
+    pub fn value(&self) -> Option<Value> {
+        self.eval_with(|_| None)
+    }
+
+    /// Evaluate the expression to a number, using `global` to look up the value of a `global.get`.
+    /// Returns `None` if a global is unknown or the expression produces a reference.
+    pub fn eval_with(&self, global: impl Fn(GlobalID) -> Option<Value>) -> Option<Value> {
+        let mut stack = vec![];
+        for instr in self.exprs.iter() {
+            let val = match instr {
+                InitInstr::Value(v) => *v,
+                InitInstr::Global(g) => global(*g)?,
+                InitInstr::I32Add | InitInstr::I32Sub | InitInstr::I32Mul => {
+                    let (Value::I32(rhs), Value::I32(lhs)) = (stack.pop()?, stack.pop()?) else {
+                        return None;
+                    };
+                    Value::I32(match instr {
+                        InitInstr::I32Add => lhs.wrapping_add(rhs),
+                        InitInstr::I32Sub => lhs.wrapping_sub(rhs),
+                        _ => lhs.wrapping_mul(rhs),
+                    })
+                }
+                InitInstr::I64Add | InitInstr::I64Sub | InitInstr::I64Mul => {
+                    let (Value::I64(rhs), Value::I64(lhs)) = (stack.pop()?, stack.pop()?) else {
+                        return None;
+                    };
+                    Value::I64(match instr {
+                        InitInstr::I64Add => lhs.wrapping_add(rhs),
+                        InitInstr::I64Sub => lhs.wrapping_sub(rhs),
+                        _ => lhs.wrapping_mul(rhs),
+                    })
+                }
+                _ => return None,
+            };
+            stack.push(val);
+        }
+        match stack.as_slice() {
+            [val] => Some(*val),
+            _ => None,
         }
-        reader.ensure_end().unwrap();
-        val
     }
 
-    pub(crate) fn to_wasmencoder_type(self) -> wasm_encoder::ConstExpr {
-        match self {
-            InitExpr::Value(v) => match v {
-                Value::I32(v) => wasm_encoder::ConstExpr::i32_const(v),
-                Value::I64(v) => wasm_encoder::ConstExpr::i64_const(v),
-                Value::F32(v) => wasm_encoder::ConstExpr::f32_const(v),
-                Value::F64(v) => wasm_encoder::ConstExpr::f64_const(v),
-                Value::V128(v) => wasm_encoder::ConstExpr::v128_const(v as i128),
-            },
-            InitExpr::Global(g) => wasm_encoder::ConstExpr::global_get(*g),
-            InitExpr::RefNull(ty) => wasm_encoder::ConstExpr::ref_null(if ty.is_func_ref() {
-                wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::Func,
+    pub(crate) fn eval(init: &ConstExpr) -> Result<InitExpr> {
+        use wasmparser::Operator::*;
+        let mut exprs = vec![];
+        let mut reader = init.get_operators_reader();
+        loop {
+            let instr = match reader.read()? {
+                I32Const { value } => InitInstr::Value(Value::I32(value)),
+                I64Const { value } => InitInstr::Value(Value::I64(value)),
+                F32Const { value } => InitInstr::Value(Value::F32(f32::from_bits(value.bits()))),
+                F64Const { value } => InitInstr::Value(Value::F64(f64::from_bits(value.bits()))),
+                V128Const { value } => InitInstr::Value(Value::V128(v128_to_u128(&value))),
+                GlobalGet { global_index } => InitInstr::Global(GlobalID(global_index)),
+                // Marking nullable as true as it's a null reference
+                RefNull { hty } => InitInstr::RefNull(RefType::new(true, hty).unwrap()),
+                RefFunc { function_index } => InitInstr::RefFunc(FunctionID(function_index)),
+                I32Add => InitInstr::I32Add,
+                I32Sub => InitInstr::I32Sub,
+                I32Mul => InitInstr::I32Mul,
+                I64Add => InitInstr::I64Add,
+                I64Sub => InitInstr::I64Sub,
+                I64Mul => InitInstr::I64Mul,
+                RefI31 => InitInstr::RefI31,
+                StructNew { struct_type_index } => InitInstr::StructNew(TypeID(struct_type_index)),
+                StructNewDefault { struct_type_index } => {
+                    InitInstr::StructNewDefault(TypeID(struct_type_index))
                 }
-            } else if ty.is_extern_ref() {
-                wasm_encoder::HeapType::Abstract {
-                    shared: false,
-                    ty: AbstractHeapType::Extern,
+                ArrayNew { array_type_index } => InitInstr::ArrayNew(TypeID(array_type_index)),
+                ArrayNewDefault { array_type_index } => {
+                    InitInstr::ArrayNewDefault(TypeID(array_type_index))
                 }
-            } else {
-                unreachable!()
-            }),
-            InitExpr::RefFunc(f) => wasm_encoder::ConstExpr::ref_func(*f),
+                ArrayNewFixed {
+                    array_type_index,
+                    array_size,
+                } => InitInstr::ArrayNewFixed {
+                    ty: TypeID(array_type_index),
+                    size: array_size,
+                },
+                AnyConvertExtern => InitInstr::AnyConvertExtern,
+                ExternConvertAny => InitInstr::ExternConvertAny,
+                End => break,
+                op => {
+                    return Err(Error::ConversionError(format!(
+                        "Invalid instruction in a constant expression: {:?}",
+                        op
+                    )))
+                }
+            };
+            exprs.push(instr);
+        }
+        reader.ensure_end()?;
+        Ok(InitExpr { exprs })
+    }
+
+    /// Encode the expression, remapping the function and global IDs to their (possibly changed)
+    /// index in the encoded module.
+    pub(crate) fn to_wasmencoder_type(
+        &self,
+        func_mapping: &HashMap<u32, u32>,
+        global_mapping: &HashMap<u32, u32>,
+    ) -> wasm_encoder::ConstExpr {
+        use wasm_encoder::{Encode, Instruction};
+        let mut bytes = vec![];
+        for instr in self.exprs.iter() {
+            let instr = match instr {
+                InitInstr::Value(v) => match v {
+                    Value::I32(v) => Instruction::I32Const(*v),
+                    Value::I64(v) => Instruction::I64Const(*v),
+                    Value::F32(v) => Instruction::F32Const(*v),
+                    Value::F64(v) => Instruction::F64Const(*v),
+                    Value::V128(v) => Instruction::V128Const(*v as i128),
+                },
+                InitInstr::Global(g) => Instruction::GlobalGet(
+                    *global_mapping
+                        .get(&**g)
+                        .expect("Constant expression refers to an unknown global"),
+                ),
+                InitInstr::RefNull(ty) => Instruction::RefNull(
+                    wasm_encoder::reencode::RoundtripReencoder
+                        .heap_type(ty.heap_type())
+                        .unwrap(),
+                ),
+                InitInstr::RefFunc(f) => Instruction::RefFunc(
+                    *func_mapping
+                        .get(&**f)
+                        .expect("Constant expression refers to an unknown function"),
+                ),
+                InitInstr::I32Add => Instruction::I32Add,
+                InitInstr::I32Sub => Instruction::I32Sub,
+                InitInstr::I32Mul => Instruction::I32Mul,
+                InitInstr::I64Add => Instruction::I64Add,
+                InitInstr::I64Sub => Instruction::I64Sub,
+                InitInstr::I64Mul => Instruction::I64Mul,
+                InitInstr::RefI31 => Instruction::RefI31,
+                InitInstr::StructNew(ty) => Instruction::StructNew(**ty),
+                InitInstr::StructNewDefault(ty) => Instruction::StructNewDefault(**ty),
+                InitInstr::ArrayNew(ty) => Instruction::ArrayNew(**ty),
+                InitInstr::ArrayNewDefault(ty) => Instruction::ArrayNewDefault(**ty),
+                InitInstr::ArrayNewFixed { ty, size } => Instruction::ArrayNewFixed {
+                    array_type_index: **ty,
+                    array_size: *size,
+                },
+                InitInstr::AnyConvertExtern => Instruction::AnyConvertExtern,
+                InitInstr::ExternConvertAny => Instruction::ExternConvertAny,
+            };
+            instr.encode(&mut bytes);
         }
+        wasm_encoder::ConstExpr::raw(bytes)
     }
 }
 
 /// Constant values that can show up in WebAssembly
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Value {
     /// A constant 32-bit integer
     I32(i32),
@@ -997,6 +1821,47 @@ pub enum BlockType {
     FuncType(TypeID),
 }
 
+/// A catch clause of a `try_table` instruction (exception-handling proposal).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Catch {
+    /// Catch exceptions with the given tag, branching to `label` with the payload.
+    One { tag: TagID, label: u32 },
+    /// Catch exceptions with the given tag, branching to `label` with the payload and the `exnref`.
+    OneRef { tag: TagID, label: u32 },
+    /// Catch any exception, branching to `label`.
+    All { label: u32 },
+    /// Catch any exception, branching to `label` with the `exnref`.
+    AllRef { label: u32 },
+}
+
+impl From<wasmparser::Catch> for Catch {
+    fn from(value: wasmparser::Catch) -> Self {
+        match value {
+            wasmparser::Catch::One { tag, label } => Catch::One {
+                tag: TagID(tag),
+                label,
+            },
+            wasmparser::Catch::OneRef { tag, label } => Catch::OneRef {
+                tag: TagID(tag),
+                label,
+            },
+            wasmparser::Catch::All { label } => Catch::All { label },
+            wasmparser::Catch::AllRef { label } => Catch::AllRef { label },
+        }
+    }
+}
+
+impl From<Catch> for wasmparser::Catch {
+    fn from(catch: Catch) -> Self {
+        match catch {
+            Catch::One { tag, label } => wasmparser::Catch::One { tag: *tag, label },
+            Catch::OneRef { tag, label } => wasmparser::Catch::OneRef { tag: *tag, label },
+            Catch::All { label } => wasmparser::Catch::All { label },
+            Catch::AllRef { label } => wasmparser::Catch::AllRef { label },
+        }
+    }
+}
+
 impl From<wasmparser::BlockType> for BlockType {
     fn from(value: wasmparser::BlockType) -> Self {
         match value {
@@ -1044,7 +1909,7 @@ impl<'a> CustomSections<'a> {
     }
 
     /// Get a custom section by its ID
-    pub fn get_by_id(&self, custom_section_id: CustomSectionID) -> &CustomSection {
+    pub fn get_by_id(&self, custom_section_id: CustomSectionID) -> &CustomSection<'_> {
         if *custom_section_id < self.custom_sections.len() as u32 {
             return &self.custom_sections[*custom_section_id as usize];
         }
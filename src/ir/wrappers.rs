@@ -1,13 +1,15 @@
 //! Wrapper functions
 
+use crate::error::Error;
+use crate::DataType;
 use std::collections::HashMap;
 use wasm_encoder::reencode::Reencode;
 use wasm_encoder::{
-    Alias, ComponentFuncTypeEncoder, ComponentTypeEncoder, CoreTypeEncoder, InstanceType,
+    Alias, ComponentFuncTypeEncoder, ComponentTypeEncoder, CoreTypeEncoder, Encode, InstanceType,
 };
 use wasmparser::{
-    ComponentAlias, ComponentFuncResult, ComponentType, ComponentTypeDeclaration, CoreType,
-    InstanceTypeDeclaration, Operator, SubType,
+    Catch, ComponentAlias, ComponentFuncResult, ComponentType, ComponentTypeDeclaration, CoreType,
+    InstanceTypeDeclaration, MemArg, Operator, SubType,
 };
 
 // Not added to wasm-tools
@@ -16,13 +18,13 @@ pub fn convert_module_type_declaration(
     module: &[wasmparser::ModuleTypeDeclaration],
     enc: CoreTypeEncoder,
     reencode: &mut wasm_encoder::reencode::RoundtripReencoder,
-) {
+) -> Result<(), Error> {
     let mut mty = wasm_encoder::ModuleType::new();
     for m in module.iter() {
         match m {
             wasmparser::ModuleTypeDeclaration::Type(sub) => {
                 let enc_mty = mty.ty();
-                encode_core_type_subtype(enc_mty, sub, reencode);
+                encode_core_type_subtype(enc_mty, sub, reencode)?;
             }
             wasmparser::ModuleTypeDeclaration::Export { name, ty } => {
                 mty.export(name, reencode.entity_type(*ty).unwrap());
@@ -44,6 +46,75 @@ pub fn convert_module_type_declaration(
         }
     }
     enc.module(&mty);
+    Ok(())
+}
+
+// Not added to wasm-tools
+/// Encode an entry of a component's core type section. Unlike `CoreTypeEncoder`, this can
+/// write struct and array types, also when they are declared in a module type.
+pub(crate) fn encode_core_type(
+    ty: &CoreType,
+    sink: &mut Vec<u8>,
+    reencode: &mut wasm_encoder::reencode::RoundtripReencoder,
+) -> Result<(), Error> {
+    match ty {
+        CoreType::Sub(sub) => {
+            // 0x50 starts a module type here, so the subtype can't have a prefix byte
+            if !sub.is_final || sub.supertype_idx.is_some() {
+                return Err(Error::UnsupportedCoreType {
+                    reason: "a component core type cannot be open or have a supertype",
+                });
+            }
+            encode_subtype(sub, sink, reencode)
+        }
+        CoreType::Module(module) => {
+            // Same layout as `wasm_encoder::ModuleType`
+            sink.push(0x50);
+            module.len().encode(sink);
+            for m in module.iter() {
+                match m {
+                    wasmparser::ModuleTypeDeclaration::Import(import) => {
+                        sink.push(0x00);
+                        import.module.encode(sink);
+                        import.name.encode(sink);
+                        reencode.entity_type(import.ty).unwrap().encode(sink);
+                    }
+                    wasmparser::ModuleTypeDeclaration::Type(sub) => {
+                        sink.push(0x01);
+                        encode_subtype(sub, sink, reencode)?;
+                    }
+                    wasmparser::ModuleTypeDeclaration::OuterAlias {
+                        kind: _kind,
+                        count,
+                        index,
+                    } => {
+                        // outer alias of a core type
+                        sink.extend([0x02, 0x10, 0x01]);
+                        count.encode(sink);
+                        index.encode(sink);
+                    }
+                    wasmparser::ModuleTypeDeclaration::Export { name, ty } => {
+                        sink.push(0x03);
+                        name.encode(sink);
+                        reencode.entity_type(*ty).unwrap().encode(sink);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn encode_subtype(
+    subtype: &SubType,
+    sink: &mut Vec<u8>,
+    reencode: &mut wasm_encoder::reencode::RoundtripReencoder,
+) -> Result<(), Error> {
+    reencode
+        .sub_type(subtype.clone())
+        .map_err(|e| Error::ConversionError(e.to_string()))?
+        .encode(sink);
+    Ok(())
 }
 
 // Not added to wasm-tools
@@ -51,23 +122,23 @@ pub fn convert_module_type_declaration(
 pub fn convert_instance_type(
     instance: &[InstanceTypeDeclaration],
     reencode: &mut wasm_encoder::reencode::RoundtripReencoder,
-) -> InstanceType {
+) -> Result<InstanceType, Error> {
     let mut ity = InstanceType::new();
     for value in instance.iter() {
         match value {
             InstanceTypeDeclaration::CoreType(core_type) => match core_type {
                 CoreType::Sub(sub) => {
                     let enc = ity.core_type();
-                    encode_core_type_subtype(enc, sub, reencode);
+                    encode_core_type_subtype(enc, sub, reencode)?;
                 }
                 CoreType::Module(module) => {
                     let enc = ity.core_type();
-                    convert_module_type_declaration(module, enc, reencode);
+                    convert_module_type_declaration(module, enc, reencode)?;
                 }
             },
             InstanceTypeDeclaration::Type(ty) => {
                 let enc = ity.ty();
-                convert_component_type(ty, enc, reencode);
+                convert_component_type(ty, enc, reencode)?;
             }
             InstanceTypeDeclaration::Alias(alias) => match alias {
                 ComponentAlias::InstanceExport {
@@ -105,7 +176,7 @@ pub fn convert_instance_type(
             }
         }
     }
-    ity
+    Ok(ity)
 }
 
 // Not added to wasm-tools
@@ -132,12 +203,14 @@ pub fn convert_results(
 
 // Not added to wasm-tools
 /// CoreTypeEncoding
+///
+/// `CoreTypeEncoder` can only write function types, so struct and array types are an error here.
+/// Those are written with `encode_core_type` where the section is encoded by hand.
 pub fn encode_core_type_subtype(
     enc: CoreTypeEncoder,
     subtype: &SubType,
     reencode: &mut wasm_encoder::reencode::RoundtripReencoder,
-) {
-    // TODO: Struct and Arrays once added to wasm_encoder - still in GC Proposal
+) -> Result<(), Error> {
     match &subtype.composite_type.inner {
         wasmparser::CompositeInnerType::Func(func) => {
             enc.function(
@@ -150,9 +223,12 @@ pub fn encode_core_type_subtype(
                     .map(|val_type| reencode.val_type(*val_type).unwrap())
                     .collect::<Vec<_>>(),
             );
+            Ok(())
         }
         wasmparser::CompositeInnerType::Array(_) | wasmparser::CompositeInnerType::Struct(_) => {
-            panic!("Still in GC Proposal")
+            Err(Error::UnsupportedCoreType {
+                reason: "struct and array types can't be encoded in instance or component types",
+            })
         }
     }
 }
@@ -194,7 +270,7 @@ pub fn convert_component_type(
     ty: &ComponentType,
     enc: ComponentTypeEncoder,
     reencode: &mut wasm_encoder::reencode::RoundtripReencoder,
-) {
+) -> Result<(), Error> {
     match ty {
         ComponentType::Defined(comp_ty) => {
             let def_enc = enc.defined_type();
@@ -261,16 +337,16 @@ pub fn convert_component_type(
                     ComponentTypeDeclaration::CoreType(core) => match core {
                         CoreType::Sub(sub) => {
                             let enc = new_comp.core_type();
-                            encode_core_type_subtype(enc, sub, reencode);
+                            encode_core_type_subtype(enc, sub, reencode)?;
                         }
                         CoreType::Module(module) => {
                             let enc = new_comp.core_type();
-                            convert_module_type_declaration(module, enc, reencode);
+                            convert_module_type_declaration(module, enc, reencode)?;
                         }
                     },
                     ComponentTypeDeclaration::Type(typ) => {
                         let enc = new_comp.ty();
-                        convert_component_type(typ, enc, reencode);
+                        convert_component_type(typ, enc, reencode)?;
                     }
                     ComponentTypeDeclaration::Alias(a) => {
                         new_comp.alias(process_alias(a, reencode));
@@ -286,13 +362,14 @@ pub fn convert_component_type(
             enc.component(&new_comp);
         }
         ComponentType::Instance(inst) => {
-            let ity = convert_instance_type(inst, reencode);
+            let ity = convert_instance_type(inst, reencode)?;
             enc.instance(&ity);
         }
         ComponentType::Resource { rep, dtor } => {
             enc.resource(reencode.val_type(*rep).unwrap(), *dtor);
         }
     }
+    Ok(())
 }
 
 pub fn indirect_namemap_parser2encoder(
@@ -322,8 +399,220 @@ pub fn add_to_namemap(namemap: &mut wasm_encoder::NameMap, names: wasmparser::Na
     }
 }
 
+/// How an instruction accesses memory
+pub(crate) struct MemAccessKind {
+    pub(crate) memarg: MemArg,
+    /// Number of bytes accessed
+    pub(crate) width: u32,
+    /// The types of the operands on top of the address, in stack order
+    pub(crate) operands: Vec<DataType>,
+    /// Index into `operands` of the value written to memory
+    pub(crate) value: Option<usize>,
+}
+
+/// Get how `op` accesses memory, `None` if it does not access memory through a `MemArg`
+pub(crate) fn memory_access_kind(op: &Operator) -> Option<MemAccessKind> {
+    use DataType::{I32, I64, V128};
+    let (memarg, width, operands, value) = match op {
+        // loads
+        Operator::I32Load8S { memarg }
+        | Operator::I32Load8U { memarg }
+        | Operator::I64Load8S { memarg }
+        | Operator::I64Load8U { memarg }
+        | Operator::I32AtomicLoad8U { memarg }
+        | Operator::I64AtomicLoad8U { memarg }
+        | Operator::V128Load8Splat { memarg } => (memarg, 1, vec![], None),
+        Operator::I32Load16S { memarg }
+        | Operator::I32Load16U { memarg }
+        | Operator::I64Load16S { memarg }
+        | Operator::I64Load16U { memarg }
+        | Operator::I32AtomicLoad16U { memarg }
+        | Operator::I64AtomicLoad16U { memarg }
+        | Operator::V128Load16Splat { memarg } => (memarg, 2, vec![], None),
+        Operator::I32Load { memarg }
+        | Operator::F32Load { memarg }
+        | Operator::I64Load32S { memarg }
+        | Operator::I64Load32U { memarg }
+        | Operator::I32AtomicLoad { memarg }
+        | Operator::I64AtomicLoad32U { memarg }
+        | Operator::V128Load32Splat { memarg }
+        | Operator::V128Load32Zero { memarg } => (memarg, 4, vec![], None),
+        Operator::I64Load { memarg }
+        | Operator::F64Load { memarg }
+        | Operator::I64AtomicLoad { memarg }
+        | Operator::V128Load8x8S { memarg }
+        | Operator::V128Load8x8U { memarg }
+        | Operator::V128Load16x4S { memarg }
+        | Operator::V128Load16x4U { memarg }
+        | Operator::V128Load32x2S { memarg }
+        | Operator::V128Load32x2U { memarg }
+        | Operator::V128Load64Splat { memarg }
+        | Operator::V128Load64Zero { memarg } => (memarg, 8, vec![], None),
+        Operator::V128Load { memarg } => (memarg, 16, vec![], None),
+        // lane loads replace a lane of the vector on top of the address
+        Operator::V128Load8Lane { memarg, .. } => (memarg, 1, vec![V128], None),
+        Operator::V128Load16Lane { memarg, .. } => (memarg, 2, vec![V128], None),
+        Operator::V128Load32Lane { memarg, .. } => (memarg, 4, vec![V128], None),
+        Operator::V128Load64Lane { memarg, .. } => (memarg, 8, vec![V128], None),
+
+        // stores
+        Operator::I32Store8 { memarg } | Operator::I32AtomicStore8 { memarg } => {
+            (memarg, 1, vec![I32], Some(0))
+        }
+        Operator::I64Store8 { memarg } | Operator::I64AtomicStore8 { memarg } => {
+            (memarg, 1, vec![I64], Some(0))
+        }
+        Operator::I32Store16 { memarg } | Operator::I32AtomicStore16 { memarg } => {
+            (memarg, 2, vec![I32], Some(0))
+        }
+        Operator::I64Store16 { memarg } | Operator::I64AtomicStore16 { memarg } => {
+            (memarg, 2, vec![I64], Some(0))
+        }
+        Operator::I32Store { memarg } | Operator::I32AtomicStore { memarg } => {
+            (memarg, 4, vec![I32], Some(0))
+        }
+        Operator::F32Store { memarg } => (memarg, 4, vec![DataType::F32], Some(0)),
+        Operator::I64Store32 { memarg } | Operator::I64AtomicStore32 { memarg } => {
+            (memarg, 4, vec![I64], Some(0))
+        }
+        Operator::I64Store { memarg } | Operator::I64AtomicStore { memarg } => {
+            (memarg, 8, vec![I64], Some(0))
+        }
+        Operator::F64Store { memarg } => (memarg, 8, vec![DataType::F64], Some(0)),
+        Operator::V128Store { memarg } => (memarg, 16, vec![V128], Some(0)),
+        // lane stores write a lane of the vector on top of the address
+        Operator::V128Store8Lane { memarg, .. } => (memarg, 1, vec![V128], Some(0)),
+        Operator::V128Store16Lane { memarg, .. } => (memarg, 2, vec![V128], Some(0)),
+        Operator::V128Store32Lane { memarg, .. } => (memarg, 4, vec![V128], Some(0)),
+        Operator::V128Store64Lane { memarg, .. } => (memarg, 8, vec![V128], Some(0)),
+
+        // read-modify-writes, the value is the operand of the operation
+        Operator::I32AtomicRmw8AddU { memarg }
+        | Operator::I32AtomicRmw8SubU { memarg }
+        | Operator::I32AtomicRmw8AndU { memarg }
+        | Operator::I32AtomicRmw8OrU { memarg }
+        | Operator::I32AtomicRmw8XorU { memarg }
+        | Operator::I32AtomicRmw8XchgU { memarg } => (memarg, 1, vec![I32], Some(0)),
+        Operator::I64AtomicRmw8AddU { memarg }
+        | Operator::I64AtomicRmw8SubU { memarg }
+        | Operator::I64AtomicRmw8AndU { memarg }
+        | Operator::I64AtomicRmw8OrU { memarg }
+        | Operator::I64AtomicRmw8XorU { memarg }
+        | Operator::I64AtomicRmw8XchgU { memarg } => (memarg, 1, vec![I64], Some(0)),
+        Operator::I32AtomicRmw16AddU { memarg }
+        | Operator::I32AtomicRmw16SubU { memarg }
+        | Operator::I32AtomicRmw16AndU { memarg }
+        | Operator::I32AtomicRmw16OrU { memarg }
+        | Operator::I32AtomicRmw16XorU { memarg }
+        | Operator::I32AtomicRmw16XchgU { memarg } => (memarg, 2, vec![I32], Some(0)),
+        Operator::I64AtomicRmw16AddU { memarg }
+        | Operator::I64AtomicRmw16SubU { memarg }
+        | Operator::I64AtomicRmw16AndU { memarg }
+        | Operator::I64AtomicRmw16OrU { memarg }
+        | Operator::I64AtomicRmw16XorU { memarg }
+        | Operator::I64AtomicRmw16XchgU { memarg } => (memarg, 2, vec![I64], Some(0)),
+        Operator::I32AtomicRmwAdd { memarg }
+        | Operator::I32AtomicRmwSub { memarg }
+        | Operator::I32AtomicRmwAnd { memarg }
+        | Operator::I32AtomicRmwOr { memarg }
+        | Operator::I32AtomicRmwXor { memarg }
+        | Operator::I32AtomicRmwXchg { memarg } => (memarg, 4, vec![I32], Some(0)),
+        Operator::I64AtomicRmw32AddU { memarg }
+        | Operator::I64AtomicRmw32SubU { memarg }
+        | Operator::I64AtomicRmw32AndU { memarg }
+        | Operator::I64AtomicRmw32OrU { memarg }
+        | Operator::I64AtomicRmw32XorU { memarg }
+        | Operator::I64AtomicRmw32XchgU { memarg } => (memarg, 4, vec![I64], Some(0)),
+        Operator::I64AtomicRmwAdd { memarg }
+        | Operator::I64AtomicRmwSub { memarg }
+        | Operator::I64AtomicRmwAnd { memarg }
+        | Operator::I64AtomicRmwOr { memarg }
+        | Operator::I64AtomicRmwXor { memarg }
+        | Operator::I64AtomicRmwXchg { memarg } => (memarg, 8, vec![I64], Some(0)),
+        // compare-exchanges, the value is the replacement
+        Operator::I32AtomicRmw8CmpxchgU { memarg } => (memarg, 1, vec![I32, I32], Some(1)),
+        Operator::I64AtomicRmw8CmpxchgU { memarg } => (memarg, 1, vec![I64, I64], Some(1)),
+        Operator::I32AtomicRmw16CmpxchgU { memarg } => (memarg, 2, vec![I32, I32], Some(1)),
+        Operator::I64AtomicRmw16CmpxchgU { memarg } => (memarg, 2, vec![I64, I64], Some(1)),
+        Operator::I32AtomicRmwCmpxchg { memarg } => (memarg, 4, vec![I32, I32], Some(1)),
+        Operator::I64AtomicRmw32CmpxchgU { memarg } => (memarg, 4, vec![I64, I64], Some(1)),
+        Operator::I64AtomicRmwCmpxchg { memarg } => (memarg, 8, vec![I64, I64], Some(1)),
+
+        // waits and notifies, which do not write to memory
+        Operator::MemoryAtomicNotify { memarg } => (memarg, 4, vec![I32], None),
+        Operator::MemoryAtomicWait32 { memarg } => (memarg, 4, vec![I32, I64], None),
+        Operator::MemoryAtomicWait64 { memarg } => (memarg, 8, vec![I64, I64], None),
+        _ => return None,
+    };
+    Some(MemAccessKind {
+        memarg: *memarg,
+        width,
+        operands,
+        value,
+    })
+}
+
+/// Whether `op` is an atomic read-modify-write, which both reads and writes memory
+pub(crate) fn is_atomic_rmw(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::I32AtomicRmw8AddU { .. }
+            | Operator::I32AtomicRmw8SubU { .. }
+            | Operator::I32AtomicRmw8AndU { .. }
+            | Operator::I32AtomicRmw8OrU { .. }
+            | Operator::I32AtomicRmw8XorU { .. }
+            | Operator::I32AtomicRmw8XchgU { .. }
+            | Operator::I32AtomicRmw8CmpxchgU { .. }
+            | Operator::I64AtomicRmw8AddU { .. }
+            | Operator::I64AtomicRmw8SubU { .. }
+            | Operator::I64AtomicRmw8AndU { .. }
+            | Operator::I64AtomicRmw8OrU { .. }
+            | Operator::I64AtomicRmw8XorU { .. }
+            | Operator::I64AtomicRmw8XchgU { .. }
+            | Operator::I64AtomicRmw8CmpxchgU { .. }
+            | Operator::I32AtomicRmw16AddU { .. }
+            | Operator::I32AtomicRmw16SubU { .. }
+            | Operator::I32AtomicRmw16AndU { .. }
+            | Operator::I32AtomicRmw16OrU { .. }
+            | Operator::I32AtomicRmw16XorU { .. }
+            | Operator::I32AtomicRmw16XchgU { .. }
+            | Operator::I32AtomicRmw16CmpxchgU { .. }
+            | Operator::I64AtomicRmw16AddU { .. }
+            | Operator::I64AtomicRmw16SubU { .. }
+            | Operator::I64AtomicRmw16AndU { .. }
+            | Operator::I64AtomicRmw16OrU { .. }
+            | Operator::I64AtomicRmw16XorU { .. }
+            | Operator::I64AtomicRmw16XchgU { .. }
+            | Operator::I64AtomicRmw16CmpxchgU { .. }
+            | Operator::I32AtomicRmwAdd { .. }
+            | Operator::I64AtomicRmw32AddU { .. }
+            | Operator::I64AtomicRmwAdd { .. }
+            | Operator::I32AtomicRmwSub { .. }
+            | Operator::I64AtomicRmw32SubU { .. }
+            | Operator::I64AtomicRmwSub { .. }
+            | Operator::I32AtomicRmwAnd { .. }
+            | Operator::I64AtomicRmw32AndU { .. }
+            | Operator::I64AtomicRmwAnd { .. }
+            | Operator::I32AtomicRmwOr { .. }
+            | Operator::I64AtomicRmw32OrU { .. }
+            | Operator::I64AtomicRmwOr { .. }
+            | Operator::I32AtomicRmwXor { .. }
+            | Operator::I64AtomicRmw32XorU { .. }
+            | Operator::I64AtomicRmwXor { .. }
+            | Operator::I32AtomicRmwXchg { .. }
+            | Operator::I64AtomicRmw32XchgU { .. }
+            | Operator::I64AtomicRmwXchg { .. }
+            | Operator::I32AtomicRmwCmpxchg { .. }
+            | Operator::I64AtomicRmw32CmpxchgU { .. }
+            | Operator::I64AtomicRmwCmpxchg { .. }
+    )
+}
+
 pub(crate) fn refers_to_func(op: &Operator) -> bool {
-    matches!(op, Operator::Call { .. } | Operator::RefFunc { .. })
+    matches!(
+        op,
+        Operator::Call { .. } | Operator::ReturnCall { .. } | Operator::RefFunc { .. }
+    )
 }
 
 pub(crate) fn refers_to_global(op: &Operator) -> bool {
@@ -345,14 +634,14 @@ pub(crate) fn refers_to_global(op: &Operator) -> bool {
 
 pub(crate) fn update_fn_instr(op: &mut Operator, mapping: &HashMap<u32, u32>) {
     match op {
-        Operator::Call { function_index } | Operator::RefFunc { function_index } => {
-            match mapping.get(&(*function_index)) {
-                Some(new_index) => {
-                    *function_index = *new_index;
-                }
-                None => panic!("Deleted function!"),
+        Operator::Call { function_index }
+        | Operator::ReturnCall { function_index }
+        | Operator::RefFunc { function_index } => match mapping.get(&(*function_index)) {
+            Some(new_index) => {
+                *function_index = *new_index;
             }
-        }
+            None => panic!("Deleted function!"),
+        },
         _ => panic!("Operation doesn't need to be checked for function IDs!"),
     }
 }
@@ -380,3 +669,41 @@ pub(crate) fn update_global_instr(op: &mut Operator, mapping: &HashMap<u32, u32>
         _ => panic!("Operation doesn't need to be checked for global IDs!"),
     }
 }
+
+pub(crate) fn refers_to_tag(op: &Operator) -> bool {
+    match op {
+        Operator::Throw { .. } | Operator::Catch { .. } => true,
+        Operator::TryTable { try_table } => try_table
+            .catches
+            .iter()
+            .any(|catch| matches!(catch, Catch::One { .. } | Catch::OneRef { .. })),
+        _ => false,
+    }
+}
+
+pub(crate) fn update_tag_instr(
+    op: &mut Operator,
+    mapping: &HashMap<u32, u32>,
+) -> Result<(), Error> {
+    let update = |tag_index: &mut u32| match mapping.get(tag_index) {
+        Some(new_index) => {
+            *tag_index = *new_index;
+            Ok(())
+        }
+        None => Err(Error::UnknownTag {
+            tag_idx: *tag_index,
+        }),
+    };
+    match op {
+        Operator::Throw { tag_index } | Operator::Catch { tag_index } => update(tag_index),
+        Operator::TryTable { try_table } => {
+            for catch in try_table.catches.iter_mut() {
+                if let Catch::One { tag, .. } | Catch::OneRef { tag, .. } = catch {
+                    update(tag)?;
+                }
+            }
+            Ok(())
+        }
+        _ => panic!("Operation doesn't need to be checked for tag IDs!"),
+    }
+}
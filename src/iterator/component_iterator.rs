@@ -1,11 +1,13 @@
 //! Iterator to traverse a Component
 
 use crate::ir::component::Component;
-use crate::ir::id::{FunctionID, GlobalID, LocalID, ModuleID};
+use crate::ir::id::{FunctionID, GlobalID, LocalID, MemoryID, ModuleID};
 use crate::ir::module::module_functions::{FuncKind, LocalFunction};
 use crate::ir::module::module_globals::Global;
-use crate::ir::module::Iter;
-use crate::ir::types::{DataType, FuncInstrMode, InstrumentationMode, Location};
+use crate::ir::module::module_types::FuncType;
+use crate::ir::module::{Iter, Module};
+use crate::ir::types::{ComponentPath, DataType, FuncInstrMode, InstrumentationMode, Location};
+use crate::iterator::func_filter::FuncFilter;
 use crate::iterator::iterator_trait::{IteratingInstrumenter, Iterator};
 use crate::module_builder::AddLocal;
 use crate::opcode::{Inject, InjectAt, Instrumenter, MacroOpcode, Opcode};
@@ -22,61 +24,68 @@ pub struct ComponentIterator<'a, 'b> {
     comp_iterator: ComponentSubIterator,
 }
 
-fn print_metadata(metadata: &HashMap<ModuleID, HashMap<FunctionID, usize>>) {
-    for c in metadata.keys() {
-        println!("Module: {:?}", c);
-        for (m, i) in metadata.get(c).unwrap().iter() {
-            println!("Function: {:?} Instr: {:?}", m, i);
-        }
-    }
-}
-
 #[allow(dead_code)]
 impl<'a, 'b> ComponentIterator<'a, 'b> {
-    /// Creates a new Component Iterator
+    /// Creates a new Component Iterator, visiting the modules of the component and of all of
+    /// its nested components. `skip_funcs` applies to the modules of the outermost component.
     pub fn new(
         comp: &'a mut Component<'b>,
         skip_funcs: HashMap<ModuleID, Vec<FunctionID>>,
     ) -> Self {
-        // Creates Module -> Function -> Number of Instructions
-        let mut metadata = HashMap::new();
-        for (mod_idx, m) in comp.modules.iter().enumerate() {
-            let mut mod_metadata = HashMap::new();
-            for func in m.functions.iter() {
-                match &func.kind {
-                    FuncKind::Import(_) => {}
-                    FuncKind::Local(LocalFunction { func_id, body, .. }) => {
-                        mod_metadata.insert(*func_id, body.num_instructions);
+        Self::with_skipped(comp, |path, mod_idx, _| {
+            if path.is_empty() {
+                skip_funcs.get(&mod_idx).cloned().unwrap_or_default()
+            } else {
+                vec![]
+            }
+        })
+    }
+
+    /// Creates a new Component Iterator that only visits the functions selected by the filter,
+    /// the filter is applied to every module of the component and its nested components
+    pub fn new_filtered(comp: &'a mut Component<'b>, filter: &FuncFilter) -> Self {
+        Self::with_skipped(comp, |_, _, module| filter.skipped_funcs(module))
+    }
+
+    fn with_skipped(
+        comp: &'a mut Component<'b>,
+        skipped: impl Fn(&ComponentPath, ModuleID, &Module) -> Vec<FunctionID>,
+    ) -> Self {
+        // Creates Module -> Function -> Number of Instructions for the modules of all components
+        let mut mods = vec![];
+        let mut metadata = vec![];
+        let mut skip_funcs = vec![];
+        for path in comp.component_paths() {
+            for (mod_idx, m) in comp.get_component(&path).modules.iter().enumerate() {
+                let mod_idx = ModuleID(mod_idx as u32);
+                let mut mod_metadata = HashMap::new();
+                for func in m.functions.iter() {
+                    match &func.kind {
+                        FuncKind::Import(_) => {}
+                        FuncKind::Local(LocalFunction { func_id, body, .. }) => {
+                            mod_metadata.insert(*func_id, body.num_instructions);
+                        }
                     }
                 }
+                mods.push((path.clone(), mod_idx));
+                metadata.push(mod_metadata);
+                skip_funcs.push(skipped(&path, mod_idx, m));
             }
-            metadata.insert(ModuleID(mod_idx as u32), mod_metadata);
         }
-        print_metadata(&metadata);
-        let num_modules = comp.num_modules;
         ComponentIterator {
             comp,
-            comp_iterator: ComponentSubIterator::new(
-                ModuleID(0),
-                num_modules,
-                metadata,
-                skip_funcs,
-            ),
+            comp_iterator: ComponentSubIterator::new_nested(mods, metadata, skip_funcs),
         }
     }
 
+    /// Returns the path to the (nested) component the iterator is in
+    pub fn curr_path(&self) -> ComponentPath {
+        self.comp_iterator.curr_path()
+    }
+
     /// Returns the current module the component iterator is in
     pub fn curr_module(&self) -> ModuleID {
-        if let (
-            Location::Component {
-                mod_idx,
-                func_idx: _func_idx,
-                instr_idx: _instr_idx,
-                ..
-            },
-            ..,
-        ) = self.curr_loc()
-        {
+        if let Some((_, mod_idx, _, _)) = self.curr_loc().0.component_parts() {
             mod_idx
         } else {
             panic!("Should have gotten component location");
@@ -86,17 +95,12 @@ impl<'a, 'b> ComponentIterator<'a, 'b> {
     pub fn curr_op_owned(&self) -> Option<Operator<'b>> {
         if self.comp_iterator.end() {
             None
-        } else if let (
-            Location::Component {
-                mod_idx,
-                func_idx,
-                instr_idx,
-                ..
-            },
-            ..,
-        ) = self.comp_iterator.curr_loc()
+        } else if let Some((path, mod_idx, func_idx, instr_idx)) =
+            self.comp_iterator.curr_loc().0.component_parts()
         {
-            match &self.comp.modules[*mod_idx as usize]
+            match &self
+                .comp
+                .get_module(&path, mod_idx)
                 .functions
                 .get(func_idx)
                 .kind
@@ -108,6 +112,18 @@ impl<'a, 'b> ComponentIterator<'a, 'b> {
             panic!("Should have gotten Component Location!")
         }
     }
+
+    /// The function the iterator is in
+    fn curr_local_func(&mut self) -> &mut LocalFunction<'b> {
+        if let Some((path, mod_idx, func_idx, _)) = self.curr_loc().0.component_parts() {
+            self.comp
+                .get_module_mut(&path, mod_idx)
+                .functions
+                .unwrap_local(func_idx)
+        } else {
+            panic!("Should have gotten Component Location and not Module Location!")
+        }
+    }
 }
 
 impl<'a, 'b> Inject<'b> for ComponentIterator<'a, 'b> {
@@ -155,17 +171,10 @@ impl<'a, 'b> Inject<'b> for ComponentIterator<'a, 'b> {
     /// }
     /// ```
     fn inject(&mut self, instr: Operator<'b>) {
-        if let (
-            Location::Component {
-                mod_idx,
-                func_idx,
-                instr_idx,
-                ..
-            },
-            ..,
-        ) = self.curr_loc()
-        {
-            match self.comp.modules[*mod_idx as usize]
+        if let Some((path, mod_idx, func_idx, instr_idx)) = self.curr_loc().0.component_parts() {
+            match self
+                .comp
+                .get_module_mut(&path, mod_idx)
                 .functions
                 .get_mut(func_idx)
                 .kind
@@ -180,19 +189,9 @@ impl<'a, 'b> Inject<'b> for ComponentIterator<'a, 'b> {
 }
 impl<'a, 'b> InjectAt<'b> for ComponentIterator<'a, 'b> {
     fn inject_at(&mut self, idx: usize, mode: InstrumentationMode, instr: Operator<'b>) {
-        if let (
-            Location::Component {
-                mod_idx, func_idx, ..
-            },
-            ..,
-        ) = self.curr_loc()
-        {
-            let loc = Location::Component {
-                mod_idx,
-                func_idx,
-                instr_idx: idx,
-            };
-            self.set_instrument_mode_at(mode, loc);
+        if let Some((path, mod_idx, func_idx, _)) = self.curr_loc().0.component_parts() {
+            let loc = Location::in_component(path, mod_idx, func_idx, idx);
+            self.set_instrument_mode_at(mode, loc.clone());
             self.add_instr_at(loc, instr);
         } else {
             panic!("Should have gotten Component Location!")
@@ -204,17 +203,12 @@ impl<'a, 'b> MacroOpcode<'b> for ComponentIterator<'a, 'b> {}
 impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     /// Returns the Instrumentation at the current Location
     fn curr_instrument_mode(&self) -> &Option<InstrumentationMode> {
-        if let (
-            Location::Component {
-                mod_idx,
-                func_idx,
-                instr_idx,
-                ..
-            },
-            ..,
-        ) = self.comp_iterator.curr_loc()
+        if let Some((path, mod_idx, func_idx, instr_idx)) =
+            self.comp_iterator.curr_loc().0.component_parts()
         {
-            match &self.comp.modules[*mod_idx as usize]
+            match &self
+                .comp
+                .get_module(&path, mod_idx)
                 .functions
                 .get(func_idx)
                 .kind
@@ -230,14 +224,10 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     }
 
     fn set_instrument_mode_at(&mut self, mode: InstrumentationMode, loc: Location) {
-        if let Location::Component {
-            mod_idx,
-            func_idx,
-            instr_idx,
-            ..
-        } = loc
-        {
-            match self.comp.modules[*mod_idx as usize]
+        if let Some((path, mod_idx, func_idx, instr_idx)) = loc.component_parts() {
+            match self
+                .comp
+                .get_module_mut(&path, mod_idx)
                 .functions
                 .get_mut(func_idx)
                 .kind
@@ -253,14 +243,12 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     }
 
     fn curr_func_instrument_mode(&self) -> &Option<FuncInstrMode> {
-        if let (
-            Location::Component {
-                mod_idx, func_idx, ..
-            },
-            ..,
-        ) = self.comp_iterator.curr_loc()
+        if let Some((path, mod_idx, func_idx, _)) =
+            self.comp_iterator.curr_loc().0.component_parts()
         {
-            match &self.comp.modules[*mod_idx as usize]
+            match &self
+                .comp
+                .get_module(&path, mod_idx)
                 .functions
                 .get(func_idx)
                 .kind
@@ -276,14 +264,10 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     }
 
     fn set_func_instrument_mode(&mut self, mode: FuncInstrMode) {
-        if let (
-            Location::Component {
-                mod_idx, func_idx, ..
-            },
-            ..,
-        ) = self.curr_loc()
-        {
-            match self.comp.modules[*mod_idx as usize]
+        if let Some((path, mod_idx, func_idx, _)) = self.curr_loc().0.component_parts() {
+            match self
+                .comp
+                .get_module_mut(&path, mod_idx)
                 .functions
                 .get_mut(func_idx)
                 .kind
@@ -297,14 +281,10 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     }
 
     fn clear_instr_at(&mut self, loc: Location, mode: InstrumentationMode) {
-        if let Location::Component {
-            mod_idx,
-            func_idx,
-            instr_idx,
-            ..
-        } = loc
-        {
-            match self.comp.modules[*mod_idx as usize]
+        if let Some((path, mod_idx, func_idx, instr_idx)) = loc.component_parts() {
+            match self
+                .comp
+                .get_module_mut(&path, mod_idx)
                 .functions
                 .get_mut(func_idx)
                 .kind
@@ -318,14 +298,10 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     }
 
     fn add_instr_at(&mut self, loc: Location, instr: Operator<'b>) {
-        if let Location::Component {
-            mod_idx,
-            func_idx,
-            instr_idx,
-            ..
-        } = loc
-        {
-            match self.comp.modules[*mod_idx as usize]
+        if let Some((path, mod_idx, func_idx, instr_idx)) = loc.component_parts() {
+            match self
+                .comp
+                .get_module_mut(&path, mod_idx)
                 .functions
                 .get_mut(func_idx)
                 .kind
@@ -341,14 +317,10 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     }
 
     fn empty_alternate_at(&mut self, loc: Location) -> &mut Self {
-        if let Location::Component {
-            mod_idx,
-            func_idx,
-            instr_idx,
-            ..
-        } = loc
-        {
-            match self.comp.modules[*mod_idx as usize]
+        if let Some((path, mod_idx, func_idx, instr_idx)) = loc.component_parts() {
+            match self
+                .comp
+                .get_module_mut(&path, mod_idx)
                 .functions
                 .get_mut(func_idx)
                 .kind
@@ -366,14 +338,10 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     }
 
     fn empty_block_alt_at(&mut self, loc: Location) -> &mut Self {
-        if let Location::Component {
-            mod_idx,
-            func_idx,
-            instr_idx,
-            ..
-        } = loc
-        {
-            match self.comp.modules[*mod_idx as usize]
+        if let Some((path, mod_idx, func_idx, instr_idx)) = loc.component_parts() {
+            match self
+                .comp
+                .get_module_mut(&path, mod_idx)
                 .functions
                 .get_mut(func_idx)
                 .kind
@@ -392,18 +360,13 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
     }
 
     /// Gets the injected instruction at the current location by index
-    fn get_injected_val(&self, idx: usize) -> &Operator {
-        if let (
-            Location::Component {
-                mod_idx,
-                func_idx,
-                instr_idx,
-                ..
-            },
-            ..,
-        ) = self.comp_iterator.curr_loc()
+    fn get_injected_val(&self, idx: usize) -> &Operator<'_> {
+        if let Some((path, mod_idx, func_idx, instr_idx)) =
+            self.comp_iterator.curr_loc().0.component_parts()
         {
-            match &self.comp.modules[*mod_idx as usize]
+            match &self
+                .comp
+                .get_module(&path, mod_idx)
                 .functions
                 .get(func_idx)
                 .kind
@@ -419,16 +382,7 @@ impl<'a, 'b> Instrumenter<'b> for ComponentIterator<'a, 'b> {
 impl<'a, 'b> IteratingInstrumenter<'b> for ComponentIterator<'a, 'b> {
     /// Sets the type of Instrumentation Mode of the current location
     fn set_instrument_mode(&mut self, mode: InstrumentationMode) {
-        if let (
-            Location::Component {
-                mod_idx: _mod_idx,
-                func_idx: _func_idx,
-                instr_idx: _instr_idx,
-                ..
-            },
-            ..,
-        ) = self.curr_loc()
-        {
+        if self.curr_loc().0.component_parts().is_some() {
             self.set_instrument_mode_at(mode, self.curr_loc().0);
         } else {
             panic!("Should have gotten component location!")
@@ -437,7 +391,20 @@ impl<'a, 'b> IteratingInstrumenter<'b> for ComponentIterator<'a, 'b> {
 
     fn add_global(&mut self, global: Global) -> GlobalID {
         let curr_mod = *self.curr_module() as usize;
-        self.comp.modules[curr_mod].globals.add(global)
+        self.comp
+            .add_globals_at(global, &self.curr_path(), curr_mod)
+    }
+
+    fn curr_call_type(&self) -> Option<(FuncType, Option<DataType>)> {
+        self.comp
+            .get_module(&self.curr_path(), self.curr_module())
+            .get_call_type(self.curr_op()?)
+    }
+
+    fn curr_memory_addr_type(&self, mem_id: MemoryID) -> Option<DataType> {
+        self.comp
+            .get_module(&self.curr_path(), self.curr_module())
+            .get_memory_addr_type(mem_id)
     }
 }
 
@@ -449,13 +416,48 @@ impl<'a, 'b> Iterator for ComponentIterator<'a, 'b> {
     }
 
     /// Goes to the next instruction
-    fn next(&mut self) -> Option<&Operator> {
+    fn next(&mut self) -> Option<&Operator<'_>> {
         match self.comp_iterator.next() {
             false => None,
             true => self.curr_op(),
         }
     }
 
+    /// Goes to the next instruction that satisfies the predicate. Searches the instructions
+    /// of the current function directly and moves on to the next function if none match.
+    fn next_matching(&mut self, pred: impl Fn(&Operator) -> bool) -> Option<&Operator<'_>> {
+        if self.comp_iterator.end() {
+            return None;
+        }
+        let mut from = self.comp_iterator.curr_instr_idx() + 1;
+        loop {
+            let num_instr = self.comp_iterator.curr_num_instr();
+            let found = match &self
+                .comp
+                .get_module(
+                    &self.comp_iterator.curr_path(),
+                    self.comp_iterator.curr_mod_idx(),
+                )
+                .functions
+                .get(self.comp_iterator.curr_func_idx())
+                .kind
+            {
+                FuncKind::Import(_) => panic!("Can't inject into an imported function!"),
+                FuncKind::Local(l) => l.body.instructions[from..num_instr]
+                    .iter()
+                    .position(|instr| pred(&instr.op)),
+            };
+            if let Some(pos) = found {
+                self.comp_iterator.goto_instr(from + pos);
+                return self.curr_op();
+            }
+            if !self.comp_iterator.next_function() {
+                return None;
+            }
+            from = 0;
+        }
+    }
+
     /// Returns the Current Location as a Location and a bool value that
     /// says whether the location is at the end of the function.
     fn curr_loc(&self) -> (Location, bool) {
@@ -463,20 +465,15 @@ impl<'a, 'b> Iterator for ComponentIterator<'a, 'b> {
     }
 
     /// Returns the instruction at the current location
-    fn curr_op(&self) -> Option<&Operator> {
+    fn curr_op(&self) -> Option<&Operator<'_>> {
         if self.comp_iterator.end() {
             None
-        } else if let (
-            Location::Component {
-                mod_idx,
-                func_idx,
-                instr_idx,
-                ..
-            },
-            ..,
-        ) = self.comp_iterator.curr_loc()
+        } else if let Some((path, mod_idx, func_idx, instr_idx)) =
+            self.comp_iterator.curr_loc().0.component_parts()
         {
-            match &self.comp.modules[*mod_idx as usize]
+            match &self
+                .comp
+                .get_module(&path, mod_idx)
                 .functions
                 .get(func_idx)
                 .kind
@@ -493,12 +490,10 @@ impl<'a, 'b> Iterator for ComponentIterator<'a, 'b> {
 impl AddLocal for ComponentIterator<'_, '_> {
     fn add_local(&mut self, val_type: DataType) -> LocalID {
         let curr_loc = self.curr_loc().0;
-        if let Location::Component {
-            mod_idx, func_idx, ..
-        } = curr_loc
-        {
+        if let Some((path, mod_idx, func_idx, _)) = curr_loc.component_parts() {
             {
-                self.comp.modules[*mod_idx as usize]
+                self.comp
+                    .get_module_mut(&path, mod_idx)
                     .functions
                     .add_local(func_idx, val_type)
             }
@@ -506,4 +501,20 @@ impl AddLocal for ComponentIterator<'_, '_> {
             panic!("Should have gotten Component Location and not Module Location!")
         }
     }
+
+    fn scratch_local(&mut self, ty: DataType) -> LocalID {
+        self.curr_local_func().scratch_local(ty)
+    }
+
+    fn release_scratch_local(&mut self, id: LocalID) {
+        self.curr_local_func().release_scratch_local(id)
+    }
+
+    fn enter_scratch_scope(&mut self) {
+        self.curr_local_func().enter_scratch_scope()
+    }
+
+    fn exit_scratch_scope(&mut self) {
+        self.curr_local_func().exit_scratch_scope()
+    }
 }
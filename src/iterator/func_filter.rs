@@ -0,0 +1,83 @@
+//! Selection of the functions an Iterator visits
+
+use crate::ir::id::FunctionID;
+use crate::ir::module::module_functions::FuncKind;
+use crate::ir::module::{Iter, Module};
+use wasmparser::ExternalKind;
+
+/// Selects the local functions of a Module that an Iterator visits,
+/// see [`ModuleIterator::new_filtered`] and [`ComponentIterator::new_filtered`].
+///
+/// [`ModuleIterator::new_filtered`]: crate::iterator::module_iterator::ModuleIterator::new_filtered
+/// [`ComponentIterator::new_filtered`]: crate::iterator::component_iterator::ComponentIterator::new_filtered
+pub enum FuncFilter<'f> {
+    /// Every local function
+    All,
+    /// Functions with a name (from the name section) or an export name matching the pattern.
+    /// A `*` in the pattern matches any sequence of characters.
+    Name(&'f str),
+    /// Exported functions
+    Exported,
+    /// Functions for which the closure returns true
+    Predicate(&'f dyn Fn(&Module, FunctionID) -> bool),
+}
+
+impl FuncFilter<'_> {
+    /// Check if a function of the module is selected by this filter
+    pub fn matches(&self, module: &Module, func_id: FunctionID) -> bool {
+        match self {
+            FuncFilter::All => true,
+            FuncFilter::Name(pattern) => {
+                let name = module.functions.get_name(func_id);
+                name.as_ref().is_some_and(|name| glob_match(pattern, name))
+                    || export_names(module, func_id).any(|name| glob_match(pattern, name))
+            }
+            FuncFilter::Exported => export_names(module, func_id).next().is_some(),
+            FuncFilter::Predicate(pred) => pred(module, func_id),
+        }
+    }
+
+    /// The local functions of the module that are not selected by this filter
+    pub(crate) fn skipped_funcs(&self, module: &Module) -> Vec<FunctionID> {
+        module
+            .functions
+            .iter()
+            .filter_map(|func| match func.kind() {
+                FuncKind::Local(l) if !self.matches(module, l.func_id) => Some(l.func_id),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn export_names<'m>(module: &'m Module, func_id: FunctionID) -> impl Iterator<Item = &'m str> {
+    module
+        .exports
+        .iter()
+        .filter(move |exp| {
+            !exp.deleted && matches!(exp.kind, ExternalKind::Func) && exp.index == *func_id
+        })
+        .map(|exp| exp.name.as_str())
+}
+
+/// Match a name against a pattern where `*` matches any sequence of characters
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // the first part is anchored at the start, the last one at the end
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // no wildcard in the pattern
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
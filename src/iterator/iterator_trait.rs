@@ -1,9 +1,12 @@
 //! Trait that needs to be satisfied by all iterators
 
-use crate::ir::id::GlobalID;
+use crate::ir::id::{GlobalID, LocalID, MemoryID};
 use crate::ir::module::module_globals::Global;
-use crate::ir::types::{InstrumentationMode, Location};
-use crate::opcode::Instrumenter;
+use crate::ir::module::module_types::FuncType;
+use crate::ir::types::{DataType, InstrumentationMode, Location, MemoryAccess};
+use crate::ir::wrappers::memory_access_kind;
+use crate::module_builder::AddLocal;
+use crate::opcode::{Instrumenter, Opcode};
 use wasmparser::Operator;
 
 #[allow(dead_code)]
@@ -13,14 +16,30 @@ pub trait Iterator {
     fn reset(&mut self);
 
     /// Go to the next Instruction
-    fn next(&mut self) -> Option<&Operator>;
+    fn next(&mut self) -> Option<&Operator<'_>>;
 
     /// Returns the Current Location as a Location and a bool value that
     /// says whether the location is at the end of the function.
     fn curr_loc(&self) -> (Location, bool);
 
     /// Get the current instruction
-    fn curr_op(&self) -> Option<&Operator>;
+    fn curr_op(&self) -> Option<&Operator<'_>>;
+
+    /// Go to the next Instruction that satisfies the predicate, skipping over the others.
+    /// The current instruction is not checked.
+    fn next_matching(&mut self, pred: impl Fn(&Operator) -> bool) -> Option<&Operator<'_>>
+    where
+        Self: Sized,
+    {
+        loop {
+            match self.next() {
+                None => return None,
+                Some(op) if pred(op) => break,
+                Some(_) => {}
+            }
+        }
+        self.curr_op()
+    }
 }
 
 /// This trait coincides with the Iterator as instrumentation occurs during Wasm visitation.
@@ -91,8 +110,200 @@ pub trait IteratingInstrumenter<'a>: Instrumenter<'a> + Iterator {
         self
     }
 
+    /// Mark the current location to InstrumentBranchTaken, for a `br_if` or `if`
+    fn branch_taken(&mut self) -> &mut Self {
+        self.set_instrument_mode(InstrumentationMode::BranchTaken);
+        self
+    }
+
+    /// Mark the current location to InstrumentBranchNotTaken, for a `br_if` or `if`
+    fn branch_not_taken(&mut self) -> &mut Self {
+        self.set_instrument_mode(InstrumentationMode::BranchNotTaken);
+        self
+    }
+
+    /// Mark the current location to InstrumentBrTableTarget, for the target at index `target`
+    /// of the target list of a `br_table`
+    fn br_table_target(&mut self, target: u32) -> &mut Self {
+        self.set_instrument_mode(InstrumentationMode::BrTableTarget(target));
+        self
+    }
+
+    /// Mark the current location to InstrumentBrTableDefault, for the default target of a `br_table`
+    fn br_table_default(&mut self) -> &mut Self {
+        self.set_instrument_mode(InstrumentationMode::BrTableDefault);
+        self
+    }
+
+    /// Mark the current location to InstrumentLoopIteration, for a `loop`
+    fn loop_iteration(&mut self) -> &mut Self {
+        self.set_instrument_mode(InstrumentationMode::LoopIteration);
+        self
+    }
+
+    /// Mark the current location to InstrumentLoopBackEdge, for a `loop`
+    fn loop_back_edge(&mut self) -> &mut Self {
+        self.set_instrument_mode(InstrumentationMode::LoopBackEdge);
+        self
+    }
+
     // ==== VAR INJECTION ====
 
     /// Adds a global to the current module and returns the Global ID
     fn add_global(&mut self, global: Global) -> GlobalID;
+
+    // ==== CALLS ====
+
+    /// Get the type of the function called at the current location, along with the type of
+    /// the operand that selects the callee of an indirect call. `None` if it is not a call.
+    fn curr_call_type(&self) -> Option<(FuncType, Option<DataType>)>;
+
+    /// Saves the arguments of the call at the current location to scratch locals, the stack is
+    /// left unchanged. Returns the locals in the order of the parameters.
+    ///
+    /// Injects `Before` the call and leaves the current location in that mode, so that a probe
+    /// using the arguments can be injected right after. Release the locals once the probe is
+    /// injected, or save them in a scratch scope, for the probes of every call to share them.
+    fn save_args_to_locals(&mut self) -> Vec<LocalID>
+    where
+        Self: AddLocal + Opcode<'a> + Sized,
+    {
+        let Some((ty, callee)) = self.curr_call_type() else {
+            panic!("Cannot save the arguments of op type: {:?}", self.curr_op());
+        };
+        let args: Vec<LocalID> = ty.params.iter().map(|ty| self.scratch_local(*ty)).collect();
+        let callee = callee.map(|ty| self.scratch_local(ty));
+
+        self.before();
+        // the callee of an indirect call is on top of the arguments
+        if let Some(callee) = callee {
+            self.local_set(callee);
+        }
+        for arg in args.iter().rev() {
+            self.local_set(*arg);
+        }
+        for arg in args.iter() {
+            self.local_get(*arg);
+        }
+        if let Some(callee) = callee {
+            self.local_get(callee);
+            self.release_scratch_local(callee);
+        }
+        args
+    }
+
+    /// Saves the results of the call at the current location to scratch locals, the stack is
+    /// left unchanged. Returns the locals in the order of the results.
+    ///
+    /// Injects `After` the call and leaves the current location in that mode, so that a probe
+    /// using the results can be injected right after. Release the locals once the probe is
+    /// injected, or save them in a scratch scope, for the probes of every call to share them.
+    fn save_results_to_locals(&mut self) -> Vec<LocalID>
+    where
+        Self: AddLocal + Opcode<'a> + Sized,
+    {
+        let Some((ty, _)) = self.curr_call_type() else {
+            panic!("Cannot save the results of op type: {:?}", self.curr_op());
+        };
+        if matches!(
+            self.curr_op(),
+            Some(
+                Operator::ReturnCall { .. }
+                    | Operator::ReturnCallIndirect { .. }
+                    | Operator::ReturnCallRef { .. }
+            )
+        ) {
+            panic!(
+                "Cannot save the results of a tail call, it does not return: {:?}",
+                self.curr_op()
+            );
+        }
+        let results: Vec<LocalID> = ty
+            .results
+            .iter()
+            .map(|ty| self.scratch_local(*ty))
+            .collect();
+
+        self.after();
+        for result in results.iter().rev() {
+            self.local_set(*result);
+        }
+        for result in results.iter() {
+            self.local_get(*result);
+        }
+        results
+    }
+
+    // ==== MEMORY ACCESSES ====
+
+    /// Get the address type of a memory of the current module
+    fn curr_memory_addr_type(&self, mem_id: MemoryID) -> Option<DataType>;
+
+    /// Saves the address and the written value of the memory access at the current location
+    /// to scratch locals, the stack is left unchanged. Supports all the loads, stores and atomic
+    /// instructions with a `MemArg`. The effective address wraps around like the address
+    /// computation of the access, which traps if it overflows.
+    ///
+    /// Injects `Before` the access and leaves the current location in that mode, so that a probe
+    /// using the locals can be injected right after. Release the locals once the probe is
+    /// injected, or save them in a scratch scope, for the probes of every access to share them.
+    fn save_mem_access_to_locals(&mut self) -> MemoryAccess
+    where
+        Self: AddLocal + Opcode<'a> + Sized,
+    {
+        let Some(kind) = self.curr_op().and_then(memory_access_kind) else {
+            panic!(
+                "Cannot save the memory access of op type: {:?}",
+                self.curr_op()
+            );
+        };
+        let memory = MemoryID(kind.memarg.memory);
+        let Some(addr_ty) = self.curr_memory_addr_type(memory) else {
+            panic!("Memory {} does not exist", *memory);
+        };
+        let base = self.scratch_local(addr_ty);
+        let operands: Vec<LocalID> = kind
+            .operands
+            .iter()
+            .map(|ty| self.scratch_local(*ty))
+            .collect();
+
+        self.before();
+        for operand in operands.iter().rev() {
+            self.local_set(*operand);
+        }
+        self.local_set(base);
+        let addr = if kind.memarg.offset == 0 {
+            base
+        } else {
+            let addr = self.scratch_local(addr_ty);
+            self.local_get(base);
+            match addr_ty {
+                DataType::I64 => self.i64_const(kind.memarg.offset as i64).i64_add(),
+                _ => self.i32_const(kind.memarg.offset as i32).i32_add(),
+            };
+            self.local_set(addr);
+            addr
+        };
+        self.local_get(base);
+        for operand in operands.iter() {
+            self.local_get(*operand);
+        }
+        if addr != base {
+            self.release_scratch_local(base);
+        }
+        // the operands that are not returned
+        for (idx, operand) in operands.iter().enumerate() {
+            if kind.value != Some(idx) {
+                self.release_scratch_local(*operand);
+            }
+        }
+
+        MemoryAccess {
+            memory,
+            addr,
+            value: kind.value.map(|idx| operands[idx]),
+            width: kind.width,
+        }
+    }
 }
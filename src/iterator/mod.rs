@@ -1,5 +1,8 @@
 //! Iterators to traverse either a Component or a Module. Supports injection of Instructions at specific locations.
 
 pub mod component_iterator;
+pub mod func_filter;
 pub mod iterator_trait;
 pub mod module_iterator;
+pub mod pass_manager;
+pub mod visitor;
@@ -1,10 +1,12 @@
 //! Iterator to traverse a Module
 
-use crate::ir::id::{FunctionID, GlobalID, LocalID};
+use crate::ir::id::{FunctionID, GlobalID, LocalID, MemoryID};
 use crate::ir::module::module_functions::{FuncKind, LocalFunction};
 use crate::ir::module::module_globals::Global;
+use crate::ir::module::module_types::FuncType;
 use crate::ir::module::{Iter, Module};
 use crate::ir::types::{DataType, FuncInstrMode, InstrumentationMode, Location};
+use crate::iterator::func_filter::FuncFilter;
 use crate::iterator::iterator_trait::{IteratingInstrumenter, Iterator};
 use crate::module_builder::AddLocal;
 use crate::opcode::{Inject, InjectAt, Instrumenter, MacroOpcode, Opcode};
@@ -42,6 +44,12 @@ impl<'a, 'b> ModuleIterator<'a, 'b> {
         }
     }
 
+    /// Creates a new ModuleIterator that only visits the functions selected by the filter
+    pub fn new_filtered(module: &'a mut Module<'b>, filter: &FuncFilter) -> Self {
+        let skip_funcs = filter.skipped_funcs(module);
+        Self::new(module, &skip_funcs)
+    }
+
     pub fn curr_op_owned(&self) -> Option<Operator<'b>> {
         if self.mod_iterator.end() {
             None
@@ -62,6 +70,15 @@ impl<'a, 'b> ModuleIterator<'a, 'b> {
             panic!("Should have gotten Module Location!")
         }
     }
+
+    /// The function the iterator is in
+    fn curr_local_func(&mut self) -> &mut LocalFunction<'b> {
+        if let (Location::Module { func_idx, .. }, ..) = self.curr_loc() {
+            self.module.functions.unwrap_local(func_idx)
+        } else {
+            panic!("Should have gotten Module Location!")
+        }
+    }
 }
 
 impl<'a, 'b> Inject<'b> for ModuleIterator<'a, 'b> {
@@ -132,7 +149,7 @@ impl<'a> InjectAt<'a> for ModuleIterator<'_, 'a> {
                 func_idx,
                 instr_idx: idx,
             };
-            self.set_instrument_mode_at(mode, loc);
+            self.set_instrument_mode_at(mode, loc.clone());
             self.add_instr_at(loc, instr);
         } else {
             panic!("Should have gotten Module Location!")
@@ -280,7 +297,7 @@ impl<'a> Instrumenter<'a> for ModuleIterator<'_, 'a> {
     }
 
     /// Gets the injected instruction at the current location by index
-    fn get_injected_val(&self, idx: usize) -> &Operator {
+    fn get_injected_val(&self, idx: usize) -> &Operator<'_> {
         if let (
             Location::Module {
                 func_idx,
@@ -307,6 +324,14 @@ impl<'a> IteratingInstrumenter<'a> for ModuleIterator<'_, 'a> {
     fn add_global(&mut self, global: Global) -> GlobalID {
         self.module.globals.add(global)
     }
+
+    fn curr_call_type(&self) -> Option<(FuncType, Option<DataType>)> {
+        self.module.get_call_type(self.curr_op()?)
+    }
+
+    fn curr_memory_addr_type(&self, mem_id: MemoryID) -> Option<DataType> {
+        self.module.get_memory_addr_type(mem_id)
+    }
 }
 
 impl AddLocal for ModuleIterator<'_, '_> {
@@ -318,6 +343,22 @@ impl AddLocal for ModuleIterator<'_, '_> {
             panic!("Should have gotten Module Location!")
         }
     }
+
+    fn scratch_local(&mut self, ty: DataType) -> LocalID {
+        self.curr_local_func().scratch_local(ty)
+    }
+
+    fn release_scratch_local(&mut self, id: LocalID) {
+        self.curr_local_func().release_scratch_local(id)
+    }
+
+    fn enter_scratch_scope(&mut self) {
+        self.curr_local_func().enter_scratch_scope()
+    }
+
+    fn exit_scratch_scope(&mut self) {
+        self.curr_local_func().exit_scratch_scope()
+    }
 }
 
 // Note: Marked Trait as the same lifetime as component
@@ -328,13 +369,37 @@ impl<'a> Iterator for ModuleIterator<'_, 'a> {
     }
 
     /// Goes to the next instruction and returns the instruction
-    fn next(&mut self) -> Option<&Operator> {
+    fn next(&mut self) -> Option<&Operator<'_>> {
         match self.mod_iterator.next() {
             false => None,
             true => self.curr_op(),
         }
     }
 
+    /// Goes to the next instruction that satisfies the predicate. Searches the instructions
+    /// of the current function directly and moves on to the next function if none match.
+    fn next_matching(&mut self, pred: impl Fn(&Operator) -> bool) -> Option<&Operator<'_>> {
+        let mut from = self.mod_iterator.func_iterator.curr_instr + 1;
+        while !self.mod_iterator.end() {
+            let num_instr = self.mod_iterator.func_iterator.num_instr();
+            let found = match &self.module.functions.get(self.mod_iterator.curr_func).kind {
+                FuncKind::Import(_) => panic!("Cannot get an instruction to an imported function"),
+                FuncKind::Local(l) => l.body.instructions[from..num_instr]
+                    .iter()
+                    .position(|instr| pred(&instr.op)),
+            };
+            if let Some(pos) = found {
+                self.mod_iterator.func_iterator.goto(from + pos);
+                return self.curr_op();
+            }
+            if !self.mod_iterator.next_function() {
+                break;
+            }
+            from = 0;
+        }
+        None
+    }
+
     /// Returns the Current Location as a Location and a bool value that
     /// says whether the location is at the end of the function.
     fn curr_loc(&self) -> (Location, bool) {
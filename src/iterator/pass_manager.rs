@@ -0,0 +1,585 @@
+//! Combine independent instrumentation passes over the same Module or Component.
+//!
+//! Every pass is run on its own: it sees the changes the earlier passes made to the module
+//! (new functions, globals, locals, ...) but not their instrumentation. What it injected is then
+//! merged with the instrumentation of the earlier passes in a fixed order, keeping track of the pass
+//! each injected sequence comes from and of the passes asking for conflicting replacements.
+
+use crate::ir::component::Component;
+use crate::ir::id::{FunctionID, ModuleID};
+use crate::ir::module::module_functions::FuncKind;
+use crate::ir::module::Module;
+use crate::ir::types::{
+    ComponentPath, FuncInstrFlag, FuncInstrMode, InstrumentationFlag, InstrumentationMode,
+    Location, TailCallExit,
+};
+use std::collections::HashMap;
+use std::ops::Range;
+use wasmparser::Operator;
+
+/// The kind of code a pass injected
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum InjectedMode {
+    /// Instrumentation of an instruction
+    Instr(InstrumentationMode),
+    /// Instrumentation of a function, its location is the first instruction of the function
+    Func(FuncInstrMode),
+}
+
+impl InjectedMode {
+    /// Orders the modes of an injection point, as they are listed in [`InstrumentationMode`]
+    fn rank(&self) -> (u8, u32) {
+        use InstrumentationMode::*;
+        match self {
+            InjectedMode::Func(FuncInstrMode::Entry) => (0, 0),
+            InjectedMode::Func(FuncInstrMode::Exit) => (1, 0),
+            InjectedMode::Instr(mode) => match mode {
+                Before => (2, 0),
+                After => (3, 0),
+                Alternate => (4, 0),
+                SemanticAfter => (5, 0),
+                BlockEntry => (6, 0),
+                BlockExit => (7, 0),
+                BlockAlt => (8, 0),
+                BranchTaken => (9, 0),
+                BranchNotTaken => (10, 0),
+                BrTableTarget(target) => (11, *target),
+                BrTableDefault => (12, 0),
+                LoopIteration => (13, 0),
+                LoopBackEdge => (14, 0),
+            },
+        }
+    }
+}
+
+/// A sequence injected by a pass
+#[derive(Debug, Clone)]
+pub struct Contribution {
+    /// The index of the pass in its [`PassManager`]
+    pub pass: usize,
+    pub location: Location,
+    pub mode: InjectedMode,
+    /// The operators of the pass in the merged body of `mode` at `location`
+    pub range: Range<usize>,
+}
+
+/// A replacement (`Alternate` or `BlockAlt`) asked for by a pass at an instruction that was
+/// already replaced. The first replacement is kept, the later ones are dropped.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub location: Location,
+    pub mode: InstrumentationMode,
+    /// The pass whose replacement is kept, `None` if the replacement was there before the passes ran
+    pub kept: Option<usize>,
+    /// The pass whose replacement is dropped
+    pub dropped: usize,
+}
+
+/// What the passes of a [`PassManager`] injected
+#[derive(Debug, Clone, Default)]
+pub struct PassReport {
+    /// The names of the passes, in the order they ran
+    pub passes: Vec<String>,
+    /// Ordered by location, then by pass
+    pub contributions: Vec<Contribution>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl PassReport {
+    /// The sequences injected by the pass named `name`
+    pub fn contributions_of<'r>(&'r self, name: &str) -> impl Iterator<Item = &'r Contribution> {
+        let pass = self.passes.iter().position(|pass| pass == name);
+        self.contributions
+            .iter()
+            .filter(move |contribution| Some(contribution.pass) == pass)
+    }
+
+    /// Whether some passes asked for conflicting replacements
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
+type Pass<'p, 'a> = Box<dyn FnMut(&mut Module<'a>) + 'p>;
+
+/// Runs instrumentation passes one after the other and merges what they inject.
+///
+/// The passes run in the order they were added. At every injection point, the code of the
+/// passes is ordered so that the code of an earlier pass encloses the code of the later ones:
+/// - `Before`, `BlockEntry`, `BranchTaken`, `BranchNotTaken`, `BrTableTarget`, `BrTableDefault`,
+///   `LoopIteration`, `LoopBackEdge` and function `Entry` code is in the order of the passes.
+/// - `After`, `SemanticAfter`, `BlockExit` and function `Exit` code is in the reverse order of the passes.
+/// - The first `Alternate` or `BlockAlt` of an instruction is kept, the later ones are reported
+///   as [`Conflict`]s.
+///
+/// Instrumentation that is already in the module when the passes run is kept, and is ordered as if it was
+/// injected by a pass that ran before them.
+///
+/// # Example
+/// ```no_run
+/// use orca_wasm::iterator::pass_manager::PassManager;
+/// use orca_wasm::iterator::visitor::visit_module;
+/// use orca_wasm::Module;
+/// # use orca_wasm::iterator::visitor::OrcaVisitor;
+/// # struct Coverage;
+/// # impl<'a> OrcaVisitor<'a> for Coverage {}
+/// # struct Tracing;
+/// # impl<'a> OrcaVisitor<'a> for Tracing {}
+///
+/// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+/// let mut module = Module::parse(&buff, false).expect("Unable to parse");
+/// let report = PassManager::new()
+///     .add_pass("coverage", |module| visit_module(module, &mut Coverage))
+///     .add_pass("tracing", |module| visit_module(module, &mut Tracing))
+///     .run_module(&mut module);
+/// assert!(!report.has_conflicts());
+/// ```
+#[derive(Default)]
+pub struct PassManager<'p, 'a> {
+    passes: Vec<(String, Pass<'p, 'a>)>,
+}
+
+impl<'p, 'a> PassManager<'p, 'a> {
+    pub fn new() -> Self {
+        PassManager { passes: vec![] }
+    }
+
+    /// Add a pass, it runs after the passes added before it
+    pub fn add_pass(&mut self, name: &str, pass: impl FnMut(&mut Module<'a>) + 'p) -> &mut Self {
+        self.passes.push((name.to_string(), Box::new(pass)));
+        self
+    }
+
+    /// Run the passes on a Module
+    pub fn run_module(&mut self, module: &mut Module<'a>) -> PassReport {
+        let mut report = self.report();
+        self.run(module, None, &mut report);
+        report
+    }
+
+    /// Run the passes on every module of a Component and of its nested components,
+    /// the modules are instrumented one after the other
+    pub fn run_component(&mut self, comp: &mut Component<'a>) -> PassReport {
+        let mut report = self.report();
+        for path in comp.component_paths() {
+            for mod_idx in 0..comp.get_component(&path).modules.len() {
+                let mod_idx = ModuleID(mod_idx as u32);
+                self.run(
+                    comp.get_module_mut(&path, mod_idx),
+                    Some((path.clone(), mod_idx)),
+                    &mut report,
+                );
+            }
+        }
+        report
+    }
+
+    fn report(&self) -> PassReport {
+        PassReport {
+            passes: self.passes.iter().map(|(name, _)| name.clone()).collect(),
+            ..PassReport::default()
+        }
+    }
+
+    fn run(
+        &mut self,
+        module: &mut Module<'a>,
+        in_comp: Option<(ComponentPath, ModuleID)>,
+        report: &mut PassReport,
+    ) {
+        let mut merger = Merger {
+            in_comp: in_comp.clone(),
+            merged: take_instrumentation(module),
+            segments: HashMap::new(),
+            conflicts: vec![],
+        };
+        merger.record_existing();
+        for (pass, (_, run_pass)) in self.passes.iter_mut().enumerate() {
+            run_pass(module);
+            for (func_idx, added) in take_instrumentation(module) {
+                merger.merge_func(pass, func_idx, added);
+            }
+        }
+        let Merger {
+            mut merged,
+            segments,
+            conflicts,
+            ..
+        } = merger;
+
+        // put the merged instrumentation back
+        for func in module.functions.iter_mut() {
+            if let FuncKind::Local(l) = &mut func.kind {
+                if let Some((instr_flag, instrs)) = merged.remove(&l.func_id) {
+                    l.instr_flag = instr_flag;
+                    for (instr, flag) in l.body.instructions.iter_mut().zip(instrs) {
+                        instr.instr_flag = flag;
+                    }
+                }
+            }
+        }
+
+        let mut contributions = vec![];
+        for ((func_idx, instr_idx, mode), segments) in segments {
+            let mut start = 0;
+            for (pass, len) in segments {
+                if let Some(pass) = pass {
+                    contributions.push((func_idx, instr_idx, start, pass, len, mode));
+                }
+                start += len;
+            }
+        }
+        contributions.sort_by_key(|(func_idx, instr_idx, _, pass, _, mode)| {
+            (*func_idx, *instr_idx, *pass, mode.rank())
+        });
+        let contributions =
+            contributions
+                .into_iter()
+                .map(
+                    |(func_idx, instr_idx, start, pass, len, mode)| Contribution {
+                        pass,
+                        location: location(in_comp.clone(), func_idx, instr_idx),
+                        mode,
+                        range: start..start + len,
+                    },
+                );
+        report.contributions.extend(contributions);
+        report.conflicts.extend(conflicts);
+    }
+}
+
+/// The instrumentation of every local function, taken out of the module
+type FuncInstrumentation<'a> =
+    HashMap<FunctionID, (FuncInstrFlag<'a>, Vec<InstrumentationFlag<'a>>)>;
+
+fn take_instrumentation<'a>(module: &mut Module<'a>) -> FuncInstrumentation<'a> {
+    let mut taken = HashMap::new();
+    for func in module.functions.iter_mut() {
+        if let FuncKind::Local(l) = &mut func.kind {
+            let instrs = l
+                .body
+                .instructions
+                .iter_mut()
+                .map(|instr| std::mem::take(&mut instr.instr_flag))
+                .collect();
+            taken.insert(l.func_id, (std::mem::take(&mut l.instr_flag), instrs));
+        }
+    }
+    taken
+}
+
+/// An injection point in a module: the function, the instruction (0 for function instrumentation) and the mode
+type Point = (FunctionID, usize, InjectedMode);
+
+struct Merger<'a> {
+    in_comp: Option<(ComponentPath, ModuleID)>,
+    merged: FuncInstrumentation<'a>,
+    /// The (pass, length) of the code at every injection point, in the order of the merged body.
+    /// The pass is `None` for the instrumentation that was there before the passes ran.
+    segments: HashMap<Point, Vec<(Option<usize>, usize)>>,
+    conflicts: Vec<Conflict>,
+}
+
+impl<'a> Merger<'a> {
+    /// Record the instrumentation that was in the module before the passes ran
+    fn record_existing(&mut self) {
+        for (func_idx, (instr_flag, instrs)) in self.merged.iter() {
+            for (mode, body) in func_bodies(instr_flag) {
+                push_segment(&mut self.segments, (*func_idx, 0, mode), None, body.len());
+            }
+            for (instr_idx, flag) in instrs.iter().enumerate() {
+                for (mode, body) in instr_bodies(flag) {
+                    let point = (*func_idx, instr_idx, InjectedMode::Instr(mode));
+                    push_segment(&mut self.segments, point, None, body.len());
+                }
+            }
+        }
+    }
+
+    fn merge_func(
+        &mut self,
+        pass: usize,
+        func_idx: FunctionID,
+        (added_flag, added_instrs): (FuncInstrFlag<'a>, Vec<InstrumentationFlag<'a>>),
+    ) {
+        let (mut instr_flag, mut instrs) = self.merged.remove(&func_idx).unwrap_or_default();
+        let FuncInstrFlag {
+            has_special_instr,
+            current_mode: _,
+            entry,
+            exit,
+            tail_call_exit,
+        } = added_flag;
+        instr_flag.has_special_instr |= has_special_instr;
+        if tail_call_exit != TailCallExit::default() {
+            instr_flag.tail_call_exit = tail_call_exit;
+        }
+        let segments = &mut self.segments;
+        let mut merge = |mode, merged: &mut Vec<Operator<'a>>, added: Vec<Operator<'a>>, rev| {
+            merge_body(segments, (func_idx, 0, mode), pass, merged, added, rev)
+        };
+        merge(
+            InjectedMode::Func(FuncInstrMode::Entry),
+            &mut instr_flag.entry,
+            entry,
+            false,
+        );
+        merge(
+            InjectedMode::Func(FuncInstrMode::Exit),
+            &mut instr_flag.exit,
+            exit,
+            true,
+        );
+
+        if instrs.len() < added_instrs.len() {
+            instrs.resize_with(added_instrs.len(), InstrumentationFlag::default);
+        }
+        for (instr_idx, (merged, added)) in instrs.iter_mut().zip(added_instrs).enumerate() {
+            if added.has_instr() {
+                self.merge_instr(pass, func_idx, instr_idx, merged, added);
+            }
+        }
+        self.merged.insert(func_idx, (instr_flag, instrs));
+    }
+
+    fn merge_instr(
+        &mut self,
+        pass: usize,
+        func_idx: FunctionID,
+        instr_idx: usize,
+        merged: &mut InstrumentationFlag<'a>,
+        added: InstrumentationFlag<'a>,
+    ) {
+        use InstrumentationMode::*;
+        let InstrumentationFlag {
+            current_mode: _,
+            before,
+            after,
+            alternate,
+            semantic_after,
+            block_entry,
+            block_exit,
+            block_alt,
+            branch_taken,
+            branch_not_taken,
+            br_table_targets,
+            br_table_default,
+            loop_iteration,
+            loop_back_edge,
+        } = added;
+        let segments = &mut self.segments;
+        let mut merge = |mode, merged: &mut Vec<Operator<'a>>, added: Vec<Operator<'a>>, rev| {
+            let point = (func_idx, instr_idx, InjectedMode::Instr(mode));
+            merge_body(segments, point, pass, merged, added, rev)
+        };
+        merge(Before, &mut merged.before, before, false);
+        merge(After, &mut merged.after, after, true);
+        merge(
+            SemanticAfter,
+            &mut merged.semantic_after,
+            semantic_after,
+            true,
+        );
+        merge(BlockEntry, &mut merged.block_entry, block_entry, false);
+        merge(BlockExit, &mut merged.block_exit, block_exit, true);
+        merge(BranchTaken, &mut merged.branch_taken, branch_taken, false);
+        merge(
+            BranchNotTaken,
+            &mut merged.branch_not_taken,
+            branch_not_taken,
+            false,
+        );
+        for (target, body) in br_table_targets {
+            let pos = match merged
+                .br_table_targets
+                .iter()
+                .position(|(t, _)| *t == target)
+            {
+                Some(pos) => pos,
+                None => {
+                    merged.br_table_targets.push((target, vec![]));
+                    merged.br_table_targets.len() - 1
+                }
+            };
+            merge(
+                BrTableTarget(target),
+                &mut merged.br_table_targets[pos].1,
+                body,
+                false,
+            );
+        }
+        merge(
+            BrTableDefault,
+            &mut merged.br_table_default,
+            br_table_default,
+            false,
+        );
+        merge(
+            LoopIteration,
+            &mut merged.loop_iteration,
+            loop_iteration,
+            false,
+        );
+        merge(
+            LoopBackEdge,
+            &mut merged.loop_back_edge,
+            loop_back_edge,
+            false,
+        );
+
+        self.merge_alt(
+            pass,
+            func_idx,
+            instr_idx,
+            Alternate,
+            &mut merged.alternate,
+            alternate,
+        );
+        self.merge_alt(
+            pass,
+            func_idx,
+            instr_idx,
+            BlockAlt,
+            &mut merged.block_alt,
+            block_alt,
+        );
+    }
+
+    fn merge_alt(
+        &mut self,
+        pass: usize,
+        func_idx: FunctionID,
+        instr_idx: usize,
+        mode: InstrumentationMode,
+        merged: &mut Option<Vec<Operator<'a>>>,
+        added: Option<Vec<Operator<'a>>>,
+    ) {
+        let Some(added) = added else {
+            return;
+        };
+        let point = (func_idx, instr_idx, InjectedMode::Instr(mode));
+        if merged.is_some() {
+            let kept = self
+                .segments
+                .get(&point)
+                .and_then(|segments| segments.first())
+                .and_then(|(kept, _)| *kept);
+            self.conflicts.push(Conflict {
+                location: location(self.in_comp.clone(), func_idx, instr_idx),
+                mode,
+                kept,
+                dropped: pass,
+            });
+        } else {
+            push_segment(&mut self.segments, point, Some(pass), added.len());
+            *merged = Some(added);
+        }
+    }
+}
+
+/// Merge the code a pass injected at a point, after the code of the earlier passes,
+/// or before it if `rev`
+fn merge_body<'a>(
+    segments: &mut HashMap<Point, Vec<(Option<usize>, usize)>>,
+    point: Point,
+    pass: usize,
+    merged: &mut Vec<Operator<'a>>,
+    added: Vec<Operator<'a>>,
+    rev: bool,
+) {
+    if added.is_empty() {
+        return;
+    }
+    let segments = segments.entry(point).or_default();
+    if rev {
+        segments.insert(0, (Some(pass), added.len()));
+        merged.splice(0..0, added);
+    } else {
+        segments.push((Some(pass), added.len()));
+        merged.extend(added);
+    }
+}
+
+fn push_segment(
+    segments: &mut HashMap<Point, Vec<(Option<usize>, usize)>>,
+    point: Point,
+    pass: Option<usize>,
+    len: usize,
+) {
+    segments.entry(point).or_default().push((pass, len));
+}
+
+/// The non-empty bodies of a function's instrumentation
+fn func_bodies<'f, 'a>(
+    flag: &'f FuncInstrFlag<'a>,
+) -> impl Iterator<Item = (InjectedMode, &'f Vec<Operator<'a>>)> {
+    [
+        (InjectedMode::Func(FuncInstrMode::Entry), &flag.entry),
+        (InjectedMode::Func(FuncInstrMode::Exit), &flag.exit),
+    ]
+    .into_iter()
+    .filter(|(_, body)| !body.is_empty())
+}
+
+/// The bodies of an instruction's instrumentation, the replacements are included even when empty
+fn instr_bodies<'f, 'a>(
+    flag: &'f InstrumentationFlag<'a>,
+) -> impl Iterator<Item = (InstrumentationMode, &'f Vec<Operator<'a>>)> {
+    use InstrumentationMode::*;
+    let InstrumentationFlag {
+        current_mode: _,
+        before,
+        after,
+        alternate,
+        semantic_after,
+        block_entry,
+        block_exit,
+        block_alt,
+        branch_taken,
+        branch_not_taken,
+        br_table_targets,
+        br_table_default,
+        loop_iteration,
+        loop_back_edge,
+    } = flag;
+    let bodies = [
+        (Before, before),
+        (After, after),
+        (SemanticAfter, semantic_after),
+        (BlockEntry, block_entry),
+        (BlockExit, block_exit),
+        (BranchTaken, branch_taken),
+        (BranchNotTaken, branch_not_taken),
+        (BrTableDefault, br_table_default),
+        (LoopIteration, loop_iteration),
+        (LoopBackEdge, loop_back_edge),
+    ];
+    let alts = [(Alternate, alternate), (BlockAlt, block_alt)];
+    bodies
+        .into_iter()
+        .filter(|(_, body)| !body.is_empty())
+        .chain(
+            br_table_targets
+                .iter()
+                .map(|(target, body)| (BrTableTarget(*target), body))
+                .filter(|(_, body)| !body.is_empty()),
+        )
+        .chain(
+            alts.into_iter()
+                .filter_map(|(mode, alt)| Some((mode, alt.as_ref()?))),
+        )
+}
+
+fn location(
+    in_comp: Option<(ComponentPath, ModuleID)>,
+    func_idx: FunctionID,
+    instr_idx: usize,
+) -> Location {
+    match in_comp {
+        None => Location::Module {
+            func_idx,
+            instr_idx,
+        },
+        Some((path, mod_idx)) => Location::in_component(path, mod_idx, func_idx, instr_idx),
+    }
+}
@@ -0,0 +1,297 @@
+//! Visitor to write passes as a set of per-opcode callbacks rather than a loop over an Iterator.
+
+use crate::ir::component::Component;
+use crate::ir::id::{FunctionID, GlobalID, LocalID, TableID, TypeID};
+use crate::ir::module::Module;
+use crate::ir::types::{ComponentPath, Location};
+use crate::ir::wrappers::{is_atomic_rmw, memory_access_kind};
+use crate::iterator::component_iterator::ComponentIterator;
+use crate::iterator::iterator_trait::IteratingInstrumenter;
+use crate::iterator::module_iterator::ModuleIterator;
+use crate::module_builder::AddLocal;
+use crate::opcode::{MacroOpcode, Opcode};
+use std::collections::HashMap;
+use wasmparser::{MemArg, Operator};
+
+/// The injector handed to the hooks of an [`OrcaVisitor`]. It points at the instruction
+/// being visited, so injection (`before()`, `after()`, `alternate()`, ...) is scoped to its [`Location`].
+/// Implemented by [`ModuleIterator`] and [`ComponentIterator`].
+pub trait ScopedInjector<'a>:
+    IteratingInstrumenter<'a> + Opcode<'a> + MacroOpcode<'a> + AddLocal
+{
+}
+impl<'a, T> ScopedInjector<'a> for T where
+    T: IteratingInstrumenter<'a> + Opcode<'a> + MacroOpcode<'a> + AddLocal
+{
+}
+
+/// Per-opcode callbacks to instrument a Module or Component, driven by [`visit_module`] and
+/// [`visit_component`]. All hooks default to doing nothing, so a pass only implements the ones
+/// it is interested in. The current instruction is always available through `it.curr_op()`.
+///
+/// Visitors can be combined by visiting a tuple of them, the hooks of the first one run first.
+///
+/// # Example
+/// ```no_run
+/// use orca_wasm::ir::id::FunctionID;
+/// use orca_wasm::iterator::visitor::{visit_module, OrcaVisitor, ScopedInjector};
+/// use orca_wasm::Module;
+///
+/// // Inject an `i32.const 1` before every call to function 1
+/// struct CallCounter;
+/// impl<'a> OrcaVisitor<'a> for CallCounter {
+///     fn visit_call<I: ScopedInjector<'a>>(&mut self, it: &mut I, func: FunctionID) {
+///         if *func == 1 {
+///             it.before().i32_const(1);
+///         }
+///     }
+/// }
+///
+/// let buff = wat::parse_file("path_to_file").expect("couldn't convert the input wat to Wasm");
+/// let mut module = Module::parse(&buff, false).expect("Unable to parse");
+/// visit_module(&mut module, &mut CallCounter);
+/// ```
+#[allow(unused_variables)]
+pub trait OrcaVisitor<'a> {
+    /// Called for every instruction, before the opcode-specific hook
+    fn visit_op<I: ScopedInjector<'a>>(&mut self, it: &mut I) {}
+
+    /// Called at the first instruction of every function
+    fn visit_function_start<I: ScopedInjector<'a>>(&mut self, it: &mut I) {}
+
+    /// Called at the final `end` of every function
+    fn visit_function_end<I: ScopedInjector<'a>>(&mut self, it: &mut I) {}
+
+    /// Called for `call` and `return_call`
+    fn visit_call<I: ScopedInjector<'a>>(&mut self, it: &mut I, func: FunctionID) {}
+
+    /// Called for `call_indirect` and `return_call_indirect`
+    fn visit_call_indirect<I: ScopedInjector<'a>>(
+        &mut self,
+        it: &mut I,
+        ty: TypeID,
+        table: TableID,
+    ) {
+    }
+
+    /// Called for `call_ref` and `return_call_ref`
+    fn visit_call_ref<I: ScopedInjector<'a>>(&mut self, it: &mut I, ty: TypeID) {}
+
+    /// Called for every instruction that reads linear memory through a [`MemArg`], including
+    /// atomic and SIMD lane loads and `memory.atomic.wait*`
+    fn visit_load<I: ScopedInjector<'a>>(&mut self, it: &mut I, memarg: MemArg) {}
+
+    /// Called for every instruction that writes linear memory through a [`MemArg`], including
+    /// atomic and SIMD lane stores. Atomic read-modify-writes call `visit_load` first, then this.
+    fn visit_store<I: ScopedInjector<'a>>(&mut self, it: &mut I, memarg: MemArg) {}
+
+    fn visit_block<I: ScopedInjector<'a>>(&mut self, it: &mut I) {}
+
+    fn visit_loop<I: ScopedInjector<'a>>(&mut self, it: &mut I) {}
+
+    fn visit_if<I: ScopedInjector<'a>>(&mut self, it: &mut I) {}
+
+    fn visit_br<I: ScopedInjector<'a>>(&mut self, it: &mut I, relative_depth: u32) {}
+
+    fn visit_br_if<I: ScopedInjector<'a>>(&mut self, it: &mut I, relative_depth: u32) {}
+
+    fn visit_br_table<I: ScopedInjector<'a>>(&mut self, it: &mut I) {}
+
+    fn visit_return<I: ScopedInjector<'a>>(&mut self, it: &mut I) {}
+
+    fn visit_local_get<I: ScopedInjector<'a>>(&mut self, it: &mut I, local: LocalID) {}
+
+    fn visit_local_set<I: ScopedInjector<'a>>(&mut self, it: &mut I, local: LocalID) {}
+
+    fn visit_local_tee<I: ScopedInjector<'a>>(&mut self, it: &mut I, local: LocalID) {}
+
+    fn visit_global_get<I: ScopedInjector<'a>>(&mut self, it: &mut I, global: GlobalID) {}
+
+    fn visit_global_set<I: ScopedInjector<'a>>(&mut self, it: &mut I, global: GlobalID) {}
+}
+
+macro_rules! forward_hooks {
+    ($($hook:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $hook<I: ScopedInjector<'a>>(&mut self, it: &mut I, $($arg: $ty),*) {
+                self.0.$hook(it, $($arg),*);
+                self.1.$hook(it, $($arg),*);
+            }
+        )*
+    };
+}
+
+impl<'a, A: OrcaVisitor<'a>, B: OrcaVisitor<'a>> OrcaVisitor<'a> for (A, B) {
+    forward_hooks! {
+        visit_op();
+        visit_function_start();
+        visit_function_end();
+        visit_call(func: FunctionID);
+        visit_call_indirect(ty: TypeID, table: TableID);
+        visit_call_ref(ty: TypeID);
+        visit_load(memarg: MemArg);
+        visit_store(memarg: MemArg);
+        visit_block();
+        visit_loop();
+        visit_if();
+        visit_br(relative_depth: u32);
+        visit_br_if(relative_depth: u32);
+        visit_br_table();
+        visit_return();
+        visit_local_get(local: LocalID);
+        visit_local_set(local: LocalID);
+        visit_local_tee(local: LocalID);
+        visit_global_get(global: GlobalID);
+        visit_global_set(global: GlobalID);
+    }
+}
+
+/// Drive a visitor over all the local functions of a Module
+pub fn visit_module<'a, V: OrcaVisitor<'a>>(module: &mut Module<'a>, visitor: &mut V) {
+    if module.num_local_functions == 0 {
+        return;
+    }
+    let mut it = ModuleIterator::new(module, &vec![]);
+    drive(&mut it, visitor);
+}
+
+/// Drive a visitor over all the local functions of every Module in a Component and its nested Components
+pub fn visit_component<'a, V: OrcaVisitor<'a>>(comp: &mut Component<'a>, visitor: &mut V) {
+    let has_local_functions = comp.component_paths().iter().any(|path| {
+        comp.get_component(path)
+            .modules
+            .iter()
+            .any(|m| m.num_local_functions > 0)
+    });
+    if !has_local_functions {
+        return;
+    }
+    let mut it = ComponentIterator::new(comp, HashMap::new());
+    drive(&mut it, visitor);
+}
+
+/// The hook to dispatch to for an instruction, holds a copy of its immediates
+/// so the iterator can be handed to the hook.
+enum Hook {
+    Call(FunctionID),
+    CallIndirect(TypeID, TableID),
+    CallRef(TypeID),
+    Load(MemArg),
+    Store(MemArg),
+    /// Atomic read-modify-write
+    LoadStore(MemArg),
+    Block,
+    Loop,
+    If,
+    Br(u32),
+    BrIf(u32),
+    BrTable,
+    Return,
+    LocalGet(LocalID),
+    LocalSet(LocalID),
+    LocalTee(LocalID),
+    GlobalGet(GlobalID),
+    GlobalSet(GlobalID),
+    Other,
+}
+
+impl Hook {
+    fn of(op: &Operator) -> Self {
+        use Operator::*;
+        if let Some(access) = memory_access_kind(op) {
+            return match op {
+                // only wakes up waiters, memory is not accessed
+                MemoryAtomicNotify { .. } => Hook::Other,
+                _ if is_atomic_rmw(op) => Hook::LoadStore(access.memarg),
+                _ if access.value.is_some() => Hook::Store(access.memarg),
+                _ => Hook::Load(access.memarg),
+            };
+        }
+        match op {
+            Call { function_index } | ReturnCall { function_index } => {
+                Hook::Call(FunctionID(*function_index))
+            }
+            CallIndirect {
+                type_index,
+                table_index,
+            }
+            | ReturnCallIndirect {
+                type_index,
+                table_index,
+            } => Hook::CallIndirect(TypeID(*type_index), TableID(*table_index)),
+            CallRef { type_index } | ReturnCallRef { type_index } => {
+                Hook::CallRef(TypeID(*type_index))
+            }
+            Block { .. } => Hook::Block,
+            Loop { .. } => Hook::Loop,
+            If { .. } => Hook::If,
+            Br { relative_depth } => Hook::Br(*relative_depth),
+            BrIf { relative_depth } => Hook::BrIf(*relative_depth),
+            BrTable { .. } => Hook::BrTable,
+            Return => Hook::Return,
+            LocalGet { local_index } => Hook::LocalGet(LocalID(*local_index)),
+            LocalSet { local_index } => Hook::LocalSet(LocalID(*local_index)),
+            LocalTee { local_index } => Hook::LocalTee(LocalID(*local_index)),
+            GlobalGet { global_index } => Hook::GlobalGet(GlobalID(*global_index)),
+            GlobalSet { global_index } => Hook::GlobalSet(GlobalID(*global_index)),
+            _ => Hook::Other,
+        }
+    }
+}
+
+fn drive<'a, I: ScopedInjector<'a>, V: OrcaVisitor<'a>>(it: &mut I, visitor: &mut V) {
+    let mut curr_func = None;
+    while let Some(hook) = it.curr_op().map(Hook::of) {
+        let (loc, at_end) = it.curr_loc();
+        let func = match loc {
+            Location::Module { func_idx, .. } => (None, func_idx),
+            Location::Component {
+                mod_idx, func_idx, ..
+            } => (Some((ComponentPath::default(), mod_idx)), func_idx),
+            Location::NestedComponent {
+                path,
+                mod_idx,
+                func_idx,
+                ..
+            } => (Some((path, mod_idx)), func_idx),
+        };
+        if curr_func.as_ref() != Some(&func) {
+            // visiting a new function
+            curr_func = Some(func);
+            visitor.visit_function_start(it);
+        }
+
+        visitor.visit_op(it);
+        match hook {
+            Hook::Call(func) => visitor.visit_call(it, func),
+            Hook::CallIndirect(ty, table) => visitor.visit_call_indirect(it, ty, table),
+            Hook::CallRef(ty) => visitor.visit_call_ref(it, ty),
+            Hook::Load(memarg) => visitor.visit_load(it, memarg),
+            Hook::Store(memarg) => visitor.visit_store(it, memarg),
+            Hook::LoadStore(memarg) => {
+                visitor.visit_load(it, memarg);
+                visitor.visit_store(it, memarg);
+            }
+            Hook::Block => visitor.visit_block(it),
+            Hook::Loop => visitor.visit_loop(it),
+            Hook::If => visitor.visit_if(it),
+            Hook::Br(depth) => visitor.visit_br(it, depth),
+            Hook::BrIf(depth) => visitor.visit_br_if(it, depth),
+            Hook::BrTable => visitor.visit_br_table(it),
+            Hook::Return => visitor.visit_return(it),
+            Hook::LocalGet(local) => visitor.visit_local_get(it, local),
+            Hook::LocalSet(local) => visitor.visit_local_set(it, local),
+            Hook::LocalTee(local) => visitor.visit_local_tee(it, local),
+            Hook::GlobalGet(global) => visitor.visit_global_get(it, global),
+            Hook::GlobalSet(global) => visitor.visit_global_set(it, global),
+            Hook::Other => {}
+        }
+        if at_end {
+            visitor.visit_function_end(it);
+        }
+
+        if it.next().is_none() {
+            break;
+        }
+    }
+}
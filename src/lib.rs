@@ -5,6 +5,7 @@
 //! [Walrus]: https://github.com/rustwasm/walrus/tree/main
 
 mod error;
+pub mod interpreter;
 pub mod ir;
 pub mod iterator;
 pub mod module_builder;
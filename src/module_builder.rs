@@ -8,4 +8,20 @@ pub trait AddLocal {
     /// note: the implementors of this trait will keep track of the location,
     /// i.e. which function, where this local is added
     fn add_local(&mut self, ty: DataType) -> LocalID;
+
+    /// Takes a scratch local of type `ty` in the current function to hold a temporary value,
+    /// a scratch local released earlier in the function is reused if there is one.
+    /// Scratch locals that end up unused by the function are removed when encoding.
+    fn scratch_local(&mut self, ty: DataType) -> LocalID;
+
+    /// Releases a scratch local of the current function so that it can be handed out again.
+    /// Panics if `id` is not a scratch local of the function.
+    fn release_scratch_local(&mut self, id: LocalID);
+
+    /// Opens a scope in the current function, the scratch locals taken in the scope
+    /// are released when it is exited
+    fn enter_scratch_scope(&mut self);
+
+    /// Exits the innermost scope of the current function and releases its scratch locals
+    fn exit_scratch_scope(&mut self);
 }
@@ -4,11 +4,15 @@
 // note that the location of the injection is handled specific implementation
 // for iterators, we inject at the location the iterator is pointing at (curr_loc)
 // for FunctionBuilder, we inject at the end of the function
-use crate::ir::id::{FunctionID, GlobalID, LocalID};
-use crate::ir::types::{BlockType, FuncInstrMode, InstrumentationMode};
-use crate::Location;
-use wasmparser::MemArg;
+use crate::ir::id::{
+    DataSegmentID, FunctionID, GlobalID, LocalID, MemoryID, TableID, TagID, TypeID,
+};
+use crate::ir::types::{BlockType, Catch, FuncInstrMode, InstrumentationMode};
+use crate::{DataType, Location};
 use wasmparser::Operator;
+use wasmparser::TryTable;
+use wasmparser::{MemArg, MemoryType};
+use wasmparser::{RefType, ValType};
 
 /// Defines instrumentation behaviour
 pub trait Instrumenter<'a> {
@@ -93,8 +97,44 @@ pub trait Instrumenter<'a> {
     /// Injects an empty block alternate at a given location
     fn empty_block_alt_at(&mut self, loc: Location) -> &mut Self;
 
+    /// Injects at a given `br_if` or `if`, to run when its branch is taken
+    fn branch_taken_at(&mut self, loc: Location) -> &mut Self {
+        self.set_instrument_mode_at(InstrumentationMode::BranchTaken, loc);
+        self
+    }
+
+    /// Injects at a given `br_if` or `if`, to run when its branch is not taken
+    fn branch_not_taken_at(&mut self, loc: Location) -> &mut Self {
+        self.set_instrument_mode_at(InstrumentationMode::BranchNotTaken, loc);
+        self
+    }
+
+    /// Injects at a given `br_table`, to run when it branches to the target at index `target` of its target list
+    fn br_table_target_at(&mut self, target: u32, loc: Location) -> &mut Self {
+        self.set_instrument_mode_at(InstrumentationMode::BrTableTarget(target), loc);
+        self
+    }
+
+    /// Injects at a given `br_table`, to run when it branches to its default target
+    fn br_table_default_at(&mut self, loc: Location) -> &mut Self {
+        self.set_instrument_mode_at(InstrumentationMode::BrTableDefault, loc);
+        self
+    }
+
+    /// Injects at a given `loop`, to run at its header each time it is re-entered through a back-edge
+    fn loop_iteration_at(&mut self, loc: Location) -> &mut Self {
+        self.set_instrument_mode_at(InstrumentationMode::LoopIteration, loc);
+        self
+    }
+
+    /// Injects at a given `loop`, to run at every `br`, `br_if` and `br_table` that branches back to it
+    fn loop_back_edge_at(&mut self, loc: Location) -> &mut Self {
+        self.set_instrument_mode_at(InstrumentationMode::LoopBackEdge, loc);
+        self
+    }
+
     /// Get the instruction injected at index idx
-    fn get_injected_val(&self, idx: usize) -> &Operator;
+    fn get_injected_val(&self, idx: usize) -> &Operator<'_>;
 }
 
 /// Defines Injection behaviour at the current location of the Iterator
@@ -138,6 +178,31 @@ pub trait Opcode<'a>: Inject<'a> {
         self
     }
 
+    /// Inject a return_call instruction (tail call)
+    fn return_call(&mut self, idx: FunctionID) -> &mut Self {
+        self.inject(Operator::ReturnCall {
+            function_index: *idx,
+        });
+        self
+    }
+
+    /// Inject a return_call_indirect instruction (tail call)
+    fn return_call_indirect(&mut self, type_index: TypeID, table_index: TableID) -> &mut Self {
+        self.inject(Operator::ReturnCallIndirect {
+            type_index: *type_index,
+            table_index: *table_index,
+        });
+        self
+    }
+
+    /// Inject a return_call_ref instruction (tail call)
+    fn return_call_ref(&mut self, type_index: TypeID) -> &mut Self {
+        self.inject(Operator::ReturnCallRef {
+            type_index: *type_index,
+        });
+        self
+    }
+
     /// Inject a no op instruction
     fn nop(&mut self) -> &mut Self {
         self.inject(Operator::Nop);
@@ -198,6 +263,30 @@ pub trait Opcode<'a>: Inject<'a> {
         self
     }
 
+    /// Inject a try_table statement. Indicates the start of a block that catches exceptions
+    /// with the given catch clauses
+    fn try_table(&mut self, block_type: BlockType, catches: Vec<Catch>) -> &mut Self {
+        self.inject(Operator::TryTable {
+            try_table: TryTable {
+                ty: wasmparser::BlockType::from(block_type),
+                catches: catches.into_iter().map(wasmparser::Catch::from).collect(),
+            },
+        });
+        self
+    }
+
+    /// Inject a throw instruction, throws an exception with the given tag
+    fn throw(&mut self, tag: TagID) -> &mut Self {
+        self.inject(Operator::Throw { tag_index: *tag });
+        self
+    }
+
+    /// Inject a throw_ref instruction, rethrows the exception referenced by the `exnref` on the stack
+    fn throw_ref(&mut self) -> &mut Self {
+        self.inject(Operator::ThrowRef);
+        self
+    }
+
     // Numerics
     /// Inject a local.get
     fn local_get(&mut self, idx: LocalID) -> &mut Self {
@@ -970,6 +1059,290 @@ pub trait Opcode<'a>: Inject<'a> {
         self.inject(Operator::GlobalSet { global_index: *idx });
         self
     }
+
+    // Reference Instructions
+    /// Inject a ref.null instruction, the null reference has the heap type of `ty`
+    fn ref_null(&mut self, ty: DataType) -> &mut Self {
+        self.inject(Operator::RefNull {
+            hty: ref_type_of(ty).heap_type(),
+        });
+        self
+    }
+
+    /// Inject a ref.is_null instruction
+    fn ref_is_null(&mut self) -> &mut Self {
+        self.inject(Operator::RefIsNull);
+        self
+    }
+
+    /// Inject a ref.as_non_null instruction
+    fn ref_as_non_null(&mut self) -> &mut Self {
+        self.inject(Operator::RefAsNonNull);
+        self
+    }
+
+    /// Inject a ref.eq instruction
+    fn ref_eq(&mut self) -> &mut Self {
+        self.inject(Operator::RefEq);
+        self
+    }
+
+    // GC Instructions
+    /// Inject a struct.new instruction
+    fn struct_new(&mut self, struct_type: TypeID) -> &mut Self {
+        self.inject(Operator::StructNew {
+            struct_type_index: *struct_type,
+        });
+        self
+    }
+
+    /// Inject a struct.new_default instruction
+    fn struct_new_default(&mut self, struct_type: TypeID) -> &mut Self {
+        self.inject(Operator::StructNewDefault {
+            struct_type_index: *struct_type,
+        });
+        self
+    }
+
+    /// Inject a struct.get instruction
+    fn struct_get(&mut self, struct_type: TypeID, field_index: u32) -> &mut Self {
+        self.inject(Operator::StructGet {
+            struct_type_index: *struct_type,
+            field_index,
+        });
+        self
+    }
+
+    /// Inject a struct.get_s instruction, sign-extends a packed field
+    fn struct_get_s(&mut self, struct_type: TypeID, field_index: u32) -> &mut Self {
+        self.inject(Operator::StructGetS {
+            struct_type_index: *struct_type,
+            field_index,
+        });
+        self
+    }
+
+    /// Inject a struct.get_u instruction, zero-extends a packed field
+    fn struct_get_u(&mut self, struct_type: TypeID, field_index: u32) -> &mut Self {
+        self.inject(Operator::StructGetU {
+            struct_type_index: *struct_type,
+            field_index,
+        });
+        self
+    }
+
+    /// Inject a struct.set instruction
+    fn struct_set(&mut self, struct_type: TypeID, field_index: u32) -> &mut Self {
+        self.inject(Operator::StructSet {
+            struct_type_index: *struct_type,
+            field_index,
+        });
+        self
+    }
+
+    /// Inject an array.new instruction
+    fn array_new(&mut self, array_type: TypeID) -> &mut Self {
+        self.inject(Operator::ArrayNew {
+            array_type_index: *array_type,
+        });
+        self
+    }
+
+    /// Inject an array.new_default instruction
+    fn array_new_default(&mut self, array_type: TypeID) -> &mut Self {
+        self.inject(Operator::ArrayNewDefault {
+            array_type_index: *array_type,
+        });
+        self
+    }
+
+    /// Inject an array.new_fixed instruction
+    fn array_new_fixed(&mut self, array_type: TypeID, array_size: u32) -> &mut Self {
+        self.inject(Operator::ArrayNewFixed {
+            array_type_index: *array_type,
+            array_size,
+        });
+        self
+    }
+
+    /// Inject an array.new_data instruction
+    fn array_new_data(&mut self, array_type: TypeID, data_index: DataSegmentID) -> &mut Self {
+        self.inject(Operator::ArrayNewData {
+            array_type_index: *array_type,
+            array_data_index: *data_index,
+        });
+        self
+    }
+
+    /// Inject an array.new_elem instruction
+    fn array_new_elem(&mut self, array_type: TypeID, elem_index: u32) -> &mut Self {
+        self.inject(Operator::ArrayNewElem {
+            array_type_index: *array_type,
+            array_elem_index: elem_index,
+        });
+        self
+    }
+
+    /// Inject an array.get instruction
+    fn array_get(&mut self, array_type: TypeID) -> &mut Self {
+        self.inject(Operator::ArrayGet {
+            array_type_index: *array_type,
+        });
+        self
+    }
+
+    /// Inject an array.get_s instruction, sign-extends a packed element
+    fn array_get_s(&mut self, array_type: TypeID) -> &mut Self {
+        self.inject(Operator::ArrayGetS {
+            array_type_index: *array_type,
+        });
+        self
+    }
+
+    /// Inject an array.get_u instruction, zero-extends a packed element
+    fn array_get_u(&mut self, array_type: TypeID) -> &mut Self {
+        self.inject(Operator::ArrayGetU {
+            array_type_index: *array_type,
+        });
+        self
+    }
+
+    /// Inject an array.set instruction
+    fn array_set(&mut self, array_type: TypeID) -> &mut Self {
+        self.inject(Operator::ArraySet {
+            array_type_index: *array_type,
+        });
+        self
+    }
+
+    /// Inject an array.len instruction
+    fn array_len(&mut self) -> &mut Self {
+        self.inject(Operator::ArrayLen);
+        self
+    }
+
+    /// Inject an array.fill instruction
+    fn array_fill(&mut self, array_type: TypeID) -> &mut Self {
+        self.inject(Operator::ArrayFill {
+            array_type_index: *array_type,
+        });
+        self
+    }
+
+    /// Inject an array.copy instruction
+    fn array_copy(&mut self, dst_array_type: TypeID, src_array_type: TypeID) -> &mut Self {
+        self.inject(Operator::ArrayCopy {
+            array_type_index_dst: *dst_array_type,
+            array_type_index_src: *src_array_type,
+        });
+        self
+    }
+
+    /// Inject an array.init_data instruction
+    fn array_init_data(&mut self, array_type: TypeID, data_index: DataSegmentID) -> &mut Self {
+        self.inject(Operator::ArrayInitData {
+            array_type_index: *array_type,
+            array_data_index: *data_index,
+        });
+        self
+    }
+
+    /// Inject an array.init_elem instruction
+    fn array_init_elem(&mut self, array_type: TypeID, elem_index: u32) -> &mut Self {
+        self.inject(Operator::ArrayInitElem {
+            array_type_index: *array_type,
+            array_elem_index: elem_index,
+        });
+        self
+    }
+
+    /// Inject a ref.test instruction, tests whether the reference on the stack is of type `ty`
+    fn ref_test(&mut self, ty: DataType) -> &mut Self {
+        let ref_type = ref_type_of(ty);
+        self.inject(if ref_type.is_nullable() {
+            Operator::RefTestNullable {
+                hty: ref_type.heap_type(),
+            }
+        } else {
+            Operator::RefTestNonNull {
+                hty: ref_type.heap_type(),
+            }
+        });
+        self
+    }
+
+    /// Inject a ref.cast instruction, casts the reference on the stack to type `ty`
+    fn ref_cast(&mut self, ty: DataType) -> &mut Self {
+        let ref_type = ref_type_of(ty);
+        self.inject(if ref_type.is_nullable() {
+            Operator::RefCastNullable {
+                hty: ref_type.heap_type(),
+            }
+        } else {
+            Operator::RefCastNonNull {
+                hty: ref_type.heap_type(),
+            }
+        });
+        self
+    }
+
+    /// Inject a br_on_cast instruction, branches if the reference of type `from` can be cast to `to`
+    fn br_on_cast(&mut self, relative_depth: u32, from: DataType, to: DataType) -> &mut Self {
+        self.inject(Operator::BrOnCast {
+            relative_depth,
+            from_ref_type: ref_type_of(from),
+            to_ref_type: ref_type_of(to),
+        });
+        self
+    }
+
+    /// Inject a br_on_cast_fail instruction, branches if the reference of type `from` cannot be cast to `to`
+    fn br_on_cast_fail(&mut self, relative_depth: u32, from: DataType, to: DataType) -> &mut Self {
+        self.inject(Operator::BrOnCastFail {
+            relative_depth,
+            from_ref_type: ref_type_of(from),
+            to_ref_type: ref_type_of(to),
+        });
+        self
+    }
+
+    /// Inject an any.convert_extern instruction
+    fn any_convert_extern(&mut self) -> &mut Self {
+        self.inject(Operator::AnyConvertExtern);
+        self
+    }
+
+    /// Inject an extern.convert_any instruction
+    fn extern_convert_any(&mut self) -> &mut Self {
+        self.inject(Operator::ExternConvertAny);
+        self
+    }
+
+    /// Inject a ref.i31 instruction
+    fn ref_i31(&mut self) -> &mut Self {
+        self.inject(Operator::RefI31);
+        self
+    }
+
+    /// Inject an i31.get_s instruction
+    fn i31_get_s(&mut self) -> &mut Self {
+        self.inject(Operator::I31GetS);
+        self
+    }
+
+    /// Inject an i31.get_u instruction
+    fn i31_get_u(&mut self) -> &mut Self {
+        self.inject(Operator::I31GetU);
+        self
+    }
+}
+
+/// Reference type of a [`DataType`], used by the instructions that carry a heap type.
+fn ref_type_of(ty: DataType) -> RefType {
+    match ValType::from(&ty) {
+        ValType::Ref(ref_type) => ref_type,
+        _ => panic!("Expected a reference type, got {}", ty),
+    }
 }
 
 #[allow(dead_code)]
@@ -996,4 +1369,48 @@ pub trait MacroOpcode<'a>: Inject<'a> {
         self.inject(Operator::I64Const { value: i64_val });
         self
     }
+
+    /// Inject a constant address into the given memory, an i64.const for a 64-bit memory
+    /// and an i32.const otherwise.
+    fn addr_const(&mut self, memory: &MemoryType, value: u64) -> &mut Self {
+        if memory.memory64 {
+            self.u64_const(value)
+        } else {
+            self.u32_const(value as u32)
+        }
+    }
+
+    /// Add the two addresses on top of the stack with the address type of the given memory.
+    fn addr_add(&mut self, memory: &MemoryType) -> &mut Self {
+        if memory.memory64 {
+            self.inject(Operator::I64Add);
+        } else {
+            self.inject(Operator::I32Add);
+        }
+        self
+    }
+
+    /// Widen the address on top of the stack to an i64, zero-extending addresses of a 32-bit memory.
+    /// (Useful to pass addresses of any memory to the same probe function.)
+    fn addr_to_i64(&mut self, memory: &MemoryType) -> &mut Self {
+        if !memory.memory64 {
+            self.inject(Operator::I64ExtendI32U);
+        }
+        self
+    }
+}
+
+/// Create a [`MemArg`] for an access into the given memory.
+/// The offset is an immediate of the instruction, for a 32-bit memory it must fit into an u32.
+pub fn memarg(memory: MemoryID, memory_ty: &MemoryType, offset: u64, align: u8) -> MemArg {
+    assert!(
+        memory_ty.memory64 || offset <= u32::MAX as u64,
+        "Offset {offset} is out of range for a 32-bit memory"
+    );
+    MemArg {
+        align,
+        max_align: align,
+        offset,
+        memory: *memory,
+    }
 }
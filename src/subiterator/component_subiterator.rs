@@ -1,96 +1,131 @@
 //! SubIterator for a Component
 
 use crate::ir::id::{FunctionID, ModuleID};
-use crate::ir::types::Location;
+use crate::ir::types::{ComponentPath, Location};
 use crate::subiterator::module_subiterator::ModuleSubIterator;
 use std::collections::HashMap;
 
-/// Sub-iterator for a Component. Keeps track of current location in a Component.
+/// Sub-iterator for a Component. Keeps track of current location in a Component,
+/// including the modules of its nested components.
 pub struct ComponentSubIterator {
-    /// The current module the SubIterator is at.
-    curr_mod: ModuleID,
-    /// The total number of modules in the component.
-    num_mods: usize,
+    /// Index into `mods` of the module the SubIterator is at.
+    curr: usize,
+    /// The path to the (nested) component and the index of the module the SubIterator is at.
+    curr_mod: (ComponentPath, ModuleID),
+    /// The modules to visit, as the path to their (nested) component and their index in it.
+    mods: Vec<(ComponentPath, ModuleID)>,
     /// The module iterator used to keep track of the location in the module.
-    pub(crate) mod_iterator: ModuleSubIterator,
-    /// Metadata that maps Module Index -> Function Index -> Instruction Index
-    metadata: HashMap<ModuleID, HashMap<FunctionID, usize>>,
-    /// Map of Module -> Functions to skip in that module. Provide an empty HashMap if no functions are to be skipped.
-    skip_funcs: HashMap<ModuleID, Vec<FunctionID>>,
+    /// `None` if there is no function to visit at all.
+    pub(crate) mod_iterator: Option<ModuleSubIterator>,
+    /// Metadata that maps Function Index -> Instruction Index for every module in `mods`
+    metadata: Vec<HashMap<FunctionID, usize>>,
+    /// Functions to skip for every module in `mods`.
+    skip_funcs: Vec<Vec<FunctionID>>,
 }
 
 impl ComponentSubIterator {
-    /// Creates a new ComponentSubIterator
+    /// Creates a new ComponentSubIterator over the modules of the outermost component, starting
+    /// at `curr_mod`. Use [`ComponentSubIterator::new_nested`] to also visit nested components.
     pub fn new(
         curr_mod: ModuleID,
         num_mods: usize,
         metadata: HashMap<ModuleID, HashMap<FunctionID, usize>>,
         skip_funcs: HashMap<ModuleID, Vec<FunctionID>>,
     ) -> Self {
-        // Get current skip func
-        // initializes to the first module
-        ComponentSubIterator {
-            curr_mod,
-            num_mods,
-            metadata: metadata.clone(),
-            mod_iterator: ModuleSubIterator::new(
-                metadata.get(&ModuleID(0)).unwrap().keys().len() as u32,
-                (*metadata.get(&curr_mod).unwrap()).clone(),
-                match skip_funcs.contains_key(&curr_mod) {
-                    true => skip_funcs.get(&curr_mod).unwrap().clone(),
-                    false => vec![],
-                },
-            ),
+        let mods: Vec<_> = (*curr_mod..num_mods as u32)
+            .map(|mod_idx| (ComponentPath::default(), ModuleID(mod_idx)))
+            .collect();
+        let metadata = mods
+            .iter()
+            .map(|(_, mod_idx)| metadata.get(mod_idx).cloned().unwrap_or_default())
+            .collect();
+        let skip_funcs = mods
+            .iter()
+            .map(|(_, mod_idx)| skip_funcs.get(mod_idx).cloned().unwrap_or_default())
+            .collect();
+        Self::new_nested(mods, metadata, skip_funcs)
+    }
+
+    /// Creates a new ComponentSubIterator over the given modules, `metadata` and `skip_funcs`
+    /// hold an entry for each of them.
+    pub fn new_nested(
+        mods: Vec<(ComponentPath, ModuleID)>,
+        metadata: Vec<HashMap<FunctionID, usize>>,
+        skip_funcs: Vec<Vec<FunctionID>>,
+    ) -> Self {
+        assert_eq!(mods.len(), metadata.len());
+        assert_eq!(mods.len(), skip_funcs.len());
+        let mut comp_it = ComponentSubIterator {
+            curr: 0,
+            curr_mod: (ComponentPath::default(), ModuleID(0)),
+            mods,
+            mod_iterator: None,
+            metadata,
             skip_funcs,
-        }
+        };
+        // initializes to the first module with a function to visit
+        comp_it.enter_module(0);
+        comp_it
     }
 
     /// Resets the ComponentSubIterator and all child SubIterators
     pub fn reset(&mut self) {
-        *self.curr_mod = 0;
-        self.mod_iterator
-            .reset_from_comp_iterator((*self.metadata.get(&self.curr_mod).unwrap()).clone());
+        self.enter_module(0);
     }
 
-    /// Goes to the next module enclosed by the component
-    fn next_module(&mut self) -> bool {
-        *self.curr_mod += 1;
-        if *self.curr_mod < self.num_mods as u32 {
-            let num_funcs = self.metadata.get(&self.curr_mod).unwrap().keys().len() as u32;
-            let met = self.metadata.get(&self.curr_mod).unwrap().clone();
-            // If we're defining a new module, we have to reset function
-            self.mod_iterator = ModuleSubIterator::new(
-                num_funcs,
-                met,
-                match self.skip_funcs.contains_key(&self.curr_mod) {
-                    true => self.skip_funcs.get(&self.curr_mod).unwrap().clone(),
-                    false => vec![],
-                },
-            );
-            true
-        } else {
-            false
+    /// Goes to the first module starting at `from` that has a function to visit
+    fn enter_module(&mut self, from: usize) -> bool {
+        self.curr = from;
+        while self.curr < self.mods.len() {
+            let met = &self.metadata[self.curr];
+            if !met.is_empty() {
+                // If we're defining a new module, we have to reset function
+                let mod_iterator = ModuleSubIterator::new(
+                    met.len() as u32,
+                    met.clone(),
+                    self.skip_funcs[self.curr].clone(),
+                );
+                // Move on if all functions of this module are skipped
+                if !mod_iterator.end() {
+                    self.mod_iterator = Some(mod_iterator);
+                    self.curr_mod = self.mods[self.curr].clone();
+                    return true;
+                }
+            }
+            self.curr += 1;
         }
+        false
+    }
+
+    fn mod_iterator(&self) -> &ModuleSubIterator {
+        self.mod_iterator
+            .as_ref()
+            .expect("There are no functions to visit in this component")
+    }
+
+    /// Gets the path to the (nested) component of the current module
+    pub fn curr_path(&self) -> ComponentPath {
+        self.curr_mod.0.clone()
     }
 
-    /// Gets the index of the current module in the component
+    /// Gets the index of the current module in its component
     pub fn curr_mod_idx(&self) -> ModuleID {
-        self.curr_mod
+        self.curr_mod.1
     }
 
     /// Gets the index of the current function in the current module
     pub fn curr_func_idx(&self) -> FunctionID {
-        self.mod_iterator.curr_func
+        self.mod_iterator().curr_func
     }
 
     /// Gets the index of the current instruction in the current function
     pub fn curr_instr_idx(&self) -> usize {
-        self.mod_iterator.func_iterator.curr_instr
+        self.mod_iterator().func_iterator.curr_instr
     }
 
     /// Checks if the SubIterator has finished traversing all the modules
     pub fn end(&self) -> bool {
-        *self.curr_mod as usize == self.num_mods
+        self.curr >= self.mods.len()
     }
 
     /// Returns the Current Location as a Location and a bool value that
@@ -102,14 +137,10 @@ impl ComponentSubIterator {
                 instr_idx,
             },
             is_end,
-        ) = self.mod_iterator.curr_loc()
+        ) = self.mod_iterator().curr_loc()
         {
             (
-                Location::Component {
-                    mod_idx: self.curr_mod,
-                    func_idx,
-                    instr_idx,
-                },
+                Location::in_component(self.curr_path(), self.curr_mod_idx(), func_idx, instr_idx),
                 is_end,
             )
         } else {
@@ -117,13 +148,39 @@ impl ComponentSubIterator {
         }
     }
 
+    /// Goes to the instruction at `instr_idx` in the current function
+    pub(crate) fn goto_instr(&mut self, instr_idx: usize) {
+        self.mod_iterator
+            .as_mut()
+            .unwrap()
+            .func_iterator
+            .goto(instr_idx);
+    }
+
+    /// Number of instructions in the current function
+    pub(crate) fn curr_num_instr(&self) -> usize {
+        self.mod_iterator().func_iterator.num_instr()
+    }
+
+    /// Goes to the first instruction of the next function to visit, in this module or the next ones
+    pub(crate) fn next_function(&mut self) -> bool {
+        if self.end() {
+            return false;
+        }
+        self.mod_iterator.as_mut().unwrap().next_function() || self.enter_module(self.curr + 1)
+    }
+
     #[allow(clippy::should_implement_trait)]
     /// Goes to the next instruction in the component
     pub fn next(&mut self) -> bool {
-        if self.mod_iterator.has_next() {
-            self.mod_iterator.next()
+        if self.end() {
+            return false;
+        }
+        let mod_iterator = self.mod_iterator.as_mut().unwrap();
+        if mod_iterator.has_next() && mod_iterator.next() {
+            true
         } else {
-            self.next_module()
+            self.enter_module(self.curr + 1)
         }
     }
 }
@@ -44,6 +44,17 @@ impl FuncSubIterator {
         }
     }
 
+    /// Number of instructions in this function
+    pub(crate) fn num_instr(&self) -> usize {
+        self.num_instr
+    }
+
+    /// Goes to the instruction at `instr_idx`
+    pub(crate) fn goto(&mut self, instr_idx: usize) {
+        assert!(instr_idx < self.num_instr);
+        self.curr_instr = instr_idx;
+    }
+
     /// Checks if the SubIterator has finished traversing all the instructions
     pub fn end(&mut self) -> bool {
         self.curr_instr == self.num_instr
@@ -39,9 +39,10 @@ impl ModuleSubIterator {
             skip_funcs,
         };
         // In case 0 is in skip func
-        while mod_it
-            .skip_funcs
-            .contains(&(mod_it.curr_func as FunctionID))
+        while !mod_it.end()
+            && mod_it
+                .skip_funcs
+                .contains(&(mod_it.curr_func as FunctionID))
         {
             mod_it.next_function();
         }
@@ -66,18 +67,6 @@ impl ModuleSubIterator {
         )
     }
 
-    /// Resets the ModuleSubIterator when it is a Child SubIterator of a ComponentSubIterator
-    pub(crate) fn reset_from_comp_iterator(&mut self, metadata: HashMap<FunctionID, usize>) {
-        *self.curr_func = 0;
-        self.metadata = metadata;
-        self.func_iterator.reset(
-            *self
-                .metadata
-                .get(self.metadata.keys().min().unwrap())
-                .unwrap(),
-        );
-    }
-
     /// Resets the ModuleSubIterator when it is not a Child SubIterator
     pub fn reset(&mut self) {
         *self.curr_func = 0;
@@ -91,7 +80,7 @@ impl ModuleSubIterator {
     }
 
     /// Goes to the next function in the module
-    fn next_function(&mut self) -> bool {
+    pub(crate) fn next_function(&mut self) -> bool {
         *self.curr_func += 1;
         self.visited_funcs += 1;
 
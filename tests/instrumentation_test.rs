@@ -1,12 +1,19 @@
+#![allow(clippy::vec_init_then_push)]
+
 use log::{error, trace};
-use orca_wasm::ir::id::FunctionID;
-use orca_wasm::ir::types::InstrumentationMode;
+use orca_wasm::ir::function::{FunctionBuilder, FunctionModifier};
+use orca_wasm::ir::id::{FunctionID, LocalID, MemoryID};
+use orca_wasm::ir::types::{
+    BlockType, DataSegment, DataSegmentKind, FuncInstrMode, InitInstr, InstrumentationMode,
+    TailCallExit, Value,
+};
 use orca_wasm::iterator::component_iterator::ComponentIterator;
 use orca_wasm::iterator::iterator_trait::{IteratingInstrumenter, Iterator};
 use orca_wasm::iterator::module_iterator::ModuleIterator;
+use orca_wasm::iterator::pass_manager::{InjectedMode, PassManager};
 use orca_wasm::module_builder::AddLocal;
-use orca_wasm::opcode::{Inject, Instrumenter};
-use orca_wasm::{Component, Location, Module, Opcode};
+use orca_wasm::opcode::{memarg, Inject, Instrumenter, MacroOpcode};
+use orca_wasm::{Component, DataType, InitExpr, Location, Module, Opcode};
 use std::collections::HashMap;
 use std::mem::discriminant;
 use wasmparser::Operator;
@@ -1201,6 +1208,140 @@ fn test_fn_exit_two_funcs() {
     }
 }
 
+#[test]
+fn test_fn_exit_throw() {
+    let file = "tests/test_inputs/instr_testing/modules/fn_exit/throw.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let mut fn_exit_body = vec![];
+    fn_exit_body.push(Operator::I32Const { value: 1 });
+    fn_exit_body.push(Operator::Drop);
+
+    inject_function_exit(&mut mod_it, fn_exit_body);
+
+    let result = module.encode();
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_fn_exit_br_throw() {
+    let file = "tests/test_inputs/instr_testing/modules/fn_exit/br_throw.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let mut fn_exit_body = vec![];
+    fn_exit_body.push(Operator::I32Const { value: 1 });
+    fn_exit_body.push(Operator::Drop);
+
+    inject_function_exit(&mut mod_it, fn_exit_body);
+
+    let result = module.encode();
+    wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+        .validate_all(&result)
+        .expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_fn_exit_tail_call() {
+    let file = "tests/test_inputs/instr_testing/modules/fn_exit/tail_call.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let mut fn_exit_body = vec![];
+    fn_exit_body.push(Operator::I32Const { value: 1 });
+    fn_exit_body.push(Operator::Drop);
+
+    inject_function_exit(&mut mod_it, fn_exit_body);
+
+    let result = module.encode();
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_fn_exit_tail_call_after_callee() {
+    let file = "tests/test_inputs/instr_testing/modules/fn_exit/tail_call_after_callee.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    for fid in 0..3 {
+        module
+            .functions
+            .unwrap_local(FunctionID(fid))
+            .instr_flag
+            .tail_call_exit = TailCallExit::AfterCallee;
+    }
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let mut fn_exit_body = vec![];
+    fn_exit_body.push(Operator::I32Const { value: 1 });
+    fn_exit_body.push(Operator::Drop);
+
+    inject_function_exit(&mut mod_it, fn_exit_body);
+
+    let result = module.encode();
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_fn_exit_tail_call_after_callee_alternate() {
+    let file = "tests/test_inputs/instr_testing/modules/fn_exit/tail_call_after_callee.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    module
+        .functions
+        .unwrap_local(FunctionID(1))
+        .instr_flag
+        .tail_call_exit = TailCallExit::AfterCallee;
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+    loop {
+        if let Some(Operator::ReturnCall { .. }) = mod_it.curr_op() {
+            mod_it.alternate().unreachable();
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+    let mut fn_exit_body = vec![];
+    fn_exit_body.push(Operator::I32Const { value: 1 });
+    fn_exit_body.push(Operator::Drop);
+
+    inject_function_exit(&mut mod_it, fn_exit_body);
+
+    // the exit cannot be placed after the callee without dropping the alternate
+    // the exit cannot be placed after the callee without dropping the alternate
+    assert!(module.try_encode().is_err());
+}
+
 // ==== SEMANTIC AFTER ====
 #[test]
 fn test_semantic_after_complex_mult_nested_diff_opcodes() {
@@ -1693,7 +1834,6 @@ fn test_semantic_after_medium_multiple() {
 fn test_semantic_after_medium_other_operators() {
     let _file = "tests/test_inputs/instr_testing/modules/semantic_after/medium_other_operators.wat";
     // todo -- test the other operators (when I know how to write wat using them)
-    assert!(true);
 }
 
 #[test]
@@ -1895,6 +2035,601 @@ fn test_semantic_after_simple_2br_table() {
     }
 }
 
+#[test]
+fn test_branch_outcome_br_if() {
+    let file = "tests/test_inputs/instr_testing/modules/branch_outcome/br_if.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let ops_of_interest = vec![
+        (
+            SupportedOperators::BrIf,
+            (
+                InstrumentationMode::BranchTaken,
+                vec![Operator::I32Const { value: 1 }, Operator::Drop],
+            ),
+        ),
+        (
+            SupportedOperators::BrIf,
+            (
+                InstrumentationMode::BranchNotTaken,
+                vec![Operator::I32Const { value: 2 }, Operator::Drop],
+            ),
+        ),
+    ];
+    run_block_injection(&mut mod_it, &ops_of_interest);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_branch_outcome_reuse() {
+    let file = "tests/test_inputs/instr_testing/modules/branch_outcome/reuse.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let ops_of_interest = vec![
+        (
+            SupportedOperators::BrIf,
+            (
+                InstrumentationMode::BranchTaken,
+                vec![Operator::I32Const { value: 1 }, Operator::Drop],
+            ),
+        ),
+        (
+            SupportedOperators::Loop,
+            (
+                InstrumentationMode::LoopIteration,
+                vec![Operator::I32Const { value: 2 }, Operator::Drop],
+            ),
+        ),
+    ];
+    run_block_injection(&mut mod_it, &ops_of_interest);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    // the condition locals are reused by every branch, the loop flags by every loop
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_branch_outcome_if() {
+    let file = "tests/test_inputs/instr_testing/modules/branch_outcome/if.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let ops_of_interest = vec![
+        (
+            SupportedOperators::If,
+            (
+                InstrumentationMode::BranchTaken,
+                vec![Operator::I32Const { value: 1 }, Operator::Drop],
+            ),
+        ),
+        (
+            SupportedOperators::If,
+            (
+                InstrumentationMode::BranchNotTaken,
+                vec![Operator::I32Const { value: 2 }, Operator::Drop],
+            ),
+        ),
+    ];
+    run_block_injection(&mut mod_it, &ops_of_interest);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_branch_outcome_br_table() {
+    let file = "tests/test_inputs/instr_testing/modules/branch_outcome/br_table.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let ops_of_interest = vec![
+        (
+            SupportedOperators::BrTable,
+            (
+                InstrumentationMode::BrTableTarget(0),
+                vec![Operator::I32Const { value: 10 }, Operator::Drop],
+            ),
+        ),
+        (
+            SupportedOperators::BrTable,
+            (
+                InstrumentationMode::BrTableTarget(1),
+                vec![Operator::I32Const { value: 11 }, Operator::Drop],
+            ),
+        ),
+        (
+            SupportedOperators::BrTable,
+            (
+                InstrumentationMode::BrTableDefault,
+                vec![Operator::I32Const { value: 12 }, Operator::Drop],
+            ),
+        ),
+    ];
+    run_block_injection(&mut mod_it, &ops_of_interest);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_loop_back_edge() {
+    let file = "tests/test_inputs/instr_testing/modules/loop/back_edge.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let ops_of_interest = vec![(
+        SupportedOperators::Loop,
+        (
+            InstrumentationMode::LoopBackEdge,
+            vec![Operator::I32Const { value: 10 }, Operator::Drop],
+        ),
+    )];
+    run_block_injection(&mut mod_it, &ops_of_interest);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_loop_iteration() {
+    let file = "tests/test_inputs/instr_testing/modules/loop/iteration.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let ops_of_interest = vec![(
+        SupportedOperators::Loop,
+        (
+            InstrumentationMode::LoopIteration,
+            vec![Operator::I32Const { value: 20 }, Operator::Drop],
+        ),
+    )];
+    run_block_injection(&mut mod_it, &ops_of_interest);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_save_call_args_and_results() {
+    let file = "tests/test_inputs/instr_testing/modules/call_args/call.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    loop {
+        if let Some(Operator::Call { .. } | Operator::CallIndirect { .. }) = mod_it.curr_op() {
+            // log the first argument
+            let args = mod_it.save_args_to_locals();
+            assert_eq!(args.len(), 2);
+            mod_it.local_get(args[0]).call(FunctionID(0));
+
+            // and look at the result
+            let results = mod_it.save_results_to_locals();
+            assert_eq!(results.len(), 1);
+            mod_it.local_get(results[0]).drop();
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_save_mem_access() {
+    let file = "tests/test_inputs/instr_testing/modules/mem_access/load_store.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let mut accesses = vec![];
+    loop {
+        let is_access = matches!(
+            mod_it.curr_op(),
+            Some(
+                Operator::I32Store { .. }
+                    | Operator::I64Load8U { .. }
+                    | Operator::V128Store32Lane { .. }
+            )
+        );
+        if is_access {
+            // trace the effective address and the width of the access
+            let access = mod_it.save_mem_access_to_locals();
+            mod_it
+                .local_get(access.addr)
+                .u32_const(access.width)
+                .call(FunctionID(0));
+            accesses.push(access);
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+    let widths: Vec<u32> = accesses.iter().map(|access| access.width).collect();
+    assert_eq!(widths, vec![4, 1, 4]);
+    let has_value: Vec<bool> = accesses
+        .iter()
+        .map(|access| access.value.is_some())
+        .collect();
+    assert_eq!(has_value, vec![true, false, true]);
+    // without a static offset, the address operand is the effective address, the address
+    // operand of the previous access is released and reused for it
+    assert_eq!(accesses[1].addr, LocalID(2));
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_branch_depth_fixup() {
+    let file = "tests/test_inputs/instr_testing/modules/branch_depth/wrap.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    // wrap the branches of the inner block in a new block
+    loop {
+        let (loc, _) = mod_it.curr_loc();
+        match (loc, mod_it.curr_op()) {
+            (Location::Module { instr_idx: 2, .. }, _) => {
+                mod_it.before().block(BlockType::Empty);
+            }
+            (_, Some(Operator::BrTable { .. })) => {
+                mod_it.after().end();
+            }
+            _ => {}
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_scratch_locals() {
+    let file = "tests/test_inputs/instr_testing/modules/scratch/reuse.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let mut scratch = vec![];
+    loop {
+        if let Some(Operator::I32Add) = mod_it.curr_op() {
+            mod_it.enter_scratch_scope();
+            let rhs = mod_it.scratch_local(DataType::I32);
+            // taken but never used, removed when encoding
+            let _unused = mod_it.scratch_local(DataType::I64);
+            mod_it.before().local_set(rhs).local_get(rhs);
+            mod_it.exit_scratch_scope();
+            scratch.push(rhs);
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+    // the scratch local is reused once its scope is exited
+    assert_eq!(scratch, vec![LocalID(1), LocalID(1)]);
+
+    // a local added after the unused scratch local is renumbered
+    mod_it.reset();
+    let local = mod_it.add_local(DataType::F32);
+    assert_eq!(local, LocalID(3));
+    mod_it.before().f32_const(0.0).local_set(local);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_pass_manager() {
+    let file = "tests/test_inputs/instr_testing/modules/passes/merge.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+
+    // wraps the `i32.add` in probes and replaces it
+    fn pass<'a>(module: &mut Module<'a>, probe: i32, alt: Operator<'a>) {
+        let mut mod_it = ModuleIterator::new(module, &vec![]);
+        loop {
+            if let Some(Operator::I32Add) = mod_it.curr_op() {
+                mod_it.before().i32_const(probe).drop();
+                mod_it.after().i32_const(probe + 1).drop();
+                mod_it.alternate().inject(alt.clone());
+            }
+            if mod_it.next().is_none() {
+                break;
+            }
+        }
+        mod_it.reset();
+        mod_it.func_entry().i32_const(probe + 2).drop();
+    }
+
+    let report = PassManager::new()
+        .add_pass("first", |module| pass(module, 10, Operator::I32Sub))
+        .add_pass("second", |module| pass(module, 20, Operator::I32Mul))
+        .run_module(&mut module);
+
+    // the replacement of the first pass is kept
+    assert_eq!(report.conflicts.len(), 1);
+    let conflict = &report.conflicts[0];
+    assert!(matches!(
+        conflict.location,
+        Location::Module { instr_idx: 2, .. }
+    ));
+    assert_eq!(conflict.mode, InstrumentationMode::Alternate);
+    assert_eq!(conflict.kept, Some(0));
+    assert_eq!(conflict.dropped, 1);
+
+    let second: Vec<_> = report
+        .contributions_of("second")
+        .map(|c| (c.mode, c.range.clone()))
+        .collect();
+    assert_eq!(
+        second,
+        vec![
+            (InjectedMode::Func(FuncInstrMode::Entry), 2..4),
+            (InjectedMode::Instr(InstrumentationMode::Before), 2..4),
+            (InjectedMode::Instr(InstrumentationMode::After), 0..2),
+        ]
+    );
+    assert_eq!(report.contributions_of("first").count(), 4);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_transaction_rollback() {
+    let file = "tests/test_inputs/instr_testing/modules/passes/merge.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut untouched = Module::parse(&buff, false).expect("Unable to parse");
+
+    let result: Result<(), &str> = module.transaction(|module| {
+        module.add_global(
+            InitExpr::new(vec![InitInstr::Value(Value::I32(0))]),
+            DataType::I32,
+            true,
+            false,
+        );
+        let ty = module.types.add(&[], &[]);
+        module.add_import_func("env".to_string(), "probe".to_string(), ty);
+        let mut mod_it = ModuleIterator::new(module, &vec![]);
+        let local = mod_it.add_local(DataType::I32);
+        mod_it.before().i32_const(1).local_set(local);
+        mod_it.func_entry().i32_const(2).drop();
+        // the generator fails halfway through
+        Err("unsupported operator")
+    });
+    assert!(result.is_err());
+    assert_eq!(module.encode(), untouched.encode());
+
+    // the changes of a successful transaction are kept
+    let result: Result<LocalID, &str> = module.transaction(|module| {
+        let mut mod_it = ModuleIterator::new(module, &vec![]);
+        let local = mod_it.add_local(DataType::I32);
+        mod_it.before().i32_const(1).local_set(local);
+        Ok(local)
+    });
+    assert_eq!(result, Ok(LocalID(1)));
+    assert_ne!(module.encode(), untouched.encode());
+}
+
+#[test]
+fn test_inspect_and_clear_instr() {
+    let file = "tests/test_inputs/instr_testing/modules/clear/two_funcs.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut untouched = Module::parse(&buff, false).expect("Unable to parse");
+
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+    loop {
+        match mod_it.curr_op() {
+            Some(Operator::Call { .. }) => {
+                mod_it.before().i32_const(1).drop();
+                mod_it.after().nop();
+            }
+            Some(Operator::I32Add) => {
+                mod_it.alternate().i32_sub();
+            }
+            _ => {}
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+    mod_it.reset();
+    mod_it.func_exit().nop();
+
+    let locations: Vec<_> = module
+        .instrumented_locations()
+        .into_iter()
+        .map(|(loc, mode)| match loc {
+            Location::Module {
+                func_idx,
+                instr_idx,
+            } => (*func_idx, instr_idx, mode),
+            _ => panic!("Should have gotten Module Location!"),
+        })
+        .collect();
+    assert_eq!(
+        locations,
+        vec![
+            (0, 1, InstrumentationMode::Before),
+            (0, 1, InstrumentationMode::After),
+            (1, 2, InstrumentationMode::Alternate),
+        ]
+    );
+    assert_eq!(
+        module.instrumented_functions(),
+        vec![(FunctionID(0), FuncInstrMode::Exit)]
+    );
+
+    module.clear_function_instr(FunctionID(0));
+    assert!(module.instrumented_functions().is_empty());
+    assert_eq!(module.instrumented_locations().len(), 1);
+
+    module.clear_all_instr();
+    assert!(module.instrumented_locations().is_empty());
+    assert_eq!(module.encode(), untouched.encode());
+}
+
+#[test]
+#[cfg(feature = "wasmprinter")]
+fn test_to_wat_annotated() {
+    let file = "tests/test_inputs/instr_testing/modules/annotated/print.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+    loop {
+        match mod_it.curr_op() {
+            Some(Operator::Call { .. }) => {
+                mod_it.before().i32_const(1).drop();
+                mod_it.after().nop();
+            }
+            Some(Operator::I32Add) => {
+                mod_it.alternate().i32_sub();
+            }
+            Some(Operator::Drop) => {
+                mod_it.empty_alternate();
+            }
+            _ => {}
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+    mod_it.reset();
+    let local = mod_it.scratch_local(DataType::I64);
+    mod_it.func_entry().i64_const(0).local_set(local);
+
+    let annotated = module
+        .to_wat_annotated()
+        .expect("Unable to print the module");
+    let expected = std::fs::read_to_string(file).expect("Unable to read the file");
+    assert_eq!(annotated, expected);
+
+    let out = wasmprinter::print_bytes(module.encode()).expect("couldn't convert Wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_branch_depth_unbalanced() {
+    let file = "tests/test_inputs/instr_testing/modules/branch_depth/wrap.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    // open a block that is never closed
+    loop {
+        if let (Location::Module { instr_idx: 2, .. }, _) = mod_it.curr_loc() {
+            mod_it.before().block(BlockType::Empty);
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+
+    assert!(module.try_encode().is_err());
+}
+
 // =================
 // ==== HELPERS ====
 // =================
@@ -1959,7 +2694,7 @@ fn run_block_injection<'a, 'b, 'c>(
                     }
                 };
                 if matches {
-                    if body.len() > 0 {
+                    if !body.is_empty() {
                         // has body
                         mod_it.set_instrument_mode(*mode);
                         mod_it.inject_all(body);
@@ -1990,6 +2725,253 @@ fn run_block_injection<'a, 'b, 'c>(
     }
 }
 
+#[test]
+fn test_memory_trace_probe() {
+    for file in [
+        "tests/test_inputs/instr_testing/modules/memory/trace_loads.wat",
+        "tests/test_inputs/instr_testing/modules/memory/trace_loads64.wat",
+    ] {
+        let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+        let mut module = Module::parse(&buff, false).expect("Unable to parse");
+        let memory = module.get_memory_type(MemoryID(0)).unwrap();
+        let addr_ty = module.get_memory_addr_type(MemoryID(0)).unwrap();
+        assert_eq!(module.data[0].kind.offset(), Some(16));
+
+        // the same probe works for both 32 and 64-bit memories
+        let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+        loop {
+            let memarg = match mod_it.curr_op() {
+                Some(Operator::I32Load { memarg } | Operator::I32Load8U { memarg }) => {
+                    Some(*memarg)
+                }
+                _ => None,
+            };
+            if let Some(memarg) = memarg {
+                let addr = mod_it.add_local(addr_ty);
+                mod_it
+                    .before()
+                    .local_tee(addr)
+                    .local_get(addr)
+                    .addr_to_i64(&memory)
+                    .u64_const(memarg.offset)
+                    .i64_add()
+                    .call(FunctionID(0));
+            }
+            if mod_it.next().is_none() {
+                break;
+            };
+        }
+
+        let mut builder = FunctionBuilder::new(&[], &[DataType::I32]);
+        builder
+            .addr_const(&memory, 8)
+            .addr_const(&memory, 8)
+            .addr_add(&memory)
+            .i32_load(memarg(MemoryID(0), &memory, 0, 2));
+        builder.finish_module(&mut module);
+        module.add_data(DataSegment {
+            kind: DataSegmentKind::active(MemoryID(0), &memory, 32),
+            data: vec![0],
+        });
+
+        let result = module.encode();
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(&result)
+            .expect("Invalid module");
+        let module = Module::parse(&result, false).expect("Unable to parse");
+        assert_eq!(module.data[1].kind.offset(), Some(32));
+    }
+}
+
+#[test]
+fn test_parallel_instrumentation() {
+    for file in [
+        "tests/test_inputs/instr_testing/modules/fn_exit/two_funcs.wat",
+        "tests/test_inputs/instr_testing/modules/fn_exit/throw.wat",
+        "tests/test_inputs/instr_testing/modules/fn_exit/tail_call.wat",
+    ] {
+        let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+        let instrument = |_: FunctionID, mut modifier: FunctionModifier| {
+            modifier.before_at(Location::Module {
+                func_idx: FunctionID(0), // not used
+                instr_idx: 0,
+            });
+            modifier.i32_const(7).drop();
+        };
+
+        // sequential
+        let mut module = Module::parse(&buff, false).expect("Unable to parse");
+        let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+        inject_function_exit(
+            &mut mod_it,
+            vec![Operator::I32Const { value: 1 }, Operator::Drop],
+        );
+        let num_funcs = (0..)
+            .take_while(|idx| module.functions.get_fn_by_id(FunctionID(*idx)).is_some())
+            .count() as u32;
+        for func_idx in 0..num_funcs {
+            if let Some(modifier) = module.functions.get_fn_modifier(FunctionID(func_idx)) {
+                instrument(FunctionID(func_idx), modifier);
+            }
+        }
+        let sequential = module.encode();
+
+        // parallel
+        let mut module = Module::parse(&buff, false).expect("Unable to parse");
+        let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+        inject_function_exit(
+            &mut mod_it,
+            vec![Operator::I32Const { value: 1 }, Operator::Drop],
+        );
+        module.par_for_each_local_func(instrument);
+        let parallel = module.encode_parallel();
+
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(&parallel)
+            .expect("Invalid module");
+        assert_eq!(sequential, parallel);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Unable to instrument function 1")]
+fn test_parallel_instrumentation_panic() {
+    let file = "tests/test_inputs/instr_testing/modules/fn_exit/two_funcs.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    module.par_for_each_local_func(|func_id, _| {
+        if *func_id == 1 {
+            panic!("Unable to instrument function {}", *func_id);
+        }
+    });
+}
+
+/// The operators of all function bodies of a binary, by offset
+fn ops_by_offset(wasm: &[u8]) -> HashMap<usize, Operator<'_>> {
+    let mut ops = HashMap::new();
+    for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+        if let wasmparser::Payload::CodeSectionEntry(body) = payload.unwrap() {
+            for op in body
+                .get_operators_reader()
+                .unwrap()
+                .into_iter_with_offsets()
+            {
+                let (op, offset) = op.unwrap();
+                ops.insert(offset, op);
+            }
+        }
+    }
+    ops
+}
+
+#[test]
+fn test_offset_map() {
+    let file = "tests/test_inputs/instr_testing/modules/fn_exit/two_funcs.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+
+    // only instrument the second function, the first one is copied as is
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+    loop {
+        match mod_it.curr_op() {
+            Some(Operator::I32Const { value: 2 }) => {
+                mod_it.alternate().i32_const(5);
+            }
+            Some(Operator::Call { .. }) => {
+                mod_it.before().i32_const(7).drop();
+                mod_it.after().nop();
+            }
+            _ => {}
+        }
+        if mod_it.next().is_none() {
+            break;
+        };
+    }
+    let (result, map) = module.encode_with_map();
+    wasmparser::validate(&result).expect("Invalid module");
+
+    // the instructions are found at their new offset
+    let (orig_ops, new_ops) = (ops_by_offset(&buff), ops_by_offset(&result));
+    assert_eq!(map.instrs().len(), orig_ops.len());
+    for instr in map.instrs() {
+        match instr.new {
+            Some(new) => {
+                assert_eq!(orig_ops[&instr.original], new_ops[&new]);
+                assert_eq!(map.new_offset(instr.original), Some(new));
+                assert_eq!(map.original_instr(new).unwrap().original, instr.original);
+            }
+            None => assert_eq!(orig_ops[&instr.original], Operator::I32Const { value: 2 }),
+        }
+    }
+    assert_eq!(map.instrs().iter().filter(|i| i.new.is_none()).count(), 1);
+
+    // the injected code is tagged with its mode
+    let modes: Vec<_> = map.injected().iter().map(|inj| inj.mode).collect();
+    assert_eq!(
+        modes,
+        vec![
+            InstrumentationMode::Alternate,
+            InstrumentationMode::Before,
+            InstrumentationMode::After
+        ]
+    );
+    for inj in map.injected() {
+        assert!(matches!(
+            inj.location,
+            Location::Module {
+                func_idx: FunctionID(2),
+                ..
+            }
+        ));
+        assert!(map.original_instr(inj.range.start).is_none());
+        assert_eq!(map.injected_at(inj.range.end - 1).unwrap().mode, inj.mode);
+    }
+    let before = &map.injected()[1];
+    assert_eq!(
+        new_ops[&before.range.start],
+        Operator::I32Const { value: 7 }
+    );
+    assert_eq!(new_ops[&(before.range.end - 1)], Operator::Drop);
+}
+
+#[test]
+fn test_offset_map_component() {
+    let file = "tests/test_inputs/handwritten/components/nested.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut component = Component::parse(&buff, false).expect("Unable to parse");
+
+    let mut comp_it = ComponentIterator::new(&mut component, HashMap::new());
+    loop {
+        if *comp_it.curr_op().unwrap() == Operator::I32Add {
+            comp_it.before().i32_const(4).i32_add();
+        }
+        if comp_it.next().is_none() {
+            break;
+        }
+    }
+    let (result, map) = component.encode_with_map();
+    wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+        .validate_all(&result)
+        .expect("Invalid component");
+
+    let (orig_ops, new_ops) = (ops_by_offset(&buff), ops_by_offset(&result));
+    assert_eq!(map.instrs().len(), orig_ops.len());
+    for instr in map.instrs() {
+        assert_eq!(orig_ops[&instr.original], new_ops[&instr.new.unwrap()]);
+    }
+    let paths: Vec<_> = map
+        .instrs()
+        .iter()
+        .map(|i| i.location.component_parts().unwrap().0.indices().to_vec())
+        .collect();
+    assert!(paths.contains(&vec![]) && paths.contains(&vec![0]) && paths.contains(&vec![0, 0]));
+    assert_eq!(map.injected().len(), 1);
+    assert!(matches!(
+        map.injected()[0].location,
+        Location::NestedComponent { .. }
+    ));
+}
+
 fn inject_function_entry<'a, 'b, 'c>(mod_it: &mut ModuleIterator<'a, 'b>, body: Vec<Operator<'c>>)
 where
     'c: 'b,
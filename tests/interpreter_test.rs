@@ -0,0 +1,186 @@
+use orca_wasm::interpreter::{Imports, Instance, Trap};
+use orca_wasm::ir::id::{FunctionID, GlobalID, MemoryID};
+use orca_wasm::ir::types::{BlockType, Value};
+use orca_wasm::iterator::iterator_trait::{IteratingInstrumenter, Iterator};
+use orca_wasm::iterator::module_iterator::ModuleIterator;
+use orca_wasm::{Location, Module, Opcode};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasmparser::Operator;
+
+fn programs(buff: &[u8]) -> Instance {
+    let module = Module::parse(buff, false).expect("Unable to parse");
+    let mut imports = Imports::new();
+    imports.global("env", "base", Value::I32(100));
+    Instance::new(&module, imports).expect("Unable to instantiate")
+}
+
+#[test]
+fn test_run_programs() {
+    let file = "tests/test_inputs/interpreter/programs.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut instance = programs(&buff);
+
+    assert_eq!(
+        instance.invoke("fac", &[Value::I64(20)]),
+        Ok(vec![Value::I64(2432902008176640000)])
+    );
+    assert_eq!(
+        instance.invoke("sum", &[Value::I32(100)]),
+        Ok(vec![Value::I32(5050)])
+    );
+    for (idx, expected) in [(0, 10), (1, 20), (2, 30), (7, 30)] {
+        assert_eq!(
+            instance.invoke("switch", &[Value::I32(idx)]),
+            Ok(vec![Value::I32(expected)])
+        );
+    }
+    assert_eq!(
+        instance.invoke("hypot", &[Value::F64(3.0), Value::F64(4.0)]),
+        Ok(vec![Value::F64(5.0)])
+    );
+
+    // tables and globals
+    let apply = |instance: &mut Instance, idx| {
+        instance.invoke("apply", &[Value::I32(idx), Value::I32(7), Value::I32(2)])
+    };
+    assert_eq!(apply(&mut instance, 0), Ok(vec![Value::I32(9)]));
+    assert_eq!(apply(&mut instance, 1), Ok(vec![Value::I32(5)]));
+    assert_eq!(apply(&mut instance, 2), Err(Trap::OutOfBoundsTableAccess));
+    assert_eq!(instance.global(GlobalID(1)), Value::I32(3));
+
+    // memories and data segments
+    assert_eq!(instance.invoke("load", &[]), Ok(vec![Value::I32(142)]));
+    assert_eq!(
+        instance.invoke("store", &[Value::I32(16), Value::I32(0x101)]),
+        Ok(vec![])
+    );
+    assert_eq!(
+        instance.invoke("load", &[]),
+        Ok(vec![Value::I32(0x12a + 100)])
+    );
+    assert_eq!(instance.memory(MemoryID(0))[16..20], [0x2a, 0x01, 0, 0]);
+    assert_eq!(
+        instance.invoke("store", &[Value::I32(65535), Value::I32(0)]),
+        Err(Trap::OutOfBoundsMemoryAccess)
+    );
+
+    // traps and errors
+    assert_eq!(
+        instance.invoke("div", &[Value::I32(1), Value::I32(0)]),
+        Err(Trap::IntegerDivideByZero)
+    );
+    assert_eq!(
+        instance.invoke("div", &[Value::I32(i32::MIN), Value::I32(-1)]),
+        Err(Trap::IntegerOverflow)
+    );
+    assert!(matches!(
+        instance.invoke("div", &[Value::I64(1), Value::I32(1)]),
+        Err(Trap::TypeMismatch(_))
+    ));
+    assert_eq!(
+        instance.invoke("missing", &[]),
+        Err(Trap::UnknownExport("missing".to_string()))
+    );
+    let module = Module::parse(&buff, false).expect("Unable to parse");
+    assert!(matches!(
+        Instance::new(&module, Imports::new()),
+        Err(Trap::UnknownImport { .. })
+    ));
+}
+
+#[test]
+fn test_run_instrumented() {
+    let file = "tests/test_inputs/interpreter/probes.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let original = Module::parse(&buff, false).expect("Unable to parse");
+    let mut module = original.clone();
+
+    // report the index of every call before it runs
+    let probe = FunctionID(0);
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+    loop {
+        if let Some(Operator::Call { .. }) = mod_it.curr_op() {
+            let (Location::Module { instr_idx, .. }, _) = mod_it.curr_loc() else {
+                panic!("Should have gotten Module Location!");
+            };
+            mod_it.before().i32_const(instr_idx as i32).call(probe);
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+
+    let run = |module: &Module| {
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut imports = Imports::new();
+        let probe_seen = seen.clone();
+        imports.func("env", "probe", move |args| {
+            probe_seen.borrow_mut().push(args[0]);
+            Ok(vec![])
+        });
+        let mut instance = Instance::new(module, imports).expect("Unable to instantiate");
+        let results = instance.invoke("main", &[Value::I32(5)]);
+        let seen = seen.borrow().clone();
+        (results, seen)
+    };
+    let (original_results, original_seen) = run(&original);
+    let (results, seen) = run(&module);
+    assert_eq!(original_results, Ok(vec![Value::I32(21)]));
+    assert_eq!(results, original_results);
+    assert!(original_seen.is_empty());
+    assert_eq!(seen, vec![Value::I32(1), Value::I32(2)]);
+}
+
+#[test]
+fn test_run_alternate() {
+    let file = "tests/test_inputs/interpreter/probes.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+    loop {
+        if let Some(Operator::I32Mul) = mod_it.curr_op() {
+            mod_it.alternate().i32_add();
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+
+    let mut imports = Imports::new();
+    imports.func("env", "probe", |_| {
+        Err(Trap::Host("Not instrumented".to_string()))
+    });
+    let mut instance = Instance::new(&module, imports).expect("Unable to instantiate");
+    // (5 + 2 + 2) + 1
+    assert_eq!(
+        instance.invoke("main", &[Value::I32(5)]),
+        Ok(vec![Value::I32(10)])
+    );
+}
+
+#[test]
+fn test_run_unbalanced_instrumentation() {
+    let file = "tests/test_inputs/interpreter/probes.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+
+    // open a block that is never closed
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+    loop {
+        if let Some(Operator::I32Mul) = mod_it.curr_op() {
+            mod_it.before().block(BlockType::Empty);
+        }
+        if mod_it.next().is_none() {
+            break;
+        }
+    }
+
+    let mut imports = Imports::new();
+    imports.func("env", "probe", |_| Ok(vec![]));
+    assert!(matches!(
+        Instance::new(&module, imports),
+        Err(Trap::InvalidModule(_))
+    ));
+}
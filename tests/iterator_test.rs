@@ -1,15 +1,20 @@
 use log::{debug, trace};
 use orca_wasm::ir::component::Component;
-use orca_wasm::ir::id::{FunctionID, ModuleID};
+use orca_wasm::ir::function::FunctionBuilder;
+use orca_wasm::ir::id::{FunctionID, GlobalID, LocalID, ModuleID, TableID, TypeID};
+use orca_wasm::ir::module::module_globals::{Global, GlobalKind, LocalGlobal};
 use orca_wasm::ir::module::Module;
-use orca_wasm::ir::types::Location;
+use orca_wasm::ir::types::{ComponentPath, Location, Value};
 use orca_wasm::iterator::component_iterator::ComponentIterator;
+use orca_wasm::iterator::func_filter::FuncFilter;
 use orca_wasm::iterator::iterator_trait::{IteratingInstrumenter, Iterator};
 use orca_wasm::iterator::module_iterator::ModuleIterator;
+use orca_wasm::iterator::visitor::{visit_component, visit_module, OrcaVisitor, ScopedInjector};
 use orca_wasm::module_builder::AddLocal;
 use orca_wasm::opcode::Instrumenter;
+use orca_wasm::{InitExpr, Opcode};
 use std::collections::{HashMap, HashSet};
-use wasmparser::Operator;
+use wasmparser::{MemArg, Operator};
 
 #[test]
 fn test_iterator_count() {
@@ -104,10 +109,13 @@ fn test_it_dup_instr() {
         {
             trace!("Func: {:?}, {}: {:?},", func_idx, instr_idx, op);
 
-            let loc = mod_it.curr_loc().0;
-            let orig = mod_it.curr_op_owned().unwrap();
-            mod_it.before();
-            mod_it.add_instr_at(loc, orig);
+            // a duplicated `end` would close the function body early
+            if !matches!(op, Some(Operator::End)) {
+                let loc = mod_it.curr_loc().0;
+                let orig = mod_it.curr_op_owned().unwrap();
+                mod_it.before();
+                mod_it.add_instr_at(loc, orig);
+            }
         } else {
             panic!("Should've gotten Component Location!");
         }
@@ -221,10 +229,323 @@ fn test_fn_name() {
     assert_eq!(None, *module.functions.get_name(FunctionID(2)));
 }
 
+#[test]
+fn test_filtered_iteration() {
+    let file = "tests/test_inputs/handwritten/modules/add.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+
+    let visited = |module: &mut Module, filter: &FuncFilter| {
+        let mut mod_it = ModuleIterator::new_filtered(module, filter);
+        let mut funcs = HashSet::new();
+        while mod_it.curr_op().is_some() {
+            if let Location::Module { func_idx, .. } = mod_it.curr_loc().0 {
+                funcs.insert(*func_idx);
+            }
+            if mod_it.next().is_none() {
+                break;
+            }
+        }
+        let mut funcs: Vec<u32> = funcs.into_iter().collect();
+        funcs.sort();
+        funcs
+    };
+    assert_eq!(visited(&mut module, &FuncFilter::All), vec![1, 2]);
+    assert_eq!(visited(&mut module, &FuncFilter::Name("add")), vec![1]);
+    assert_eq!(visited(&mut module, &FuncFilter::Name("a*d")), vec![1]);
+    assert_eq!(
+        visited(&mut module, &FuncFilter::Name("*x*")),
+        Vec::<u32>::new()
+    );
+    assert_eq!(visited(&mut module, &FuncFilter::Exported), vec![1]);
+    let pred = |_: &Module, id: FunctionID| *id == 2;
+    assert_eq!(visited(&mut module, &FuncFilter::Predicate(&pred)), vec![2]);
+}
+
+#[test]
+fn test_next_matching() {
+    let file = "tests/test_inputs/handwritten/modules/add.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+    let mut mod_it = ModuleIterator::new(&mut module, &vec![]);
+
+    let op = mod_it.next_matching(|op| matches!(op, Operator::Call { .. }));
+    assert_eq!(op, Some(&Operator::Call { function_index: 1 }));
+    assert!(matches!(
+        mod_it.curr_loc().0,
+        Location::Module {
+            func_idx: FunctionID(2),
+            instr_idx: 2
+        }
+    ));
+    assert!(mod_it
+        .next_matching(|op| matches!(op, Operator::Call { .. }))
+        .is_none());
+}
+
+#[test]
+fn test_filtered_iteration_component() {
+    let file = "tests/test_inputs/handwritten/components/mul_mod.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut component = Component::parse(&buff, false).expect("Unable to parse");
+
+    let mut comp_it = ComponentIterator::new_filtered(&mut component, &FuncFilter::Exported);
+    loop {
+        if let Location::Component {
+            mod_idx, func_idx, ..
+        } = comp_it.curr_loc().0
+        {
+            let module = &comp_it.comp.modules[*mod_idx as usize];
+            assert!(FuncFilter::Exported.matches(module, func_idx));
+        }
+        if comp_it.next().is_none() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_next_matching_component() {
+    let file = "tests/test_inputs/handwritten/components/mul_mod.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut component = Component::parse(&buff, false).expect("Unable to parse");
+    let mut comp_it = ComponentIterator::new(&mut component, HashMap::new());
+
+    let mut calls = vec![];
+    while comp_it
+        .next_matching(|op| matches!(op, Operator::Call { .. }))
+        .is_some()
+    {
+        let (_, mod_idx, func_idx, instr_idx) = comp_it.curr_loc().0.component_parts().unwrap();
+        calls.push((*mod_idx, *func_idx, instr_idx));
+    }
+    assert_eq!(calls, vec![(0, 1, 2), (1, 1, 2)]);
+    assert!(comp_it.curr_op().is_none());
+}
+
+#[test]
+fn test_nested_component_iteration() {
+    let file = "tests/test_inputs/handwritten/components/nested.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut component = Component::parse(&buff, false).expect("Unable to parse");
+
+    let mut comp_it = ComponentIterator::new(&mut component, HashMap::new());
+    let mut visited = vec![];
+    loop {
+        let loc = comp_it.curr_loc().0;
+        let (path, mod_idx, func_idx, _) = loc.component_parts().unwrap();
+        visited.push((path.indices().to_vec(), *mod_idx, *func_idx));
+        if path.indices() == [0] && *comp_it.curr_op().unwrap() == Operator::I32Add {
+            assert!(matches!(loc, Location::NestedComponent { .. }));
+            comp_it.before().i32_const(4).i32_add();
+        }
+        if comp_it.next().is_none() {
+            break;
+        }
+    }
+    visited.dedup();
+    assert_eq!(
+        visited,
+        vec![(vec![], 0, 0), (vec![0], 0, 0), (vec![0, 0], 0, 0)]
+    );
+
+    // add a function and a global to the innermost module
+    let path = ComponentPath::new(&[0, 0]);
+    let mut builder = FunctionBuilder::new(&[], &[]);
+    builder.nop();
+    assert_eq!(
+        *builder.finish_component_at(&mut component, &path, ModuleID(0)),
+        1
+    );
+    component.add_globals_at(
+        Global::new(GlobalKind::Local(LocalGlobal {
+            global_id: GlobalID(0),
+            ty: wasmparser::GlobalType {
+                content_type: wasmparser::ValType::I32,
+                mutable: true,
+                shared: false,
+            },
+            init_expr: InitExpr::from(Value::I32(0)),
+        })),
+        &path,
+        0,
+    );
+
+    let result = component.encode();
+    wasmparser::validate(&result).expect("Invalid component");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    assert!(out.contains("i32.const 4\n        i32.add\n        i32.add"));
+    assert!(out.contains("(func (;1;) (type 0)\n          nop"));
+    assert!(out.contains("(global (;0;) (mut i32) i32.const 0)"));
+}
+
+#[test]
+fn test_deeply_nested_component_iteration() {
+    let mut text = "(core module (func nop))".to_string();
+    for _ in 0..12 {
+        text = format!("(component {text})");
+    }
+    let buff = wat::parse_str(&text).expect("couldn't convert the input wat to Wasm");
+    let mut component = Component::parse(&buff, false).expect("Unable to parse");
+
+    let comp_it = ComponentIterator::new(&mut component, HashMap::new());
+    let (path, mod_idx, func_idx, _) = comp_it.curr_loc().0.component_parts().unwrap();
+    assert_eq!(path.indices(), [0; 11]);
+    assert_eq!((*mod_idx, *func_idx), (0, 0));
+    wasmparser::validate(&component.encode()).expect("Invalid component");
+}
+
+#[test]
+fn test_visitor() {
+    let file = "tests/test_inputs/handwritten/modules/add.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+
+    let mut visitor = (Counter::default(), CallProbe);
+    visit_module(&mut module, &mut visitor);
+    let counter = visitor.0;
+    assert_eq!(counter.ops, 9);
+    assert_eq!(counter.starts, 2);
+    assert_eq!(counter.ends, 2);
+    assert_eq!(counter.local_gets, 2);
+    assert_eq!(counter.calls, vec![FunctionID(1)]);
+
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    assert!(out.contains("i32.const 1\n    drop\n    call $add"));
+}
+
+#[test]
+fn test_visitor_component() {
+    let file = "tests/test_inputs/handwritten/components/mul_mod.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut component = Component::parse(&buff, false).expect("Unable to parse");
+
+    let mut counter = Counter::default();
+    visit_component(&mut component, &mut counter);
+    assert_eq!(counter.ops, 15);
+    assert_eq!(counter.starts, counter.ends);
+}
+
+#[test]
+fn test_visitor_nested_component() {
+    // the only module is in a nested component
+    let buff = wat::parse_str("(component (component (core module (func nop))))")
+        .expect("couldn't convert the input wat to Wasm");
+    let mut component = Component::parse(&buff, false).expect("Unable to parse");
+
+    let mut counter = Counter::default();
+    visit_component(&mut component, &mut counter);
+    assert_eq!(counter.ops, 2);
+    assert_eq!(counter.starts, 1);
+    assert_eq!(counter.ends, 1);
+}
+
+#[test]
+fn test_visitor_hooks() {
+    let buff = wat::parse_str(
+        r#"(module
+            (type $t (func))
+            (memory 1 1 shared)
+            (table 1 funcref)
+            (elem declare func $f)
+            (func $f
+                i32.const 0 i32.const 1 i32.atomic.rmw.add drop
+                i32.const 0 i64.atomic.load drop
+                i32.const 0 v128.const i64x2 0 0 v128.load8_lane 0 drop
+                i32.const 0 v128.const i64x2 0 0 v128.store8_lane 0
+                i32.const 0 i32.const 1 memory.atomic.notify drop
+                ref.func $f call_ref $t
+                i32.const 0
+                if
+                    return_call $f
+                end
+                i32.const 0 return_call_indirect (type $t)
+            )
+        )"#,
+    )
+    .expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse");
+
+    let mut visitor = HookCounter::default();
+    visit_module(&mut module, &mut visitor);
+    assert_eq!(visitor.loads, 3);
+    assert_eq!(visitor.stores, 2);
+    assert_eq!(visitor.calls, vec![FunctionID(0)]);
+    assert_eq!(visitor.call_indirects, 1);
+    assert_eq!(visitor.call_refs, 1);
+}
+
 // =================
 // ==== HELPERS ====
 // =================
 
+#[derive(Default)]
+struct Counter {
+    ops: u32,
+    starts: u32,
+    ends: u32,
+    local_gets: u32,
+    calls: Vec<FunctionID>,
+}
+impl<'a> OrcaVisitor<'a> for Counter {
+    fn visit_op<I: ScopedInjector<'a>>(&mut self, _it: &mut I) {
+        self.ops += 1;
+    }
+    fn visit_function_start<I: ScopedInjector<'a>>(&mut self, _it: &mut I) {
+        self.starts += 1;
+    }
+    fn visit_function_end<I: ScopedInjector<'a>>(&mut self, it: &mut I) {
+        assert_eq!(*it.curr_op().unwrap(), Operator::End);
+        self.ends += 1;
+    }
+    fn visit_local_get<I: ScopedInjector<'a>>(&mut self, _it: &mut I, _local: LocalID) {
+        self.local_gets += 1;
+    }
+    fn visit_call<I: ScopedInjector<'a>>(&mut self, _it: &mut I, func: FunctionID) {
+        self.calls.push(func);
+    }
+}
+
+struct CallProbe;
+impl<'a> OrcaVisitor<'a> for CallProbe {
+    fn visit_call<I: ScopedInjector<'a>>(&mut self, it: &mut I, _func: FunctionID) {
+        it.before().i32_const(1).drop();
+    }
+}
+
+#[derive(Default)]
+struct HookCounter {
+    loads: u32,
+    stores: u32,
+    calls: Vec<FunctionID>,
+    call_indirects: u32,
+    call_refs: u32,
+}
+impl<'a> OrcaVisitor<'a> for HookCounter {
+    fn visit_load<I: ScopedInjector<'a>>(&mut self, _it: &mut I, _memarg: MemArg) {
+        self.loads += 1;
+    }
+    fn visit_store<I: ScopedInjector<'a>>(&mut self, _it: &mut I, _memarg: MemArg) {
+        self.stores += 1;
+    }
+    fn visit_call<I: ScopedInjector<'a>>(&mut self, _it: &mut I, func: FunctionID) {
+        self.calls.push(func);
+    }
+    fn visit_call_indirect<I: ScopedInjector<'a>>(
+        &mut self,
+        _it: &mut I,
+        _ty: TypeID,
+        _table: TableID,
+    ) {
+        self.call_indirects += 1;
+    }
+    fn visit_call_ref<I: ScopedInjector<'a>>(&mut self, _it: &mut I, _ty: TypeID) {
+        self.call_refs += 1;
+    }
+}
+
 fn iterate_component_and_count(comp_it: &mut ComponentIterator, exp_count: u32) {
     let mut count = 0;
     loop {
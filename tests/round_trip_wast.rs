@@ -38,7 +38,7 @@ fn test_wast() {
         let mut cmd = wasm_tools();
         let td = tempfile::TempDir::new().unwrap();
         cmd.arg("json-from-wast")
-            .arg(&file.path())
+            .arg(file.path())
             .arg("--pretty")
             .arg("--wasm-dir")
             .arg(td.path())
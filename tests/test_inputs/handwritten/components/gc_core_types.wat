@@ -0,0 +1,9 @@
+(component
+  (core type (module
+    (type (struct (field i32) (field (mut i64))))
+    (type (array (mut i8)))
+    (type (func (param i32) (result i32)))
+    (export "f" (func (type 2)))
+  ))
+  (core type (func (param i32)))
+)
@@ -0,0 +1,28 @@
+(component
+  (core module (;0;)
+    (type (;0;) (func (result i32)))
+    (func (;0;) (type 0) (result i32)
+      i32.const 1
+    )
+    (export "one" (func 0))
+  )
+  (component (;0;)
+    (core module (;0;)
+      (type (;0;) (func (result i32)))
+      (func (;0;) (type 0) (result i32)
+        i32.const 2
+        i32.const 3
+        i32.add
+      )
+      (export "five" (func 0))
+    )
+    (component (;0;)
+      (core module (;0;)
+        (type (;0;) (func))
+        (func (;0;) (type 0)
+          nop
+        )
+      )
+    )
+  )
+)
@@ -0,0 +1,21 @@
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (import "env" "exn" (tag $imported (;0;) (type 0) (param i32)))
+  (tag $e (;1;) (type 0) (param i32))
+  (func $catch (;0;) (type 1) (param i32) (result i32)
+    block $caught (result i32)
+      block $rethrow (result exnref)
+        try_table (catch $e $caught) (catch_all_ref $rethrow)
+          local.get 0
+          throw $e
+        end
+        i32.const 0
+        return
+      end
+      throw_ref
+    end
+  )
+  (export "catch" (func $catch))
+  (export "e" (tag $e))
+)
@@ -0,0 +1,14 @@
+(module
+  (type (;0;) (func))
+  (import "env" "__unused_base" (global (;0;) i32))
+  (import "env" "__memory_base" (global (;1;) i32))
+  (import "env" "__table_base" (global (;2;) i32))
+  (func (;0;) (type 0))
+  (table (;0;) 4 funcref)
+  (memory (;0;) 1)
+  (global (;3;) i32 i32.const 4 i32.const 8 i32.mul i32.const 2 i32.sub)
+  (global (;4;) i64 i64.const 1 i64.const 2 i64.add)
+  (global (;5;) i32 global.get 1 i32.const 16 i32.add)
+  (elem (;0;) (offset global.get 2 i32.const 1 i32.add) func 0)
+  (data (;0;) (offset global.get 1 i32.const 32 i32.add) "\2a")
+)
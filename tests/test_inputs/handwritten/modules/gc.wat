@@ -0,0 +1,62 @@
+(module
+  (type $point (sub (struct (field $x (mut i32)) (field $y (mut i32)))))
+  (type $point3 (sub final $point (struct (field $x (mut i32)) (field $y (mut i32)) (field $z i8))))
+  (type $bytes (array (mut i8)))
+  (rec
+    (type $node (struct (field $val i32) (field $next (ref null $list))))
+    (type $list (struct (field $head (ref null $node))))
+  )
+  (rec
+    (type $single (func (param (ref $single))))
+  )
+  (type $sum_t (func (param (ref null $point)) (result i32)))
+  (global $origin (mut (ref null $point)) (ref.null $point))
+  (global $any (mut anyref) (ref.null any))
+  (func $sum (type $sum_t) (param $p (ref null $point)) (result i32)
+    local.get $p
+    struct.get $point $x
+    local.get $p
+    struct.get $point $y
+    i32.add
+  )
+  (func $make (param $x i32) (param $y i32) (result (ref $point))
+    local.get $x
+    local.get $y
+    struct.new $point
+  )
+  (func $bytes_len (param $len i32) (result i32)
+    (local $arr (ref null $bytes))
+    i32.const 7
+    local.get $len
+    array.new $bytes
+    local.tee $arr
+    i32.const 0
+    array.get_u $bytes
+    local.get $arr
+    array.len
+    i32.add
+  )
+  (func $is_point3 (param $a anyref) (result i32)
+    local.get $a
+    ref.test (ref $point3)
+  )
+  (func $as_point (param $a anyref) (result (ref null $point))
+    (block $l (result (ref $point))
+      local.get $a
+      br_on_cast $l anyref (ref $point)
+      ref.cast (ref null $point)
+      return
+    )
+  )
+  (func $i31 (param $v i32) (result i32)
+    local.get $v
+    ref.i31
+    i31.get_s
+  )
+  (func $list (result (ref $list))
+    i32.const 1
+    ref.null $list
+    struct.new $node
+    struct.new $list
+  )
+)
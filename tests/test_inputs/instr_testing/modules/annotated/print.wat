@@ -0,0 +1,21 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    ;; << (local i64)
+    ;; << i64.const 0 ;; Before
+    ;; << local.set 1 ;; Before
+    local.get 0
+    ;; << i32.const 1 ;; Before
+    ;; << drop ;; Before
+    call 1
+    ;; << nop ;; After
+  )
+  (func (;1;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.const 1
+    i32.add ;; rm
+    ;; << i32.sub ;; Alternate
+    i32.const 2
+    drop ;; rm
+  )
+)
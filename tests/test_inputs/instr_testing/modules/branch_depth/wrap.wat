@@ -0,0 +1,15 @@
+(module
+  (type (;0;) (func (param i32)))
+  (func (;0;) (type 0) (param i32)
+    block ;; label = @1
+      block ;; label = @2
+        ;; << block ;; label = @3
+        local.get 0
+        br_if 1 (;@1;) ;; < br_if 2 (;@1;)
+        local.get 0
+        br_table 0 (;@2;) 1 (;@1;) 2 (;@0;) ;; < br_table 1 (;@2;) 2 (;@1;) 3
+        ;; << end
+      end
+    end
+  )
+)
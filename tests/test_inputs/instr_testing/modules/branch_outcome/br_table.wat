@@ -0,0 +1,36 @@
+(module
+  (type (;0;) (func (param i32)))
+  (func (;0;) (type 0) (param i32)
+    ;; << (local i32)
+    block ;; label = @1
+      block ;; label = @2
+        local.get 0
+        ;; << local.tee 1
+        ;; << local.get 1
+        ;; << i32.const 0
+        ;; << i32.eq
+        ;; << if ;; label = @3
+        ;; <<   i32.const 10
+        ;; <<   drop
+        ;; << end
+        ;; << local.get 1
+        ;; << i32.const 1
+        ;; << i32.eq
+        ;; << if ;; label = @3
+        ;; <<   i32.const 11
+        ;; <<   drop
+        ;; << end
+        ;; << local.get 1
+        ;; << i32.const 2
+        ;; << i32.ge_u
+        ;; << if ;; label = @3
+        ;; <<   i32.const 12
+        ;; <<   drop
+        ;; << end
+        br_table 0 (;@2;) 1 (;@1;) 1 (;@1;)
+      end
+      nop
+    end
+  )
+  (memory (;0;) 1)
+)
@@ -0,0 +1,20 @@
+(module
+  (type (;0;) (func (param i32)))
+  (func (;0;) (type 0) (param i32)
+    ;; << (local i32)
+    local.get 0
+    ;; << local.tee 1
+    ;; << i32.eqz
+    ;; << if ;; label = @1
+    ;; <<   i32.const 2
+    ;; <<   drop
+    ;; << end
+    ;; << local.get 1
+    if ;; label = @1
+      ;; << i32.const 1
+      ;; << drop
+      nop
+    end
+  )
+  (memory (;0;) 1)
+)
@@ -0,0 +1,75 @@
+(module
+  (type (;0;) (func (param i32)))
+  (func (;0;) (type 0) (param i32)
+    ;; << (local i32 i32)
+    block ;; label = @1
+      local.get 0
+      ;; << local.tee 1
+      ;; << if ;; label = @2
+      ;; <<   i32.const 1
+      ;; <<   drop
+      ;; << end
+      ;; << local.get 1
+      br_if 0 (;@1;)
+      local.get 0
+      ;; << local.tee 1
+      ;; << if ;; label = @2
+      ;; <<   i32.const 1
+      ;; <<   drop
+      ;; << end
+      ;; << local.get 1
+      br_if 0 (;@1;)
+      ;; << i32.const 0
+      ;; << local.set 1
+      loop ;; label = @2
+        ;; << local.get 1
+        ;; << if ;; label = @3
+        ;; <<   i32.const 2
+        ;; <<   drop
+        ;; <<   i32.const 0
+        ;; <<   local.set 1
+        ;; << end
+        local.get 0
+        ;; << local.tee 2
+        ;; << if ;; label = @3
+        ;; <<   i32.const 1
+        ;; <<   local.set 1
+        ;; << end
+        ;; << local.get 2
+        ;; << local.tee 2
+        ;; << if ;; label = @3
+        ;; <<   i32.const 1
+        ;; <<   drop
+        ;; << end
+        ;; << local.get 2
+        br_if 0 (;@2;)
+      end
+      ;; << i32.const 0
+      ;; << local.set 1
+      loop ;; label = @2
+        ;; << local.get 1
+        ;; << if ;; label = @3
+        ;; <<   i32.const 2
+        ;; <<   drop
+        ;; <<   i32.const 0
+        ;; <<   local.set 1
+        ;; << end
+        local.get 0
+        ;; << local.tee 2
+        ;; << if ;; label = @3
+        ;; <<   i32.const 1
+        ;; <<   local.set 1
+        ;; << end
+        ;; << local.get 2
+        ;; << local.tee 2
+        ;; << if ;; label = @3
+        ;; <<   i32.const 1
+        ;; <<   drop
+        ;; << end
+        ;; << local.get 2
+        br_if 0 (;@2;)
+      end
+    end
+  )
+  (memory (;0;) 1)
+)
@@ -0,0 +1,44 @@
+(module
+  (type (;0;) (func (param i32 i64) (result f32)))
+  (type (;1;) (func (param i32)))
+  (import "env" "log" (func (;0;) (type 1)))
+  (func (;1;) (type 0) (param i32 i64) (result f32)
+    f32.const 0x1p+0 (;=1;)
+  )
+  (func (;2;) (type 1) (param i32)
+    ;; << (local i32 i64 f32 i32 i64 i32 f32)
+    local.get 0
+    i64.const 2
+    ;; << local.set 2
+    ;; << local.set 1
+    ;; << local.get 1
+    ;; << local.get 2
+    ;; << local.get 1
+    ;; << call 0
+    call 1
+    ;; << local.set 3
+    ;; << local.get 3
+    ;; << local.get 3
+    ;; << drop
+    drop
+    local.get 0
+    i64.const 3
+    local.get 0
+    ;; << local.set 6
+    ;; << local.set 5
+    ;; << local.set 4
+    ;; << local.get 4
+    ;; << local.get 5
+    ;; << local.get 6
+    ;; << local.get 4
+    ;; << call 0
+    call_indirect (type 0)
+    ;; << local.set 7
+    ;; << local.get 7
+    ;; << local.get 7
+    ;; << drop
+    drop
+  )
+  (table (;0;) 2 2 funcref)
+  (elem (;0;) (i32.const 0) func 1 2)
+)
@@ -0,0 +1,34 @@
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (tag (;0;) (type 0) (param i32))
+  (func (;0;) (type 1) (param i32) (result i32)
+    ;; << (local i32)
+    ;; << block (result exnref) ;; label = @1
+    ;; << try_table (result i32) (catch_all_ref 0 (;@1;)) ;; label = @2
+    i32.const 2
+    local.get 0
+    ;; << local.tee 1
+    ;; << if ;; label = @3
+    ;; << i32.const 1
+    ;; << drop
+    ;; << end
+    ;; << local.get 1
+    br_if 0 (;@0;) ;; < br_if 2
+    drop
+    local.get 0
+    if ;; label = @3
+      local.get 0
+      throw 0
+    end
+    i32.const 0
+    ;; << end
+    ;; << i32.const 1
+    ;; << drop
+    ;; << return
+    ;; << end
+    ;; << i32.const 1
+    ;; << drop
+    ;; << throw_ref
+  )
+)
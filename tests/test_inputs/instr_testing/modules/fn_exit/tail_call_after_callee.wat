@@ -0,0 +1,37 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (table (;0;) 1 funcref)
+  (func $id (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    ;; << i32.const 1
+    ;; << drop
+  )
+  (func $tail (;1;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if
+      i32.const 0
+      ;; << i32.const 1
+      ;; << drop
+      return
+    end
+    local.get 0
+    return_call $id ;; < call $id
+    ;; << i32.const 1
+    ;; << drop
+    ;; << return
+    ;; << i32.const 1
+    ;; << drop
+  )
+  (func $tail_indirect (;2;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.const 0
+    return_call_indirect (type 0) ;; < call_indirect (type 0)
+    ;; << i32.const 1
+    ;; << drop
+    ;; << return
+    ;; << i32.const 1
+    ;; << drop
+  )
+  (elem (;0;) (i32.const 0) func $id)
+)
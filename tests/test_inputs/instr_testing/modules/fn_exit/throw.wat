@@ -0,0 +1,24 @@
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (tag $e (;0;) (type 0) (param i32))
+  (func $may_throw (;0;) (type 1) (param i32) (result i32)
+    ;; << block (result exnref)
+    ;; << try_table (result i32) (catch_all_ref 0)
+    local.get 0
+    if
+      local.get 0
+      throw $e
+    end
+    i32.const 0
+    ;; << end
+    ;; << i32.const 1
+    ;; << drop
+    ;; << return
+    ;; << end
+    ;; << i32.const 1
+    ;; << drop
+    ;; << throw_ref
+  )
+  (export "may_throw" (func $may_throw))
+)
@@ -0,0 +1,26 @@
+(module
+  (type (;0;) (struct (field (mut i32))))
+  (type (;1;) (func (param i32) (result i32)))
+  ;; << (type (;2;) (array (mut (ref null 0))))
+  ;; << (rec
+  ;; <<   (type (;3;) (struct (field (ref null 4))))
+  ;; <<   (type (;4;) (struct (field (ref null 3))))
+  ;; << )
+  ;; << (type (;5;) (func (param anyref) (result i32)))
+  (func (;0;) (type 1) (param i32) (result i32)
+    local.get 0
+  )
+  ;; << (func (;1;) (type 1) (param i32) (result i32)
+  ;; <<   local.get 0
+  ;; <<   struct.new 0
+  ;; <<   i32.const 1
+  ;; <<   array.new 2
+  ;; <<   i32.const 0
+  ;; <<   array.get 2
+  ;; <<   struct.get 0 0
+  ;; << )
+  ;; << (func (;2;) (type 5) (param anyref) (result i32)
+  ;; <<   local.get 0
+  ;; <<   ref.test (ref 0)
+  ;; << )
+)
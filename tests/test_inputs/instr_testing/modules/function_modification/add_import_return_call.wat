@@ -0,0 +1,15 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  ;; << (import "orca" "better" (func (;0;) (type 0)))
+  (func $id (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+  )
+  (func (;1;) (type 0) (param i32) (result i32)
+    local.get 0
+    return_call $id ;; < return_call 1
+  )
+  ;; << (func (;3;) (type 0) (param i32) (result i32)
+  ;; <<   local.get 0
+  ;; <<   return_call 2
+  ;; << )
+)
@@ -0,0 +1,16 @@
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func))
+  ;; << (import "orca" "exn" (tag (;0;) (type 0) (param i32)))
+  (tag (;0;) (type 0) (param i32)) ;; < (tag (;1;) (type 0) (param i32))
+  (func (;0;) (type 1)
+    i32.const 1
+    throw 0 ;; < throw 1
+  )
+  ;; << (func (;1;) (type 1)
+  ;; <<   i32.const 2
+  ;; <<   throw 0
+  ;; << )
+  (export "tag" (tag 0)) ;; < (export "tag" (tag 1))
+  ;; << (export "exn" (tag 0))
+)
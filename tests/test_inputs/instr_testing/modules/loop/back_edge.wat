@@ -0,0 +1,36 @@
+(module
+  (type (;0;) (func (param i32)))
+  (func (;0;) (type 0) (param i32)
+    ;; << (local i32)
+    block ;; label = @1
+      loop ;; label = @2
+        block ;; label = @3
+          local.get 0
+          ;; << local.tee 1
+          ;; << if ;; label = @4
+          ;; <<   i32.const 10
+          ;; <<   drop
+          ;; << end
+          ;; << local.get 1
+          br_if 1 (;@2;)
+          local.get 0
+          ;; << local.tee 1
+          ;; << local.get 1
+          ;; << i32.const 1
+          ;; << i32.eq
+          ;; << if ;; label = @4
+          ;; <<   i32.const 10
+          ;; <<   drop
+          ;; << end
+          br_table 2 (;@1;) 1 (;@2;) 0 (;@3;)
+        end
+        local.get 0
+        br_if 1 (;@1;)
+        ;; << i32.const 10
+        ;; << drop
+        br 0 (;@2;)
+      end
+    end
+  )
+  (memory (;0;) 1)
+)
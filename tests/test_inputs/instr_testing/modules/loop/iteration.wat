@@ -0,0 +1,45 @@
+(module
+  (type (;0;) (func (param i32)))
+  (func (;0;) (type 0) (param i32)
+    ;; << (local i32 i32)
+    block ;; label = @1
+      ;; << i32.const 0
+      ;; << local.set 1
+      loop ;; label = @2
+        ;; << local.get 1
+        ;; << if ;; label = @3
+        ;; <<   i32.const 20
+        ;; <<   drop
+        ;; <<   i32.const 0
+        ;; <<   local.set 1
+        ;; << end
+        block ;; label = @3
+          local.get 0
+          ;; << local.tee 2
+          ;; << if ;; label = @4
+          ;; <<   i32.const 1
+          ;; <<   local.set 1
+          ;; << end
+          ;; << local.get 2
+          br_if 1 (;@2;)
+          local.get 0
+          ;; << local.tee 2
+          ;; << local.get 2
+          ;; << i32.const 1
+          ;; << i32.eq
+          ;; << if ;; label = @4
+          ;; <<   i32.const 1
+          ;; <<   local.set 1
+          ;; << end
+          br_table 2 (;@1;) 1 (;@2;) 0 (;@3;)
+        end
+        local.get 0
+        br_if 1 (;@1;)
+        ;; << i32.const 1
+        ;; << local.set 1
+        br 0 (;@2;)
+      end
+    end
+  )
+  (memory (;0;) 1)
+)
@@ -0,0 +1,44 @@
+(module
+  (type (;0;) (func (param i32 i32)))
+  (import "env" "trace" (func (;0;) (type 0)))
+  (func (;1;) (type 0) (param i32 i32)
+    ;; << (local i32 i32 i32 i32 v128 i32)
+    local.get 0
+    local.get 1
+    ;; << local.set 3
+    ;; << local.set 2
+    ;; << local.get 2
+    ;; << i32.const 8
+    ;; << i32.add
+    ;; << local.set 4
+    ;; << local.get 2
+    ;; << local.get 3
+    ;; << local.get 4
+    ;; << i32.const 4
+    ;; << call 0
+    i32.store offset=8
+    local.get 0
+    ;; << local.set 2
+    ;; << local.get 2
+    ;; << local.get 2
+    ;; << i32.const 1
+    ;; << call 0
+    i64.load8_u
+    drop
+    local.get 0
+    v128.const i32x4 0x00000001 0x00000002 0x00000003 0x00000004
+    ;; << local.set 6
+    ;; << local.set 5
+    ;; << local.get 5
+    ;; << i32.const 4
+    ;; << i32.add
+    ;; << local.set 7
+    ;; << local.get 5
+    ;; << local.get 6
+    ;; << local.get 7
+    ;; << i32.const 4
+    ;; << call 0
+    v128.store32_lane offset=4 1
+  )
+  (memory (;0;) 1)
+)
@@ -0,0 +1,14 @@
+(module
+  (type (;0;) (func (param i64)))
+  (type (;1;) (func (param i32) (result i32)))
+  (import "probe" "trace" (func (;0;) (type 0)))
+  (memory (;0;) 1)
+  (func (;1;) (type 1) (param i32) (result i32)
+    local.get 0
+    i32.load offset=4
+    local.get 0
+    i32.load8_u
+    i32.add
+  )
+  (data (;0;) (i32.const 16) "\2a")
+)
@@ -0,0 +1,20 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    ;; << i32.const 12
+    ;; << drop
+    ;; << i32.const 22
+    ;; << drop
+    local.get 0
+    i32.const 1
+    ;; << i32.const 10
+    ;; << drop
+    ;; << i32.const 20
+    ;; << drop
+    i32.add ;; < i32.sub
+    ;; << i32.const 21
+    ;; << drop
+    ;; << i32.const 11
+    ;; << drop
+  )
+)
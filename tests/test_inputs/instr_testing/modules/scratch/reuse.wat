@@ -0,0 +1,17 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    ;; << (local i32 f32)
+    ;; << f32.const 0x0p+0 (;=0;)
+    ;; << local.set 2
+    local.get 0
+    i32.const 1
+    ;; << local.set 1
+    ;; << local.get 1
+    i32.add
+    i32.const 2
+    ;; << local.set 1
+    ;; << local.get 1
+    i32.add
+  )
+)
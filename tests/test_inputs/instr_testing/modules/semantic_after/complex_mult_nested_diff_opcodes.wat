@@ -2,6 +2,12 @@
   (type (;0;) (func))
   (func (;0;) (type 0)
     ;; << (local i32 i32 i32 i32)
+    ;; << i32.const 0
+    ;; << local.set 0
+    ;; << i32.const 0
+    ;; << local.set 1
+    ;; << i32.const 0
+    ;; << local.set 2
     block ;; label = @1
       block ;; label = @2
         i32.const 0
@@ -61,6 +67,8 @@
           ;; << end
           ;; << i32.const 12
           ;; << drop
+          ;; << i32.const 0
+          ;; << local.set 3
           loop ;; label = @4
             i32.const 0
             ;; << i32.const 1
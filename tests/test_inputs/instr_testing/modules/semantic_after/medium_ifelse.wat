@@ -2,7 +2,11 @@
   (type (;0;) (func))
   (func (;0;) (type 0)
     ;; << (local i32 i32 i32)
+    ;; << i32.const 0
+    ;; << local.set 0
     block $hi
+      ;; << i32.const 0
+      ;; << local.set 2
       block ;; label = @2
         i32.const 1
         ;; << i32.const 1
@@ -11,6 +15,8 @@
         ;; << i32.const 0
         ;; << local.set 0
         i32.const 1
+        ;; << i32.const 0
+        ;; << local.set 1
         if ;; label = @3
           ;; << i32.const 1
           ;; << local.set 1
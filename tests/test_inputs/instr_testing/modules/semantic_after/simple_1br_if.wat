@@ -2,6 +2,8 @@
   (type (;0;) (func))
   (func (;0;) (type 0)
     ;; << (local i32)
+    ;; << i32.const 0
+    ;; << local.set 0
     block ;; label = @1
       i32.const 0
       ;; << i32.const 1
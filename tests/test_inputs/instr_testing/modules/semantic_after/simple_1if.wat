@@ -6,6 +6,8 @@
       block ;; label = @2
         br_if 0 (;@2;)
         br_table 0 (;@2;)
+        ;; << i32.const 0
+        ;; << local.set 0
         if ;; label = @3
           ;; << i32.const 1
           ;; << local.set 0
@@ -0,0 +1,15 @@
+(module
+  (import "env" "probe" (func $probe (param i32)))
+  (func $double (param i32) (result i32)
+    local.get 0
+    i32.const 2
+    i32.mul
+  )
+  (func (export "main") (param i32) (result i32)
+    local.get 0
+    call $double
+    call $double
+    i32.const 1
+    i32.add
+  )
+)
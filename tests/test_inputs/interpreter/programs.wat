@@ -0,0 +1,104 @@
+(module
+  (type $binop (func (param i32 i32) (result i32)))
+  (import "env" "base" (global $base i32))
+  (memory 1)
+  (data (i32.const 16) "\2a\00\00\00")
+  (global $calls (mut i32) (i32.const 0))
+  (table 2 funcref)
+  (elem (i32.const 0) $add $sub)
+  (func $add (type $binop)
+    local.get 0
+    local.get 1
+    i32.add
+  )
+  (func $sub (type $binop)
+    local.get 0
+    local.get 1
+    i32.sub
+  )
+  (func (export "fac") (param i64) (result i64)
+    local.get 0
+    i64.eqz
+    if (result i64)
+      i64.const 1
+    else
+      local.get 0
+      local.get 0
+      i64.const 1
+      i64.sub
+      call 2
+      i64.mul
+    end
+  )
+  (func (export "sum") (param i32) (result i32)
+    (local i32)
+    block
+      loop
+        local.get 0
+        i32.eqz
+        br_if 1
+        local.get 1
+        local.get 0
+        i32.add
+        local.set 1
+        local.get 0
+        i32.const 1
+        i32.sub
+        local.set 0
+        br 0
+      end
+    end
+    local.get 1
+  )
+  (func (export "switch") (param i32) (result i32)
+    block
+      block
+        block
+          local.get 0
+          br_table 0 1 2
+        end
+        i32.const 10
+        return
+      end
+      i32.const 20
+      return
+    end
+    i32.const 30
+  )
+  (func (export "apply") (param i32 i32 i32) (result i32)
+    global.get $calls
+    i32.const 1
+    i32.add
+    global.set $calls
+    local.get 1
+    local.get 2
+    local.get 0
+    call_indirect (type $binop)
+  )
+  (func (export "load") (result i32)
+    i32.const 16
+    i32.load
+    global.get $base
+    i32.add
+  )
+  (func (export "store") (param i32 i32)
+    local.get 0
+    local.get 1
+    i32.store8 offset=1
+  )
+  (func (export "hypot") (param f64 f64) (result f64)
+    local.get 0
+    local.get 0
+    f64.mul
+    local.get 1
+    local.get 1
+    f64.mul
+    f64.add
+    f64.sqrt
+  )
+  (func (export "div") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.div_s
+  )
+)
@@ -1,10 +1,14 @@
 use log::{debug, error};
+use orca_wasm::ir::diff::{diff, Change, FuncDiff, InstrDiff};
 use orca_wasm::ir::function::FunctionBuilder;
-use orca_wasm::ir::id::{ExportsID, FunctionID, ImportsID, TypeID};
+use orca_wasm::ir::id::{ExportsID, FunctionID, GlobalID, ImportsID, LocalID, TagID, TypeID};
 use orca_wasm::ir::module::module_functions::FuncKind::{Import, Local};
 use orca_wasm::ir::module::module_functions::{ImportedFunction, LocalFunction};
-use orca_wasm::ir::types::{Body, Value};
-use orca_wasm::{DataType, InitExpr, Module, Opcode};
+use orca_wasm::ir::module::module_globals::{GlobalKind, LocalGlobal};
+use orca_wasm::ir::module::module_types::{CompositeType, FieldType, StorageType, SubType};
+use orca_wasm::ir::types::{BlockType, Body, DataSegmentKind, ElementKind, InitInstr, Value};
+use orca_wasm::opcode::Instrumenter;
+use orca_wasm::{DataType, InitExpr, Location, Module, Opcode};
 use std::path::PathBuf;
 use std::process::Command;
 
@@ -99,8 +103,8 @@ fn test_import_delete() {
     let fid = module
         .imports
         .get_func("bogus".to_string(), "hi".to_string());
-    assert!(!id.is_none());
-    assert!(!fid.is_none());
+    assert!(id.is_some());
+    assert!(fid.is_some());
 
     let id = id.unwrap();
     let fid = fid.unwrap();
@@ -188,7 +192,7 @@ fn test_middle_import_to_local() {
     let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
-    let mut builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     builder.i32_const(1);
     builder.drop();
 
@@ -215,7 +219,7 @@ fn test_first_import_to_local() {
     let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
-    let mut builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     builder.i32_const(1);
     builder.drop();
 
@@ -242,7 +246,7 @@ fn test_last_import_to_local() {
     let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
-    let mut builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     builder.i32_const(1);
     builder.drop();
 
@@ -270,17 +274,17 @@ fn test_all_import_to_local() {
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
     // Convert all to local
-    let mut first_builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut first_builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     first_builder.i32_const(1);
     first_builder.drop();
     first_builder.replace_import_in_module(&mut module, ImportsID(0));
 
-    let mut second_builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut second_builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     second_builder.i32_const(2);
     second_builder.drop();
     second_builder.replace_import_in_module(&mut module, ImportsID(1));
 
-    let mut third_builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut third_builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     third_builder.i32_const(3);
     third_builder.drop();
     third_builder.replace_import_in_module(&mut module, ImportsID(2));
@@ -307,12 +311,12 @@ fn test_some_import_to_local() {
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
     // Convert all to local
-    let mut first_builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut first_builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     first_builder.i32_const(1);
     first_builder.drop();
     first_builder.replace_import_in_module(&mut module, ImportsID(0));
 
-    let mut second_builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut second_builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     second_builder.i32_const(2);
     second_builder.drop();
     second_builder.replace_import_in_module(&mut module, ImportsID(1));
@@ -338,7 +342,7 @@ fn test_middle_import_to_local_import_delete() {
     let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
-    let mut builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     builder.i32_const(1);
     builder.drop();
 
@@ -368,7 +372,7 @@ fn test_middle_import_to_local_local_delete() {
     let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
-    let mut builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     builder.i32_const(1);
     builder.drop();
 
@@ -413,6 +417,215 @@ fn test_add_import() {
     }
 }
 
+#[test]
+fn test_add_import_tag() {
+    let file = "tests/test_inputs/instr_testing/modules/function_modification/add_import_tag.wat";
+
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+
+    // the imported tag is placed after the local one in the ID space, but before it on encode
+    let (tag_id, imp_id) = module.add_import_tag("orca".to_string(), "exn".to_string(), TypeID(0));
+    assert_eq!(*tag_id, 1);
+    assert_eq!(*imp_id, 0);
+    module.add_export_tag("exn".to_string(), tag_id);
+
+    let mut builder = FunctionBuilder::new(&[], &[]);
+    builder.i32_const(2).throw(tag_id);
+    builder.finish_module(&mut module);
+
+    let result = module.encode();
+    let output_wasm_path = format!("{TEST_DEBUG_DIR}/test_add_import_tag.wasm");
+    validate(&result, &output_wasm_path).expect("Failed to write out to wasm file.");
+
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_throw_unknown_tag() {
+    let file = "tests/test_inputs/instr_testing/modules/function_modification/add_import_tag.wat";
+
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+
+    let mut builder = FunctionBuilder::new(&[], &[]);
+    builder.i32_const(2).throw(TagID(5));
+    builder.finish_module(&mut module);
+
+    assert!(module.try_encode().is_err());
+}
+
+#[test]
+fn test_add_import_return_call() {
+    let file =
+        "tests/test_inputs/instr_testing/modules/function_modification/add_import_return_call.wat";
+
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+
+    module.add_import_func("orca".to_string(), "better".to_string(), TypeID(0));
+
+    // tail calls should be remapped like regular calls
+    let mut builder = FunctionBuilder::new(&[DataType::I32], &[DataType::I32]);
+    builder.local_get(LocalID(0)).return_call(FunctionID(1));
+    builder.finish_module(&mut module);
+
+    let result = module.encode();
+    let output_wasm_path = format!("{TEST_DEBUG_DIR}/test_add_import_return_call.wasm");
+    validate(&result, &output_wasm_path).expect("Failed to write out to wasm file.");
+
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_add_gc_types() {
+    let file = "tests/test_inputs/instr_testing/modules/function_modification/add_gc_types.wat";
+
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+
+    let point = TypeID(0);
+    let point_ref = DataType::Concrete {
+        ty_id: point,
+        nullable: true,
+    };
+    // identical types in their own recursion group are shared
+    assert_eq!(
+        module
+            .types
+            .add_struct(&[FieldType::new(StorageType::Val(DataType::I32), true)]),
+        point
+    );
+    let points = module
+        .types
+        .add_array(FieldType::new(StorageType::Val(point_ref), true));
+    assert_eq!(*points, 2);
+
+    // types of a recursion group can refer to each other
+    let field = |ty: u32| {
+        CompositeType::Struct(Box::new([FieldType::new(
+            StorageType::Val(DataType::Concrete {
+                ty_id: TypeID(ty),
+                nullable: true,
+            }),
+            false,
+        )]))
+    };
+    let group = module
+        .types
+        .add_rec_group(vec![SubType::new(field(4)), SubType::new(field(3))]);
+    assert_eq!(group, vec![TypeID(3), TypeID(4)]);
+    assert_eq!(
+        module.types.get_rec_group(TypeID(4)).unwrap().start,
+        TypeID(3)
+    );
+    assert!(module.types.get(TypeID(3)).is_none());
+
+    let mut builder = FunctionBuilder::new(&[DataType::I32], &[DataType::I32]);
+    builder
+        .local_get(LocalID(0))
+        .struct_new(point)
+        .i32_const(1)
+        .array_new(points)
+        .i32_const(0)
+        .array_get(points)
+        .struct_get(point, 0);
+    builder.finish_module(&mut module);
+
+    let mut builder = FunctionBuilder::new(&[DataType::AnyRef], &[DataType::I32]);
+    builder.local_get(LocalID(0)).ref_test(DataType::Concrete {
+        ty_id: point,
+        nullable: false,
+    });
+    builder.finish_module(&mut module);
+
+    let result = module.encode();
+    wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+        .validate_all(&result)
+        .expect("Invalid module");
+
+    let out = wasmprinter::print_bytes(result).expect("couldn't translate wasm to wat");
+    if let Err(e) = check_instrumentation_encoding(&out, file) {
+        error!(
+            "Something went wrong when checking instrumentation encoding: {}",
+            e
+        )
+    }
+}
+
+#[test]
+fn test_extended_const() {
+    let file = "tests/test_inputs/handwritten/modules/extended_const.wat";
+
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+
+    let init_value = |module: &Module, id: u32| match module.globals.get_kind(GlobalID(id)) {
+        GlobalKind::Local(LocalGlobal { init_expr, .. }) => init_expr.value(),
+        GlobalKind::Import(_) => panic!("Expected a local global"),
+    };
+    assert!(matches!(init_value(&module, 3), Some(Value::I32(30))));
+    assert!(matches!(init_value(&module, 4), Some(Value::I64(3))));
+    // the value of an imported global is not known
+    assert!(init_value(&module, 5).is_none());
+    assert_eq!(module.data[0].kind.offset(), None);
+
+    // global and function indices inside of the expressions are remapped
+    module.delete_global(GlobalID(0));
+    module.add_global(
+        InitExpr::new(vec![
+            InitInstr::Global(GlobalID(5)),
+            InitInstr::Value(Value::I32(4)),
+            InitInstr::I32Add,
+        ]),
+        DataType::I32,
+        false,
+        false,
+    );
+    let result = module.encode();
+    wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+        .validate_all(&result)
+        .expect("Invalid module");
+
+    let module = Module::parse(&result, false).expect("Unable to parse module");
+    match module.globals.get_kind(GlobalID(5)) {
+        GlobalKind::Local(LocalGlobal { init_expr, .. }) => {
+            assert!(matches!(init_expr.exprs[0], InitInstr::Global(GlobalID(4))))
+        }
+        GlobalKind::Import(_) => panic!("Expected a local global"),
+    }
+    match &module.data[0].kind {
+        DataSegmentKind::Active { offset_expr, .. } => {
+            assert!(matches!(
+                offset_expr.exprs[0],
+                InitInstr::Global(GlobalID(0))
+            ))
+        }
+        DataSegmentKind::Passive => panic!("Expected an active data segment"),
+    }
+    match &module.elements[0].0 {
+        ElementKind::Active { offset_expr, .. } => {
+            assert!(matches!(
+                offset_expr.exprs[0],
+                InitInstr::Global(GlobalID(1))
+            ))
+        }
+        _ => panic!("Expected an active element segment"),
+    }
+}
+
 #[test]
 fn test_middle_local_to_import() {
     let file =
@@ -579,17 +792,17 @@ fn test_all_local_to_import_all_import_to_local() {
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
     // Convert all to local
-    let mut first_builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut first_builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     first_builder.i32_const(4);
     first_builder.drop();
     first_builder.replace_import_in_module(&mut module, ImportsID(0));
 
-    let mut second_builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut second_builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     second_builder.i32_const(5);
     second_builder.drop();
     second_builder.replace_import_in_module(&mut module, ImportsID(1));
 
-    let mut third_builder = FunctionBuilder::new(&*vec![DataType::I32, DataType::I32], &*vec![]);
+    let mut third_builder = FunctionBuilder::new(&[DataType::I32, DataType::I32], &[]);
     third_builder.i32_const(6);
     third_builder.drop();
     third_builder.replace_import_in_module(&mut module, ImportsID(2));
@@ -637,14 +850,14 @@ fn test_add_imports_and_local_fns() {
     let (fid, ..) = module.add_import_func("test0".to_string(), "func0".to_string(), TypeID(2));
 
     // add first local func
-    let mut first_builder = FunctionBuilder::new(&*vec![], &*vec![]);
+    let mut first_builder = FunctionBuilder::new(&[], &[]);
     first_builder.i32_const(1);
     first_builder.i32_const(1);
     first_builder.call(fid);
     let fid0 = first_builder.finish_module(&mut module);
 
     // add second local func
-    let mut sec_builder = FunctionBuilder::new(&*vec![], &*vec![]);
+    let mut sec_builder = FunctionBuilder::new(&[], &[]);
     sec_builder.i32_const(2);
     sec_builder.drop();
     sec_builder.call(fid0);
@@ -674,7 +887,7 @@ fn add_global_with_import() {
     let mut module = Module::parse(&buff, false).expect("Unable to parse module");
 
     // add new global
-    let gid = module.add_global(InitExpr::Value(Value::I32(0)), DataType::I32, true, false);
+    let gid = module.add_global(InitExpr::from(Value::I32(0)), DataType::I32, true, false);
     assert_eq!(1, *gid);
 
     let result = module.encode();
@@ -703,7 +916,7 @@ pub(crate) fn try_path(path: &str) {
 pub(crate) fn validate(wasm: &Vec<u8>, output_wasm_path: &str) -> Result<(), std::io::Error> {
     try_path(output_wasm_path);
     std::fs::write(output_wasm_path, wasm)?;
-    validate_wasm(&output_wasm_path);
+    validate_wasm(output_wasm_path);
     Ok(())
 }
 
@@ -720,3 +933,289 @@ pub(crate) fn validate_wasm(wasm_path: &str) -> bool {
 
     res.status.success()
 }
+
+#[test]
+fn test_reuse_original_bodies() {
+    // `i32.const 1` with a padded LEB, encoding the instruction again would shorten it
+    let padded = [0x00, 0x41, 0x81, 0x80, 0x80, 0x80, 0x00, 0x1a, 0x0b];
+    let mut input = wasm_encoder::Module::new();
+    let mut types = wasm_encoder::TypeSection::new();
+    types.function([], []);
+    input.section(&types);
+    let mut funcs = wasm_encoder::FunctionSection::new();
+    funcs.function(0).function(0);
+    input.section(&funcs);
+    let mut code = wasm_encoder::CodeSection::new();
+    code.raw(&padded).raw(&padded);
+    input.section(&code);
+    let buff = input.finish();
+
+    let bodies = |wasm: &[u8]| -> Vec<Vec<u8>> {
+        wasmparser::Parser::new(0)
+            .parse_all(wasm)
+            .filter_map(|payload| match payload.unwrap() {
+                wasmparser::Payload::CodeSectionEntry(body) => Some(body.as_bytes().to_vec()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    // only the instrumented function is encoded again
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+    let mut modifier = module.functions.get_fn_modifier(FunctionID(1)).unwrap();
+    modifier.before_at(Location::Module {
+        func_idx: FunctionID(1),
+        instr_idx: 0,
+    });
+    modifier.nop();
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    let result_bodies = bodies(&result);
+    assert_eq!(result_bodies[0], padded);
+    assert_eq!(result_bodies[1], [0x00, 0x01, 0x41, 0x01, 0x1a, 0x0b]);
+
+    // adding an imported function shifts the function IDs, the bodies are encoded again
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+    module.add_import_func("env".to_string(), "f".to_string(), TypeID(0));
+    let result = module.encode();
+    wasmparser::validate(&result).expect("Invalid module");
+    assert!(bodies(&result)
+        .iter()
+        .all(|body| body == &[0x00, 0x41, 0x01, 0x1a, 0x0b]));
+}
+
+#[test]
+#[cfg(feature = "wat")]
+fn test_from_wat() {
+    let file = "tests/test_inputs/handwritten/modules/add.wat";
+    let text = std::fs::read_to_string(file).expect("Unable to read the file");
+    let wat = Module::from_wat(&text).expect("Unable to convert the text format");
+    let mut module = wat.module().expect("Unable to parse module");
+
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut parsed = Module::parse(&buff, false).expect("Unable to parse module");
+    assert_eq!(module.encode(), parsed.encode());
+
+    assert!(Module::from_wat("(module (func (result i32) i32.const))").is_err());
+}
+
+#[test]
+#[cfg(feature = "wat")]
+fn test_component_from_wat() {
+    let file = "tests/test_inputs/handwritten/components/add.wat";
+    let text = std::fs::read_to_string(file).expect("Unable to read the file");
+    let wat = orca_wasm::Component::from_wat(&text).expect("Unable to convert the text format");
+    let mut component = wat.component().expect("Unable to parse component");
+
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut parsed = orca_wasm::Component::parse(&buff, false).expect("Unable to parse component");
+    assert_eq!(component.encode(), parsed.encode());
+}
+
+#[test]
+fn test_component_nested_gc_core_type() {
+    let buff = wat::parse_str(
+        "(component (type (instance (core type (module (type (struct (field i32))))))))",
+    )
+    .expect("couldn't convert the input wat to Wasm");
+    let mut component = orca_wasm::Component::parse(&buff, false).expect("Unable to parse");
+    assert!(component.try_encode().is_err());
+}
+
+#[test]
+fn test_delete_func_in_element_exprs() {
+    let buff = wat::parse_str(
+        "(module (table 2 funcref) (func) (func) (elem (i32.const 0) funcref (ref.func 1)))",
+    )
+    .expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+    module.delete_func(FunctionID(0));
+    let result = module.encode();
+
+    let expected = wat::parse_str(
+        "(module (table 2 funcref) (func) (elem (i32.const 0) funcref (ref.func 0)))",
+    )
+    .expect("couldn't convert the input wat to Wasm");
+    assert_eq!(
+        wasmprinter::print_bytes(result).expect("couldn't translate Wasm to wat"),
+        wasmprinter::print_bytes(expected).expect("couldn't translate Wasm to wat")
+    );
+}
+
+#[test]
+fn test_invalid_const_expr() {
+    let buff = wat::parse_str("(module (global i32 (i32.const 1) (i32.const 2) (i32.div_s)))")
+        .expect("couldn't convert the input wat to Wasm");
+    assert!(Module::parse(&buff, false).is_err());
+}
+
+#[test]
+fn test_edit_body_in_place() {
+    let file = "tests/test_inputs/handwritten/modules/add.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+    let body = &mut module.functions.unwrap_local(FunctionID(1)).body;
+    body.instructions[2].op = wasmparser::Operator::I32Sub;
+    body.locals[0].1 = DataType::F64;
+
+    let out = wasmprinter::print_bytes(module.encode()).expect("couldn't translate Wasm to wat");
+    assert!(out.contains("(local f64)"));
+    assert!(out.contains("i32.sub"));
+    assert!(!out.contains("i32.add"));
+}
+
+#[test]
+fn test_module_diff() {
+    let file = "tests/test_inputs/handwritten/modules/add.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let before = Module::parse(&buff, false).expect("Unable to parse module");
+    assert!(diff(&before, &before).is_empty());
+
+    let mut after = before.clone();
+    let mut modifier = after.functions.get_fn_modifier(FunctionID(1)).unwrap();
+    modifier.alternate_at(Location::Module {
+        func_idx: FunctionID(1),
+        instr_idx: 2,
+    });
+    modifier.i32_sub();
+    let global = after.add_global(
+        InitExpr::new(vec![InitInstr::Value(Value::I32(0))]),
+        DataType::I32,
+        true,
+        false,
+    );
+    after.exports.add_export_func("sub".to_string(), 1);
+    after.exports.delete(
+        after
+            .exports
+            .get_export_id_by_name("memory".to_string())
+            .unwrap(),
+    );
+
+    let module_diff = diff(&before, &after);
+    assert_eq!(
+        module_diff.functions,
+        vec![FuncDiff {
+            func_idx: FunctionID(1),
+            change: Change::Modified,
+            before: Some("[I32, I32] -> [I32] locals [(1, F32)]".to_string()),
+            after: Some("[I32, I32] -> [I32] locals [(1, F32)]".to_string()),
+            instrs: vec![
+                InstrDiff::Removed {
+                    instr_idx: 2,
+                    op: "I32Add".to_string()
+                },
+                InstrDiff::Added {
+                    instr_idx: 2,
+                    op: "I32Sub".to_string()
+                },
+            ],
+        }]
+    );
+    assert_eq!(module_diff.globals.len(), 1);
+    assert_eq!(module_diff.globals[0].key, (*global).to_string());
+    assert_eq!(module_diff.globals[0].change, Change::Added);
+    let exports: Vec<_> = module_diff
+        .exports
+        .iter()
+        .map(|export| (export.key.as_str(), export.change))
+        .collect();
+    assert_eq!(
+        exports,
+        vec![("memory", Change::Removed), ("sub", Change::Added)]
+    );
+    assert!(module_diff.imports.is_empty());
+    assert!(module_diff.data.is_empty());
+    assert!(module_diff.custom_sections.is_empty());
+    assert!(module_diff
+        .to_string()
+        .starts_with("function 1 modified\n  - 2: I32Add\n  + 2: I32Sub\nexport memory removed\n"));
+}
+
+#[test]
+fn test_module_stats() {
+    let file = "tests/test_inputs/handwritten/modules/add.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+    let mut modifier = module.functions.get_fn_modifier(FunctionID(1)).unwrap();
+    modifier.before_at(Location::Module {
+        func_idx: FunctionID(1),
+        instr_idx: 0,
+    });
+    modifier.i32_const(1).drop();
+
+    let stats = module.stats().expect("Unable to compute the stats");
+    assert_eq!(stats.num_local_functions, 2);
+    assert_eq!(stats.num_imported_functions, 1);
+    assert_eq!(stats.num_instructions, 9);
+    assert_eq!(stats.num_locals, 2);
+    assert_eq!(stats.opcodes["LocalGet"], 2);
+    assert_eq!(stats.opcodes["I32Const"], 2);
+    assert_eq!(stats.opcodes["End"], 2);
+    assert_eq!(stats.data_bytes, 0);
+    assert_eq!(stats.size, module.clone().encode().len());
+
+    let add = &stats.functions[0];
+    assert_eq!(add.func_idx, FunctionID(1));
+    assert_eq!(add.num_instructions, 4);
+    assert_eq!(add.num_injected, 2);
+    // i32.const 1, drop
+    assert_eq!(add.instrumented_size, add.size + 3);
+    let other = &stats.functions[1];
+    assert_eq!(other.num_injected, 0);
+    assert_eq!(other.instrumented_size, other.size);
+
+    let sections: Vec<_> = stats
+        .sections
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(
+        sections,
+        vec![
+            "type",
+            "import",
+            "function",
+            "memory",
+            "export",
+            "code",
+            "custom:name"
+        ]
+    );
+    assert_eq!(stats.to_json()["functions"][0]["num_injected"], 2);
+}
+
+#[test]
+fn test_module_stats_unbalanced_instrumentation() {
+    let file = "tests/test_inputs/handwritten/modules/add.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let mut module = Module::parse(&buff, false).expect("Unable to parse module");
+    let mut modifier = module.functions.get_fn_modifier(FunctionID(1)).unwrap();
+    modifier.before_at(Location::Module {
+        func_idx: FunctionID(1),
+        instr_idx: 0,
+    });
+    // never closed
+    modifier.block(BlockType::Empty);
+
+    assert!(module.stats().is_err());
+}
+
+#[test]
+fn test_component_stats() {
+    let file = "tests/test_inputs/handwritten/components/nested.wat";
+    let buff = wat::parse_file(file).expect("couldn't convert the input wat to Wasm");
+    let component = orca_wasm::Component::parse(&buff, false).expect("Unable to parse component");
+
+    let stats = component.stats().expect("Unable to compute the stats");
+    assert_eq!(stats.modules.len(), 1);
+    assert_eq!(stats.modules[0].num_instructions, 2);
+    let nested = &stats.components[0];
+    assert_eq!(nested.modules[0].opcodes["I32Add"], 1);
+    assert_eq!(nested.components[0].modules[0].opcodes["Nop"], 1);
+    assert!(nested.components[0].components.is_empty());
+    assert_eq!(
+        stats.to_json()["components"][0]["modules"][0]["num_instructions"],
+        4
+    );
+}